@@ -2,9 +2,10 @@
 import { fileURLToPath } from 'node:url';
 import { dirname, join, basename, resolve } from 'node:path';
 import { readFileSync } from 'node:fs';
+import { randomBytes } from 'node:crypto';
 import * as registry from './registry.js';
-import * as server from './server.js';
 import * as watcher from './watcher.js';
+import { builder } from './server-builder.js';
 import pkg from '../package.json' with { type: 'json' };
 
 const __filename = fileURLToPath(import.meta.url);
@@ -12,12 +13,19 @@ const __dirname = dirname(__filename);
 
 /**
  * Parse command-line arguments
- * @returns {{ root: string, port: number | null, help: boolean, version: boolean }}
+ * @returns {{ root: string, port: number | null, tlsCert: string | null, tlsKey: string | null, token: string | null, cors: string[] | null, corsCredentials: boolean, eventLog: string | null, help: boolean, version: boolean }}
  */
 function parseArgs() {
   const args = process.argv.slice(2);
   let root = null;
   let port = null;
+  let tlsCert = null;
+  let tlsKey = null;
+  let token = null;
+  let cors = null;
+  let corsCredentials = false;
+  /** @type {string | null} */
+  let eventLog = null;
   let help = false;
   let version = false;
 
@@ -40,6 +48,34 @@ function parseArgs() {
       }
     } else if (arg.startsWith('--port=')) {
       port = Number(arg.slice(7));
+    } else if (arg === '--tls-cert') {
+      if (i + 1 < args.length) {
+        tlsCert = resolve(args[++i]);
+      }
+    } else if (arg.startsWith('--tls-cert=')) {
+      tlsCert = resolve(arg.slice(11));
+    } else if (arg === '--tls-key') {
+      if (i + 1 < args.length) {
+        tlsKey = resolve(args[++i]);
+      }
+    } else if (arg.startsWith('--tls-key=')) {
+      tlsKey = resolve(arg.slice(10));
+    } else if (arg === '--token') {
+      token = randomBytes(24).toString('base64url');
+    } else if (arg.startsWith('--token=')) {
+      token = arg.slice(8);
+    } else if (arg === '--cors') {
+      if (i + 1 < args.length) {
+        cors = args[++i].split(',');
+      }
+    } else if (arg.startsWith('--cors=')) {
+      cors = arg.slice(7).split(',');
+    } else if (arg === '--cors-credentials') {
+      corsCredentials = true;
+    } else if (arg === '--event-log') {
+      eventLog = '';
+    } else if (arg.startsWith('--event-log=')) {
+      eventLog = resolve(arg.slice(12));
     }
   }
   
@@ -47,8 +83,9 @@ function parseArgs() {
   if (root === null) {
     root = process.cwd();
   }
+  if (eventLog === '') eventLog = join(root, 'daebug', '.events.ndjson');
 
-  return { root, port, help, version };
+  return { root, port, tlsCert, tlsKey, token, cors, corsCredentials, eventLog, help, version };
 }
 
 /**
@@ -91,6 +128,12 @@ Usage: daebug [options]
 Options:
   --root, -r <path>    Root directory to serve (default: current directory)
   --port, -p <number>  Port to listen on (default: derived from directory name)
+  --tls-cert <path>    PEM certificate to serve HTTPS with (needs --tls-key)
+  --tls-key <path>     PEM private key for --tls-cert
+  --token[=<value>]    Require an access token on daebug's routes (generated when no value)
+  --cors <origins>     Comma-separated origins whose pages may call daebug, or *
+  --cors-credentials   Let those pages send the page cookie with --token (not with *)
+  --event-log[=<path>] Keep an NDJSON log of server events (default: daebug/.events.ndjson)
   --help, -h          Show this help message
   --version, -v       Show version number
 
@@ -99,6 +142,11 @@ Examples:
   daebug --root=/path/to/project
   daebug --port=9000
   daebug --root=/project --port=9000
+  daebug --tls-cert=cert.pem --tls-key=key.pem
+  daebug --token                # Print a fresh access token for agents to send as a bearer
+  daebug --cors=http://localhost:5173
+  daebug --token --cors=http://localhost:5173 --cors-credentials  # then <script src=".../-daebug-client.js">
+  daebug --event-log            # Record job and page events for /-daebug-events/history
 `);
 }
 
@@ -116,7 +164,7 @@ function showVersion() {
 }
 
 export async function run() {
-  const { root, port, help, version } = parseArgs();
+  const { root, port, tlsCert, tlsKey, token, cors, corsCredentials, eventLog, help, version } = parseArgs();
 
   if (help) {
     showHelp();
@@ -135,7 +183,20 @@ export async function run() {
   const bannerPrefix = `👾Daebug v${pkg.version} serving  ${root}  👉  `;
   const dirName = basename(root);
 
+  const configured = builder()
+    .root(root)
+    .port(finalPort);
+  if (tlsCert || tlsKey) configured.tls(tlsCert || '', tlsKey || '');
+  if (token !== null) configured.accessToken(token);
+  if (cors) configured.cors(cors, corsCredentials);
+  if (eventLog) configured.eventLog(eventLog);
+  const app = configured.build();
+  if (token) console.log(`🔑 access token: ${token} (open pages once with ?daebug-token=${encodeURIComponent(token)})`);
+
   registry.init(root);
-  await server.start(root, finalPort, dirName, bannerPrefix);
+  registry.loadManifest(root);
+  watcher.loadContentHashes(root);
+  await app.start(dirName, bannerPrefix);
   watcher.watchForRestart(root);
+  app.markReady();
 }
\ No newline at end of file
@@ -6,10 +6,11 @@
  *  'Date' |
  *  'sessionStorage' | 'location' | 'Worker' | 'fetch' |
  *  'setInterval' | 'clearInterval' | 'setTimeout' | 'clearTimeout' | 'addEventListener' |
- *  'console'>} overrides
+ *  'console'> & { document?: Pick<Document, 'currentScript'> }} overrides
  * @param {Record<string, Function>} testExport
+ * @param {RequestCredentials} [credentials] Whether requests to daebug carry cookies, 'include' when they cross origins
  */
-export async function clientMainFunction(overrides, testExport) {
+export async function clientMainFunction(overrides, testExport, credentials = 'same-origin') {
   // Setup globals: use provided overrides or fallback to window
   const {
     Date,
@@ -22,9 +23,14 @@ export async function clientMainFunction(overrides, testExport) {
     setTimeout,
     clearTimeout,
     console,
-    addEventListener
+    addEventListener,
+    document
   } = overrides || window;
 
+  // Loaded by <script src> from another origin, the script still talks to the daebug that served it
+  const scriptSrc = /** @type {HTMLScriptElement | null | undefined} */(document?.currentScript)?.src;
+  const origin = scriptSrc ? new URL(scriptSrc).origin : location.origin;
+
   // Worker management
   /** @type {Worker | null} */
   let worker = null;
@@ -38,6 +44,14 @@ export async function clientMainFunction(overrides, testExport) {
   const WORKER_TIMEOUT = 20000;
   let endpoint = '';
 
+  /**
+   * Retry timing after failed requests: doubles from baseMs up to maxMs, spread by ±jitter
+   * so many pages coming back after a server restart don't retry in lockstep
+   * @typedef {{ baseMs: number, maxMs: number, jitter: number }} Backoff
+   */
+  /** @type {Backoff} */
+  const BACKOFF = { baseMs: 500, maxMs: 30000, jitter: 0.2 };
+
   /**
   /** @type {{
    *  type: 'error' | 'console',
@@ -60,6 +74,10 @@ export async function clientMainFunction(overrides, testExport) {
   if (testExport) {
     Object.assign(testExport, {
       serializeValue,
+      tagBigInt,
+      valueType,
+      splitJobEvents,
+      predicateHolds,
       sanitizeName,
       createWorker,
       checkWorkerHealth,
@@ -67,6 +85,9 @@ export async function clientMainFunction(overrides, testExport) {
       handlePromiseRejectionEvent,
       monkeyPatchConsole,
       scheduleBackgroundFlush,
+      watchCancel,
+      backoffDelay,
+      postWithRetry,
       start,
       sleep
     });
@@ -116,8 +137,9 @@ export async function clientMainFunction(overrides, testExport) {
       workerRestartCount++;
       lastWorkerPong = Date.now();
 
-      fetch('/-daebug-channel?name=' + encodeURIComponent(workerName) + '&url=worker://' + encodeURIComponent(workerName), {
+      fetch(origin + '/-daebug-channel?name=' + encodeURIComponent(workerName) + '&url=worker://' + encodeURIComponent(workerName), {
         method: 'POST',
+        credentials,
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify({ type: 'worker-init', mainPage: pageName })
       }).catch(() => { });
@@ -136,8 +158,9 @@ export async function clientMainFunction(overrides, testExport) {
     if (timeSinceLastPong > WORKER_TIMEOUT) {
       console.warn('👾𝘂𝗻𝗿𝗲𝘀𝗽𝗼𝗻𝘀𝗶𝘃𝗲 worker, restarting');
       const workerName = sanitizeName(pageName + '-webworker');
-      fetch('/-daebug-channel?name=' + encodeURIComponent(workerName) + '&url=worker://' + encodeURIComponent(workerName), {
+      fetch(origin + '/-daebug-channel?name=' + encodeURIComponent(workerName) + '&url=worker://' + encodeURIComponent(workerName), {
         method: 'POST',
+        credentials,
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify({ type: 'worker-timeout', duration: timeSinceLastPong })
       }).catch(() => { });
@@ -163,6 +186,77 @@ export async function clientMainFunction(overrides, testExport) {
     return new Promise(r => setTimeout(r, ms));
   }
 
+  /**
+   * Delay before the next attempt after `attempt` consecutive failures
+   * @param {number} attempt
+   * @param {Backoff} [backoff]
+   * @param {() => number} [random]
+   */
+  function backoffDelay(attempt, backoff = BACKOFF, random = Math.random) {
+    const delay = Math.min(backoff.maxMs, backoff.baseMs * 2 ** attempt);
+    return Math.round(delay * (1 - backoff.jitter + 2 * backoff.jitter * random()));
+  }
+
+  /**
+   * JSON.stringify replacer carrying BigInts as `{ "$bigint": "<digits>" }`, which JSON itself
+   * cannot hold and a number would round past 2^53
+   * @param {string} key
+   * @param {unknown} value
+   */
+  function tagBigInt(key, value) {
+    return typeof value === 'bigint' ? { $bigint: value.toString() } : value;
+  }
+
+  /**
+   * Type of a result as JS sees it, sent beside the value since JSON drops `undefined`
+   * and cannot tell a function or symbol from nothing
+   * @param {unknown} value
+   */
+  function valueType(value) {
+    return value === null ? 'null' : Array.isArray(value) ? 'array' : typeof value;
+  }
+
+  /**
+   * POST to the channel, retrying with backoff so a result survives a brief server outage
+   * @param {*} payload
+   * @param {number} [attempts]
+   */
+  async function postWithRetry(payload, attempts = 5) {
+    for (let attempt = 0; ; attempt++) {
+      try {
+        const res = await fetch(endpoint, {
+          method: 'POST',
+          credentials,
+          headers: { 'Content-Type': 'application/json' },
+          body: JSON.stringify(payload, tagBigInt)
+        });
+        if (res.ok) return;
+        throw new Error('server responded ' + res.status);
+      } catch (err) {
+        if (attempt + 1 >= attempts) throw err;
+        await sleep(backoffDelay(attempt));
+      }
+    }
+  }
+
+  /**
+   * Hold a cancellation watch open on the server while a job runs
+   * @param {string | null} jobId
+   * @param {() => boolean} isSettled
+   * @returns {Promise<boolean>} true when the server asks to stop the job
+   */
+  async function watchCancel(jobId, isSettled) {
+    while (jobId && !isSettled()) {
+      try {
+        const res = await fetch(endpoint + '&cancel=' + encodeURIComponent(jobId), { cache: 'no-cache', credentials });
+        if (await res.text() === 'cancelled') return !isSettled();
+      } catch (err) {
+        await sleep(3000);
+      }
+    }
+    return false;
+  }
+
   function scheduleBackgroundFlush() {
     const now = Date.now();
     const timeSinceLastFlush = now - lastFlushTime;
@@ -185,6 +279,7 @@ export async function clientMainFunction(overrides, testExport) {
       try {
         await fetch(endpoint, {
           method: 'POST',
+          credentials,
           headers: { 'Content-Type': 'application/json' },
           body: JSON.stringify({
               type: 'background-flush',
@@ -199,6 +294,29 @@ export async function clientMainFunction(overrides, testExport) {
     }
   }
 
+  /**
+   * Whether a job's `when` predicate holds: the expression is awaited and its value taken as truthy or not
+   * @param {string} predicate
+   * @param {AbortSignal} [signal]
+   */
+  async function predicateHolds(predicate, signal) {
+    const AsyncFunction = Object.getPrototypeOf(async function () { }).constructor;
+    return !!(await new AsyncFunction('signal', 'return (' + predicate + ')')(signal));
+  }
+
+  /**
+   * Split what happened during a job into its console output and the remaining background events
+   * @param {typeof backgroundEvents} events
+   */
+  function splitJobEvents(events) {
+    return {
+      logs: events
+        .filter(event => event.type === 'console')
+        .map(({ level, message, eventAt }) => ({ level: level || 'log', message, eventAt })),
+      backgroundEvents: events.filter(event => event.type !== 'console')
+    };
+  }
+
   /**
    * Helper to serialize values for console output
    * @param {any} val
@@ -212,6 +330,7 @@ export async function clientMainFunction(overrides, testExport) {
     if (typeof val === 'function') return val.name || '[Function]';
     if (typeof val === 'string') return val;
     if (typeof val === 'number' || typeof val === 'boolean') return String(val);
+    if (typeof val === 'bigint') return val + 'n';
     if (Array.isArray(val)) {
       try {
         return '[' + val.map(
@@ -223,7 +342,7 @@ export async function clientMainFunction(overrides, testExport) {
     }
     if (typeof val === 'object') {
       try {
-        return JSON.stringify(val);
+        return JSON.stringify(val, tagBigInt);
       } catch (e) {
         return String(val);
       }
@@ -332,51 +451,82 @@ export async function clientMainFunction(overrides, testExport) {
     addEventListener('error', handleErrorEvent);
     addEventListener('unhandledrejection', handlePromiseRejectionEvent);  
 
-    endpoint = '/-daebug-channel?name=' + encodeURIComponent(name) + '&url=' + encodeURIComponent(location.href);
+    // Fresh on every load, so the server can tell a reload from the same page polling again
+    const instance = Math.random().toString(36).slice(2, 10) + Date.now().toString(36);
+    // The page runs JavaScript; its URL can ask for more, e.g. ?daebug-caps=ephemeral for a scratch page
+    const asked = new URLSearchParams(location.href.split('#')[0].split('?')[1]).get('daebug-caps');
+    const caps = new Set(['js', ...(asked ? asked.split(',') : [])]);
+    endpoint = origin + '/-daebug-channel?name=' + encodeURIComponent(name) + '&url=' + encodeURIComponent(location.href) + '&instance=' + instance
+      + '&caps=' + encodeURIComponent(Array.from(caps).join(','));
 
     worker = createWorker();
     if (worker) {
       workerHealthCheckInterval = setInterval(checkWorkerHealth, WORKER_HEALTH_CHECK_INTERVAL);
     }
 
+    let failures = 0;
+    // Hash of the prelude already evaluated in this realm
+    let preludeHash = '';
     while (true) {
       try {
-        const res = await fetch(endpoint, { cache: 'no-cache' });
+        const res = await fetch(endpoint + (preludeHash ? '&prelude=' + preludeHash : ''), { cache: 'no-cache', credentials });
+        if (!res.ok) throw new Error('server responded ' + res.status);
         const script = await res.text();
+        failures = 0;
         if (!script) { await sleep(500); continue; }
 
         // Mark start of job execution for background event association
         const jobStartIdx = backgroundEvents.length;
         const AsyncFunction = Object.getPrototypeOf(async function () { }).constructor;
+        const jobId = res.headers.get('x-job-id');
+        // Snippets can observe `signal` to stop early; the page stops awaiting them either way
+        const controller = new AbortController();
+        const cancelled = new Promise((_, reject) =>
+          controller.signal.addEventListener('abort', () => reject(new Error('Cancelled'))));
+        let settled = false;
+        watchCancel(jobId, () => settled).then(stop => { if (stop) controller.abort(); });
         let payload;
 
         try {
+          // Shared setup sent only when it changed; evaluated globally so its declarations persist
+          const prelude = res.headers.get('x-prelude');
+          if (prelude) {
+            (0, eval)(decodeURIComponent(prelude));
+            preludeHash = res.headers.get('x-prelude-hash') || '';
+          }
+
+          // A guarded job runs only when its predicate holds, and otherwise reports itself skipped
+          const when = res.headers.get('x-job-when');
+          const skipped = !!when && !(await Promise.race([predicateHolds(decodeURIComponent(when), controller.signal), cancelled]));
+
           let result;
-          try {
-            result = await new AsyncFunction('return (' + script + ')')();
-          } catch {
-            result = await new AsyncFunction(script)();
+          if (!skipped) {
+            try {
+              result = await Promise.race([new AsyncFunction('signal', 'return (' + script + ')')(controller.signal), cancelled]);
+            } catch {
+              if (controller.signal.aborted) throw new Error('Cancelled');
+              result = await Promise.race([new AsyncFunction('signal', script)(controller.signal), cancelled]);
+            }
           }
 
           // Capture background events that occurred during execution
           const jobEvents = backgroundEvents.splice(jobStartIdx);
-          payload = { ok: true, value: result, backgroundEvents: jobEvents, jobId: res.headers.get('x-job-id') };
+          payload = { ok: true, value: result, valueType: valueType(result), ...(skipped && { skipped: true }), ...splitJobEvents(jobEvents), jobId };
         } catch (err) {
           // Capture background events even on error
           const jobEvents = backgroundEvents.splice(jobStartIdx);
-          payload = { ok: false, error: /** @type {*} */(err)?.stack || String(err), backgroundEvents: jobEvents, jobId: res.headers.get('x-job-id') };
+          payload = controller.signal.aborted
+            ? { ok: false, cancelled: true, error: 'Cancelled', ...splitJobEvents(jobEvents), jobId }
+            : { ok: false, error: /** @type {*} */(err)?.stack || String(err), ...splitJobEvents(jobEvents), jobId };
         }
+        settled = true;
 
-        await fetch(endpoint, {
-          method: 'POST',
-          headers: { 'Content-Type': 'application/json' },
-          body: JSON.stringify(payload)
-        });
+        await postWithRetry(payload);
 
         await sleep(100);
       } catch (err) {
         console.warn('👾𝗳𝗲𝘁𝗰𝗵 error ', err);
-        await sleep(3000);
+        await sleep(backoffDelay(failures++));
       }
     }
   }
@@ -384,3 +534,9 @@ export async function clientMainFunction(overrides, testExport) {
 }
 
 export const clientScript = '(' + clientMainFunction + ')();';
+
+/**
+ * The client for pages that load it by `<script src>`, possibly from another origin
+ * @param {RequestCredentials} credentials
+ */
+export const clientScriptWith = credentials => '(' + clientMainFunction + ')(undefined, undefined, ' + JSON.stringify(credentials) + ');';
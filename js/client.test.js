@@ -3,6 +3,7 @@
 import { test, describe } from 'node:test';
 import assert from 'node:assert';
 import { clientMainFunction } from './client.js';
+import { buildBlocks } from './writer.js';
 
 const defaultOverrides = {
   Date,
@@ -131,6 +132,75 @@ describe('clientMainFunction', () => {
     });
   });
 
+  describe('splitJobEvents', () => {
+    test('separates console output from other background events', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      const error = { type: 'error', source: 'unhandledrejection', eventAt: '2024-01-01T10:00:01.000Z', message: 'boom' };
+      const { logs, backgroundEvents } = exported.splitJobEvents([
+        { type: 'console', level: 'log', eventAt: '2024-01-01T10:00:00.000Z', message: 'hello', caller: 'at x.js:1' },
+        error,
+        { type: 'console', level: 'warn', eventAt: '2024-01-01T10:00:02.000Z', message: '{"a":1}' }
+      ]);
+      assert.deepStrictEqual(logs, [
+        { level: 'log', message: 'hello', eventAt: '2024-01-01T10:00:00.000Z' },
+        { level: 'warn', message: '{"a":1}', eventAt: '2024-01-01T10:00:02.000Z' }
+      ]);
+      assert.deepStrictEqual(backgroundEvents, [error]);
+    });
+
+    test('logs survive JSON transport unchanged', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      const payload = { ok: true, value: 1, ...exported.splitJobEvents([{ type: 'console', eventAt: '2024-01-01T10:00:00.000Z', message: 'no level' }]) };
+      assert.deepStrictEqual(JSON.parse(JSON.stringify(payload)).logs, [{ level: 'log', message: 'no level', eventAt: '2024-01-01T10:00:00.000Z' }]);
+    });
+  });
+
+  describe('BigInt results', () => {
+    test('integers past 2^53 round-trip to the reply with every digit', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      const big = 2n ** 64n + 1n;
+      const body = JSON.stringify({ ok: true, value: { big, list: [big, -3n], small: 1 } }, exported.tagBigInt);
+      const payload = JSON.parse(body);
+      assert.deepStrictEqual(payload.value.big, { $bigint: '18446744073709551617' });
+      assert.strictEqual(BigInt(payload.value.list[1].$bigint), -3n);
+
+      const [block] = buildBlocks(payload);
+      assert.match(block, /"big": 18446744073709551617,/);
+      assert.match(block, /18446744073709551617,\n\s+-3\n/);
+      assert.strictEqual(buildBlocks(JSON.parse(JSON.stringify({ ok: true, value: big }, exported.tagBigInt)))[0], '```JSON bigint\n18446744073709551617\n```');
+      assert.strictEqual(exported.serializeValue(big), '18446744073709551617n');
+    });
+  });
+
+  describe('valueType', () => {
+    test('names what JSON alone would blur', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      assert.deepStrictEqual([null, undefined, [], {}, '', 1, 1n, () => { }, Symbol('s')].map(exported.valueType),
+        ['null', 'undefined', 'array', 'object', 'string', 'number', 'bigint', 'function', 'symbol']);
+    });
+  });
+
+  describe('predicateHolds', () => {
+    test('decides between running and skipping a guarded job', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      assert.strictEqual(await exported.predicateHolds('1 + 1 === 2'), true);
+      assert.strictEqual(await exported.predicateHolds('[].length'), false);
+      assert.strictEqual(await exported.predicateHolds('await Promise.resolve("ready")'), true);
+      assert.strictEqual(await exported.predicateHolds('typeof nothingHere !== "undefined"'), false);
+    });
+
+    test('a predicate that throws fails the job rather than skipping it', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      await assert.rejects(exported.predicateHolds('nothingHere.ready'), ReferenceError);
+    });
+  });
+
   describe('handleErrorEvent', () => {
     test('captures window.onerror events', async () => {
       const exported = {};
@@ -176,6 +246,9 @@ describe('clientMainFunction', () => {
         'handleErrorEvent',
         'handlePromiseRejectionEvent',
         'scheduleBackgroundFlush',
+        'watchCancel',
+        'backoffDelay',
+        'postWithRetry',
         'start',
         'sleep'
       ];
@@ -200,4 +273,90 @@ describe('clientMainFunction', () => {
     });
   });
 
+  describe('watchCancel', () => {
+    test('resolves true when the server reports cancellation', async () => {
+      /** @type {string[]} */
+      const urls = [];
+      const responses = ['', 'cancelled'];
+      const exported = {};
+      await clientMainFunction(/** @type {*} */({
+        ...defaultOverrides,
+        fetch: async (/** @type {string} */ url) => { urls.push(url); return { text: async () => responses.shift() }; }
+      }), exported);
+
+      const stop = await exported.watchCancel('7', () => false);
+      assert.strictEqual(stop, true);
+      assert.strictEqual(urls.length, 2);
+      assert.ok(urls.every(url => url.endsWith('&cancel=7')));
+    });
+
+    test('resolves false without polling once the job has settled', async () => {
+      let calls = 0;
+      const exported = {};
+      await clientMainFunction(/** @type {*} */({
+        ...defaultOverrides,
+        fetch: async () => { calls++; return { text: async () => 'cancelled' }; }
+      }), exported);
+
+      assert.strictEqual(await exported.watchCancel('7', () => true), false);
+      assert.strictEqual(calls, 0);
+    });
+
+    test('does not watch when the job has no id', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      assert.strictEqual(await exported.watchCancel(null, () => false), false);
+    });
+  });
+
+  describe('backoffDelay', () => {
+    const backoff = { baseMs: 100, maxMs: 1000, jitter: 0.2 };
+    const middle = () => 0.5;
+
+    test('doubles per attempt up to the maximum', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      const delays = [0, 1, 2, 3, 4, 5].map(attempt => exported.backoffDelay(attempt, backoff, middle));
+      assert.deepStrictEqual(delays, [100, 200, 400, 800, 1000, 1000]);
+    });
+
+    test('stays within the jitter bounds', async () => {
+      const exported = {};
+      await clientMainFunction(defaultOverrides, exported);
+      assert.strictEqual(exported.backoffDelay(2, backoff, () => 0), 320);
+      assert.strictEqual(exported.backoffDelay(2, backoff, () => 1), 480);
+      for (let i = 0; i < 100; i++) {
+        const delay = exported.backoffDelay(10, backoff);
+        assert.ok(delay >= 800 && delay <= 1200, `delay ${delay} out of bounds`);
+      }
+    });
+  });
+
+  describe('postWithRetry', () => {
+    test('retries a failed post until the server accepts it', async () => {
+      const statuses = [503, 200];
+      let calls = 0;
+      const exported = {};
+      await clientMainFunction(/** @type {*} */({
+        ...defaultOverrides,
+        fetch: async () => { calls++; const status = statuses.shift(); return { ok: status === 200, status }; }
+      }), exported);
+
+      await exported.postWithRetry({ ok: true, value: 1 });
+      assert.strictEqual(calls, 2);
+    });
+
+    test('gives up after the attempt limit', async () => {
+      let calls = 0;
+      const exported = {};
+      await clientMainFunction(/** @type {*} */({
+        ...defaultOverrides,
+        fetch: async () => { calls++; return { ok: false, status: 500 }; }
+      }), exported);
+
+      await assert.rejects(exported.postWithRetry({ ok: true }, 1), /server responded 500/);
+      assert.strictEqual(calls, 1);
+    });
+  });
+
 });
\ No newline at end of file
@@ -1,5 +1,6 @@
 // @ts-check
 import { relative } from 'node:path';
+import { getTheme } from './theme.js';
 
 /**
  * Format milliseconds as HH:MM:SS time string
@@ -17,7 +18,7 @@ function clockFmt(ms) {
  * Template for daebug.md index
  * @param {{
  *   startTime: number|Date,
- *   pageList?: Array<{name: string, url: string, file: string, state: string, lastSeen: number}>,
+ *   pageList?: Array<{name: string, url: string, file: string, state: string, stateSince?: number, lastSeen: number, queue?: {depth: number, executing: string | null}}>,
  *   isShutdown?: boolean,
  *   endTime?: number|Date,
  *   root?: string
@@ -34,15 +35,19 @@ export function daebugMD_template({ startTime, pageList = [], isShutdown = false
   const endTimeStr = endMs !== undefined ? new Date(endMs).toLocaleTimeString() : '';
   
   // Format page list as markdown
+  // Pages arrive in the registry's configured order
   const pageListStr = pageList
-    .sort((a, b) => b.lastSeen - a.lastSeen)
     .map(p => {
       const filePath = root ? relative(root, p.file).replace(/\\/g, '/') : p.file.replace(/\\/g, '/');
-      return `* [${p.name}](${filePath}) (${p.url}) at ${new Date(p.lastSeen).toLocaleTimeString()}: ${p.state === 'idle' ? 'live' : p.state}`;
+      const queue = p.queue ? `, ${p.queue.depth} queued${p.queue.executing ? `, running job ${p.queue.executing}` : ''}` : '';
+      const state = p.state === 'idle' ? 'live' : p.state + (p.stateSince ? ` since ${clockFmt(p.stateSince)}` : '');
+      return `* [${p.name}](${filePath}) (${p.url}) at ${new Date(p.lastSeen).toLocaleTimeString()}: ${state}${queue}`;
     })
     .join('\n');
 
-  return `# 👾 Daebug remote debugging REPL${isShutdown ? `: SERVER SHUT DOWN ${endTimeStr}` : ` started ${startTimeStr}`}
+  const title = getTheme().title;
+
+  return `# ${title ? title + ' ' : ''}Daebug remote debugging REPL${isShutdown ? `: SERVER SHUT DOWN ${endTimeStr}` : ` started ${startTimeStr}`}
 > ${isShutdown ? 'This debugging session has concluded.' : 'Interactive debugging REPL sessions for live browser contexts'}
 
 ${isShutdown ?
@@ -0,0 +1,92 @@
+// @ts-check
+import { compareIds } from './ids.js';
+
+/**
+ * Chooses which of a realm's waiting jobs it picks up next. `order` lines up all of them the
+ * way `selectNext` would hand them out, without moving any state the strategy keeps.
+ * @typedef {{
+ *   selectNext: (pollerName: string, candidates: import('./job.js').Job[]) => import('./job.js').Job | undefined,
+ *   order?: (pollerName: string, candidates: import('./job.js').Job[]) => import('./job.js').Job[]
+ * }} DispatchStrategy
+ */
+
+/**
+ * @param {import('./job.js').Job} a
+ * @param {import('./job.js').Job} b
+ */
+const byArrival = (a, b) => Date.parse(a.requestedAt) - Date.parse(b.requestedAt) || compareIds(a.id, b.id);
+
+/**
+ * Oldest job first
+ * @type {DispatchStrategy}
+ */
+export const fifo = {
+  selectNext: (_pollerName, candidates) => [...candidates].sort(byArrival)[0],
+  order: (_pollerName, candidates) => [...candidates].sort(byArrival)
+};
+
+/**
+ * Highest `priority` first, oldest first among equals
+ * @type {DispatchStrategy}
+ */
+export const priority = {
+  selectNext: (_pollerName, candidates) => byPriority(candidates)[0],
+  order: (_pollerName, candidates) => byPriority(candidates)
+};
+
+/** @param {import('./job.js').Job[]} candidates */
+function byPriority(candidates) {
+  return [...candidates].sort((a, b) => (b.priority || 0) - (a.priority || 0) || byArrival(a, b));
+}
+
+/**
+ * Take turns between agents so one busy agent can't starve the others:
+ * the oldest job from an agent other than the one served last, if there is one
+ * @returns {DispatchStrategy}
+ */
+export function roundRobin() {
+  /** @type {Map<string, string>} */
+  const lastAgent = new Map();
+  /** @param {import('./job.js').Job[]} ordered @param {string | undefined} last */
+  const pick = (ordered, last) => ordered.find(j => j.agent !== last) || ordered[0];
+  return {
+    selectNext(pollerName, candidates) {
+      const next = pick([...candidates].sort(byArrival), lastAgent.get(pollerName));
+      if (next) lastAgent.set(pollerName, next.agent);
+      return next;
+    },
+    order(pollerName, candidates) {
+      const waiting = [...candidates].sort(byArrival);
+      const lineUp = [];
+      let last = lastAgent.get(pollerName);
+      while (waiting.length) {
+        const next = pick(waiting, last);
+        waiting.splice(waiting.indexOf(next), 1);
+        lineUp.push(next);
+        last = next.agent;
+      }
+      return lineUp;
+    }
+  };
+}
+
+/**
+ * The order a strategy would hand a realm's waiting jobs out in. A strategy without `order`
+ * is asked for its next pick over and over, which moves whatever state it keeps.
+ * @param {DispatchStrategy} strategy
+ * @param {string} pollerName
+ * @param {import('./job.js').Job[]} candidates
+ * @returns {import('./job.js').Job[]}
+ */
+export function dispatchOrder(strategy, pollerName, candidates) {
+  if (strategy.order) return strategy.order(pollerName, candidates);
+  const waiting = [...candidates];
+  const lineUp = [];
+  while (waiting.length) {
+    const next = strategy.selectNext(pollerName, waiting);
+    if (!next) break;
+    waiting.splice(waiting.indexOf(next), 1);
+    lineUp.push(next);
+  }
+  return lineUp;
+}
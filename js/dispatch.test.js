@@ -0,0 +1,82 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { fifo, priority, roundRobin, dispatchOrder } from './dispatch.js';
+
+/**
+ * @param {string} id
+ * @param {string} agent
+ * @param {number} [prio]
+ * @returns {import('./job.js').Job}
+ */
+const fakeJob = (id, agent, prio) => /** @type {*} */({
+  id,
+  agent,
+  requestedAt: new Date(Date.UTC(2024, 0, 1, 0, 0, Number(id))).toISOString(),
+  ...(prio && { priority: prio })
+});
+
+test('fifo picks the oldest job', () => {
+  const jobs = [fakeJob('3', 'a'), fakeJob('1', 'b'), fakeJob('2', 'a')];
+  assert.strictEqual(fifo.selectNext('page', jobs)?.id, '1');
+  assert.strictEqual(fifo.selectNext('page', []), undefined);
+});
+
+test('priority picks the highest priority, oldest among equals', () => {
+  const jobs = [fakeJob('1', 'a'), fakeJob('3', 'b', 5), fakeJob('2', 'c', 5)];
+  assert.strictEqual(priority.selectNext('page', jobs)?.id, '2');
+  assert.strictEqual(priority.selectNext('page', [fakeJob('1', 'a'), fakeJob('2', 'b')])?.id, '1');
+});
+
+test('roundRobin alternates between agents per page', () => {
+  const strategy = roundRobin();
+  const pending = [fakeJob('1', 'a'), fakeJob('2', 'a'), fakeJob('3', 'b'), fakeJob('4', 'a')];
+  const order = [];
+  while (pending.length) {
+    const next = /** @type {import('./job.js').Job} */(strategy.selectNext('page', pending));
+    order.push(next.id);
+    pending.splice(pending.indexOf(next), 1);
+  }
+  assert.deepStrictEqual(order, ['1', '3', '2', '4']);
+  // Turns are tracked separately for each page
+  assert.strictEqual(strategy.selectNext('other', [fakeJob('5', 'a'), fakeJob('6', 'b')])?.id, '5');
+});
+
+/**
+ * Hand jobs out one at a time the way polling does
+ * @param {import('./dispatch.js').DispatchStrategy} strategy
+ * @param {import('./job.js').Job[]} candidates
+ */
+const drainInTurn = (strategy, candidates) => {
+  const pending = [...candidates];
+  const order = [];
+  while (pending.length) {
+    const next = /** @type {import('./job.js').Job} */(strategy.selectNext('page', pending));
+    order.push(next.id);
+    pending.splice(pending.indexOf(next), 1);
+  }
+  return order;
+};
+
+test('dispatchOrder of a priority queue matches the order jobs are handed out', () => {
+  const jobs = [fakeJob('1', 'a'), fakeJob('2', 'b', 3), fakeJob('3', 'a', 9), fakeJob('4', 'c', 3), fakeJob('5', 'b')];
+  const reported = dispatchOrder(priority, 'page', jobs).map(j => j.id);
+  assert.deepStrictEqual(reported, ['3', '2', '4', '1', '5']);
+  assert.deepStrictEqual(reported, drainInTurn(priority, jobs));
+});
+
+test('dispatchOrder previews round-robin turns without taking one', () => {
+  const strategy = roundRobin();
+  strategy.selectNext('page', [fakeJob('0', 'a')]);
+  const jobs = [fakeJob('1', 'a'), fakeJob('2', 'a'), fakeJob('3', 'b')];
+  const reported = dispatchOrder(strategy, 'page', jobs).map(j => j.id);
+  assert.deepStrictEqual(reported, ['3', '1', '2']);
+  assert.deepStrictEqual(dispatchOrder(strategy, 'page', jobs).map(j => j.id), reported);
+  assert.deepStrictEqual(drainInTurn(strategy, jobs), reported);
+});
+
+test('dispatchOrder asks a strategy without order for one pick after another', () => {
+  /** @type {import('./dispatch.js').DispatchStrategy} */
+  const newestFirst = { selectNext: (_poller, candidates) => [...candidates].sort((a, b) => b.id.localeCompare(a.id))[0] };
+  assert.deepStrictEqual(dispatchOrder(newestFirst, 'page', [fakeJob('1', 'a'), fakeJob('3', 'a'), fakeJob('2', 'a')]).map(j => j.id), ['3', '2', '1']);
+});
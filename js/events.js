@@ -0,0 +1,148 @@
+// @ts-check
+import { createReadStream, existsSync } from 'node:fs';
+import { appendFile } from 'node:fs/promises';
+import { createInterface } from 'node:readline';
+
+/**
+ * @typedef {{
+ *   at: string,
+ *   type: string,
+ *   page: string,
+ *   [key: string]: any
+ * }} ServerEvent
+ */
+
+/**
+ * @typedef {{
+ *   file: string | null,
+ *   compact: Record<string, number>
+ * }} EventLogOptions
+ */
+
+/** @type {EventLogOptions} */
+const options = {
+  file: null,
+  // Event type → window in ms during which a repeat of the page's last event of that type is dropped
+  compact: {}
+};
+
+/** Last written event per type and page, for compaction: its payload, when, and repeats dropped since */
+/** @type {Map<string, { payload: string, at: number, suppressed: number }>} */
+const lastWritten = new Map();
+
+/**
+ * Appends on their way to the log, chained so events land in the order they were recorded
+ * without holding up the request that recorded them
+ * @type {Promise<void>}
+ */
+let appending = Promise.resolve();
+
+/**
+ * Called with each event as it is recorded, log file or not
+ * @type {Set<(event: ServerEvent) => void>}
+ */
+const listeners = new Set();
+
+/** @param {Partial<EventLogOptions>} config */
+export function configure(config) {
+  Object.assign(options, config);
+  lastWritten.clear();
+}
+
+/**
+ * Whether this event repeats the page's last one of its type inside the type's compaction window.
+ * A repeat that falls outside the window is written, carrying how many were dropped before it.
+ * @param {string} type
+ * @param {string} page
+ * @param {Record<string, any>} data
+ * @returns {{ drop: true } | { drop: false, suppressed: number }}
+ */
+function compaction(type, page, data) {
+  const windowMs = options.compact[type];
+  if (!(windowMs > 0)) return { drop: false, suppressed: 0 };
+  const key = type + '\0' + page;
+  const payload = JSON.stringify(data);
+  const now = Date.now();
+  const last = lastWritten.get(key);
+  if (last && last.payload === payload && now - last.at < windowMs) {
+    last.suppressed++;
+    return { drop: true };
+  }
+  const suppressed = last && last.payload === payload ? last.suppressed : 0;
+  lastWritten.set(key, { payload, at: now, suppressed: 0 });
+  return { drop: false, suppressed };
+}
+
+/**
+ * Hand an event to those listening and append it to the NDJSON log, if one is configured
+ * @param {string} type
+ * @param {string} page
+ * @param {Record<string, any>} [data]
+ */
+export function record(type, page, data = {}) {
+  if (!options.file && !listeners.size) return;
+  const compacted = compaction(type, page, data);
+  if (compacted.drop) return;
+  /** @type {ServerEvent} */
+  const event = { at: new Date().toISOString(), type, page, ...data, ...(compacted.suppressed && { suppressed: compacted.suppressed }) };
+  for (const listener of listeners) {
+    try { listener(event); }
+    catch (err) { console.warn('[events] listener failed:', err); }
+  }
+  const file = options.file;
+  if (!file) return;
+  appending = appending
+    .then(() => appendFile(file, JSON.stringify(event) + '\n', 'utf8'))
+    .catch(err => console.warn('[events] could not append to log:', err));
+}
+
+/**
+ * Hear every event from now on
+ * @param {(event: ServerEvent) => void} listener
+ * @returns {() => void} Stops listening
+ */
+export function subscribe(listener) {
+  listeners.add(listener);
+  return () => { listeners.delete(listener); };
+}
+
+/**
+ * @typedef {{
+ *   page?: string | null,
+ *   type?: string | null,
+ *   since?: string | null
+ * }} EventFilter
+ */
+
+/**
+ * Read the event log line by line, keeping events that match every given filter.
+ * `since` is an ISO timestamp; events at or after it match.
+ * @param {EventFilter} filter
+ * @param {string | null} [file]
+ * @returns {Promise<ServerEvent[]>}
+ */
+export async function history({ page, type, since } = {}, file = options.file) {
+  // Events recorded before the read are in the file by the time it starts
+  await appending;
+  if (!file || !existsSync(file)) return [];
+  const sinceMs = since ? Date.parse(since) : NaN;
+
+  /** @type {ServerEvent[]} */
+  const matches = [];
+  const lines = createInterface({ input: createReadStream(file, 'utf8'), crlfDelay: Infinity });
+  for await (const line of lines) {
+    if (!line.trim()) continue;
+    /** @type {ServerEvent} */
+    let event;
+    try {
+      event = JSON.parse(line);
+    } catch {
+      continue; // A torn write from a crash: skip the line
+    }
+    if (page && event.page !== page) continue;
+    if (type && event.type !== type) continue;
+    if (!isNaN(sinceMs) && Date.parse(event.at) < sinceMs) continue;
+    matches.push(event);
+  }
+  return matches;
+}
@@ -0,0 +1,113 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { mkdtempSync, rmSync, writeFileSync, existsSync } from 'node:fs';
+import { tmpdir } from 'node:os';
+import { join } from 'node:path';
+import * as events from './events.js';
+
+/** @param {(file: string) => Promise<void>} body */
+async function withLog(body) {
+  const dir = mkdtempSync(join(tmpdir(), 'daebug-events-'));
+  const file = join(dir, '.events.ndjson');
+  events.configure({ file });
+  try {
+    await body(file);
+  } finally {
+    events.configure({ file: null, compact: {} });
+    rmSync(dir, { recursive: true, force: true });
+  }
+}
+
+test('history filters by page', () => withLog(async () => {
+  events.record('job-created', 'alpha', { job: '1' });
+  events.record('job-created', 'beta', { job: '2' });
+  events.record('job-finished', 'alpha', { job: '1' });
+
+  const alpha = await events.history({ page: 'alpha' });
+  assert.deepStrictEqual(alpha.map(e => [e.type, e.job]), [['job-created', '1'], ['job-finished', '1']]);
+}));
+
+test('recording leaves the append to happen in the background, in order', () => withLog(async file => {
+  for (let i = 0; i < 20; i++) events.record('heartbeat', 'alpha', { n: i });
+  assert.strictEqual(existsSync(file), false);
+
+  const beats = await events.history({ page: 'alpha' });
+  assert.deepStrictEqual(beats.map(e => e.n), Array.from({ length: 20 }, (_, i) => i));
+}));
+
+test('history filters by event type', () => withLog(async () => {
+  events.record('job-created', 'alpha', { job: '1' });
+  events.record('job-finished', 'alpha', { job: '1' });
+  events.record('job-finished', 'beta', { job: '2' });
+
+  const finished = await events.history({ type: 'job-finished' });
+  assert.deepStrictEqual(finished.map(e => e.page), ['alpha', 'beta']);
+}));
+
+test('history filters by time and skips torn lines', () => withLog(async file => {
+  writeFileSync(file, [
+    JSON.stringify({ at: '2024-01-01T00:00:00.000Z', type: 'job-created', page: 'p' }),
+    '{"at": "2024-01-01T00:00:01',
+    JSON.stringify({ at: '2024-01-02T00:00:00.000Z', type: 'job-finished', page: 'p' }),
+    ''
+  ].join('\n'));
+
+  const recent = await events.history({ since: '2024-01-01T12:00:00.000Z' });
+  assert.deepStrictEqual(recent.map(e => e.type), ['job-finished']);
+}));
+
+test('history of a missing log is empty', async () => {
+  assert.deepStrictEqual(await events.history({}, join(tmpdir(), 'no-such-dir', '.events.ndjson')), []);
+  assert.deepStrictEqual(await events.history({}, null), []);
+});
+
+test('repeated identical heartbeats collapse within the compaction window', () => withLog(async () => {
+  events.configure({ compact: { heartbeat: 60_000 } });
+  for (let i = 0; i < 5; i++) events.record('heartbeat', 'alpha', { state: 'idle' });
+  events.record('heartbeat', 'beta', { state: 'idle' });
+
+  const beats = await events.history({ type: 'heartbeat' });
+  assert.deepStrictEqual(beats.map(e => e.page), ['alpha', 'beta']);
+}));
+
+test('compaction still records every genuine change', () => withLog(async () => {
+  events.configure({ compact: { heartbeat: 60_000 } });
+  events.record('heartbeat', 'alpha', { state: 'idle' });
+  events.record('heartbeat', 'alpha', { state: 'executing' });
+  events.record('heartbeat', 'alpha', { state: 'executing' });
+  events.record('heartbeat', 'alpha', { state: 'idle' });
+  events.record('job-created', 'alpha', { job: '1' });
+  events.record('job-created', 'alpha', { job: '1' });
+
+  const all = await events.history({ page: 'alpha' });
+  assert.deepStrictEqual(all.map(e => [e.type, e.state || e.job]), [
+    ['heartbeat', 'idle'], ['heartbeat', 'executing'], ['heartbeat', 'idle'],
+    ['job-created', '1'], ['job-created', '1']
+  ]);
+}));
+
+test('a repeat after the window is recorded with the count it stands for', () => withLog(async () => {
+  events.configure({ compact: { heartbeat: 20 } });
+  events.record('heartbeat', 'alpha', { state: 'idle' });
+  events.record('heartbeat', 'alpha', { state: 'idle' });
+  events.record('heartbeat', 'alpha', { state: 'idle' });
+  await new Promise(resolve => setTimeout(resolve, 40));
+  events.record('heartbeat', 'alpha', { state: 'idle' });
+
+  const beats = await events.history({ type: 'heartbeat' });
+  assert.deepStrictEqual(beats.map(e => e.suppressed), [undefined, 2]);
+}));
+
+test('subscribers hear events as they are recorded, with or without a log file', () => {
+  /** @type {import('./events.js').ServerEvent[]} */
+  const heard = [];
+  const stop = events.subscribe(event => heard.push(event));
+  try {
+    events.record('job-created', 'alpha', { job: '1' });
+  } finally {
+    stop();
+  }
+  events.record('job-finished', 'alpha', { job: '1' });
+  assert.deepStrictEqual(heard.map(e => [e.type, e.page, e.job]), [['job-created', 'alpha', '1']]);
+});
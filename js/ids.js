@@ -0,0 +1,70 @@
+// @ts-check
+import { randomUUID } from 'node:crypto';
+
+/**
+ * Names new jobs. `seq` counts jobs created since the first start, carried across snapshots,
+ * so generators built on it stay unique after a restore.
+ * @typedef {{
+ *   next: (seq: number) => string
+ * }} IdGenerator
+ */
+
+/**
+ * The job's sequence number itself: `1`, `2`, ...
+ * @type {IdGenerator}
+ */
+export const counterIds = {
+  next: seq => String(seq)
+};
+
+/**
+ * Time-ordered ids for single-node setups: base-36 milliseconds then sequence, so ids sort
+ * by creation and stay distinct from those of earlier server runs
+ * @param {() => number} [now]
+ * @returns {IdGenerator}
+ */
+export function timestampIds(now = Date.now) {
+  return {
+    next: seq => now().toString(36).padStart(9, '0') + '-' + seq.toString(36).padStart(4, '0')
+  };
+}
+
+/**
+ * Random UUIDs, for several servers writing into one place
+ * @type {IdGenerator}
+ */
+export const uuidIds = {
+  next: () => randomUUID()
+};
+
+/**
+ * Its own count from `start`, unaffected by jobs created elsewhere, for predictable ids in tests
+ * @param {number} [start]
+ * @param {string} [prefix]
+ * @returns {IdGenerator}
+ */
+export function sequentialIds(start = 1, prefix = '') {
+  let next = start;
+  return {
+    next: () => prefix + next++
+  };
+}
+
+/**
+ * Numbers from a durable counter, so ids keep climbing across restarts without a snapshot
+ * @param {import('./sequence.js').SequenceStore} store
+ * @param {string} [key]
+ * @returns {IdGenerator}
+ */
+export function storedIds(store, key = 'job') {
+  return {
+    next: () => String(store.next(key))
+  };
+}
+
+/**
+ * Creation order of ids from any generator: numerically when both are numbers, else as text
+ * @param {string} a
+ * @param {string} b
+ */
+export const compareIds = (a, b) => Number(a) - Number(b) || (a < b ? -1 : a > b ? 1 : 0);
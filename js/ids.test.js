@@ -0,0 +1,33 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { counterIds, timestampIds, uuidIds, sequentialIds, compareIds } from './ids.js';
+
+test('counterIds uses the job sequence number', () => {
+  assert.strictEqual(counterIds.next(7), '7');
+});
+
+test('sequentialIds counts on its own from the start given', () => {
+  const ids = sequentialIds(100, 'job-');
+  assert.deepStrictEqual([ids.next(1), ids.next(50), ids.next(3)], ['job-100', 'job-101', 'job-102']);
+});
+
+test('timestampIds sort in creation order', () => {
+  let clock = 1_700_000_000_000;
+  const ids = timestampIds(() => clock);
+  const made = [ids.next(9), ids.next(10)];
+  clock += 1;
+  made.push(ids.next(11));
+  assert.deepStrictEqual([...made].sort(), made);
+  assert.strictEqual(new Set(made).size, 3);
+});
+
+test('uuidIds are distinct', () => {
+  assert.notStrictEqual(uuidIds.next(1), uuidIds.next(1));
+  assert.match(uuidIds.next(1), /^[0-9a-f-]{36}$/);
+});
+
+test('compareIds orders numbers numerically and other ids as text', () => {
+  assert.deepStrictEqual(['10', '9', '100'].sort(compareIds), ['9', '10', '100']);
+  assert.deepStrictEqual(['b', 'a', 'c'].sort(compareIds), ['a', 'b', 'c']);
+});
@@ -1,6 +1,12 @@
 // @ts-check
 import * as writer from './writer.js';
-import fs from 'node:fs';
+import * as events from './events.js';
+import * as registry from './registry.js';
+import { fifo, dispatchOrder } from './dispatch.js';
+import { counterIds, compareIds } from './ids.js';
+import * as pageConfig from './page-config.js';
+import { transpile, TranspileError } from './transpile.js';
+import { createHash } from 'node:crypto';
 
 /**
  * @typedef {{
@@ -9,9 +15,25 @@ import fs from 'node:fs';
  *   agent: string,
  *   requestHasFooter?: boolean,
  *   code: string,
+ *   compiled?: string,
+ *   language: 'js' | 'ts',
+ *   context: 'page' | 'worker',
+ *   prelude?: string,
+ *   thread?: string,
+ *   session?: string,
+ *   priority?: number,
+ *   expect?: unknown,
+ *   when?: string,
+ *   verdict?: 'pass' | 'fail',
+ *   instance?: string,
+ *   notBefore?: number,
+ *   timeoutMs?: number,
+ *   deadline?: number,
+ *   timedOut?: boolean,
  *   requestedAt: string,
  *   startedAt: string | null,
  *   finishedAt: string | null,
+ *   cancelRequestedAt?: string,
  *   timeout: ReturnType<typeof setTimeout> | null,
  *   _placeholderInterval?: ReturnType<typeof setInterval>
  * }} Job
@@ -24,52 +46,585 @@ import fs from 'node:fs';
  * }} JobPromise
  */
 
-/** @type {Map<string, Job>} */
-const jobs = new Map();
+/**
+ * Queued and running jobs by the realm that runs them, in arrival order. A realm runs one job
+ * at a time and the dispatch strategy picks which of its waiting jobs goes next.
+ * @type {Map<string, Job[]>}
+ */
+const queues = new Map();
+
+/** Every queued or running job, realm by realm */
+const allJobs = () => Array.from(queues.values()).flat();
+
+/**
+ * A page's queued and running jobs, whichever realm runs them
+ * @param {string} pageName
+ */
+const jobsOf = pageName => allJobs().filter(j => j.page.name === pageName);
 
 /** @type {Map<string, JobPromise>} */
 const waitingPromises = new Map();
 
+/** @type {Map<string, { promise: Promise<boolean>, resolve: (stop: boolean) => void, timer: ReturnType<typeof setTimeout> }>} */
+const cancelWaiters = new Map();
+
+/**
+ * Callers waiting for a job to finish, by job id
+ * @type {Map<string, Array<() => void>>}
+ */
+const finishWaiters = new Map();
+
+/**
+ * Callers waiting for a page to pick a job up, by job id
+ * @type {Map<string, Array<() => void>>}
+ */
+const startWaiters = new Map();
+
 let nextId = 1;
-const TIMEOUT_MS = 60_000;
 
-/** @param {import('./registry.js').Page} page @param {string} agent @param {string} code @param {boolean} requestHasFooter */
-export function create(page, agent, code, requestHasFooter = true) {
+/**
+ * Rewrites a job's code on its way to the page, e.g. to wrap it or add instrumentation.
+ * The job keeps the code it was submitted with.
+ * @typedef {(code: string, job: Job) => string} CodeTransform
+ */
+
+/**
+ * @typedef {{
+ *   strategy: import('./dispatch.js').DispatchStrategy,
+ *   denyPatterns: RegExp[],
+ *   allowPatterns: RegExp[] | null,
+ *   timeoutMs: number,
+ *   transpiler: import('./transpile.js').Transpiler | null,
+ *   transformCode: CodeTransform | null,
+ *   resultRetentionMs: number,
+ *   resultsPerPage: number,
+ *   resultRetentionBytes: number,
+ *   idempotencyWindowMs: number,
+ *   idempotencyMaxKeys: number,
+ *   idGenerator: import('./ids.js').IdGenerator,
+ *   sequences: import('./sequence.js').SequenceStore | null,
+ *   agentJobsPerMinute: number,
+ *   agentMaxConcurrent: number,
+ *   now: () => number
+ * }} JobManagerOptions
+ */
+
+/** @type {JobManagerOptions} */
+const options = {
+  strategy: fifo,
+  denyPatterns: [],
+  allowPatterns: null,
+  timeoutMs: 60_000,
+  transpiler: null,
+  transformCode: null,
+  resultRetentionMs: 10 * 60_000,
+  resultsPerPage: 0,
+  // Most bytes of results kept in memory at once, oldest forgotten first; 0 for no cap
+  resultRetentionBytes: 0,
+  idempotencyWindowMs: 10 * 60_000,
+  // Most idempotency keys remembered at once, oldest forgotten first
+  idempotencyMaxKeys: 10_000,
+  idGenerator: counterIds,
+  sequences: null,
+  agentJobsPerMinute: 0,
+  agentMaxConcurrent: 0,
+  now: () => Date.now()
+};
+
+/** Current time in epoch milliseconds, from the configured clock */
+const now = () => options.now();
+
+/** Id for a new job, from the configured generator */
+const nextJobId = () => options.idGenerator.next(nextId++);
+
+/**
+ * Whether a job may be handed to its page yet
+ * @param {Job} job
+ */
+const isDue = job => !job.notBefore || job.notBefore <= now();
+
+/**
+ * How long a page's jobs may run: its own config, else the server default
+ * @param {import('./registry.js').Page} page
+ */
+const timeoutFor = page => pageConfig.forPage(page).timeoutMs ?? options.timeoutMs;
+
+/**
+ * How long a job may run: its own limit when it was submitted with one, else its page's
+ * @param {Job} job
+ */
+const limitFor = job => job.timeoutMs ?? timeoutFor(job.page);
+
+/** @param {Partial<JobManagerOptions>} config */
+export function configure(config) {
+  Object.assign(options, config);
+}
+
+/** Raised when a job cannot be dispatched to its target page */
+export class JobRejectedError extends Error {
+  /** @param {string} message */
+  constructor(message) {
+    super(message);
+    this.name = 'JobRejectedError';
+  }
+}
+
+/** Raised when an agent has used up its job quota; `retryAfterMs` says when it may try again */
+export class QuotaExceededError extends JobRejectedError {
+  /** @param {string} message @param {number} retryAfterMs */
+  constructor(message, retryAfterMs) {
+    super(message);
+    this.name = 'QuotaExceededError';
+    this.retryAfterMs = retryAfterMs;
+  }
+}
+
+/** Raised when an idempotency key comes back with a different page or different code than it first came with */
+export class IdempotencyKeyReusedError extends JobRejectedError {
+  /** @param {string} message */
+  constructor(message) {
+    super(message);
+    this.name = 'IdempotencyKeyReusedError';
+  }
+}
+
+/**
+ * When each agent created jobs in the past minute, oldest first
+ * @type {Map<string, number[]>}
+ */
+const agentCreations = new Map();
+
+/**
+ * Refuse a job an agent has no quota left for: too many created in the past minute,
+ * or too many still queued or running
+ * @param {string} agent
+ */
+function checkQuota(agent) {
+  if (options.agentMaxConcurrent > 0) {
+    const active = allJobs().filter(j => j.agent === agent).length;
+    if (active >= options.agentMaxConcurrent)
+      throw new QuotaExceededError(`agent ${agent} already has ${active} job(s) in progress (limit ${options.agentMaxConcurrent})`, 1000);
+  }
+  if (options.agentJobsPerMinute > 0) {
+    const recent = (agentCreations.get(agent) || []).filter(at => at > now() - 60_000);
+    agentCreations.set(agent, recent);
+    if (recent.length >= options.agentJobsPerMinute)
+      throw new QuotaExceededError(`agent ${agent} created ${recent.length} jobs in the past minute (limit ${options.agentJobsPerMinute})`, recent[0] + 60_000 - now());
+  }
+}
+
+/** @param {string} agent */
+function countCreation(agent) {
+  if (!(options.agentJobsPerMinute > 0)) return;
+  const recent = agentCreations.get(agent) || [];
+  recent.push(now());
+  agentCreations.set(agent, recent);
+}
+
+/**
+ * Coarse pre-flight guardrail, not a sandbox: code matching a deny pattern is refused,
+ * and when allow patterns are set, code must match at least one of them
+ * @param {string} code
+ * @returns {string | null} Why the code is refused, or null when it may run
+ */
+export function screenCode(code) {
+  const denied = options.denyPatterns.find(pattern => pattern.test(code));
+  if (denied) return `code matches blocked pattern ${denied}`;
+  if (options.allowPatterns && !options.allowPatterns.some(pattern => pattern.test(code)))
+    return 'code matches none of the allowed patterns';
+  return null;
+}
+
+/**
+ * JavaScript for a TypeScript job headed to a page that only runs JavaScript,
+ * or undefined when no transpiler is configured
+ * @param {import('./registry.js').Page} page
+ * @param {string} code
+ * @returns {string | undefined}
+ */
+function compileForPage(page, code) {
+  if (!options.transpiler) return undefined;
+  try {
+    return transpile(code, options.transpiler);
+  } catch (err) {
+    if (!(err instanceof TranspileError)) throw err;
+    throw new JobRejectedError(`could not transpile TypeScript for ${page.name}: ${err.message}`);
+  }
+}
+
+/**
+ * @typedef {{
+ *   language?: 'js' | 'ts',
+ *   context?: 'page' | 'worker',
+ *   prelude?: string,
+ *   thread?: string,
+ *   session?: string,
+ *   priority?: number,
+ *   expect?: unknown,
+ *   when?: string,
+ *   pin?: boolean,
+ *   notBefore?: number,
+ *   delayMs?: number,
+ *   timeoutMs?: number,
+ *   idempotencyKey?: string
+ * }} JobOptions
+ */
+
+/**
+ * Jobs created under an idempotency key, by agent and key, oldest first. A retried
+ * submission carrying the same key gets the job it made the first time rather than a copy,
+ * for `idempotencyWindowMs` after that job was created. Each remembers the page and a hash
+ * of the code it was for, so a key reused for something else is caught rather than answered.
+ * @type {Map<string, { job: Job, page: string, codeHash: string, createdAt: number }>}
+ */
+const idempotentJobs = new Map();
+
+/** @param {string} agent @param {string} key */
+const idempotencyEntry = (agent, key) => agent + '\n' + key;
+
+/** @param {string} code */
+const hashCode = code => createHash('sha1').update(code).digest('hex');
+
+/** Forget keys past the window, then the oldest beyond `idempotencyMaxKeys` */
+function pruneIdempotencyKeys() {
+  const cutoff = now() - options.idempotencyWindowMs;
+  for (const [entry, kept] of idempotentJobs) {
+    if (kept.createdAt > cutoff && idempotentJobs.size <= options.idempotencyMaxKeys) break;
+    idempotentJobs.delete(entry);
+  }
+}
+
+/**
+ * The job an agent already created under an idempotency key, if the key is still remembered
+ * @param {string} agent
+ * @param {string} key
+ * @returns {Job | undefined}
+ */
+export function forIdempotencyKey(agent, key) {
+  pruneIdempotencyKeys();
+  return idempotentJobs.get(idempotencyEntry(agent, key))?.job;
+}
+
+/**
+ * The job a retry should get back: the one made under its key, as long as the retry
+ * names the same page and the same code
+ * @param {import('./registry.js').Page} page
+ * @param {string} agent
+ * @param {string} code
+ * @param {string} key
+ * @returns {Job | undefined}
+ * @throws {IdempotencyKeyReusedError} When the key was first used for another page or other code
+ */
+function replayFor(page, agent, code, key) {
+  pruneIdempotencyKeys();
+  const kept = idempotentJobs.get(idempotencyEntry(agent, key));
+  if (!kept) return undefined;
+  if (kept.page !== page.name || kept.codeHash !== hashCode(code))
+    throw new IdempotencyKeyReusedError(`idempotency key ${key} was already used for job ${kept.job.id} with ${kept.page !== page.name ? 'another page' : 'other code'}`);
+  return kept.job;
+}
+
+/**
+ * @param {import('./registry.js').Page} page
+ * @param {string} agent
+ * @param {string} code
+ * @param {boolean} requestHasFooter
+ * @param {JobOptions} [jobOptions]
+ */
+export function create(page, agent, code, requestHasFooter = true, jobOptions = {}) {
+  const earlier = jobOptions.idempotencyKey ? replayFor(page, agent, code, jobOptions.idempotencyKey) : undefined;
+  if (earlier) return earlier;
+  const language = jobOptions.language || 'js';
+  const capabilities = page.capabilities || ['js'];
+  const compiled = language === 'ts' && !capabilities.includes('ts') && capabilities.includes('js')
+    ? compileForPage(page, code)
+    : undefined;
+  if (compiled === undefined && !capabilities.includes(language))
+    throw new JobRejectedError(`${page.name} cannot run ${language} code (supports: ${capabilities.join(', ')})`);
+  if (jobOptions.context === 'worker' && !registry.get(registry.workerNameFor(page.name)))
+    throw new JobRejectedError(`${page.name} has no web worker to run the job`);
+  const refusal = screenCode(code);
+  if (refusal) throw new JobRejectedError(`safe mode: ${refusal}`);
+  checkQuota(agent);
+  countCreation(agent);
+
+  const prelude = jobOptions.prelude ?? page.prelude;
+  const named = jobOptions.session ? sessions.get(jobOptions.session) : undefined;
+  const session = named && !named.closedAt ? named : openSessionFor(page.name, agent);
+  const notBefore = jobOptions.notBefore ?? (jobOptions.delayMs ? now() + jobOptions.delayMs : undefined);
+  /** @type {Job} */
   const job = {
-    id: String(nextId++),
+    id: nextJobId(),
     page,
     agent,
     code,
+    ...(compiled !== undefined && { compiled }),
+    language,
+    context: jobOptions.context || 'page',
+    ...(prelude && { prelude }),
+    ...(jobOptions.thread && { thread: jobOptions.thread }),
+    ...(session && { session: session.id }),
+    ...(jobOptions.priority && { priority: jobOptions.priority }),
+    ...(jobOptions.expect !== undefined && { expect: jobOptions.expect }),
+    ...(jobOptions.when?.trim() && { when: jobOptions.when }),
+    ...(jobOptions.pin && page.instance && { instance: page.instance }),
+    ...(notBefore && { notBefore }),
+    ...(jobOptions.timeoutMs && { timeoutMs: jobOptions.timeoutMs }),
     requestHasFooter,
-  requestedAt: new Date().toISOString(),
+  requestedAt: new Date(now()).toISOString(),
   startedAt: null,
   finishedAt: null,
     timeout: null
   };
-  jobs.set(page.name, job);
-  page.state = 'executing';
-  // @ts-ignore
-  job.timeout = setTimeout(() => onTimeout(job), TIMEOUT_MS);
+  session?.jobs.push({ id: job.id, page: page.name, agent, requestedAt: job.requestedAt, code, language });
+  if (jobOptions.idempotencyKey) {
+    idempotentJobs.set(idempotencyEntry(agent, jobOptions.idempotencyKey), { job, page: page.name, codeHash: hashCode(code), createdAt: now() });
+    pruneIdempotencyKeys();
+  }
+  // A full log has no room for the reply, so the job fails without running
+  if (writer.isLogFull(page.file)) {
+    job.finishedAt = job.requestedAt;
+    keepOutcome(job, { ok: false, error: 'log full' });
+    events.record('job-finished', page.name, { job: job.id, error: 'log full' });
+    return job;
+  }
+
+  enqueue(job);
+  // A page that has yet to connect stays disconnected; its first poll picks the job up
+  if (page.state !== 'disconnected') registry.setState(page, 'executing');
+  events.record('job-created', page.name, { job: job.id, agent, language });
+  armTimeout(job);
+  
+  announce(job);
+  return job;
+}
+
+/**
+ * Line a job up behind whatever its realm already has
+ * @param {Job} job
+ */
+function enqueue(job) {
+  const poller = pollerFor(job);
+  queues.set(poller, [...(queues.get(poller) || []), job]);
+}
+
+/**
+ * Take a job out of its realm's queue
+ * @param {Job} job
+ * @returns {string | undefined} The realm it was queued for, or undefined when it was not queued
+ */
+function dequeue(job) {
+  for (const [poller, queued] of queues) {
+    if (!queued.includes(job)) continue;
+    const rest = queued.filter(j => j !== job);
+    if (rest.length) queues.set(poller, rest);
+    else queues.delete(poller);
+    return poller;
+  }
+  return undefined;
+}
+
+/**
+ * Start a job's time limit; a delayed job's limit starts once it becomes due
+ * @param {Job} job
+ */
+function armTimeout(job) {
+  const wait = limitFor(job) + Math.max(0, (job.notBefore || 0) - now());
+  job.deadline = now() + wait;
+  job.timeout = setTimeout(() => onTimeout(job), wait);
   // Don't keep the event loop alive for long-running timers in tests
   try { if (job.timeout && typeof job.timeout.unref === 'function') job.timeout.unref(); } catch {}
-  
-  // Resolve any waiting promise for this job
-  const waiting = waitingPromises.get(page.name);
+}
+
+/**
+ * The code a page is sent for a job: compiled if it had to be, then through `transformCode`
+ * @param {Job} job
+ * @returns {string}
+ */
+export function dispatchedCode(job) {
+  const code = job.compiled ?? job.code;
+  return options.transformCode ? options.transformCode(code, job) : code;
+}
+
+/**
+ * Hand a new job to a poll waiting for it, or, for a delayed job, once it becomes due
+ * @param {Job} job
+ */
+function announce(job) {
+  const poller = pollerFor(job);
+  if (!isDue(job)) {
+    const wake = setTimeout(() => announce(job), /** @type {number} */(job.notBefore) - now());
+    try { wake.unref(); } catch {}
+    return;
+  }
+  if (job.finishedAt || !allJobs().includes(job)) return;
+  const waiting = waitingPromises.get(poller);
   if (waiting) {
-    waiting.resolve(job);
-    waitingPromises.delete(page.name);
+    waiting.resolve(forPoller(poller) ?? job);
+    waitingPromises.delete(poller);
   }
-  
-  return job;
 }
 
-/** @param {string} pageName */
-export const get = pageName => jobs.get(pageName);
+/**
+ * A page's running job, else the oldest one it has queued
+ * @param {string} pageName
+ * @returns {Job | undefined}
+ */
+export function get(pageName) {
+  const own = jobsOf(pageName);
+  return own.find(j => j.startedAt) || own[0];
+}
+
+/**
+ * The queued or running job with this id
+ * @param {string} id
+ * @returns {Job | undefined}
+ */
+export const byId = id => allJobs().find(j => j.id === id);
+
+/**
+ * @typedef {{
+ *   names?: string[],
+ *   capability?: string,
+ *   state?: 'idle' | 'executing' | 'disconnected',
+ *   limit?: number
+ * }} BroadcastOptions
+ */
+
+/**
+ * Create the same job on every matching registered page. Pages that already have a job,
+ * or can't run the language, are skipped; `limit` caps how many pages are started at once.
+ * @param {string} agent
+ * @param {string} code
+ * @param {BroadcastOptions & JobOptions} [options]
+ * @returns {Job[]}
+ */
+export function broadcast(agent, code, { names, capability, state = 'idle', limit = Infinity, ...jobOptions } = {}) {
+  /** @type {Job[]} */
+  const created = [];
+  for (const page of registry.all()) {
+    if (created.length >= limit) break;
+    if (names && !names.includes(page.name)) continue;
+    if (page.state !== state || get(page.name)) continue;
+    if (capability && !page.capabilities.includes(capability)) continue;
+    try {
+      created.push(create(page, agent, code, true, jobOptions));
+    } catch (err) {
+      if (!(err instanceof JobRejectedError)) throw err;
+    }
+  }
+  return created;
+}
 
-/** Wait for a job to be created for a page name with timeout */
+/**
+ * Name of the realm that should run a job: the page itself, or its web worker
+ * @param {Job} job
+ */
+export const pollerFor = job => job.context === 'worker' ? registry.workerNameFor(job.page.name) : job.page.name;
+
+/**
+ * The job a polling realm should run, if any. Worker-context jobs queue for the page's worker
+ * while their replies still go to the page's log. A job already running keeps the realm;
+ * otherwise the dispatch strategy picks among the waiting ones that are due.
+ * @param {string} pollerName
+ * @returns {Job | undefined}
+ */
+export function forPoller(pollerName) {
+  const candidates = (queues.get(pollerName) || []).filter(j => j.startedAt || isDue(j));
+  if (!candidates.length) return undefined;
+  return candidates.find(j => j.startedAt)
+    || restoredLineUp(pollerName, candidates)?.[0]
+    || options.strategy.selectNext(pollerName, candidates);
+}
+
+/**
+ * Ids of the jobs each realm's queue was restored with, by realm name. `restore` queues them
+ * in the dispatch order the snapshot recorded, but a strategy may keep state of its own, such
+ * as whose turn it is, that no snapshot carries; so restored jobs go out in queue order
+ * instead, until a job from outside the snapshot joins them.
+ * @type {Map<string, Set<string>>}
+ */
+const restoredOrder = new Map();
+
+/**
+ * The waiting jobs in their restored queue order, or undefined once the realm has none
+ * or a newer job waits alongside them
+ * @param {string} pollerName
+ * @param {Job[]} waiting Some of the realm's queue, in queue order
+ * @returns {Job[] | undefined}
+ */
+function restoredLineUp(pollerName, waiting) {
+  const saved = restoredOrder.get(pollerName);
+  if (!saved) return undefined;
+  if (waiting.length && waiting.every(j => saved.has(j.id))) return waiting;
+  restoredOrder.delete(pollerName);
+  return undefined;
+}
+
+/**
+ * @typedef {{ depth: number, executing: string | null }} QueueSummary
+ */
+
+/**
+ * What a polling realm has lined up: how many jobs wait for it, and the id of the one it runs
+ * @param {string} pollerName
+ * @returns {QueueSummary}
+ */
+export function queueSummary(pollerName) {
+  const queued = queues.get(pollerName) || [];
+  const running = queued.find(j => j.startedAt);
+  return { depth: queued.length - (running ? 1 : 0), executing: running?.id ?? null };
+}
+
+/**
+ * @typedef {{
+ *   id: string,
+ *   position: number,
+ *   agent: string,
+ *   priority: number,
+ *   requestedAt: string,
+ *   due: boolean,
+ *   notBefore?: number
+ * }} QueuedJob
+ */
+
+/**
+ * @typedef {{ poller: string, running: string | null, pending: QueuedJob[] }} RealmQueue
+ */
+
+/**
+ * What every polling realm has lined up, in the order the dispatch strategy would hand it out:
+ * jobs that are due first, then delayed ones by when they become due
+ * @param {string[]} [pollers] Realms to report even when nothing waits for them
+ * @returns {RealmQueue[]} By realm name
+ */
+export function queue(pollers = []) {
+  /** @type {Map<string, Job[]>} */
+  const byPoller = new Map([...pollers.map(name => /** @type {[string, Job[]]} */([name, []])), ...queues]);
+  return Array.from(byPoller, ([poller, realmJobs]) => {
+    const waiting = realmJobs.filter(j => !j.startedAt);
+    const due = restoredLineUp(poller, waiting.filter(isDue)) || dispatchOrder(options.strategy, poller, waiting.filter(isDue));
+    const later = waiting.filter(j => !isDue(j)).sort((a, b) => (a.notBefore || 0) - (b.notBefore || 0));
+    return {
+      poller,
+      running: realmJobs.find(j => j.startedAt)?.id ?? null,
+      pending: [...due, ...later].map((j, i) => ({
+        id: j.id,
+        position: i + 1,
+        agent: j.agent,
+        priority: j.priority || 0,
+        requestedAt: j.requestedAt,
+        due: isDue(j),
+        ...(j.notBefore && { notBefore: j.notBefore })
+      }))
+    };
+  }).sort((a, b) => a.poller < b.poller ? -1 : a.poller > b.poller ? 1 : 0);
+}
+
+/** Wait for a job to be created for a polling realm with timeout */
 export function waitForJob(pageName, timeoutMs = 25000) {
   // Check if job already exists
-  const existingJob = jobs.get(pageName);
+  const existingJob = forPoller(pageName);
   if (existingJob) {
     return Promise.resolve(existingJob);
   }
@@ -103,41 +658,637 @@ export function waitForJob(pageName, timeoutMs = 25000) {
   return promise;
 }
 
+/**
+ * Cancel a job of a page. A job the page hasn't picked up yet is answered right away;
+ * a dispatched job is flagged so the page's cancellation watch tells it to stop.
+ * @param {string} pageName
+ * @param {string} [jobId] Which of the page's jobs; its current one, as `get` finds it, when omitted
+ * @returns {Job | null}
+ */
+export function cancel(pageName, jobId) {
+  const job = jobId ? jobsOf(pageName).find(j => j.id === jobId) : get(pageName);
+  if (!job) return null;
+
+  if (!job.startedAt) {
+    answer(job, { ok: false, error: 'Cancelled before dispatch' }, 'cancel');
+    return job;
+  }
+
+  job.cancelRequestedAt = job.cancelRequestedAt || new Date().toISOString();
+  events.record('job-cancel-requested', pageName, { job: job.id });
+  cancelWaiters.get(job.id)?.resolve(true);
+  return job;
+}
+
+/**
+ * Drop pending work for a page: the jobs it hasn't picked up yet are answered and removed,
+ * and with `includeDispatched` a running job is cancelled as well
+ * @param {string} pageName
+ * @param {{ includeDispatched?: boolean }} [options]
+ * @returns {number} How many jobs were dropped
+ */
+export function drain(pageName, { includeDispatched = false } = {}) {
+  let dropped = 0;
+  for (const job of jobsOf(pageName)) {
+    if (job.startedAt) {
+      if (!includeDispatched) continue;
+      cancel(pageName, job.id);
+    } else {
+      answer(job, { ok: false, error: 'Drained before dispatch' }, 'drain');
+    }
+    dropped++;
+  }
+  return dropped;
+}
+
+/**
+ * Fail a page's jobs that were pinned to a page instance that has since reloaded:
+ * the new execution context is not the one they were meant for
+ * @param {import('./registry.js').Page} page
+ * @returns {Job[]} The invalidated jobs
+ */
+export function invalidatePinned(page) {
+  const stale = jobsOf(page.name).filter(job => job.instance && job.instance !== page.instance);
+  for (const job of stale)
+    answer(job, { ok: false, error: `Page reloaded: job was pinned to instance ${job.instance}` }, 'invalidatePinned');
+  return stale;
+}
+
+/**
+ * Refile a page's queue, and any poll waiting for a job, under the page's new name
+ * @param {string} oldName
+ * @param {string} newName
+ * @returns {Job[]} The jobs that moved
+ */
+export function rename(oldName, newName) {
+  const waiting = waitingPromises.get(oldName);
+  if (waiting) {
+    waitingPromises.delete(oldName);
+    waitingPromises.set(newName, waiting);
+  }
+  const queued = queues.get(oldName);
+  if (!queued) return [];
+  queues.delete(oldName);
+  queues.set(newName, queued);
+  return queued;
+}
+
+/**
+ * Drop whatever jobs a page has, running or not, answering each with `reason`.
+ * Used when the page itself is going away, so there is nobody left to honour a cancel.
+ * @param {string} pageName
+ * @param {string} reason
+ * @returns {number} How many jobs were dropped
+ */
+export function abandon(pageName, reason) {
+  const dropped = jobsOf(pageName);
+  for (const job of dropped) answer(job, { ok: false, error: reason }, 'abandon');
+  return dropped.length;
+}
+
+/**
+ * Wait until the page should stop running a job: resolves true once the job is cancelled
+ * or no longer active, false if neither happens within the timeout
+ * @param {string} jobId
+ * @param {number} [timeoutMs]
+ * @returns {Promise<boolean>}
+ */
+export function waitForCancel(jobId, timeoutMs = 25000) {
+  const active = allJobs().find(j => j.id === jobId);
+  if (!active || active.cancelRequestedAt) return Promise.resolve(true);
+
+  const existing = cancelWaiters.get(jobId);
+  if (existing) return existing.promise;
+
+  /** @type {(stop: boolean) => void} */
+  let resolveWait = () => { };
+  const promise = new Promise(resolve => { resolveWait = resolve; });
+  const timer = setTimeout(() => settle(false), timeoutMs);
+  // The page's own request holds the process open while it waits, not this timer
+  try { timer.unref(); } catch {}
+  /** @param {boolean} stop */
+  const settle = stop => {
+    clearTimeout(timer);
+    cancelWaiters.delete(jobId);
+    resolveWait(stop);
+  };
+  cancelWaiters.set(jobId, { promise, resolve: settle, timer });
+  return promise;
+}
+
 /** @param {Job} job */
 async function onTimeout(job) {
+  if (job.finishedAt) return;
+  job.timedOut = true;
+  answer(job, { ok: false, error: `job timed out after ${limitFor(job)}ms`, errors: [] }, 'onTimeout');
+}
+
+/**
+ * Time out every active job past its deadline now, without waiting for its timer
+ * @param {number} [at] Moment to judge deadlines against
+ * @returns {Job[]} The jobs this sweep timed out
+ */
+export function reapTimeouts(at = now()) {
+  const overdue = allJobs().filter(job => !job.finishedAt && job.deadline !== undefined && job.deadline <= at);
+  for (const job of overdue) onTimeout(job);
+  return overdue;
+}
+
+/**
+ * Reply on the page's behalf and finish the job with that reply
+ * @param {Job} job
+ * @param {import('./writer.js').JobResult} result
+ * @param {string} caller
+ */
+function answer(job, result, caller) {
   if (job.finishedAt) return;
   try {
-    writer.writeReply(job, { ok: false, error: `job timed out after ${TIMEOUT_MS}ms`, errors: [] });
+    writer.writeReply(job, result);
   } catch (err) {
-    console.warn('[job] onTimeout: writeReply failed', err);
-  } finally {
-    finish(job);
+    console.warn(`[job] ${caller}: writeReply failed`, err);
+    writer.writeError(job.page.file, 'reply', `could not write the reply to job ${job.id}: ${err instanceof Error ? err.message : String(err)}`);
   }
+  finish(job, result);
+}
+
+/**
+ * Settle a job with the result its page sent. Whichever of a result, a cancel before dispatch,
+ * a timeout or an eviction reaches a job first decides its outcome; the rest find it finished
+ * and change nothing. Each runs start to finish without yielding, so none can interleave.
+ * A result beats a cancel the page has not acted on yet, since the code did run to the end.
+ * @param {Job} job
+ * @param {import('./writer.js').JobResult} result
+ * @returns {boolean} Whether this result settled the job
+ */
+export function complete(job, result) {
+  if (job.finishedAt || !allJobs().includes(job)) return false;
+  if (job.cancelRequestedAt) events.record('job-cancel-superseded', job.page.name, { job: job.id });
+  judge(job, result);
+  try {
+    writer.writeReply(job, result);
+  } catch (err) {
+    if (err instanceof writer.LogFullError) {
+      console.warn(`[job] ${err.message}`);
+      result = { ok: false, error: 'log full' };
+    } else {
+      // The job still settles, so its caller and the queue behind it are not left waiting
+      const message = err instanceof Error ? err.message : String(err);
+      console.warn('[job] writeReply failed', err);
+      writer.writeError(job.page.file, 'result', `could not write the reply to job ${job.id}: ${message}`);
+      result = { ok: false, error: 'could not write the reply: ' + message };
+    }
+  }
+  finish(job, result);
+  return true;
 }
 
 /** @param {Job} job */
 export function start(job) {
   if (job.startedAt) return;
   job.startedAt = new Date().toISOString();
+  events.record('job-started', job.page.name, { job: job.id });
+  for (const wake of startWaiters.get(job.id) || []) wake();
+  startWaiters.delete(job.id);
   try { writer.writeExecuting(job); } 
   catch (err) { console.warn('[job] writeExecuting failed', err); }
   
   job._placeholderInterval = setInterval(() => {
     const startedMs = job.startedAt ? Date.parse(job.startedAt) : Date.now();
     const secs = Math.floor((Date.now() - startedMs) / 1000);
-    try {
-      const text = fs.readFileSync(job.page.file, 'utf8').replace(/executing \(\d+s\)/, `executing (${secs}s)`);
-      fs.writeFileSync(job.page.file, text, 'utf8');
-    } catch {}
+    try { writer.writeExecutingElapsed(job, secs); } catch {}
   }, 5000);
   try { if (job._placeholderInterval && typeof job._placeholderInterval.unref === 'function') job._placeholderInterval.unref(); } catch {}
 }
 
-/** @param {Job} job */
-export function finish(job) {
+/**
+ * @typedef {{
+ *   id: string,
+ *   page: string,
+ *   ok: boolean,
+ *   value?: unknown,
+ *   error?: unknown,
+ *   skipped?: boolean,
+ *   logs?: { level: string, message: string, eventAt?: string }[],
+ *   durationMs: number,
+ *   finishedAt: string
+ * }} JobOutcome
+ */
+
+/**
+ * Results of finished jobs by id, oldest first, kept for `resultRetentionMs` and, when
+ * `resultsPerPage` is set, only that many of the newest for each page. The log stays the
+ * durable record; these are a convenience for agents reading results over HTTP.
+ * @type {Map<string, JobOutcome & { keptAt: number, bytes: number }>}
+ */
+const outcomes = new Map();
+
+/** Size of every kept outcome together, as counted against `resultRetentionBytes` */
+let retainedBytes = 0;
+
+/** @param {string} id */
+function forgetOutcome(id) {
+  retainedBytes -= outcomes.get(id)?.bytes || 0;
+  outcomes.delete(id);
+}
+
+/** Forget outcomes older than the retention window */
+function pruneOutcomes() {
+  const cutoff = now() - options.resultRetentionMs;
+  for (const [id, kept] of outcomes) {
+    if (kept.keptAt > cutoff) break;
+    forgetOutcome(id);
+  }
+}
+
+/** Forget the oldest outcomes until the rest fit in `resultRetentionBytes`; the newest always stays */
+function capOutcomes() {
+  for (const id of outcomes.keys()) {
+    if (retainedBytes <= options.resultRetentionBytes || outcomes.size <= 1) break;
+    forgetOutcome(id);
+  }
+}
+
+/**
+ * @param {Job} job
+ * @param {import('./writer.js').JobResult} result
+ */
+function keepOutcome(job, result) {
+  pruneOutcomes();
+  const finishedAt = /** @type {string} */(job.finishedAt);
+  /** @type {JobOutcome} */
+  const kept = {
+    id: job.id,
+    page: job.page.name,
+    ok: result.ok,
+    ...(result.ok ? { value: result.value } : { error: result.error }),
+    ...(result.skipped && { skipped: true }),
+    ...(result.logs?.length && { logs: result.logs }),
+    durationMs: job.startedAt ? Date.parse(finishedAt) - Date.parse(job.startedAt) : 0,
+    finishedAt
+  };
+  forgetOutcome(job.id);
+  const bytes = Buffer.byteLength(JSON.stringify(kept));
+  outcomes.set(job.id, { ...kept, keptAt: now(), bytes });
+  retainedBytes += bytes;
+  if (options.resultsPerPage > 0) trimOutcomes(job.page.name);
+  if (options.resultRetentionBytes > 0) capOutcomes();
+}
+
+/**
+ * Forget a page's oldest outcomes beyond the `resultsPerPage` newest
+ * @param {string} pageName
+ */
+function trimOutcomes(pageName) {
+  const ids = [];
+  for (const [id, kept] of outcomes) if (kept.page === pageName) ids.push(id);
+  for (const id of ids.slice(0, Math.max(0, ids.length - options.resultsPerPage))) forgetOutcome(id);
+}
+
+/**
+ * Structured result of a recently finished job, so agents need not read it back from the log
+ * @param {string} id
+ * @returns {JobOutcome | undefined}
+ */
+export function outcome(id) {
+  pruneOutcomes();
+  const kept = outcomes.get(id);
+  if (!kept || kept.keptAt <= now() - options.resultRetentionMs) return undefined;
+  const { keptAt, bytes, ...result } = kept;
+  return result;
+}
+
+/**
+ * @typedef {{ id: string, page: string, agent: string, requestedAt: string, code: string, language: Job['language'] }} SessionJob
+ */
+
+/**
+ * @typedef {{
+ *   id: string,
+ *   page: string,
+ *   agent: string,
+ *   openedAt: string,
+ *   closedAt: string | null,
+ *   jobs: SessionJob[]
+ * }} Session
+ */
+
+/**
+ * Sessions by id: an agent's related exchanges with one page, open until closed.
+ * Closed sessions are forgotten along with outcomes, after `resultRetentionMs`.
+ * @type {Map<string, Session & { closedAtMs?: number }>}
+ */
+const sessions = new Map();
+
+let nextSessionId = 1;
+
+/** Forget sessions closed longer ago than the retention window */
+function pruneSessions() {
+  const cutoff = now() - options.resultRetentionMs;
+  for (const [id, session] of sessions)
+    if (session.closedAtMs !== undefined && session.closedAtMs <= cutoff) sessions.delete(id);
+}
+
+/**
+ * The open session an agent holds with a page, if any
+ * @param {string} pageName
+ * @param {string} agent
+ */
+function openSessionFor(pageName, agent) {
+  for (const session of sessions.values())
+    if (!session.closedAt && session.page === pageName && session.agent === agent) return session;
+  return undefined;
+}
+
+/**
+ * Start grouping an agent's jobs for a page under one session; jobs it submits there join it
+ * until it is closed. An agent already holding an open session with the page gets that one back.
+ * @param {string} pageName
+ * @param {string} agent
+ * @returns {Session}
+ */
+export function openSession(pageName, agent) {
+  pruneSessions();
+  const existing = openSessionFor(pageName, agent);
+  if (existing) return sessionView(existing);
+  const number = options.sequences ? options.sequences.next('session') : nextSessionId++;
+  const session = { id: 's' + number, page: pageName, agent, openedAt: new Date().toISOString(), closedAt: null, jobs: [] };
+  sessions.set(session.id, session);
+  events.record('session-opened', pageName, { session: session.id, agent });
+  return sessionView(session);
+}
+
+/**
+ * @param {string} id
+ * @returns {Session | undefined} The closed session, or undefined when it is unknown
+ */
+export function closeSession(id) {
+  const session = sessions.get(id);
+  if (!session) return undefined;
+  if (!session.closedAt) {
+    session.closedAt = new Date().toISOString();
+    session.closedAtMs = now();
+    events.record('session-closed', session.page, { session: id, jobs: session.jobs.length });
+  }
+  return sessionView(session);
+}
+
+/** @param {string} id */
+export function getSession(id) {
+  pruneSessions();
+  const session = sessions.get(id);
+  return session && sessionView(session);
+}
+
+/** @param {Session & { closedAtMs?: number }} session @returns {Session} */
+const sessionView = ({ closedAtMs, jobs, ...session }) => ({ ...session, jobs: jobs.slice() });
+
+/**
+ * A request running the other way: the page asks its agents for something, such as running
+ * its test suite, and waits for one of them to answer
+ * @typedef {{
+ *   id: string,
+ *   page: string,
+ *   message: string,
+ *   askedAt: string,
+ *   agent: string | null,
+ *   answer: string | null,
+ *   answeredAt: string | null
+ * }} PageRequest
+ */
+
+/**
+ * Page requests by id. Answered ones are forgotten after `resultRetentionMs`.
+ * @type {Map<string, PageRequest & { answeredAtMs?: number }>}
+ */
+const pageRequests = new Map();
+
+let nextPageRequestId = 1;
+
+/** Forget page requests answered longer ago than the retention window */
+function prunePageRequests() {
+  const cutoff = now() - options.resultRetentionMs;
+  for (const [id, request] of pageRequests)
+    if (request.answeredAtMs !== undefined && request.answeredAtMs <= cutoff) pageRequests.delete(id);
+}
+
+/** @param {PageRequest & { answeredAtMs?: number }} request @returns {PageRequest} */
+const pageRequestView = ({ answeredAtMs, ...request }) => ({ ...request });
+
+/**
+ * Record a page's request of its agents and note it in the page's log
+ * @param {import('./registry.js').Page} page
+ * @param {string} message
+ * @returns {PageRequest}
+ */
+export function ask(page, message) {
+  prunePageRequests();
+  const number = options.sequences ? options.sequences.next('page-request') : nextPageRequestId++;
+  /** @type {PageRequest} */
+  const request = { id: 'p' + number, page: page.name, message, askedAt: new Date().toISOString(), agent: null, answer: null, answeredAt: null };
+  pageRequests.set(request.id, request);
+  writer.writePageRequest(page.file, request);
+  events.record('page-request', page.name, { request: request.id });
+  return pageRequestView(request);
+}
+
+/**
+ * Answer a page request, noting the answer in the page's log
+ * @param {string} id
+ * @param {string} agent
+ * @param {string} answer
+ * @returns {PageRequest | undefined} undefined for an unknown request
+ * @throws {Error} When the request was already answered
+ */
+export function answerPageRequest(id, agent, answer) {
+  prunePageRequests();
+  const request = pageRequests.get(id);
+  if (!request) return undefined;
+  if (request.answeredAt) throw new Error(`page request ${id} was already answered by ${request.agent}`);
+  Object.assign(request, { agent, answer, answeredAt: new Date().toISOString(), answeredAtMs: now() });
+  const page = registry.get(request.page);
+  if (page) writer.writePageRequest(page.file, request);
+  events.record('page-request-answered', request.page, { request: id, agent });
+  return pageRequestView(request);
+}
+
+/** @param {string} id */
+export function getPageRequest(id) {
+  prunePageRequests();
+  const request = pageRequests.get(id);
+  return request && pageRequestView(request);
+}
+
+/**
+ * Page requests in the order they were made
+ * @param {{ page?: string | null, pending?: boolean }} [filter] One page's only; unanswered only
+ * @returns {PageRequest[]}
+ */
+export function pageRequestsFor({ page, pending } = {}) {
+  prunePageRequests();
+  return Array.from(pageRequests.values())
+    .filter(request => (!page || request.page === page) && (!pending || !request.answeredAt))
+    .map(pageRequestView);
+}
+
+/**
+ * @typedef {Omit<SessionJob, 'code' | 'language'> & Partial<SessionJob> & { session?: string, state: 'queued' | 'running' | 'finished' }} JobListing
+ */
+
+/**
+ * Jobs of a session in submission order, or every active job when no session is given
+ * @param {{ session?: string | null }} [filter]
+ * @returns {JobListing[] | undefined} undefined for an unknown session
+ */
+export function list({ session } = {}) {
+  const active = new Map(allJobs().map(j => [j.id, j]));
+  /** @param {string} id */
+  const stateOf = id => {
+    const j = active.get(id);
+    return j ? (j.startedAt ? 'running' : 'queued') : 'finished';
+  };
+  if (session) {
+    const found = getSession(session);
+    return found?.jobs.map(j => ({ ...j, session, state: stateOf(j.id) }));
+  }
+  return Array.from(active.values()).sort((a, b) => compareIds(a.id, b.id)).map(j => ({
+    id: j.id,
+    page: j.page.name,
+    agent: j.agent,
+    requestedAt: j.requestedAt,
+    ...(j.session && { session: j.session }),
+    state: stateOf(j.id)
+  }));
+}
+
+/**
+ * @param {Job} job
+ * @param {import('./writer.js').JobResult} [result] What the job produced, kept for `outcome()`
+ */
+export function finish(job, result) {
   if (job.timeout) clearTimeout(job.timeout);
   if (job._placeholderInterval) { clearInterval(job._placeholderInterval); delete job._placeholderInterval; }
   job.finishedAt = job.finishedAt || new Date().toISOString();
-  job.page.state = 'idle';
-  jobs.delete(job.page.name);
+  if (result) keepOutcome(job, result);
+  // Only the job itself leaves the queue; the page stays busy while it has others
+  if (dequeue(job) !== undefined && !get(job.page.name)) registry.setState(job.page, 'idle');
+  events.record('job-finished', job.page.name, { job: job.id, ...(result && { ok: result.ok }), ...(job.verdict && { verdict: job.verdict }) });
+  const watch = cancelWaiters.get(job.id);
+  if (watch) {
+    clearTimeout(watch.timer);
+    watch.resolve(true);
+  }
+  // A job finished before it ever started never wakes those waiting for its start
+  startWaiters.delete(job.id);
+  for (const wake of finishWaiters.get(job.id) || []) wake();
+  finishWaiters.delete(job.id);
+}
+
+/**
+ * Resolves once the job has finished, however that came about
+ * @param {Job} job
+ * @returns {Promise<void>}
+ */
+export function settled(job) {
+  if (job.finishedAt) return Promise.resolve();
+  return new Promise(resolve => {
+    const waiting = finishWaiters.get(job.id) || [];
+    waiting.push(resolve);
+    finishWaiters.set(job.id, waiting);
+  });
+}
+
+/**
+ * Resolves once the page has picked the job up; never, for a job finished before that
+ * @param {Job} job
+ * @returns {Promise<void>}
+ */
+export function started(job) {
+  if (job.startedAt) return Promise.resolve();
+  return new Promise(resolve => {
+    const waiting = startWaiters.get(job.id) || [];
+    waiting.push(resolve);
+    startWaiters.set(job.id, waiting);
+  });
+}
+
+/**
+ * JSON text with object keys sorted, so equal values compare equal regardless of key order
+ * @param {unknown} value
+ * @returns {string | undefined}
+ */
+const canonicalJSON = value => JSON.stringify(value, (_key, v) =>
+  v && typeof v === 'object' && !Array.isArray(v)
+    ? Object.fromEntries(Object.keys(v).sort().map(k => [k, v[k]]))
+    : v);
+
+/**
+ * Mark a job that was submitted with an expected value as passed or failed: it passes when
+ * it succeeded and its value is JSON-equal to the expectation
+ * @param {Job} job
+ * @param {{ ok: boolean, value?: unknown }} result
+ * @returns {'pass' | 'fail' | undefined}
+ */
+export function judge(job, result) {
+  if (job.expect === undefined) return undefined;
+  job.verdict = result.ok && canonicalJSON(result.value) === canonicalJSON(job.expect) ? 'pass' : 'fail';
+  return job.verdict;
+}
+
+/**
+ * @typedef {{
+ *   nextId: number,
+ *   jobs: Array<Omit<Job, 'page' | 'timeout' | 'deadline' | '_placeholderInterval'> & { page: string }>,
+ *   queues?: Record<string, string[]>
+ * }} JobsSnapshot
+ */
+
+/**
+ * Plain copy of the active jobs, with pages referenced by name, and the ids of each realm's
+ * waiting jobs in the order they would be dispatched
+ * @returns {JobsSnapshot}
+ */
+export function snapshot() {
+  return {
+    nextId,
+    jobs: allJobs().map(({ page, timeout, deadline, _placeholderInterval, ...rest }) => ({ ...rest, page: page.name })),
+    queues: Object.fromEntries(queue().filter(q => q.pending.length).map(q => [q.poller, q.pending.map(j => j.id)]))
+  };
+}
+
+/**
+ * Replace the active jobs with snapshotted ones. Timeouts restart from the moment of restore.
+ * Waiting jobs go out in the order the snapshot recorded, whatever state the strategy has now.
+ * @param {JobsSnapshot} state
+ * @param {(name: string) => import('./registry.js').Page | undefined} pageOf
+ */
+export function restore(state, pageOf) {
+  for (const job of allJobs()) {
+    if (job.timeout) clearTimeout(job.timeout);
+    if (job._placeholderInterval) clearInterval(job._placeholderInterval);
+  }
+  queues.clear();
+  restoredOrder.clear();
+  for (const [poller, ids] of Object.entries(state.queues || {})) restoredOrder.set(poller, new Set(ids));
+  // Running jobs keep their realm; the waiting ones queue in the order they would have gone out
+  const position = new Map(Object.values(state.queues || {}).flatMap(ids => ids.map((id, i) => [id, i])));
+  /** @param {JobsSnapshot['jobs'][number]} saved */
+  const rank = saved => saved.startedAt ? -1 : position.get(saved.id) ?? state.jobs.length;
+
+  /** @type {Job[]} */
+  const restored = [];
+  for (const saved of [...state.jobs].sort((a, b) => rank(a) - rank(b))) {
+    const page = pageOf(saved.page);
+    if (!page) {
+      console.warn(`[job] restore: dropping job ${saved.id} for unknown page ${saved.page}`);
+      continue;
+    }
+    /** @type {Job} */
+    const job = { ...saved, page, timeout: null };
+    enqueue(job);
+    registry.setState(page, 'executing');
+    armTimeout(job);
+    restored.push(job);
+  }
+  for (const job of restored) if (!job.startedAt) announce(job);
+  nextId = Math.max(nextId, state.nextId);
 }
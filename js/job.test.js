@@ -1,10 +1,17 @@
 // @ts-check
 import { test } from 'node:test';
 import assert from 'node:assert/strict';
-import { mkdtempSync, rmSync, writeFileSync, mkdirSync } from 'node:fs';
+import { mkdtempSync, rmSync, writeFileSync, mkdirSync, readFileSync } from 'node:fs';
 import { tmpdir } from 'node:os';
 import { join } from 'node:path';
 import * as job from './job.js';
+import * as registry from './registry.js';
+import * as writer from './writer.js';
+import { fifo, priority, roundRobin } from './dispatch.js';
+import { sequentialIds, counterIds } from './ids.js';
+import { createSequenceStore } from './sequence.js';
+import { parseRequest } from './parser.js';
+import { createMemoryStorage, fsStorage } from './storage.js';
 
 test('create sets agent field correctly', () => {
   const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
@@ -149,7 +156,7 @@ test('finish sets page state to idle', () => {
     const file = join(root, 'daebug', 'test.md');
     writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
     
-    const page = { name: 'test', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const page = { name: 'test-idle', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
     const j = job.create(page, 'agent', 'x');
     job.finish(j);
     
@@ -194,6 +201,25 @@ test('finish removes job from registry', () => {
   }
 });
 
+test('finish of a job the page has moved on from leaves its successor in place', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
+    
+    const page = { name: 'test-successor', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const stale = job.create(page, 'agent', 'x');
+    const successor = job.create(page, 'agent', 'y');
+    job.finish(stale);
+    
+    assert.strictEqual(job.get('test-successor'), successor);
+    job.finish(successor);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
 test('start sets startedAt timestamp', () => {
   const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
   try {
@@ -235,3 +261,857 @@ test('start is idempotent', () => {
     rmSync(root, { recursive: true, force: true });
   }
 });
+
+test('create accepts a language the page advertises', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
+    
+    const page = { name: 'test-ts-page', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js', 'ts'] };
+    const j = job.create(page, 'agent', 'const x: number = 1', true, { language: 'ts' });
+    
+    assert.strictEqual(j.language, 'ts');
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('create rejects a language the page cannot run', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
+    
+    const page = { name: 'test-js-only', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] };
+    assert.throws(
+      () => job.create(page, 'agent', 'const x: number = 1', true, { language: 'ts' }),
+      /test-js-only cannot run ts code/);
+    assert.strictEqual(job.get('test-js-only'), undefined);
+    assert.strictEqual(page.state, 'idle');
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('cancel of a dispatched job signals the waiting page', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
+    
+    const page = { name: 'test-cancel', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const j = job.create(page, 'agent', 'await new Promise(() => {})');
+    job.start(j);
+    
+    const waiting = job.waitForCancel(j.id, 5000);
+    assert.strictEqual(job.cancel('test-cancel'), j);
+    assert.strictEqual(await waiting, true);
+    assert.ok(j.cancelRequestedAt);
+    // The job stays active until the page reports back
+    assert.strictEqual(job.get('test-cancel'), j);
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('waitForCancel resolves false when nothing happens before the timeout', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
+    
+    const page = { name: 'test-cancel-timeout', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const j = job.create(page, 'agent', 'x');
+    job.start(j);
+    
+    assert.strictEqual(await job.waitForCancel(j.id, 10), false);
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('finish ends a cancellation watch at once and drops its timer', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Write code in a fenced JS block below\n', 'utf8');
+
+    const page = { name: 'test-cancel-finish', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const j = job.create(page, 'agent', 'x');
+    job.start(j);
+
+    const cleared = [];
+    const realClear = globalThis.clearTimeout;
+    globalThis.clearTimeout = timer => { cleared.push(timer); realClear(timer); };
+    try {
+      // Long enough that the test would be held open if the timer outlived the job
+      const waiting = job.waitForCancel(j.id, 60_000);
+      const before = cleared.length;
+      job.finish(j);
+      assert.strictEqual(await waiting, true);
+      assert.ok(cleared.length > before);
+    } finally {
+      globalThis.clearTimeout = realClear;
+    }
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('waitForCancel tells the page to stop once the job is gone', async () => {
+  assert.strictEqual(await job.waitForCancel('no-such-job', 10), true);
+});
+
+test('cancel of an undispatched job finishes it with a cancelled reply', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    
+    const page = { name: 'test-cancel-early', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const j = job.create(page, 'agent', 'x');
+    job.cancel('test-cancel-early');
+    
+    assert.strictEqual(job.get('test-cancel-early'), undefined);
+    assert.ok(j.finishedAt);
+    assert.match(readFileSync(file, 'utf8'), /Cancelled before dispatch/);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('cancel returns null when the page has no job', () => {
+  assert.strictEqual(job.cancel('no-job-page'), null);
+});
+
+test('drain drops the undispatched job and answers it', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    
+    const page = { name: 'test-drain', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    job.create(page, 'agent', 'first()');
+    assert.strictEqual(job.drain('test-drain'), 1);
+    assert.strictEqual(job.get('test-drain'), undefined);
+    assert.strictEqual(page.state, 'idle');
+    assert.match(readFileSync(file, 'utf8'), /Drained before dispatch/);
+    
+    assert.strictEqual(job.drain('test-drain'), 0);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('drain leaves a dispatched job running unless asked to cancel it', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'test.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    
+    const page = { name: 'test-drain-running', state: 'idle', file, url: 'http://localhost', lastSeen: Date.now() };
+    const j = job.create(page, 'agent', 'running()');
+    job.start(j);
+    assert.strictEqual(job.drain('test-drain-running'), 0);
+    assert.strictEqual(j.cancelRequestedAt, undefined);
+    
+    assert.strictEqual(job.drain('test-drain-running', { includeDispatched: true }), 1);
+    assert.ok(j.cancelRequestedAt);
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('worker-context jobs are routed to the page worker', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'route-page', 'http://localhost');
+    const workerName = registry.workerNameFor('route-page');
+    registry.getOrCreate(root, workerName, 'worker://' + workerName);
+    
+    const waiting = job.waitForJob(workerName, 1000);
+    const j = job.create(page, 'agent', 'self.name', true, { context: 'worker' });
+    
+    assert.strictEqual(await waiting, j);
+    assert.strictEqual(job.forPoller(workerName), j);
+    assert.strictEqual(job.forPoller('route-page'), undefined);
+    // Still filed under the page whose log gets the reply
+    assert.strictEqual(job.get('route-page'), j);
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('page-context jobs stay with the page', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'main-only', 'http://localhost');
+    const j = job.create(page, 'agent', 'document.title');
+    assert.strictEqual(j.context, 'page');
+    assert.strictEqual(job.forPoller('main-only'), j);
+    assert.strictEqual(job.forPoller(registry.workerNameFor('main-only')), undefined);
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('worker-context jobs are rejected when the page has no worker', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'no-worker-page', 'http://localhost');
+    assert.throws(() => job.create(page, 'agent', 'x', true, { context: 'worker' }), job.JobRejectedError);
+    assert.strictEqual(page.state, 'idle');
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('broadcast creates one job per matching idle page', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const names = ['fleet-a', 'fleet-b', 'fleet-c'];
+    for (const name of names) registry.getOrCreate(root, name, 'http://localhost');
+    
+    const created = job.broadcast('agent', 'location.href', { names });
+    assert.deepStrictEqual(created.map(j => j.page.name).sort(), names);
+    assert.strictEqual(new Set(created.map(j => j.id)).size, 3);
+    for (const name of names) assert.strictEqual(registry.get(name)?.state, 'executing');
+    
+    // Pages already busy are not given a second job
+    assert.deepStrictEqual(job.broadcast('agent', 'again()', { names }), []);
+    created.forEach(j => job.finish(j));
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('broadcast respects the capability filter and limit', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    registry.getOrCreate(root, 'cap-js', 'http://localhost');
+    registry.getOrCreate(root, 'cap-ts-1', 'http://localhost', ['js', 'ts']);
+    registry.getOrCreate(root, 'cap-ts-2', 'http://localhost', ['js', 'ts']);
+    const names = ['cap-js', 'cap-ts-1', 'cap-ts-2'];
+    
+    const ts = job.broadcast('agent', 'let x: number = 1', { names, capability: 'ts', language: 'ts' });
+    assert.deepStrictEqual(ts.map(j => j.page.name).sort(), ['cap-ts-1', 'cap-ts-2']);
+    ts.forEach(j => job.finish(j));
+    
+    const limited = job.broadcast('agent', '1', { names, limit: 1 });
+    assert.strictEqual(limited.length, 1);
+    limited.forEach(j => job.finish(j));
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('the dispatch strategy chooses among jobs waiting for the same realm', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'strategy-page', 'http://localhost');
+    
+    const first = job.create(page, 'agent', 'first()');
+    const urgent = job.create(page, 'agent', 'urgent()', true, { priority: 10 });
+    assert.strictEqual(job.forPoller('strategy-page'), first);
+    
+    job.configure({ strategy: priority });
+    assert.strictEqual(job.forPoller('strategy-page'), urgent);
+    job.start(urgent);
+    // A job arriving while another runs waits its turn rather than taking the page over
+    const later = job.create(page, 'agent', 'later()', true, { priority: 20 });
+    assert.strictEqual(job.forPoller('strategy-page'), urgent);
+    assert.strictEqual(job.get('strategy-page'), urgent);
+    
+    job.finish(urgent);
+    assert.strictEqual(page.state, 'executing');
+    assert.strictEqual(job.forPoller('strategy-page'), later);
+    job.finish(later);
+    assert.strictEqual(job.forPoller('strategy-page'), first);
+    job.finish(first);
+    assert.strictEqual(page.state, 'idle');
+    assert.strictEqual(job.get('strategy-page'), undefined);
+  } finally {
+    job.configure({ strategy: fifo });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a restored queue dispatches in the order it had before the snapshot', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'reload-order-page', 'http://localhost');
+    job.configure({ strategy: roundRobin() });
+
+    // alice was served last, so bob's job goes ahead of her older ones
+    const served = job.create(page, 'alice', 'served()');
+    assert.strictEqual(job.forPoller('reload-order-page'), served);
+    job.start(served);
+    const older = job.create(page, 'alice', 'older()');
+    const turn = job.create(page, 'bob', 'turn()');
+    const oldest = job.create(page, 'alice', 'oldest()');
+    const before = job.queue().find(q => q.poller === 'reload-order-page')?.pending.map(j => j.id);
+    assert.deepStrictEqual(before, [turn.id, older.id, oldest.id]);
+
+    const saved = JSON.parse(JSON.stringify(job.snapshot()));
+    assert.deepStrictEqual(saved.queues['reload-order-page'], before);
+    // A restart starts the strategy afresh: on its own it would hand out alice's jobs first
+    job.configure({ strategy: roundRobin() });
+    job.restore(saved, registry.get);
+
+    assert.deepStrictEqual(job.queue().find(q => q.poller === 'reload-order-page')?.pending.map(j => j.id), before);
+    const handedOut = [];
+    for (let next = job.forPoller('reload-order-page'); next; next = job.forPoller('reload-order-page')) {
+      handedOut.push(next.id);
+      job.start(next);
+      job.finish(next);
+    }
+    assert.deepStrictEqual(handedOut, [served.id, ...before]);
+  } finally {
+    job.configure({ strategy: fifo });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('safe mode rejects code matching a deny pattern', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  job.configure({ denyPatterns: [/while\s*\(\s*true\s*\)/, /\beval\s*\(/] });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'safe-deny', 'http://localhost');
+    assert.throws(() => job.create(page, 'agent', 'while (true) {}'), /safe mode: code matches blocked pattern/);
+    assert.throws(() => job.create(page, 'agent', 'eval("1")'), job.JobRejectedError);
+    assert.strictEqual(page.state, 'idle');
+    
+    const j = job.create(page, 'agent', 'document.title');
+    job.finish(j);
+  } finally {
+    job.configure({ denyPatterns: [] });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('safe mode with allow patterns only permits matching code', () => {
+  job.configure({ allowPatterns: [/^document\./] });
+  try {
+    assert.strictEqual(job.screenCode('document.title'), null);
+    assert.match(String(job.screenCode('fetch("/x")')), /none of the allowed patterns/);
+  } finally {
+    job.configure({ allowPatterns: null });
+  }
+});
+
+test('judge passes a result that JSON-equals the expected value', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'expect-pass', 'http://localhost');
+    const j = job.create(page, 'agent', '({ b: [1, 2], a: 1 })', true, { expect: { a: 1, b: [1, 2] } });
+    assert.strictEqual(job.judge(j, { ok: true, value: { b: [1, 2], a: 1 } }), 'pass');
+    assert.strictEqual(j.verdict, 'pass');
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('judge fails mismatching values and errors, and ignores jobs without expectations', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'expect-fail', 'http://localhost');
+    const j = job.create(page, 'agent', '1 + 1', true, { expect: 3 });
+    assert.strictEqual(job.judge(j, { ok: true, value: 2 }), 'fail');
+    assert.strictEqual(job.judge(j, { ok: false }), 'fail');
+    job.finish(j);
+    
+    const plain = job.create(page, 'agent', '1 + 1');
+    assert.strictEqual(job.judge(plain, { ok: true, value: 2 }), undefined);
+    assert.strictEqual(plain.verdict, undefined);
+    job.finish(plain);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a per-page config timeout overrides the server default', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    writeFileSync(join(root, 'daebug', 'quick-page.config.json'), JSON.stringify({ timeoutMs: 30 }));
+    const quick = registry.getOrCreate(root, 'quick-page', 'http://localhost');
+    const slow = registry.getOrCreate(root, 'slow-page', 'http://localhost');
+    writeFileSync(quick.file, '# quick-page Session\n');
+    
+    const timedOut = job.create(quick, 'agent', 'await new Promise(() => {})');
+    const regular = job.create(slow, 'agent', 'await new Promise(() => {})');
+    await new Promise(resolve => setTimeout(resolve, 100));
+    
+    assert.notStrictEqual(timedOut.finishedAt, null);
+    assert.match(readFileSync(quick.file, 'utf8'), /job timed out after 30ms/);
+    assert.strictEqual(regular.finishedAt, null);
+    job.finish(regular);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a pinned job fails when its page reloads, an unpinned one survives', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'pinned-page', 'http://localhost');
+    writeFileSync(page.file, '# pinned-page Session\n');
+    assert.strictEqual(registry.noteInstance(page, 'load-1'), false);
+    assert.strictEqual(registry.noteInstance(page, 'load-1'), false);
+    
+    const pinned = job.create(page, 'agent', 'window.state', true, { pin: true });
+    assert.strictEqual(pinned.instance, 'load-1');
+    assert.strictEqual(registry.noteInstance(page, 'load-2'), true);
+    assert.deepStrictEqual(job.invalidatePinned(page), [pinned]);
+    assert.notStrictEqual(pinned.finishedAt, null);
+    assert.strictEqual(job.get('pinned-page'), undefined);
+    assert.match(readFileSync(page.file, 'utf8'), /Page reloaded: job was pinned to instance load-1/);
+    
+    const loose = job.create(page, 'agent', 'window.state');
+    registry.noteInstance(page, 'load-3');
+    assert.deepStrictEqual(job.invalidatePinned(page), []);
+    assert.strictEqual(job.get('pinned-page'), loose);
+    job.finish(loose);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('TypeScript for a JS-only page is transpiled once per distinct code', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  let calls = 0;
+  job.configure({ transpiler: code => { calls++; return code.replace(/: number/g, ''); } });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'js-only-transpile', 'http://localhost', ['js']);
+    const code = 'const cacheProbe: number = 1';
+    
+    const first = job.create(page, 'agent', code, true, { language: 'ts' });
+    assert.strictEqual(first.compiled, 'const cacheProbe = 1');
+    assert.strictEqual(first.code, code);
+    job.finish(first);
+    
+    const second = job.create(page, 'agent', code, true, { language: 'ts' });
+    assert.strictEqual(second.compiled, 'const cacheProbe = 1');
+    assert.strictEqual(calls, 1);
+    job.finish(second);
+  } finally {
+    job.configure({ transpiler: null });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a failing transpiler rejects the job', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  job.configure({ transpiler: () => { throw new Error('unexpected token'); } });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'js-only-broken', 'http://localhost', ['js']);
+    assert.throws(
+      () => job.create(page, 'agent', 'let broken: = 1', true, { language: 'ts' }),
+      /could not transpile TypeScript for js-only-broken: unexpected token/);
+    assert.strictEqual(page.state, 'idle');
+  } finally {
+    job.configure({ transpiler: null });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a delayed job is not dispatched before its time', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  let clock = 1_000_000;
+  job.configure({ now: () => clock });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'delayed-page', 'http://localhost');
+    const j = job.create(page, 'agent', 'later()', true, { delayMs: 10_000 });
+    assert.strictEqual(j.notBefore, 1_010_000);
+    assert.strictEqual(j.requestedAt, new Date(1_000_000).toISOString());
+    assert.strictEqual(job.forPoller('delayed-page'), undefined);
+    
+    clock += 9_999;
+    assert.strictEqual(job.forPoller('delayed-page'), undefined);
+    clock += 1;
+    assert.strictEqual(job.forPoller('delayed-page'), j);
+    job.finish(j);
+  } finally {
+    job.configure({ now: () => Date.now() });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a poll waiting for a delayed job is answered once it is due', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'delayed-wait', 'http://localhost');
+    const started = Date.now();
+    const waiting = job.waitForJob('delayed-wait', 1000);
+    const j = job.create(page, 'agent', 'soon()', true, { notBefore: started + 80 });
+    
+    assert.strictEqual(await waiting, j);
+    assert.ok(Date.now() - started >= 75, 'dispatched early');
+    job.finish(j);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('only the newest results of each page are kept when a per-page limit is set', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  let clock = 3_000_000;
+  job.configure({ now: () => clock, resultsPerPage: 2, resultRetentionMs: 1000 });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'trimmed-page', 'http://localhost');
+    const other = registry.getOrCreate(root, 'untrimmed-page', 'http://localhost');
+    const ran = (/** @type {*} */ p, /** @type {number} */ value) => {
+      const j = job.create(p, 'agent', String(value));
+      job.start(j);
+      job.finish(j, { ok: true, value });
+      clock += 100;
+      return j.id;
+    };
+    const otherId = ran(other, 0);
+    const ids = [1, 2, 3].map(value => ran(page, value));
+
+    assert.strictEqual(job.outcome(ids[0]), undefined);
+    assert.strictEqual(job.outcome(ids[1])?.value, 2);
+    assert.strictEqual(job.outcome(ids[2])?.value, 3);
+    assert.strictEqual(job.outcome(otherId)?.value, 0);
+
+    clock += 1000;
+    assert.strictEqual(job.outcome(ids[2]), undefined);
+  } finally {
+    job.configure({ now: () => Date.now(), resultsPerPage: 0, resultRetentionMs: 10 * 60_000 });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('results past the byte cap push out the oldest while recent ones stay queryable', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  job.configure({ resultRetentionBytes: 2500 });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'capped-page', 'http://localhost');
+    const ids = ['a', 'b', 'c'].map(letter => {
+      const j = job.create(page, 'agent', 'big()');
+      job.start(j);
+      job.finish(j, { ok: true, value: letter.repeat(1000) });
+      return j.id;
+    });
+
+    assert.strictEqual(job.outcome(ids[0]), undefined);
+    assert.strictEqual(job.outcome(ids[1])?.value, 'b'.repeat(1000));
+    assert.strictEqual(job.outcome(ids[2])?.value, 'c'.repeat(1000));
+  } finally {
+    job.configure({ resultRetentionBytes: 0 });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('finished jobs keep their result until the retention window passes', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  let clock = 2_000_000;
+  job.configure({ now: () => clock, resultRetentionMs: 1000 });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'outcome-page', 'http://localhost');
+    const j = job.create(page, 'agent', '6 * 7');
+    job.start(j);
+    job.finish(j, { ok: true, value: 42 });
+
+    const kept = job.outcome(j.id);
+    assert.strictEqual(kept?.ok, true);
+    assert.strictEqual(kept?.value, 42);
+    assert.strictEqual(kept?.page, 'outcome-page');
+    assert.strictEqual(typeof kept?.durationMs, 'number');
+
+    clock += 1000;
+    assert.strictEqual(job.outcome(j.id), undefined);
+  } finally {
+    job.configure({ now: () => Date.now(), resultRetentionMs: 10 * 60_000 });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('jobs for a full log fail with "log full" until the log is reset', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  writer.configure({ maxLogBytes: 300 });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'full-log', 'http://localhost');
+    writeFileSync(page.file, '# full-log\n\n' + 'filler\n'.repeat(40), 'utf8');
+    assert.throws(() => writer.writeDiagnostic(page.file, 'one more line'), writer.LogFullError);
+
+    const refused = job.create(page, 'agent', '1 + 1');
+    assert.ok(refused.finishedAt);
+    assert.strictEqual(job.get('full-log'), undefined);
+    assert.strictEqual(job.outcome(refused.id)?.error, 'log full');
+
+    writeFileSync(page.file, '# full-log\n', 'utf8');
+    const accepted = job.create(page, 'agent', '1 + 1');
+    assert.strictEqual(job.get('full-log'), accepted);
+    job.finish(accepted);
+  } finally {
+    writer.configure({ maxLogBytes: 0 });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a reply the storage cannot take fails the job and says why in the log', () => {
+  const file = '/logs/write-fails.md';
+  const storage = createMemoryStorage({ [file]: '# write-fails\n\n----\n> Append your JavaScript snippet below to execute against this page.\n' });
+  let failing = false;
+  writer.configure({
+    storage: {
+      ...storage,
+      write: (path, content) => {
+        if (failing) { failing = false; throw new Error('disk unplugged'); }
+        storage.write(path, content);
+      }
+    }
+  });
+  try {
+    const j = job.create({ name: 'write-fails', state: 'idle', stateSince: 0, file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] }, 'agent', '1 + 1');
+    job.start(j);
+    failing = true;
+    assert.strictEqual(job.complete(j, { ok: true, value: 2 }), true);
+    assert.ok(j.finishedAt);
+    assert.strictEqual(job.get('write-fails'), undefined);
+    assert.strictEqual(job.outcome(j.id)?.ok, false);
+    assert.match(String(job.outcome(j.id)?.error), /disk unplugged/);
+    assert.match(storage.read(file), /could not write the reply to job .*: disk unplugged/);
+  } finally {
+    writer.configure({ storage: fsStorage });
+  }
+});
+
+test('a result and a cancel racing for the same job settle it exactly once', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'race.md');
+    for (const [order, page] of [['result first', 'race-result'], ['cancel first', 'race-cancel']]) {
+      writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+      const j = job.create({ name: page, state: 'idle', stateSince: 0, file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] }, 'agent', 'work()');
+      job.start(j);
+
+      /** @type {boolean[]} */
+      const settled = [];
+      const deliver = () => new Promise(resolve => setImmediate(() => { settled.push(job.complete(j, { ok: true, value: 'done' })); resolve(undefined); }));
+      const cancel = () => new Promise(resolve => setImmediate(() => { job.cancel(page); resolve(undefined); }));
+      await Promise.all(order === 'result first' ? [deliver(), cancel(), deliver()] : [cancel(), deliver(), deliver()]);
+
+      assert.deepStrictEqual(settled, [true, false], order);
+      assert.strictEqual(job.get(page), undefined, order);
+      assert.strictEqual(job.outcome(j.id)?.value, 'done', order);
+      const replies = readFileSync(file, 'utf8').match(/^#### /gm) || [];
+      assert.strictEqual(replies.length, 1, order);
+    }
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('a job cancelled before dispatch ignores a late result', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'race-early.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    const j = job.create({ name: 'race-early', state: 'idle', stateSince: 0, file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] }, 'agent', 'work()');
+    job.cancel('race-early');
+    assert.strictEqual(job.complete(j, { ok: true, value: 'late' }), false);
+    assert.strictEqual(job.outcome(j.id)?.error, 'Cancelled before dispatch');
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('jobs an agent submits while a session is open are grouped under it', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'session.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    const page = { name: 'session-page', state: 'idle', stateSince: 0, file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] };
+
+    const outside = job.create(page, 'alice', '0');
+    job.finish(outside);
+    const session = job.openSession('session-page', 'alice');
+    assert.strictEqual(job.openSession('session-page', 'alice').id, session.id);
+
+    const ids = [];
+    for (const code of ['1', '2', '3']) {
+      const j = job.create(page, 'alice', code);
+      assert.strictEqual(j.session, session.id);
+      ids.push(j.id);
+      if (code !== '3') job.finish(j, { ok: true, value: Number(code) });
+    }
+    const otherAgent = job.create({ ...page, name: 'session-other' }, 'bob', '4');
+    assert.strictEqual(otherAgent.session, undefined);
+
+    const listed = job.list({ session: session.id });
+    assert.deepStrictEqual(listed?.map(j => j.id), ids);
+    assert.deepStrictEqual(listed?.map(j => j.state), ['finished', 'finished', 'queued']);
+    assert.ok(!listed?.some(j => j.id === outside.id));
+
+    job.finish(/** @type {any} */(job.get('session-page')));
+    job.finish(otherAgent);
+    assert.ok(job.closeSession(session.id)?.closedAt);
+    assert.strictEqual(job.create(page, 'alice', '5').session, undefined);
+    job.finish(/** @type {any} */(job.get('session-page')));
+    assert.strictEqual(job.list({ session: 'no-such-session' }), undefined);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('jobs take their ids from the configured generator', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  job.configure({ idGenerator: sequentialIds(1, 'test-') });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'ids.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    const ids = ['ids-a', 'ids-b', 'ids-c'].map(name => {
+      const j = job.create({ name, state: 'idle', stateSince: 0, file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] }, 'agent', '1');
+      job.finish(j, { ok: true, value: 1 });
+      return j.id;
+    });
+    assert.deepStrictEqual(ids, ['test-1', 'test-2', 'test-3']);
+    assert.strictEqual(job.outcome('test-2')?.page, 'ids-b');
+  } finally {
+    job.configure({ idGenerator: counterIds });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('an agent over its quota is refused until its window passes or its jobs finish', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  let clock = 1_000_000;
+  job.configure({ agentJobsPerMinute: 2, now: () => clock });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const file = join(root, 'daebug', 'quota.md');
+    writeFileSync(file, '> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+    /** @param {string} name */
+    const page = name => ({ name, state: 'idle', stateSince: 0, file, url: 'http://localhost', lastSeen: Date.now(), capabilities: ['js'] });
+
+    for (const name of ['quota-a', 'quota-b']) job.finish(job.create(page(name), 'greedy', '1'));
+    clock += 30_000;
+    assert.throws(() => job.create(page('quota-c'), 'greedy', '1'), job.QuotaExceededError);
+    assert.throws(() => job.create(page('quota-c'), 'greedy', '1'), (/** @type {*} */ err) => err.retryAfterMs === 30_000);
+    job.finish(job.create(page('quota-c'), 'patient', '1'));
+
+    clock += 30_001;
+    job.finish(job.create(page('quota-c'), 'greedy', '1'));
+
+    job.configure({ agentJobsPerMinute: 0, agentMaxConcurrent: 1 });
+    const running = job.create(page('quota-d'), 'greedy', '1');
+    assert.throws(() => job.create(page('quota-e'), 'greedy', '1'), /already has 1 job/);
+    job.finish(running);
+    job.finish(job.create(page('quota-e'), 'greedy', '1'));
+  } finally {
+    job.configure({ agentJobsPerMinute: 0, agentMaxConcurrent: 0, now: () => Date.now() });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('an idempotency key replays only the page and code it was first used with, and only so many are kept', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'idempotent-page', 'http://localhost');
+    const other = registry.getOrCreate(root, 'idempotent-other', 'http://localhost');
+    
+    const first = job.create(page, 'agent', 'charge()', true, { idempotencyKey: 'k1' });
+    assert.strictEqual(job.create(page, 'agent', 'charge()', true, { idempotencyKey: 'k1' }), first);
+    assert.throws(() => job.create(page, 'agent', 'refund()', true, { idempotencyKey: 'k1' }), job.IdempotencyKeyReusedError);
+    assert.throws(() => job.create(other, 'agent', 'charge()', true, { idempotencyKey: 'k1' }), /another page/);
+    // Another agent's key is its own
+    const theirs = job.create(other, 'someone', 'refund()', true, { idempotencyKey: 'k1' });
+    assert.notStrictEqual(theirs, first);
+    
+    job.configure({ idempotencyMaxKeys: 1 });
+    const newest = job.create(other, 'agent', 'later()', true, { idempotencyKey: 'k2' });
+    assert.strictEqual(job.forIdempotencyKey('agent', 'k1'), undefined);
+    assert.strictEqual(job.forIdempotencyKey('agent', 'k2'), newest);
+    for (const j of [first, theirs, newest]) job.finish(j);
+  } finally {
+    job.configure({ idempotencyMaxKeys: 10_000 });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('sessions are numbered from the sequence store when one is configured', () => {
+  const storage = createMemoryStorage({ '/seq.json': '{"session":7}' });
+  job.configure({ sequences: createSequenceStore('/seq.json', storage) });
+  try {
+    const session = job.openSession('sequenced-page', 'alice');
+    assert.strictEqual(session.id, 's8');
+    job.closeSession(session.id);
+  } finally {
+    job.configure({ sequences: null });
+  }
+});
+
+test('a page request stays pending in the log until an agent answers it', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'asking-page', 'http://localhost');
+    writeFileSync(page.file, '# asking-page\n\n----\n> Append your JavaScript snippet below to execute against this page.\n', 'utf8');
+
+    const request = job.ask(page, 'Please run my test suite\n```js\nnot code\n```');
+    assert.strictEqual(request.answeredAt, null);
+    assert.ok(job.pageRequestsFor({ page: 'asking-page', pending: true }).some(r => r.id === request.id));
+    writer.flushPending(page.file);
+    let log = readFileSync(page.file, 'utf8');
+    assert.match(log, new RegExp(`### 📣asking-page to agent at \\d{2}:\\d{2}:\\d{2} #${request.id}\\n\\n> Please run my test suite\\n> \`\`\`js`));
+    assert.ok(log.indexOf(request.id) < log.indexOf('> Append your JavaScript'));
+    assert.strictEqual(parseRequest(log, 'asking-page'), null);
+
+    const answered = job.answerPageRequest(request.id, 'alice', 'All 12 tests pass');
+    assert.strictEqual(answered?.agent, 'alice');
+    assert.ok(answered?.answeredAt);
+    assert.ok(!job.pageRequestsFor({ pending: true }).some(r => r.id === request.id));
+    assert.strictEqual(job.getPageRequest(request.id)?.answer, 'All 12 tests pass');
+    assert.throws(() => job.answerPageRequest(request.id, 'bob', 'again'), /already answered by alice/);
+    assert.strictEqual(job.answerPageRequest('p-unknown', 'bob', 'x'), undefined);
+
+    writer.flushPending(page.file);
+    log = readFileSync(page.file, 'utf8');
+    assert.match(log, new RegExp(`#### 📣alice to asking-page at \\d{2}:\\d{2}:\\d{2} #${request.id}\\n\\n> All 12 tests pass`));
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
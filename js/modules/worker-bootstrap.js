@@ -10,7 +10,8 @@ function workerBootstrap() {
   const name = self.name || 'worker-unknown';
 
   const __ORIGIN__ = location.origin;
-  const endpoint = __ORIGIN__ + '/-daebug-channel?name=' + encodeURIComponent(name) + '&url=worker://' + encodeURIComponent(name);
+  const instance = Math.random().toString(36).slice(2, 10) + Date.now().toString(36);
+  const endpoint = __ORIGIN__ + '/-daebug-channel?name=' + encodeURIComponent(name) + '&url=worker://' + encodeURIComponent(name) + '&instance=' + instance + '&caps=js';
   const sleep = /** @param {number} ms */(ms) => new Promise(r => setTimeout(r, ms));
   /**
    * @type {{
@@ -24,6 +25,29 @@ function workerBootstrap() {
    */
   const backgroundEvents = [];
 
+  /**
+   * Whether a job's `when` predicate holds: the expression is awaited and its value taken as truthy or not
+   * @param {string} predicate
+   * @param {AbortSignal} [signal]
+   */
+  async function predicateHolds(predicate, signal) {
+    const AsyncFunction = Object.getPrototypeOf(async function () { }).constructor;
+    return !!(await new AsyncFunction('signal', 'return (' + predicate + ')')(signal));
+  }
+
+  /**
+   * Split what happened during a job into its console output and the remaining background events
+   * @param {typeof backgroundEvents} events
+   */
+  function splitJobEvents(events) {
+    return {
+      logs: events
+        .filter(event => event.type === 'console')
+        .map(({ level, message, eventAt }) => ({ level: level || 'log', message, eventAt })),
+      backgroundEvents: events.filter(event => event.type !== 'console')
+    };
+  }
+
   /**
    * @param {*} val
    * @param {number} [depth]
@@ -36,6 +60,7 @@ function workerBootstrap() {
     if (typeof val === 'function') return val.name || '[Function]';
     if (typeof val === 'string') return val;
     if (typeof val === 'number' || typeof val === 'boolean') return String(val);
+    if (typeof val === 'bigint') return val + 'n';
     if (Array.isArray(val)) {
       try {
         return '[' + val.map(v => serializeValue(v, depth + 1)).join(', ') + ']';
@@ -45,7 +70,7 @@ function workerBootstrap() {
     }
     if (typeof val === 'object') {
       try {
-        return JSON.stringify(val);
+        return JSON.stringify(val, tagBigInt);
       } catch (e) {
         return String(val);
       }
@@ -108,46 +133,148 @@ function workerBootstrap() {
 
   console.log('👾𝘄𝗼𝗿𝗸𝗲𝗿: initialized');
 
+  /**
+   * Retry timing after failed requests: doubles from baseMs up to maxMs, spread by ±jitter
+   * so many pages coming back after a server restart don't retry in lockstep
+   * @typedef {{ baseMs: number, maxMs: number, jitter: number }} Backoff
+   */
+  /** @type {Backoff} */
+  const BACKOFF = { baseMs: 500, maxMs: 30000, jitter: 0.2 };
+
+  /**
+   * Delay before the next attempt after `attempt` consecutive failures
+   * @param {number} attempt
+   * @param {Backoff} [backoff]
+   * @param {() => number} [random]
+   */
+  function backoffDelay(attempt, backoff = BACKOFF, random = Math.random) {
+    const delay = Math.min(backoff.maxMs, backoff.baseMs * 2 ** attempt);
+    return Math.round(delay * (1 - backoff.jitter + 2 * backoff.jitter * random()));
+  }
+
+  /**
+   * JSON.stringify replacer carrying BigInts as `{ "$bigint": "<digits>" }`, which JSON itself
+   * cannot hold and a number would round past 2^53
+   * @param {string} key
+   * @param {unknown} value
+   */
+  function tagBigInt(key, value) {
+    return typeof value === 'bigint' ? { $bigint: value.toString() } : value;
+  }
+
+  /**
+   * Type of a result as JS sees it, sent beside the value since JSON drops `undefined`
+   * and cannot tell a function or symbol from nothing
+   * @param {unknown} value
+   */
+  function valueType(value) {
+    return value === null ? 'null' : Array.isArray(value) ? 'array' : typeof value;
+  }
+
+  /**
+   * POST to the channel, retrying with backoff so a result survives a brief server outage
+   * @param {*} payload
+   * @param {number} [attempts]
+   */
+  async function postWithRetry(payload, attempts = 5) {
+    for (let attempt = 0; ; attempt++) {
+      try {
+        const res = await fetch(endpoint, {
+          method: 'POST',
+          headers: { 'Content-Type': 'application/json' },
+          body: JSON.stringify(payload, tagBigInt)
+        });
+        if (res.ok) return;
+        throw new Error('server responded ' + res.status);
+      } catch (err) {
+        if (attempt + 1 >= attempts) throw err;
+        await sleep(backoffDelay(attempt));
+      }
+    }
+  }
+
+  /**
+   * Hold a cancellation watch open on the server while a job runs
+   * @param {string | null} jobId
+   * @param {() => boolean} isSettled
+   * @returns {Promise<boolean>} true when the server asks to stop the job
+   */
+  async function watchCancel(jobId, isSettled) {
+    while (jobId && !isSettled()) {
+      try {
+        const res = await fetch(endpoint + '&cancel=' + encodeURIComponent(jobId), { cache: 'no-cache' });
+        if (await res.text() === 'cancelled') return !isSettled();
+      } catch (err) {
+        await sleep(3000);
+      }
+    }
+    return false;
+  }
+
   // Main polling loop
   async function mainPollingLoop() {
+    let failures = 0;
+    // Hash of the prelude already evaluated in this realm
+    let preludeHash = '';
     while (true) {
       try {
-        const res = await fetch(endpoint, { cache: 'no-cache' });
+        const res = await fetch(endpoint + (preludeHash ? '&prelude=' + preludeHash : ''), { cache: 'no-cache' });
+        if (!res.ok) throw new Error('server responded ' + res.status);
         const script = await res.text();
+        failures = 0;
         if (!script) { await sleep(500); continue; }
       
         // Mark start of job execution for background event association
         const jobStartIdx = backgroundEvents.length;
         const AsyncFunction = Object.getPrototypeOf(async function () { }).constructor;
+        const jobId = res.headers.get('x-job-id');
+        const controller = new AbortController();
+        const cancelled = new Promise((_, reject) =>
+          controller.signal.addEventListener('abort', () => reject(new Error('Cancelled'))));
+        let settled = false;
+        watchCancel(jobId, () => settled).then(stop => { if (stop) controller.abort(); });
         let payload;
       
         try {
+          // Shared setup sent only when it changed; evaluated globally so its declarations persist
+          const prelude = res.headers.get('x-prelude');
+          if (prelude) {
+            (0, eval)(decodeURIComponent(prelude));
+            preludeHash = res.headers.get('x-prelude-hash') || '';
+          }
+
+          // A guarded job runs only when its predicate holds, and otherwise reports itself skipped
+          const when = res.headers.get('x-job-when');
+          const skipped = !!when && !(await Promise.race([predicateHolds(decodeURIComponent(when), controller.signal), cancelled]));
+
           let result;
-          try {
-            result = await new AsyncFunction('return (' + script + ')')();
-          } catch {
-            result = await new AsyncFunction(script)();
+          if (!skipped) {
+            try {
+              result = await Promise.race([new AsyncFunction('signal', 'return (' + script + ')')(controller.signal), cancelled]);
+            } catch {
+              if (controller.signal.aborted) throw new Error('Cancelled');
+              result = await Promise.race([new AsyncFunction('signal', script)(controller.signal), cancelled]);
+            }
           }
         
           // Capture background events that occurred during execution
           const jobEvents = backgroundEvents.splice(jobStartIdx);
-          payload = { ok: true, value: result, backgroundEvents: jobEvents, jobId: res.headers.get('x-job-id') };
+          payload = { ok: true, value: result, valueType: valueType(result), ...(skipped && { skipped: true }), ...splitJobEvents(jobEvents), jobId };
         } catch (err) {
           // Capture background events even on error
           const jobEvents = backgroundEvents.splice(jobStartIdx);
-          payload = { ok: false, error: /** @type {*} */(err)?.stack || String(err), backgroundEvents: jobEvents, jobId: res.headers.get('x-job-id') };
+          payload = controller.signal.aborted
+            ? { ok: false, cancelled: true, error: 'Cancelled', ...splitJobEvents(jobEvents), jobId }
+            : { ok: false, error: /** @type {*} */(err)?.stack || String(err), ...splitJobEvents(jobEvents), jobId };
         }
+        settled = true;
       
-        await fetch(endpoint, {
-          method: 'POST',
-          headers: { 'Content-Type': 'application/json' },
-          body: JSON.stringify(payload)
-        });
+        await postWithRetry(payload);
       
         await sleep(100);
       } catch (err) {
         console.warn('👾𝘄𝗼𝗿𝗸𝗲𝗿: fetch error:', err);
-        await sleep(3000);
+        await sleep(backoffDelay(failures++));
       }
     }
   }
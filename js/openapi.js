@@ -0,0 +1,839 @@
+// @ts-check
+
+/** @param {string} description @param {object} [schema] */
+const jsonResponse = (description, schema) => ({
+  description,
+  content: { 'application/json': { schema: schema || { type: 'object' } } }
+});
+
+/** @param {string} description */
+const textResponse = description => ({ description, content: { 'text/plain': { schema: { type: 'string' } } } });
+
+const ref = (/** @type {string} */ name) => ({ $ref: `#/components/schemas/${name}` });
+
+const nameQuery = { name: 'name', in: 'query', required: true, schema: { type: 'string' }, description: 'Page name' };
+const namePath = { name: 'name', in: 'path', required: true, schema: { type: 'string' }, description: 'Page name' };
+
+/**
+ * OpenAPI description of the daebug HTTP API. Maintained by hand next to the routes in server.js.
+ * @param {string} version
+ */
+export function openApiDocument(version) {
+  return {
+    openapi: '3.0.3',
+    info: { title: 'Daebug remote REPL', version },
+    // Enforced only when the server runs with an access token
+    security: [{ accessToken: [] }, { accessCookie: [] }, {}],
+    paths: {
+      '/-daebug-channel': {
+        get: {
+          summary: 'Register the page and long-poll for the next job',
+          parameters: [
+            nameQuery,
+            { name: 'url', in: 'query', schema: { type: 'string' }, description: 'Page location' },
+            { name: 'caps', in: 'query', schema: { type: 'string' }, description: 'Comma-separated capabilities: js, ts, ephemeral. The client sends js, plus any its page URL asks for with ?daebug-caps=' },
+            { name: 'cancel', in: 'query', schema: { type: 'string' }, description: 'Job id to watch for cancellation instead of polling' },
+            { name: 'prelude', in: 'query', schema: { type: 'string' }, description: 'Hash of the prelude the page has already evaluated' },
+            { name: 'instance', in: 'query', schema: { type: 'string' }, description: 'Id the page picked for this load; a new id means the page reloaded' }
+          ],
+          responses: {
+            200: {
+              description: 'Job code to run (empty when the hold elapses); `x-job-id` carries the job id',
+              headers: {
+                'x-job-id': { schema: { type: 'string' } },
+                'x-prelude-hash': { schema: { type: 'string' }, description: 'Hash of the prelude the job expects' },
+                'x-prelude': { schema: { type: 'string' }, description: 'URI-encoded prelude, sent only when the page copy is stale' },
+                'x-job-when': { schema: { type: 'string' }, description: 'URI-encoded predicate the page evaluates before the job; falsy skips it' }
+              },
+              content: { 'application/javascript': { schema: { type: 'string' } } }
+            },
+            400: textResponse('Missing page name, or an unknown capability'),
+            403: textResponse('Page is not allowed to register')
+          }
+        },
+        post: {
+          summary: 'Report a job result or page diagnostics',
+          parameters: [nameQuery],
+          requestBody: { required: true, content: { 'application/json': { schema: ref('ResultPayload') } } },
+          responses: { 200: textResponse('Accepted'), 413: textResponse('Body over the result size limit; the job fails'), 500: textResponse('Malformed payload') }
+        }
+      },
+      '/-daebug-cancel': {
+        post: {
+          summary: 'Cancel the job for a page',
+          parameters: [nameQuery],
+          responses: {
+            200: jsonResponse('Job cancelled', { type: 'object', properties: { id: { type: 'string' }, dispatched: { type: 'boolean' } } }),
+            404: textResponse('No job for the page')
+          }
+        }
+      },
+      '/-daebug-broadcast': {
+        post: {
+          summary: 'Run the same code on every matching page',
+          requestBody: {
+            required: true,
+            content: {
+              'application/json': {
+                schema: {
+                  type: 'object',
+                  required: ['code'],
+                  properties: {
+                    agent: { type: 'string' },
+                    code: { type: 'string' },
+                    language: { type: 'string', enum: ['js', 'ts'] },
+                    pages: { type: 'array', items: { type: 'string' } },
+                    capability: { type: 'string' },
+                    state: { type: 'string', enum: ['idle', 'executing', 'disconnected'] },
+                    limit: { type: 'integer' },
+                    expect: { description: 'Value the result must JSON-equal for the job to pass' },
+                    when: { type: 'string', description: 'Expression evaluated on the page first; the job is skipped unless it is truthy' },
+                    pin: { type: 'boolean', description: 'Fail the job instead of running it if the page reloads first' },
+                    notBefore: { oneOf: [{ type: 'number' }, { type: 'string', format: 'date-time' }], description: 'Do not dispatch before this time (epoch ms or ISO)' },
+                    delayMs: { type: 'integer', description: 'Do not dispatch until this many milliseconds from now' }
+                  }
+                }
+              }
+            }
+          },
+          responses: {
+            200: jsonResponse('Created jobs', {
+              type: 'object',
+              properties: { jobs: { type: 'array', items: { type: 'object', properties: { page: { type: 'string' }, id: { type: 'string' } } } } }
+            }),
+            400: textResponse('Missing code'),
+            413: textResponse('The request body is over the size limit')
+          }
+        }
+      },
+      '/-daebug-page/{name}': {
+        delete: {
+          summary: 'Force-evict a page, dropping its job (admin)',
+          parameters: [namePath, { name: 'archive', in: 'query', schema: { type: 'string', enum: ['1'] }, description: 'Also archive the page log' }],
+          responses: {
+            200: jsonResponse('Evicted page', {
+              type: 'object',
+              properties: { page: ref('Page'), dropped: { type: 'integer' }, archive: { type: 'integer', nullable: true } }
+            }),
+            403: textResponse('Admin token required'),
+            404: textResponse('Unknown page')
+          }
+        }
+      },
+      '/-daebug-job/{id}/result': {
+        get: {
+          summary: 'Result of a recently finished job',
+          parameters: [{ name: 'id', in: 'path', required: true, schema: { type: 'string' } }],
+          responses: {
+            200: jsonResponse('Job result', {
+              type: 'object',
+              properties: { ok: { type: 'boolean' }, value: {}, error: {}, skipped: { type: 'boolean' }, logs: { type: 'array', items: ref('LogEntry') }, durationMs: { type: 'integer' } }
+            }),
+            404: textResponse('Unknown job, still running, or past the retention window')
+          }
+        }
+      },
+      '/-daebug-job/{id}/ws': {
+        get: {
+          summary: 'WebSocket pushing a job\'s state changes as `{ type: "state", id, state }` frames, '
+            + 'then one `{ type: "result", id, ok, value, error, skipped, durationMs }` frame before closing',
+          parameters: [{ name: 'id', in: 'path', required: true, schema: { type: 'string' } }],
+          responses: {
+            101: { description: 'Switching to the websocket protocol' },
+            400: textResponse('Not a websocket upgrade'),
+            404: textResponse('Unknown job, or its result is past the retention window')
+          }
+        }
+      },
+      '/-daebug-run': {
+        post: {
+          summary: 'Run a snippet on a page and wait for its result, behind any jobs the page already has',
+          parameters: [{
+            name: 'Idempotency-Key', in: 'header', schema: { type: 'string' },
+            description: 'A retry with the same key, page and code from the same agent waits on the job the first request created'
+          }],
+          requestBody: {
+            required: true,
+            content: {
+              'application/json': {
+                schema: {
+                  type: 'object',
+                  required: ['page', 'code'],
+                  properties: {
+                    page: { type: 'string' },
+                    agent: { type: 'string' },
+                    code: { type: 'string' },
+                    language: { type: 'string', enum: ['js', 'ts'] },
+                    when: { type: 'string', description: 'Expression evaluated on the page first; the job is skipped unless it is truthy' },
+                    timeoutMs: { type: 'integer', description: 'Time limit for this job from submission, queueing included, capped by the server maximum' },
+                    runId: { type: 'string', description: 'Id for cancelling the run; one is assigned when omitted' }
+                  }
+                }
+              }
+            }
+          },
+          responses: {
+            200: {
+              ...jsonResponse('Job finished or run cancelled', ref('RunResult')),
+              headers: { 'x-run-id': { schema: { type: 'string' } } }
+            },
+            400: textResponse('Malformed request or job refused'),
+            404: textResponse('Unknown page'),
+            409: textResponse('A run with this id is already in progress'),
+            413: textResponse('The request body is over the size limit'),
+            422: textResponse('The Idempotency-Key was already used for another page or other code'),
+            429: textResponse('The agent has used up its job quota; `Retry-After` says when to try again'),
+            504: jsonResponse('Job ran out of time', ref('RunResult'))
+          }
+        }
+      },
+      '/-daebug-run/{runId}': {
+        delete: {
+          summary: 'Cancel a run in progress; its request answers as cancelled',
+          parameters: [{ name: 'runId', in: 'path', required: true, schema: { type: 'string' } }],
+          responses: {
+            200: jsonResponse('Run cancelled', {
+              type: 'object',
+              properties: { runId: { type: 'string' }, job: { type: 'string' }, dispatched: { type: 'boolean' } }
+            }),
+            404: textResponse('No run in progress with this id')
+          }
+        }
+      },
+      '/-daebug-page/{name}/ask': {
+        post: {
+          summary: 'Ask the page\'s agents for something; the request is noted in the page log until answered',
+          parameters: [namePath],
+          requestBody: {
+            required: true,
+            content: { 'application/json': { schema: { type: 'object', required: ['message'], properties: { message: { type: 'string' } } } } }
+          },
+          responses: { 200: jsonResponse('Page request', ref('PageRequest')), 400: textResponse('Malformed request'), 404: textResponse('Unknown page'), 413: textResponse('The request body is over the size limit') }
+        }
+      },
+      '/-daebug-page-requests': {
+        get: {
+          summary: 'Requests pages have made of their agents',
+          parameters: [
+            { name: 'page', in: 'query', schema: { type: 'string' } },
+            { name: 'pending', in: 'query', schema: { type: 'string', enum: ['1'] }, description: 'Only unanswered requests' }
+          ],
+          responses: {
+            200: jsonResponse('Page requests', { type: 'object', properties: { requests: { type: 'array', items: ref('PageRequest') } } })
+          }
+        }
+      },
+      '/-daebug-page-request/{id}': {
+        get: {
+          summary: 'A page request and its answer, once given',
+          parameters: [{ name: 'id', in: 'path', required: true, schema: { type: 'string' } }],
+          responses: { 200: jsonResponse('Page request', ref('PageRequest')), 404: textResponse('Unknown page request') }
+        },
+        post: {
+          summary: 'Answer a page request',
+          parameters: [{ name: 'id', in: 'path', required: true, schema: { type: 'string' } }],
+          requestBody: {
+            required: true,
+            content: { 'application/json': { schema: { type: 'object', required: ['answer'], properties: { agent: { type: 'string' }, answer: { type: 'string' } } } } }
+          },
+          responses: {
+            200: jsonResponse('Answered page request', ref('PageRequest')),
+            400: textResponse('Malformed request'),
+            413: textResponse('The request body is over the size limit'),
+            404: textResponse('Unknown page request'),
+            409: textResponse('Already answered')
+          }
+        }
+      },
+      '/-daebug-reap': {
+        post: {
+          summary: 'Time out every job past its deadline now, without waiting for its timer',
+          responses: {
+            200: jsonResponse('Jobs this sweep timed out', {
+              type: 'object',
+              properties: {
+                reaped: {
+                  type: 'array',
+                  items: {
+                    type: 'object',
+                    properties: {
+                      id: { type: 'string' },
+                      page: { type: 'string' },
+                      startedAt: { type: 'string', nullable: true },
+                      finishedAt: { type: 'string' },
+                      timedOut: { type: 'boolean' }
+                    }
+                  }
+                }
+              }
+            })
+          }
+        }
+      },
+      '/-daebug-queue': {
+        get: {
+          summary: 'Waiting jobs of every page and worker, in the order the dispatch strategy would run them',
+          responses: {
+            200: jsonResponse('Queues', {
+              type: 'object',
+              properties: {
+                queues: {
+                  type: 'array',
+                  items: {
+                    type: 'object',
+                    properties: {
+                      poller: { type: 'string', description: 'Page or worker the jobs run in' },
+                      running: { type: 'string', nullable: true },
+                      pending: {
+                        type: 'array',
+                        items: {
+                          type: 'object',
+                          properties: {
+                            id: { type: 'string' },
+                            position: { type: 'integer', description: '1 runs next' },
+                            agent: { type: 'string' },
+                            priority: { type: 'integer' },
+                            requestedAt: { type: 'string', format: 'date-time' },
+                            due: { type: 'boolean', description: 'False while a delayed job waits for its time' },
+                            notBefore: { type: 'number' }
+                          }
+                        }
+                      }
+                    }
+                  }
+                }
+              }
+            })
+          }
+        }
+      },
+      '/-daebug-session': {
+        post: {
+          summary: 'Open a session grouping an agent\'s jobs for a page',
+          requestBody: {
+            required: true,
+            content: { 'application/json': { schema: { type: 'object', required: ['page'], properties: { page: { type: 'string' }, agent: { type: 'string' } } } } }
+          },
+          responses: { 200: jsonResponse('Open session', ref('Session')), 400: textResponse('Malformed request'), 404: textResponse('Unknown page'), 413: textResponse('The request body is over the size limit') }
+        }
+      },
+      '/-daebug-session/{id}': {
+        get: {
+          summary: 'A session and the jobs submitted in it',
+          parameters: [{ name: 'id', in: 'path', required: true, schema: { type: 'string' } }],
+          responses: { 200: jsonResponse('Session', ref('Session')), 404: textResponse('Unknown session') }
+        },
+        delete: {
+          summary: 'Close a session; later jobs no longer join it',
+          parameters: [{ name: 'id', in: 'path', required: true, schema: { type: 'string' } }],
+          responses: { 200: jsonResponse('Closed session', ref('Session')), 404: textResponse('Unknown session') }
+        }
+      },
+      '/-daebug-session/{id}/export': {
+        get: {
+          summary: 'Transcript of a session\'s exchanges, free of log markers, to share or hand back to an agent',
+          parameters: [
+            { name: 'id', in: 'path', required: true, schema: { type: 'string' } },
+            { name: 'format', in: 'query', schema: { type: 'string', enum: ['json', 'markdown'], default: 'json' } }
+          ],
+          responses: {
+            200: {
+              description: 'Transcript',
+              content: { 'application/json': { schema: ref('Transcript') }, 'text/markdown': { schema: { type: 'string' } } }
+            },
+            400: textResponse('Unknown format'),
+            404: textResponse('Unknown session')
+          }
+        }
+      },
+      '/-daebug-jobs': {
+        get: {
+          summary: 'Active jobs, or every job of a session',
+          parameters: [{ name: 'session', in: 'query', schema: { type: 'string' }, description: 'Session id to list jobs of' }],
+          responses: {
+            200: jsonResponse('Jobs', {
+              type: 'object',
+              properties: {
+                jobs: {
+                  type: 'array',
+                  items: {
+                    type: 'object',
+                    properties: {
+                      id: { type: 'string' },
+                      page: { type: 'string' },
+                      agent: { type: 'string' },
+                      session: { type: 'string' },
+                      requestedAt: { type: 'string', format: 'date-time' },
+                      state: { type: 'string', enum: ['queued', 'running', 'finished'] }
+                    }
+                  }
+                }
+              }
+            }),
+            404: textResponse('Unknown session')
+          }
+        }
+      },
+      '/-daebug-page/{name}/ast': {
+        get: {
+          summary: 'Structural summary of a page log',
+          parameters: [namePath],
+          responses: { 200: jsonResponse('Summary', ref('DocumentSummary')), 404: textResponse('Unknown page') }
+        }
+      },
+      '/-daebug-page/{name}/stream': {
+        get: {
+          summary: 'Follow a page log as it grows: its current text, then the text each change adds, over a chunked response left open',
+          parameters: [namePath],
+          responses: {
+            200: { description: 'Log text, streamed', content: { 'text/markdown': { schema: { type: 'string' } } } },
+            404: textResponse('Unknown page')
+          }
+        }
+      },
+      '/-daebug-page/{name}/diagnose': {
+        get: {
+          summary: 'Explain what the server makes of a page log and why it would or would not run it',
+          parameters: [namePath],
+          responses: { 200: jsonResponse('Diagnosis', ref('Diagnosis')), 404: textResponse('Unknown page') }
+        }
+      },
+      '/-daebug-page/{name}/reindex': {
+        post: {
+          summary: 'Re-read a page log and dispatch an unanswered request the watcher missed',
+          parameters: [namePath],
+          responses: {
+            200: jsonResponse('Reindexed', {
+              type: 'object',
+              properties: {
+                job: { type: 'string', nullable: true, description: 'Job dispatched by the reindex' },
+                pending: { type: 'string', nullable: true, description: 'Job the page has now' }
+              }
+            }),
+            404: textResponse('Unknown page')
+          }
+        }
+      },
+      '/-daebug-page/{name}/drain': {
+        post: {
+          summary: 'Drop the pending job of a page',
+          parameters: [namePath, { name: 'dispatched', in: 'query', schema: { type: 'string', enum: ['1'] }, description: 'Also cancel a running job' }],
+          responses: {
+            200: jsonResponse('Jobs dropped', { type: 'object', properties: { dropped: { type: 'integer' } } }),
+            404: textResponse('Unknown page')
+          }
+        }
+      },
+      '/-daebug-page/{name}/prelude': {
+        put: {
+          summary: 'Set setup code the page evaluates once before its jobs',
+          parameters: [namePath],
+          requestBody: { required: true, content: { 'text/plain': { schema: { type: 'string' } } } },
+          responses: { 200: textResponse('Set'), 404: textResponse('Unknown page'), 413: textResponse('The request body is over the size limit') }
+        },
+        delete: {
+          summary: 'Clear the page prelude',
+          parameters: [namePath],
+          responses: { 200: textResponse('Cleared'), 404: textResponse('Unknown page') }
+        }
+      },
+      '/-daebug-snapshot': {
+        get: {
+          summary: 'Export registry and job state (admin); the x-snapshot-id header names it for later diffs',
+          responses: { 200: jsonResponse('State snapshot', ref('StateSnapshot')), 403: textResponse('Admin token required') }
+        },
+        post: {
+          summary: 'Restore registry and job state (admin)',
+          requestBody: { required: true, content: { 'application/json': { schema: ref('StateSnapshot') } } },
+          responses: { 200: textResponse('Restored'), 400: textResponse('Malformed snapshot'), 403: textResponse('Admin token required'), 413: textResponse('The request body is over the size limit') }
+        }
+      },
+      '/-daebug-snapshot/diff': {
+        post: {
+          summary: 'Compare two state snapshots (admin)',
+          requestBody: {
+            required: true,
+            content: {
+              'application/json': {
+                schema: {
+                  type: 'object',
+                  required: ['from', 'to'],
+                  properties: {
+                    from: { oneOf: [{ type: 'string', description: 'x-snapshot-id of an earlier GET' }, ref('StateSnapshot')] },
+                    to: { oneOf: [{ type: 'string', description: 'x-snapshot-id of an earlier GET' }, ref('StateSnapshot')] }
+                  }
+                }
+              }
+            }
+          },
+          responses: { 200: jsonResponse('State diff', ref('StateDiff')), 400: textResponse('Unknown or malformed snapshot'), 403: textResponse('Admin token required'), 413: textResponse('The request body is over the size limit') }
+        }
+      },
+      '/-daebug-discover-tests': {
+        post: {
+          summary: 'Find test files under the served root',
+          requestBody: {
+            content: {
+              'application/json': {
+                schema: {
+                  type: 'object',
+                  properties: {
+                    files: { oneOf: [{ type: 'string' }, { type: 'array', items: { type: 'string' } }] },
+                    cwd: { type: 'string' },
+                    exclude: { type: 'array', items: { type: 'string' } }
+                  }
+                }
+              }
+            }
+          },
+          responses: {
+            200: jsonResponse('Test file URLs', { type: 'object', properties: { files: { type: 'array', items: { type: 'string' } } } }),
+            413: textResponse('The request body is over the size limit')
+          }
+        }
+      },
+      '/-daebug-test-progress': {
+        post: {
+          summary: 'Stream test run progress into a page log',
+          requestBody: { content: { 'application/json': { schema: { type: 'object', properties: { realmName: { type: 'string' }, totals: { type: 'object' } } } } } },
+          responses: { 200: textResponse('Recorded'), 404: textResponse('Unknown page'), 413: textResponse('The request body is over the size limit') }
+        }
+      },
+      '/-daebug-connections': {
+        get: {
+          summary: 'Channel requests pages are holding open (long-polls and cancellation watches)',
+          responses: {
+            200: jsonResponse('Open connections', {
+              type: 'object',
+              properties: {
+                connections: {
+                  type: 'array',
+                  items: {
+                    type: 'object',
+                    properties: {
+                      page: { type: 'string' },
+                      instance: { type: 'string', nullable: true },
+                      kind: { type: 'string', enum: ['poll', 'cancel-watch'] },
+                      connectedAt: { type: 'string', format: 'date-time' },
+                      lastSeen: { type: 'string', format: 'date-time' }
+                    }
+                  }
+                }
+              }
+            })
+          }
+        }
+      },
+      '/-daebug-health/ready': {
+        get: {
+          summary: 'Readiness probe: succeeds once startup has finished',
+          security: [],
+          responses: { 200: textResponse('Ready'), 503: textResponse('Still starting') }
+        }
+      },
+      '/-daebug-client.js': {
+        get: {
+          summary: 'The page client, for pages that load it by <script src>, from another --cors origin too',
+          security: [],
+          responses: { 200: { description: 'Client script', content: { 'text/javascript': { schema: { type: 'string' } } } } }
+        }
+      },
+      '/-daebug-pages': {
+        get: {
+          summary: 'Registered pages, optionally with their job queues',
+          parameters: [{ name: 'jobs', in: 'query', schema: { type: 'string', enum: ['1'] }, description: 'Include queue depth and the running job id' }],
+          responses: {
+            200: jsonResponse('Pages', {
+              type: 'object',
+              properties: {
+                pages: {
+                  type: 'array',
+                  items: {
+                    type: 'object',
+                    properties: {
+                      name: { type: 'string' },
+                      url: { type: 'string' },
+                      state: { type: 'string', enum: ['idle', 'executing', 'disconnected'] },
+                      stateSince: { type: 'number', description: 'Epoch milliseconds of the last state change' },
+                      lastSeen: { type: 'number' },
+                      capabilities: { type: 'array', items: { type: 'string' } },
+                      queue: {
+                        type: 'object',
+                        properties: { depth: { type: 'integer' }, executing: { type: 'string', nullable: true } }
+                      },
+                      pageRequests: { type: 'array', items: { type: 'string' }, description: 'Unanswered requests the page has made' }
+                    }
+                  }
+                }
+              }
+            })
+          }
+        }
+      },
+      '/-daebug-events': {
+        get: {
+          summary: 'Live events as Server-Sent Events, each named by its type with the event JSON as data',
+          parameters: [
+            { name: 'page', in: 'query', schema: { type: 'string' } },
+            { name: 'type', in: 'query', schema: { type: 'string' }, description: 'e.g. job-created, job-finished' }
+          ],
+          responses: { 200: { description: 'Event stream', content: { 'text/event-stream': { schema: { type: 'string' } } } } }
+        }
+      },
+      '/-daebug-events/history': {
+        get: {
+          summary: 'Events from the NDJSON log, filtered; empty unless the server keeps one (--event-log)',
+          parameters: [
+            { name: 'page', in: 'query', schema: { type: 'string' } },
+            { name: 'type', in: 'query', schema: { type: 'string' }, description: 'e.g. job-created, job-finished' },
+            { name: 'since', in: 'query', schema: { type: 'string', format: 'date-time' } }
+          ],
+          responses: { 200: jsonResponse('Matching events', { type: 'array', items: { type: 'object' } }) }
+        }
+      },
+      '/-daebug-openapi.json': {
+        get: { summary: 'This document', responses: { 200: jsonResponse('OpenAPI document') } }
+      }
+    },
+    components: {
+      securitySchemes: {
+        accessToken: { type: 'http', scheme: 'bearer', description: 'The access token, or the admin token' },
+        accessCookie: { type: 'apiKey', in: 'cookie', name: 'daebug-page', description: 'Set on pages opened with access or with ?daebug-token=<token>; derived from the token, not the token itself' }
+      },
+      schemas: {
+        Page: {
+          type: 'object',
+          required: ['name', 'url', 'file', 'state', 'lastSeen', 'capabilities'],
+          properties: {
+            name: { type: 'string' },
+            url: { type: 'string' },
+            file: { type: 'string' },
+            state: { type: 'string', enum: ['idle', 'executing', 'disconnected'] },
+            stateSince: { type: 'number', description: 'Epoch milliseconds of the last state change' },
+            lastSeen: { type: 'number', description: 'Epoch milliseconds' },
+            capabilities: { type: 'array', items: { type: 'string' } }
+          }
+        },
+        LogEntry: {
+          type: 'object',
+          required: ['level', 'message'],
+          properties: { level: { type: 'string' }, message: { type: 'string' }, eventAt: { type: 'string', format: 'date-time' } }
+        },
+        RunResult: {
+          type: 'object',
+          required: ['id', 'ok', 'durationMs'],
+          properties: {
+            id: { type: 'string' },
+            ok: { type: 'boolean' },
+            value: {},
+            error: {},
+            skipped: { type: 'boolean' },
+            logs: { type: 'array', items: ref('LogEntry') },
+            runId: { type: 'string' },
+            durationMs: { type: 'integer' },
+            timedOut: { type: 'boolean' },
+            cancelled: { type: 'boolean' }
+          }
+        },
+        PageRequest: {
+          type: 'object',
+          required: ['id', 'page', 'message', 'askedAt', 'agent', 'answer', 'answeredAt'],
+          properties: {
+            id: { type: 'string' },
+            page: { type: 'string' },
+            message: { type: 'string' },
+            askedAt: { type: 'string', format: 'date-time' },
+            agent: { type: 'string', nullable: true, description: 'Agent that answered' },
+            answer: { type: 'string', nullable: true },
+            answeredAt: { type: 'string', format: 'date-time', nullable: true }
+          }
+        },
+        Session: {
+          type: 'object',
+          required: ['id', 'page', 'agent', 'openedAt', 'closedAt', 'jobs'],
+          properties: {
+            id: { type: 'string' },
+            page: { type: 'string' },
+            agent: { type: 'string' },
+            openedAt: { type: 'string', format: 'date-time' },
+            closedAt: { type: 'string', format: 'date-time', nullable: true },
+            jobs: {
+              type: 'array',
+              items: {
+                type: 'object',
+                properties: {
+                  id: { type: 'string' },
+                  page: { type: 'string' },
+                  agent: { type: 'string' },
+                  requestedAt: { type: 'string', format: 'date-time' },
+                  code: { type: 'string' },
+                  language: { type: 'string', enum: ['js', 'ts'] }
+                }
+              }
+            }
+          }
+        },
+        Transcript: {
+          type: 'object',
+          properties: {
+            session: { type: 'string' },
+            page: { type: 'string' },
+            agent: { type: 'string' },
+            openedAt: { type: 'string', format: 'date-time' },
+            closedAt: { type: 'string', format: 'date-time', nullable: true },
+            exchanges: {
+              type: 'array',
+              items: {
+                type: 'object',
+                properties: {
+                  job: { type: 'string' },
+                  requestedAt: { type: 'string', format: 'date-time' },
+                  language: { type: 'string', enum: ['js', 'ts'] },
+                  code: { type: 'string' },
+                  status: { type: 'string', enum: ['ok', 'error', 'skipped', 'pending', 'expired'], description: 'expired: finished, but its result is past the retention window' },
+                  value: {},
+                  error: {},
+                  logs: { type: 'array', items: { type: 'object' } },
+                  durationMs: { type: 'number' }
+                }
+              }
+            }
+          }
+        },
+        Job: {
+          type: 'object',
+          required: ['id', 'page', 'agent', 'code', 'language', 'requestedAt'],
+          properties: {
+            id: { type: 'string' },
+            page: { type: 'string', description: 'Page name' },
+            agent: { type: 'string' },
+            code: { type: 'string' },
+            language: { type: 'string', enum: ['js', 'ts'] },
+            context: { type: 'string', enum: ['page', 'worker'], description: 'Realm that runs the job' },
+            prelude: { type: 'string' },
+            session: { type: 'string', description: 'Session the job was submitted in' },
+            requestHasFooter: { type: 'boolean' },
+            requestedAt: { type: 'string', format: 'date-time' },
+            startedAt: { type: 'string', format: 'date-time', nullable: true },
+            finishedAt: { type: 'string', format: 'date-time', nullable: true },
+            cancelRequestedAt: { type: 'string', format: 'date-time' }
+          }
+        },
+        ResultPayload: {
+          type: 'object',
+          properties: {
+            ok: { type: 'boolean' },
+            value: {},
+            valueType: { type: 'string', description: 'JS type of the value, such as `undefined` or `function`, which JSON cannot carry; named in the reply' },
+            error: { type: 'string' },
+            cancelled: { type: 'boolean' },
+            skipped: { type: 'boolean', description: 'The job\'s `when` predicate did not hold, so its code never ran' },
+            jobId: { type: 'string' },
+            type: { type: 'string', enum: ['worker-timeout', 'worker-init', 'background-flush'], description: 'Set for diagnostics instead of job results' },
+            logs: {
+              type: 'array',
+              description: 'Console output of the job, separate from its value',
+              items: { type: 'object', properties: { level: { type: 'string' }, message: { type: 'string' }, eventAt: { type: 'string', format: 'date-time' } } }
+            },
+            backgroundEvents: { type: 'array', items: { type: 'object' } },
+            attachments: {
+              type: 'array',
+              description: 'Files saved beside the log and linked from the reply, unless the server refuses their content type',
+              items: {
+                type: 'object',
+                required: ['type', 'data'],
+                properties: { name: { type: 'string' }, type: { type: 'string' }, data: { type: 'string', format: 'byte' } }
+              }
+            }
+          }
+        },
+        Diagnosis: {
+          type: 'object',
+          properties: {
+            page: {
+              type: 'object',
+              properties: { name: { type: 'string' }, state: { type: 'string' }, stateSince: { type: 'number' }, lastSeen: { type: 'number' } }
+            },
+            job: {
+              type: 'object',
+              nullable: true,
+              properties: { id: { type: 'string' }, state: { type: 'string', enum: ['queued', 'running'] } }
+            },
+            request: {
+              type: 'object',
+              nullable: true,
+              description: 'Last request found in the log',
+              properties: { agent: { type: 'string' }, target: { type: 'string' }, time: { type: 'string' }, thread: { type: 'string' } }
+            },
+            empty: { type: 'boolean', description: 'The log holds nothing but whitespace' },
+            answered: { type: 'boolean' },
+            language: { type: 'string', nullable: true, description: 'Tag of the code block the request would run' },
+            recognized: { type: 'boolean' },
+            pending: { type: 'boolean', description: 'The log holds a request the server would run' },
+            dispatchable: { type: 'boolean', description: 'That request would be dispatched now' },
+            reasons: { type: 'array', items: { type: 'string' } }
+          }
+        },
+        DocumentSummary: {
+          type: 'object',
+          properties: {
+            name: { type: 'string' },
+            headings: { type: 'object', properties: { total: { type: 'integer' }, byLevel: { type: 'object', additionalProperties: { type: 'integer' } } } },
+            codeBlocks: { type: 'object', properties: { total: { type: 'integer' }, byLanguage: { type: 'object', additionalProperties: { type: 'integer' } } } },
+            requests: { type: 'integer' },
+            replies: { type: 'integer' },
+            pairs: { type: 'integer' }
+          }
+        },
+        StateSnapshot: {
+          type: 'object',
+          required: ['version', 'pages', 'jobs'],
+          properties: {
+            version: { type: 'integer', enum: [1] },
+            takenAt: { type: 'string', format: 'date-time' },
+            pages: { type: 'array', items: ref('Page') },
+            jobs: {
+              type: 'object',
+              properties: {
+                nextId: { type: 'integer' },
+                jobs: { type: 'array', items: ref('Job') },
+                queues: {
+                  type: 'object',
+                  description: 'Ids of each realm\'s waiting jobs in dispatch order, by realm name',
+                  additionalProperties: { type: 'array', items: { type: 'string' } }
+                }
+              }
+            }
+          }
+        },
+        StateDiff: {
+          type: 'object',
+          properties: {
+            pages: {
+              type: 'object',
+              properties: {
+                added: { type: 'array', items: { type: 'string' } },
+                removed: { type: 'array', items: { type: 'string' } },
+                changed: { type: 'array', items: { type: 'object', properties: { name: { type: 'string' }, from: { type: 'string' }, to: { type: 'string' } } } }
+              }
+            },
+            jobs: {
+              type: 'object',
+              properties: {
+                added: { type: 'array', items: { type: 'object', properties: { id: { type: 'string' }, page: { type: 'string' } } } },
+                terminated: { type: 'array', items: { type: 'object', properties: { id: { type: 'string' }, page: { type: 'string' } } } },
+                changed: {
+                  type: 'array',
+                  items: {
+                    type: 'object',
+                    properties: { id: { type: 'string' }, page: { type: 'string' }, from: { type: 'string', enum: ['queued', 'running', 'finished'] }, to: { type: 'string', enum: ['queued', 'running', 'finished'] } }
+                  }
+                }
+              }
+            }
+          }
+        }
+      }
+    }
+  };
+}
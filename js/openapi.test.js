@@ -0,0 +1,20 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { openApiDocument } from './openapi.js';
+
+test('openApiDocument is valid JSON listing the core routes', () => {
+  const doc = JSON.parse(JSON.stringify(openApiDocument('0.0.0')));
+  assert.strictEqual(doc.openapi, '3.0.3');
+  for (const route of ['/-daebug-channel', '/-daebug-cancel', '/-daebug-page/{name}/ast', '/-daebug-snapshot'])
+    assert.ok(doc.paths[route], `missing ${route}`);
+  assert.ok(doc.paths['/-daebug-channel'].get);
+  assert.ok(doc.paths['/-daebug-channel'].post);
+});
+
+test('openApiDocument references only defined schemas', () => {
+  const doc = openApiDocument('0.0.0');
+  const refs = JSON.stringify(doc).match(/#\/components\/schemas\/\w+/g) || [];
+  for (const ref of refs)
+    assert.ok(doc.components.schemas[/** @type {keyof typeof doc.components.schemas} */(ref.split('/').pop())], `dangling ${ref}`);
+});
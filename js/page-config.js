@@ -0,0 +1,70 @@
+// @ts-check
+import { readFileSync } from 'node:fs';
+
+/**
+ * Settings one page may override next to its log in `daebug/<name>.config.json`
+ * @typedef {{
+ *   timeoutMs?: number,
+ *   order?: 'append' | 'newest-first',
+ *   rotateBytes?: number,
+ *   theme?: Partial<import('./theme.js').Theme>
+ * }} PageConfig
+ */
+
+/** @type {Map<string, PageConfig>} */
+const cache = new Map();
+
+/**
+ * Config file that sits beside a page log: `daebug/page.md` → `daebug/page.config.json`
+ * @param {string} pageFile
+ */
+export const configFileFor = pageFile => pageFile.replace(/(\.md)?$/, '.config.json');
+
+/**
+ * Keep only the recognised, well-typed settings
+ * @param {any} raw
+ * @returns {PageConfig}
+ */
+function sanitize(raw) {
+  /** @type {PageConfig} */
+  const config = {};
+  if (!raw || typeof raw !== 'object') return config;
+  if (typeof raw.timeoutMs === 'number' && raw.timeoutMs > 0) config.timeoutMs = raw.timeoutMs;
+  if (raw.order === 'append' || raw.order === 'newest-first') config.order = raw.order;
+  if (typeof raw.rotateBytes === 'number' && raw.rotateBytes >= 0) config.rotateBytes = raw.rotateBytes;
+  if (raw.theme && typeof raw.theme === 'object') {
+    config.theme = Object.fromEntries(Object.entries(raw.theme).filter(([, glyph]) => typeof glyph === 'string'));
+  }
+  return config;
+}
+
+/**
+ * Overrides for a page, read once and cached until the watcher sees the file change.
+ * A missing or malformed file means no overrides.
+ * @param {{ name: string, file: string }} page
+ * @returns {PageConfig}
+ */
+export function forPage(page) {
+  const file = configFileFor(page.file);
+  const cached = cache.get(file);
+  if (cached) return cached;
+
+  /** @type {PageConfig} */
+  let config = {};
+  try {
+    config = sanitize(JSON.parse(readFileSync(file, 'utf8')));
+  } catch (err) {
+    if (!(err && typeof err === 'object' && 'code' in err && err.code === 'ENOENT'))
+      console.warn(`[${page.name}] ignoring ${file}:`, err instanceof Error ? err.message : err);
+  }
+  cache.set(file, config);
+  return config;
+}
+
+/**
+ * Forget the cached config of a page so the next lookup rereads it
+ * @param {string} pageFile
+ */
+export function invalidate(pageFile) {
+  cache.delete(configFileFor(pageFile));
+}
@@ -0,0 +1,41 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { mkdtempSync, rmSync, writeFileSync, mkdirSync } from 'node:fs';
+import { tmpdir } from 'node:os';
+import { join } from 'node:path';
+import * as pageConfig from './page-config.js';
+
+test('forPage reads recognised settings and caches them until invalidated', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = { name: 'cfg', file: join(root, 'daebug', 'cfg.md') };
+    const configFile = join(root, 'daebug', 'cfg.config.json');
+    assert.strictEqual(pageConfig.configFileFor(page.file), configFile);
+
+    writeFileSync(configFile, JSON.stringify({ timeoutMs: 500, order: 'newest-first', bogus: 1, theme: { success: 'OK ' } }));
+    assert.deepStrictEqual(pageConfig.forPage(page), { timeoutMs: 500, order: 'newest-first', theme: { success: 'OK ' } });
+
+    writeFileSync(configFile, JSON.stringify({ timeoutMs: 900 }));
+    assert.strictEqual(pageConfig.forPage(page).timeoutMs, 500);
+    pageConfig.invalidate(page.file);
+    assert.deepStrictEqual(pageConfig.forPage(page), { timeoutMs: 900 });
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('forPage treats a missing or malformed file as no overrides', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const missing = { name: 'none', file: join(root, 'none.md') };
+    assert.deepStrictEqual(pageConfig.forPage(missing), {});
+
+    const broken = { name: 'broken', file: join(root, 'broken.md') };
+    writeFileSync(join(root, 'broken.config.json'), '{ not json');
+    assert.deepStrictEqual(pageConfig.forPage(broken), {});
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
@@ -0,0 +1,36 @@
+// @ts-check
+
+/**
+ * Work holding each page, by page name: the promise that settles once the last task queued
+ * for the page has. Only asynchronous tasks hold a page; synchronous work runs to completion
+ * without yielding, so nothing can interleave with it anyway.
+ * @type {Map<string, Promise<unknown>>}
+ */
+const held = new Map();
+
+/**
+ * Run a task that reads or changes a page's log or job once no earlier task for the page is
+ * still in flight. Dispatch, reindexing, synchronous runs and eviction all go through here,
+ * so a reset never lands between a check reading the log and acting on what it read.
+ * A free page runs the task at once, synchronously; a busy one queues it behind the others.
+ * @template T
+ * @param {string} name Page name
+ * @param {() => T | Promise<T>} task
+ * @returns {T | Promise<T>}
+ */
+export function withPage(name, task) {
+  const before = held.get(name);
+  /** @type {T | Promise<T>} */
+  const result = before ? before.then(task) : task();
+  if (!(result instanceof Promise)) return result;
+  const settled = result.then(() => { }, () => { });
+  held.set(name, settled);
+  settled.then(() => { if (held.get(name) === settled) held.delete(name); });
+  return result;
+}
+
+/**
+ * Whether a task for the page is in flight or waiting
+ * @param {string} name
+ */
+export const isHeld = name => held.has(name);
@@ -0,0 +1,36 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { withPage, isHeld } from './page-lock.js';
+
+const tick = () => new Promise(resolve => setTimeout(resolve, 5));
+
+test('a free page runs its task at once and synchronously', () => {
+  let ran = false;
+  assert.equal(withPage('free-page', () => { ran = true; return 7; }), 7);
+  assert.equal(ran, true);
+  assert.equal(isHeld('free-page'), false);
+});
+
+test('tasks for one page run one after another, in the order queued', async () => {
+  /** @type {string[]} */
+  const steps = [];
+  const first = withPage('busy-page', async () => { steps.push('first start'); await tick(); steps.push('first end'); });
+  const second = withPage('busy-page', () => { steps.push('second'); });
+  assert.equal(isHeld('busy-page'), true);
+  await Promise.all([first, second]);
+  assert.deepEqual(steps, ['first start', 'first end', 'second']);
+  await tick();
+  assert.equal(isHeld('busy-page'), false);
+});
+
+test('other pages are not held up, and a failed task releases the page', async () => {
+  /** @type {string[]} */
+  const steps = [];
+  const failing = withPage('page-a', async () => { await tick(); throw new Error('boom'); });
+  withPage('page-b', () => { steps.push('b'); });
+  assert.deepEqual(steps, ['b']);
+  await assert.rejects(failing, /boom/);
+  await withPage('page-a', () => { steps.push('a'); });
+  assert.deepEqual(steps, ['b', 'a']);
+});
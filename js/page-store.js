@@ -0,0 +1,34 @@
+// @ts-check
+
+/**
+ * Where the registry keeps its pages. Pages are live objects that other modules update
+ * in place (state, lastSeen); the registry calls `update` after its own changes so a
+ * store backed by a file or a database can write them through.
+ * @typedef {{
+ *   get: (name: string) => import('./registry.js').Page | undefined,
+ *   insert: (page: import('./registry.js').Page) => void,
+ *   update: (page: import('./registry.js').Page) => void,
+ *   remove: (name: string) => boolean,
+ *   list: () => import('./registry.js').Page[],
+ *   size: () => number,
+ *   clear: () => void
+ * }} PageStore
+ */
+
+/**
+ * Pages held in a Map for the lifetime of the process
+ * @returns {PageStore}
+ */
+export function createMemoryPageStore() {
+  /** @type {Map<string, import('./registry.js').Page>} */
+  const pages = new Map();
+  return {
+    get: name => pages.get(name),
+    insert: page => { pages.set(page.name, page); },
+    update: page => { pages.set(page.name, page); },
+    remove: name => pages.delete(name),
+    list: () => Array.from(pages.values()),
+    size: () => pages.size,
+    clear: () => pages.clear()
+  };
+}
@@ -0,0 +1,27 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { createMemoryPageStore } from './page-store.js';
+
+/** @param {string} name @returns {import('./registry.js').Page} */
+const makePage = name => ({ name, url: 'http://localhost', file: `/daebug/${name}.md`, state: 'idle', lastSeen: 0, capabilities: ['js'] });
+
+test('memory page store supports insert, get, update, list and remove', () => {
+  const store = createMemoryPageStore();
+  const a = makePage('a');
+  store.insert(a);
+  store.insert(makePage('b'));
+  assert.strictEqual(store.get('a'), a);
+  assert.strictEqual(store.size(), 2);
+  
+  a.state = 'executing';
+  store.update(a);
+  assert.strictEqual(store.get('a')?.state, 'executing');
+  assert.deepStrictEqual(store.list().map(p => p.name), ['a', 'b']);
+  
+  assert.strictEqual(store.remove('a'), true);
+  assert.strictEqual(store.remove('a'), false);
+  assert.strictEqual(store.get('a'), undefined);
+  store.clear();
+  assert.strictEqual(store.size(), 0);
+});
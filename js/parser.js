@@ -1,6 +1,7 @@
 // @ts-check
 
-import { parseRequest as parseRequestImpl } from './repl.template.js';
+import { parseRequest as parseRequestImpl, findFooter, stripBom } from './repl.template.js';
+import { getTheme, agentMarkerPattern, replyMarkerPattern } from './theme.js';
 
 /**
  * @typedef {{
@@ -8,6 +9,10 @@ import { parseRequest as parseRequestImpl } from './repl.template.js';
  *   target: string,
  *   time: string,
  *   code: string,
+ *   language: 'js' | 'ts',
+ *   context?: 'worker',
+ *   thread?: string,
+ *   options?: Record<string, string | number | boolean>,
  *   hasFooter: boolean
  * }} Request
  */
@@ -21,3 +26,382 @@ import { parseRequest as parseRequestImpl } from './repl.template.js';
 export function parseRequest(text, pageName) {
   return parseRequestImpl(text, pageName);
 }
+
+/**
+ * Whether the text ends inside a code fence that was opened and never closed, as a file
+ * caught halfway through being saved does
+ * @param {string} text
+ */
+export function endsInOpenFence(text) {
+  let fence = '';
+  for (const line of stripBom(text).split(/\r?\n/)) {
+    const trimmed = line.trim();
+    if (fence) {
+      if (trimmed === fence) fence = '';
+      continue;
+    }
+    const open = /^`{3,}/.exec(trimmed);
+    if (open) fence = open[0];
+  }
+  return !!fence;
+}
+
+/**
+ * @typedef {{
+ *   headings: { total: number, byLevel: Record<number, number> },
+ *   codeBlocks: { total: number, byLanguage: Record<string, number> },
+ *   requests: number,
+ *   replies: number,
+ *   pairs: number
+ * }} DocumentSummary
+ */
+
+/**
+ * Summarize the markdown structure of a page log: headings, fenced blocks and exchanges.
+ * Fenced content is skipped so code that looks like markdown is not counted.
+ * @param {string} text
+ * @returns {DocumentSummary}
+ */
+export function summarizeDocument(text) {
+  text = stripBom(text);
+  /** @type {DocumentSummary} */
+  const summary = {
+    headings: { total: 0, byLevel: {} },
+    codeBlocks: { total: 0, byLanguage: {} },
+    requests: 0,
+    replies: 0,
+    pairs: 0
+  };
+
+  let fence = '';
+  let awaitingReply = false;
+  for (const line of text.split(/\r?\n/)) {
+    const trimmed = line.trim();
+    if (fence) {
+      if (trimmed.startsWith(fence) && !trimmed.slice(fence.length).trim()) fence = '';
+      continue;
+    }
+
+    const open = /^(`{3,}|~{3,})\s*([^\s`]*)/.exec(trimmed);
+    if (open) {
+      fence = open[1];
+      const language = open[2].toLowerCase() || 'none';
+      summary.codeBlocks.total++;
+      summary.codeBlocks.byLanguage[language] = (summary.codeBlocks.byLanguage[language] || 0) + 1;
+      continue;
+    }
+
+    const heading = /^(#{1,6})\s/.exec(trimmed);
+    if (!heading) continue;
+    const level = heading[1].length;
+    summary.headings.total++;
+    summary.headings.byLevel[level] = (summary.headings.byLevel[level] || 0) + 1;
+
+    if (/^###\s*🗣️/.test(trimmed)) {
+      summary.requests++;
+      awaitingReply = true;
+    } else if (/^####\s*(👍|🚫)/.test(trimmed)) {
+      summary.replies++;
+      if (awaitingReply) summary.pairs++;
+      awaitingReply = false;
+    }
+  }
+
+  return summary;
+}
+
+/**
+ * @typedef {{
+ *   agent: string,
+ *   target: string,
+ *   time: string,
+ *   code: string,
+ *   language: 'js' | 'ts',
+ *   thread?: string,
+ *   line: number
+ * }} ExchangeRequest
+ */
+
+/**
+ * @typedef {{
+ *   ok: boolean,
+ *   page: string,
+ *   agent: string,
+ *   time: string,
+ *   duration: string,
+ *   body: string,
+ *   thread?: string,
+ *   line: number
+ * }} ExchangeReply
+ */
+
+/**
+ * @typedef {{
+ *   request: ExchangeRequest,
+ *   reply: ExchangeReply | null
+ * }} Exchange
+ */
+
+/**
+ * @typedef {{
+ *   title: string,
+ *   empty: boolean,
+ *   exchanges: Exchange[],
+ *   pending: Request | null
+ * }} Document
+ */
+
+/**
+ * Whether a page log has nothing in it but whitespace, as opposed to content with no request to act on
+ * @param {string} text
+ */
+export const isBlankDocument = text => !text.trim();
+
+/** A request whose code was moved to a sidecar file: `[code](code/12-ab12cd34.js)` */
+const CODE_LINK = /^\[code\]\(([^)\s]+\.([jt]s))\)$/i;
+
+/**
+ * Parse a page log into its exchanges: each request heading with its code, paired with the
+ * reply that follows it. A reply carrying a thread id (`#abc123`) pairs with the request of
+ * the same id wherever it sits; `pending` is the unanswered request `parseRequest` would dispatch.
+ * Code linked to a sidecar file is read back through `resolveCode` when given.
+ * @param {string} text
+ * @param {string} pageName
+ * @param {(href: string) => string | undefined} [resolveCode]
+ * @returns {Document}
+ */
+export function parseDocument(text, pageName, resolveCode) {
+  const lines = stripBom(text).split(/\r?\n/);
+  const footerIdx = findFooter(lines);
+  const end = footerIdx >= 0 ? footerIdx : lines.length;
+  const failure = getTheme().failure.trim();
+  const requestRe = new RegExp('^###\\s*' + agentMarkerPattern() + '\\s*(\\S+)\\s+to\\s+(\\S+)\\s+at\\s+(\\d{2}:\\d{2}:\\d{2})(?:\\s+#(\\w+))?');
+  const replyRe = new RegExp('^####\\s*(' + replyMarkerPattern() + ')\\s*(\\S+)\\s+to\\s+(\\S+)\\s+at\\s+(\\d{2}:\\d{2}:\\d{2})(?:\\s+\\(([^)]*)\\))?(?:\\s+#(\\w+))?');
+
+  /** @type {Document} */
+  const doc = { title: '', empty: isBlankDocument(text), exchanges: [], pending: parseRequest(text, pageName) };
+  /** @type {Exchange | null} */
+  let current = null;
+  /** @type {{ reply: ExchangeReply, body: string[] } | null} */
+  let replyBody = null;
+
+  const closeReply = () => {
+    if (replyBody) replyBody.reply.body = replyBody.body.join('\n').trim();
+    replyBody = null;
+  };
+
+  for (let i = 0; i < end; i++) {
+    const line = lines[i];
+
+    const fence = /^(`{3,})\s*([^\s`]*)/.exec(line.trim());
+    if (fence) {
+      let close = i + 1;
+      while (close < end && lines[close].trim() !== fence[1]) close++;
+      if (replyBody) replyBody.body.push(...lines.slice(i, close + 1));
+      else if (current && !current.reply && !current.request.code) {
+        current.request.code = lines.slice(i + 1, close).join('\n');
+        current.request.language = /^t/i.test(fence[2]) ? 'ts' : 'js';
+      }
+      i = close;
+      continue;
+    }
+
+    const link = current && !current.reply && !current.request.code && resolveCode ? CODE_LINK.exec(line.trim()) : null;
+    if (link && current) {
+      current.request.code = resolveCode?.(link[1]) ?? '';
+      current.request.language = /^t/i.test(link[2]) ? 'ts' : 'js';
+      continue;
+    }
+
+    if (!doc.title && /^# /.test(line)) {
+      doc.title = line.slice(2).trim();
+      continue;
+    }
+
+    const request = requestRe.exec(line.trim());
+    if (request) {
+      closeReply();
+      current = {
+        request: {
+          agent: request[1], target: request[2], time: request[3], code: '', language: 'js',
+          ...(request[4] && { thread: request[4] }),
+          line: i
+        },
+        reply: null
+      };
+      doc.exchanges.push(current);
+      continue;
+    }
+
+    const reply = replyRe.exec(line.trim());
+    const owner = reply?.[6]
+      ? doc.exchanges.find(x => x.request.thread === reply[6] && !x.reply)
+      : current && !current.reply ? current : undefined;
+    if (reply && owner) {
+      closeReply();
+      owner.reply = {
+        ok: reply[1] !== failure,
+        page: reply[2],
+        agent: reply[3],
+        time: reply[4],
+        duration: reply[5] || '',
+        body: '',
+        ...(reply[6] && { thread: reply[6] }),
+        line: i
+      };
+      replyBody = { reply: owner.reply, body: [] };
+      continue;
+    }
+
+    if (/^#{1,4}\s/.test(line)) closeReply();
+    else if (replyBody) replyBody.body.push(line);
+  }
+  closeReply();
+
+  return doc;
+}
+
+/**
+ * Every request in a page log that has no reply yet, in document order: unanswered
+ * exchanges above the footer, then the fresh request below it
+ * @param {string} text
+ * @param {string} pageName
+ * @param {(href: string) => string | undefined} [resolveCode]
+ * @returns {Request[]}
+ */
+export function parsePendingRequests(text, pageName, resolveCode) {
+  const doc = parseDocument(text, pageName, resolveCode);
+  /** @type {Request[]} */
+  const pending = doc.exchanges
+    .filter(exchange => !exchange.reply && exchange.request.code)
+    .map(({ request: { line, ...request } }) => ({ ...request, hasFooter: false }));
+  if (doc.pending?.hasFooter) pending.push(doc.pending);
+  return pending;
+}
+
+/** Heading that opens a page's section of a combined log, with the page name as its first group */
+export const PAGE_SECTION = /^##\s+page:\s*(\S+)\s*$/i;
+
+/**
+ * Split a log that keeps several pages' exchanges in one file into each page's section.
+ * A section runs from its heading to the next; headings inside fenced code don't count,
+ * text above the first one belongs to no page, and a page with several sections gets them
+ * joined in document order.
+ * @param {string} text
+ * @param {RegExp} [heading] Matches a section heading line, capturing the page name
+ * @returns {Map<string, string>} Page name to its section text, in order of first appearance
+ */
+export function splitPages(text, heading = PAGE_SECTION) {
+  /** @type {Map<string, string[]>} */
+  const sections = new Map();
+  /** @type {string[] | null} */
+  let current = null;
+  let fence = '';
+  for (const line of stripBom(text).split(/\r?\n/)) {
+    const trimmed = line.trim();
+    if (fence) {
+      if (trimmed.startsWith(fence) && !trimmed.slice(fence.length).trim()) fence = '';
+    } else {
+      const open = /^(`{3,}|~{3,})/.exec(trimmed);
+      if (open) fence = open[1];
+      const section = open ? null : heading.exec(trimmed);
+      if (section) {
+        current = sections.get(section[1]) || [];
+        sections.set(section[1], current);
+        continue;
+      }
+    }
+    current?.push(line);
+  }
+  return new Map(Array.from(sections, ([name, lines]) => [name, lines.join('\n')]));
+}
+
+/**
+ * The requests waiting in each page's section of a combined log, as `parsePendingRequests`
+ * finds them in a page's own log
+ * @param {string} text
+ * @param {RegExp} [heading] Matches a section heading line, capturing the page name
+ * @param {(href: string) => string | undefined} [resolveCode]
+ * @returns {Map<string, Request[]>}
+ */
+export function parseCombinedDocument(text, heading = PAGE_SECTION, resolveCode) {
+  return new Map(Array.from(splitPages(text, heading), ([name, section]) => [name, parsePendingRequests(section, name, resolveCode)]));
+}
+
+/** Fence tags `parseRequest` accepts below the footer, and in a log without one */
+const FOOTER_LANGUAGES = ['', 'js', 'javascript', 'ts', 'typescript'];
+const BARE_LANGUAGES = ['', 'js', 'javascript'];
+
+/**
+ * @typedef {{
+ *   request: { agent: string, target: string, time: string, thread?: string } | null,
+ *   empty: boolean,
+ *   answered: boolean,
+ *   language: string | null,
+ *   recognized: boolean,
+ *   pending: Request | null,
+ *   reasons: string[]
+ * }} Diagnosis
+ */
+
+/**
+ * Explain what the server makes of a page log: the last request in it, whether it has been
+ * answered, and the language of the code block it would run, with a plain reason for each
+ * thing that stops `parseRequest` from returning a request.
+ * @param {string} text
+ * @param {string} pageName
+ * @returns {Diagnosis}
+ */
+export function diagnoseDocument(text, pageName) {
+  text = stripBom(text);
+  const doc = parseDocument(text, pageName);
+  const lines = text.split('\n');
+  let footer = -1;
+  for (let i = lines.length - 1; i >= 0 && footer < 0; i--)
+    if (lines[i].startsWith('> Append your JavaScript snippet below')) footer = i;
+
+  // Below the footer is a fresh request; without one the last exchange is the one in question
+  const last = doc.exchanges[doc.exchanges.length - 1];
+  const chunk = footer >= 0 ? lines.slice(footer + 1)
+    : last ? lines.slice(last.request.line + 1, last.reply ? last.reply.line : undefined)
+      : lines;
+  const requestRe = new RegExp('^###\\s*' + agentMarkerPattern() + '\\s*(\\S+)\\s+to\\s+(\\S+)\\s+at\\s+(\\d{2}:\\d{2}:\\d{2})(?:\\s+#(\\w+))?');
+  const header = footer >= 0 ? requestRe.exec(chunk.find(line => line.trim())?.trim() || '') : null;
+  const request = doc.pending
+    ? { agent: doc.pending.agent, target: doc.pending.target, time: doc.pending.time, ...(doc.pending.thread && { thread: doc.pending.thread }) }
+    : header
+      ? { agent: header[1], target: header[2], time: header[3], ...(header[4] && { thread: header[4] }) }
+      : last
+        ? { agent: last.request.agent, target: last.request.target, time: last.request.time, ...(last.request.thread && { thread: last.request.thread }) }
+        : null;
+
+  /** @type {string | null} */
+  let language = null;
+  let fence = '';
+  for (const line of chunk) {
+    const trimmed = line.trim();
+    if (fence) {
+      if (trimmed === fence) fence = '';
+      continue;
+    }
+    const open = /^(`{3,})\s*([^\s`]*)/.exec(trimmed);
+    if (!open) continue;
+    fence = open[1];
+    language = open[2].toLowerCase();
+  }
+  const recognized = language !== null && (footer >= 0 ? FOOTER_LANGUAGES : BARE_LANGUAGES).includes(language);
+  const answered = !doc.pending && !header && !!last?.reply;
+
+  /** @type {string[]} */
+  const reasons = [];
+  if (doc.empty) reasons.push('the log is empty');
+  else if (!doc.pending) {
+    if (!request) reasons.push('no request found in the log');
+    else if (answered) reasons.push(`request from ${request.agent} at ${request.time} already has a reply`);
+    if (!answered && language === null) reasons.push(footer >= 0 ? 'no code block below the footer' : 'no code block found');
+    else if (!answered && !recognized) reasons.push(`code block language '${language}' is not recognized`);
+    else if (!answered) reasons.push('code block is empty or starts with a reply header');
+  }
+
+  return { request, empty: doc.empty, answered, language, recognized, pending: doc.pending, reasons };
+}
@@ -1,7 +1,7 @@
 // @ts-check
 import { test } from 'node:test';
 import assert from 'node:assert/strict';
-import { parseRequest } from './parser.js';
+import { parseRequest, summarizeDocument, parseDocument, parsePendingRequests, diagnoseDocument, splitPages, parseCombinedDocument } from './parser.js';
 
 test('parseRequest extracts code from footer-based request with agent header', () => {
   const input = [
@@ -88,6 +88,7 @@ test('parseRequest returns complete object for footer-based request', () => {
     target: 'page-1',
     time: '09:15:30',
     code: '1 + 1',
+    language: 'js',
     hasFooter: true
   });
 });
@@ -338,6 +339,7 @@ test('parseRequest handles no-footer scenario with valid code', () => {
     target: 'test-page',
     time: '',
     code: '2+3',
+    language: 'js',
     hasFooter: false
   });
 });
@@ -564,6 +566,7 @@ test('parseRequest handles complete session with footer', () => {
     target: 'test-page',
     time: '10:01:00',
     code: 'Math.sqrt(16)',
+    language: 'js',
     hasFooter: true
   });
 });
@@ -595,6 +598,7 @@ test('parseRequest handles complete session without footer after response deleti
     target: 'test-page',
     time: '',
     code: '3+3',
+    language: 'js',
     hasFooter: false
   });
 });
@@ -670,6 +674,7 @@ test('parseRequest returns complete object for footer-based request', () => {
     target: 'page-1',
     time: '09:15:30',
     code: '1 + 1',
+    language: 'js',
     hasFooter: true
   });
 });
@@ -920,6 +925,7 @@ test('parseRequest handles no-footer scenario with valid code', () => {
     target: 'test-page',
     time: '',
     code: '2+3',
+    language: 'js',
     hasFooter: false
   });
 });
@@ -1146,6 +1152,7 @@ test('parseRequest handles complete session with footer', () => {
     target: 'test-page',
     time: '10:01:00',
     code: 'Math.sqrt(16)',
+    language: 'js',
     hasFooter: true
   });
 });
@@ -1177,6 +1184,358 @@ test('parseRequest handles complete session without footer after response deleti
     target: 'test-page',
     time: '',
     code: '3+3',
+    language: 'js',
     hasFooter: false
   });
 });
+
+test('parseRequest reports js language for js fences', () => {
+  const input = [
+    '> Append your JavaScript snippet below',
+    '```js',
+    'x',
+    '```'
+  ].join('\n');
+  
+  const result = parseRequest(input, 'test-page');
+  
+  assert.strictEqual(result?.language, 'js');
+});
+
+test('parseRequest reports ts language for typescript fences', () => {
+  const input = [
+    '> Append your JavaScript snippet below',
+    '```typescript',
+    'const x: number = 1',
+    '```'
+  ].join('\n');
+  
+  const result = parseRequest(input, 'test-page');
+  
+  assert.strictEqual(result?.language, 'ts');
+  assert.strictEqual(result?.code, 'const x: number = 1');
+});
+
+test('parseRequest reports ts language for a fenced block without a footer', () => {
+  const input = [
+    '# test-page',
+    'Type this in:',
+    '```ts',
+    'const x: number = 1',
+    '```'
+  ].join('\n');
+  
+  const result = parseRequest(input, 'test-page');
+  
+  assert.strictEqual(result?.language, 'ts');
+  assert.strictEqual(result?.code, 'const x: number = 1');
+  assert.strictEqual(result?.hasFooter, false);
+});
+
+test('parseRequest without a footer finds no request when the last block is in another language', () => {
+  const input = [
+    '```js',
+    'x',
+    '```',
+    '',
+    '```python',
+    'print(1)',
+    '```'
+  ].join('\n');
+  
+  assert.strictEqual(parseRequest(input, 'test-page'), null);
+});
+
+test('summarizeDocument counts headings, code blocks and exchanges', () => {
+  const input = [
+    '# 👾 page',
+    '',
+    '### 🗣️agent to page at 10:00:00',
+    '```JS',
+    '1 + 1',
+    '```',
+    '#### 👍page to agent at 10:00:01 (5ms)',
+    '```JSON',
+    '2',
+    '```',
+    '### 🗣️agent to page at 10:01:00',
+    '```ts',
+    '# not a heading',
+    '```',
+    '#### 🚫page to agent at 10:01:01 (3ms)',
+    '```Error',
+    'boom',
+    '```',
+    '### 🗣️agent to page at 10:02:00',
+    '```',
+    'pending()',
+    '```'
+  ].join('\n');
+
+  assert.deepStrictEqual(summarizeDocument(input), {
+    headings: { total: 6, byLevel: { 1: 1, 3: 3, 4: 2 } },
+    codeBlocks: { total: 5, byLanguage: { js: 1, json: 1, ts: 1, error: 1, none: 1 } },
+    requests: 3,
+    replies: 2,
+    pairs: 2
+  });
+});
+
+test('parseDocument pairs requests with their replies and reports the pending request', () => {
+  const input = [
+    '# page Session',
+    '',
+    '### 🗣️alice to page at 10:00:00',
+    '```JS',
+    '1 + 1',
+    '```',
+    '',
+    '#### 👍page to alice at 10:00:01 (5ms)',
+    '```JSON',
+    '2',
+    '```',
+    '',
+    '### 🗣️bob to page at 10:01:00',
+    '```ts',
+    'throw new Error("x")',
+    '```',
+    '#### 🚫page to bob at 10:01:01 (3ms)',
+    '```Error',
+    'x',
+    '```',
+    '',
+    '### 🗣️alice to page at 10:02:00',
+    '```js',
+    'later()',
+    '```',
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    '```js',
+    'pending()',
+    '```'
+  ].join('\n');
+
+  const doc = parseDocument(input, 'page');
+  assert.strictEqual(doc.title, 'page Session');
+  assert.strictEqual(doc.exchanges.length, 3);
+
+  const [first, second, third] = doc.exchanges;
+  assert.deepStrictEqual(first.request, { agent: 'alice', target: 'page', time: '10:00:00', code: '1 + 1', language: 'js', line: 2 });
+  assert.strictEqual(first.reply?.ok, true);
+  assert.strictEqual(first.reply?.duration, '5ms');
+  assert.strictEqual(first.reply?.body, '```JSON\n2\n```');
+
+  assert.strictEqual(second.request.agent, 'bob');
+  assert.strictEqual(second.request.language, 'ts');
+  assert.strictEqual(second.reply?.ok, false);
+  assert.strictEqual(second.reply?.body, '```Error\nx\n```');
+
+  assert.strictEqual(third.request.code, 'later()');
+  assert.strictEqual(third.reply, null);
+
+  assert.strictEqual(doc.pending?.code, 'pending()');
+});
+
+test('a leading byte order mark does not hide the title or the first request', () => {
+  const input = [
+    '\uFEFF# page Session',
+    '### 🗣️alice to page at 10:00:00',
+    '```js',
+    '1 + 1',
+    '```'
+  ].join('\n');
+
+  const doc = parseDocument(input, 'page');
+  assert.strictEqual(doc.title, 'page Session');
+  assert.strictEqual(doc.exchanges[0]?.request.agent, 'alice');
+  assert.strictEqual(summarizeDocument(input).headings.byLevel[1], 1);
+  assert.strictEqual(parseRequest('\uFEFF```js\n2 + 2\n```', 'page')?.code, '2 + 2');
+});
+
+test('parseDocument of an empty log has no exchanges', () => {
+  assert.deepStrictEqual(parseDocument('', 'page'), { title: '', empty: true, exchanges: [], pending: null });
+});
+
+test('parseDocument tells a blank log from one with nothing to run', () => {
+  assert.strictEqual(parseDocument(' \n\t\n', 'page').empty, true);
+  const idle = parseDocument('# page\n\nSome notes\n', 'page');
+  assert.strictEqual(idle.empty, false);
+  assert.strictEqual(idle.pending, null);
+  assert.deepStrictEqual(diagnoseDocument('\n\n', 'page').reasons, ['the log is empty']);
+});
+
+test('parseRequest marks fences that ask for the web worker', () => {
+  const input = [
+    '> Append your JavaScript snippet below',
+    '```js worker',
+    'self.name',
+    '```'
+  ].join('\n');
+
+  const result = parseRequest(input, 'test-page');
+  assert.strictEqual(result?.code, 'self.name');
+  assert.strictEqual(result?.language, 'js');
+  assert.strictEqual(result?.context, 'worker');
+});
+
+test('parseRequest reads the thread id from the request heading', () => {
+  const input = [
+    '> Append your JavaScript snippet below',
+    '### 🗣️agent to page at 10:00:00 #abc123',
+    '```js',
+    'go()',
+    '```'
+  ].join('\n');
+
+  assert.strictEqual(parseRequest(input, 'page')?.thread, 'abc123');
+});
+
+test('parseDocument pairs replies with requests by thread id', () => {
+  const input = [
+    '# page Session',
+    '### 🗣️agent to page at 10:00:00 #aaa',
+    '```js',
+    'first()',
+    '```',
+    '### 🗣️agent to page at 10:00:01 #bbb',
+    '```js',
+    'second()',
+    '```',
+    '#### 👍page to agent at 10:00:02 (5ms) #bbb',
+    '```JSON',
+    '2',
+    '```',
+    '#### 👍page to agent at 10:00:03 (9ms) #aaa',
+    '```JSON',
+    '1',
+    '```'
+  ].join('\n');
+
+  const [first, second] = parseDocument(input, 'page').exchanges;
+  assert.strictEqual(first.request.thread, 'aaa');
+  assert.strictEqual(first.reply?.thread, 'aaa');
+  assert.strictEqual(first.reply?.body, '```JSON\n1\n```');
+  assert.strictEqual(second.reply?.thread, 'bbb');
+  assert.strictEqual(second.reply?.body, '```JSON\n2\n```');
+});
+
+test('parsePendingRequests returns only the unanswered requests, in order', () => {
+  const input = [
+    '# page Session',
+    '### 🗣️alice to page at 10:00:00',
+    '```js',
+    'answered()',
+    '```',
+    '#### 👍page to alice at 10:00:01 (3ms)',
+    '```JSON',
+    '1',
+    '```',
+    '### 🗣️bob to page at 10:00:02',
+    '```ts',
+    'waiting()',
+    '```',
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '```js',
+    'fresh()',
+    '```'
+  ].join('\n');
+
+  const pending = parsePendingRequests(input, 'page');
+  assert.deepStrictEqual(pending.map(r => r.code), ['waiting()', 'fresh()']);
+  assert.strictEqual(pending[0].agent, 'bob');
+  assert.strictEqual(pending[0].language, 'ts');
+  assert.strictEqual(pending[0].hasFooter, false);
+  assert.strictEqual(pending[1].hasFooter, true);
+});
+
+test('parseCombinedDocument finds each page\'s requests in its own section', () => {
+  const input = [
+    '# daebug',
+    'Notes above the first section belong to no page.',
+    '## page: alpha',
+    '### 🗣️alice to alpha at 10:00:00',
+    '```js',
+    '1 + 1',
+    '```',
+    '#### 👍alpha to alice at 10:00:01 (2ms)',
+    '```JSON number',
+    '2',
+    '```',
+    '### 🗣️alice to alpha at 10:01:00',
+    '```js',
+    'document.title',
+    '```',
+    '## page: beta',
+    '### 🗣️bob to beta at 10:02:00',
+    '```ts',
+    '// ## page: gamma is code, not a section',
+    'const x: number = 3',
+    '```'
+  ].join('\n');
+
+  const sections = splitPages(input);
+  assert.deepStrictEqual(Array.from(sections.keys()), ['alpha', 'beta']);
+  assert.doesNotMatch(/** @type {string} */(sections.get('alpha')), /no page|beta/);
+
+  const requests = parseCombinedDocument(input);
+  assert.deepStrictEqual(Array.from(requests.keys()), ['alpha', 'beta']);
+  assert.deepStrictEqual(requests.get('alpha'), [{ agent: 'alice', target: 'alpha', time: '10:01:00', code: 'document.title', language: 'js', hasFooter: false }]);
+  assert.deepStrictEqual(requests.get('beta'), [{ agent: 'bob', target: 'beta', time: '10:02:00', code: '// ## page: gamma is code, not a section\nconst x: number = 3', language: 'ts', hasFooter: false }]);
+});
+
+test('splitPages takes a custom heading and joins a page\'s repeated sections', () => {
+  const input = '=== one\nfirst\n=== two\nsecond\n=== one\nthird';
+  assert.deepStrictEqual(Object.fromEntries(splitPages(input, /^===\s+(\S+)$/)), { one: 'first\nthird', two: 'second' });
+});
+
+test('diagnoseDocument explains an already answered request', () => {
+  const log = '# p\n### 🗣️agent to p at 10:00:00\n```js\n1\n```\n#### 👍p to agent at 10:00:01 (1ms)\n```JSON\n1\n```\n';
+  const diagnosis = diagnoseDocument(log, 'p');
+  assert.equal(diagnosis.answered, true);
+  assert.equal(diagnosis.pending, null);
+  assert.equal(diagnosis.language, 'js');
+  assert.deepEqual(diagnosis.request, { agent: 'agent', target: 'p', time: '10:00:00' });
+  assert.deepEqual(diagnosis.reasons, ['request from agent at 10:00:00 already has a reply']);
+});
+
+test('diagnoseDocument names an unrecognized code block language', () => {
+  const log = '# p\n\n----\n> Append your JavaScript snippet below\n### 🗣️agent to p at 10:00:00\n```python\nprint(1)\n```\n';
+  const diagnosis = diagnoseDocument(log, 'p');
+  assert.equal(diagnosis.answered, false);
+  assert.equal(diagnosis.language, 'python');
+  assert.equal(diagnosis.recognized, false);
+  assert.deepEqual(diagnosis.reasons, ["code block language 'python' is not recognized"]);
+});
+
+test('diagnoseDocument finds nothing in the way of a fresh request', () => {
+  const log = '# p\n\n----\n> Append your JavaScript snippet below\n### 🗣️agent to p at 10:00:00\n```ts\n1\n```\n';
+  const diagnosis = diagnoseDocument(log, 'p');
+  assert.equal(diagnosis.pending?.code, '1');
+  assert.equal(diagnosis.recognized, true);
+  assert.deepEqual(diagnosis.reasons, []);
+});
+
+test('parseRequest reads execution options from the fence info string', () => {
+  const input = [
+    '> Append your JavaScript snippet below to execute against this page.',
+    '### 🗣️agent to page at 10:00:00',
+    '```js {timeout=5000, worker=true, when="window.ready === true", label=smoke}',
+    'runSuite()',
+    '```'
+  ].join('\n');
+  const request = parseRequest(input, 'page');
+  assert.equal(request?.code, 'runSuite()');
+  assert.equal(request?.language, 'js');
+  assert.equal(request?.context, 'worker');
+  assert.deepEqual(request?.options, { timeout: 5000, worker: true, when: 'window.ready === true', label: 'smoke' });
+});
+
+test('parseRequest takes fence options without a footer too', () => {
+  const request = parseRequest('# page\n\n```ts {timeout=250}\nlet x: number = 1\n```\n', 'page');
+  assert.equal(request?.language, 'ts');
+  assert.equal(request?.context, undefined);
+  assert.deepEqual(request?.options, { timeout: 250 });
+  assert.equal(parseRequest('# page\n\n```js\n1\n```\n', 'page')?.options, undefined);
+});
@@ -1,10 +1,14 @@
 // @ts-check
-import { join, relative } from 'node:path';
-import { readFileSync, writeFileSync, readdirSync, existsSync } from 'node:fs';
+import { createHash } from 'node:crypto';
+import { join, relative, basename } from 'node:path';
+import { readFileSync, writeFileSync, readdirSync, existsSync, statSync, mkdirSync, rmSync } from 'node:fs';
 import { daebugMD_template } from './daebug.md.template.js';
+import * as events from './events.js';
+import { createMemoryPageStore } from './page-store.js';
 
 const DAEBUG_DIR = 'daebug';
 const MASTER_FILE = 'daebug.md';
+const MANIFEST_FILE = 'pages.json';
 
 const startTime = new Date();
 
@@ -16,17 +20,105 @@ const sanitizeName = name => name.toLowerCase().replace(/[^a-z0-9]+/g, '-').repl
  *   name: string,
  *   url: string,
  *   file: string,
- *   state: 'idle' | 'executing',
+ *   state: 'idle' | 'executing' | 'disconnected',
+ *   stateSince: number,
  *   lastSeen: number,
+ *   capabilities: string[],
+ *   prelude?: string,
+ *   preludeHash?: string,
+ *   instance?: string,
  *   lastTestProgress?: {pass: number, fail: number, skip: number}
  * }} Page
  */
 
-/** @type {Map<string, Page>} */
-const pages = new Map();
+
+/**
+ * Old page names mapped to the name their log was renamed to, so a page that keeps
+ * polling under its old name still reaches its entry
+ * @type {Map<string, string>}
+ */
+const renamed = new Map();
+
+/**
+ * @typedef {{
+ *   allowedPages: string[] | RegExp | null,
+ *   maxPages: number,
+ *   whenFull: 'evict' | 'reject',
+ *   validateUrls: boolean,
+ *   allowedHosts: string[] | null,
+ *   listOrder: 'name' | 'last-seen',
+ *   jobSummary: ((pageName: string) => { depth: number, executing: string | null }) | null,
+ *   onEvicted: ((page: Page) => void) | null,
+ *   presenceTtlMs: number,
+ *   logRetentionMs: number,
+ *   store: import('./page-store.js').PageStore
+ * }} RegistryOptions
+ */
+
+/** @type {RegistryOptions} */
+const options = {
+  allowedPages: null,
+  maxPages: Infinity,
+  whenFull: 'evict',
+  validateUrls: false,
+  allowedHosts: null,
+  listOrder: 'name',
+  jobSummary: null,
+  onEvicted: null,
+  presenceTtlMs: 0,
+  logRetentionMs: 0,
+  store: createMemoryPageStore()
+};
+
+/** Raised when a page is not permitted to register */
+export class RegistrationError extends Error {
+  /** @param {string} message */
+  constructor(message) {
+    super(message);
+    this.name = 'RegistrationError';
+  }
+}
+
+/** @param {Partial<RegistryOptions>} config */
+export function configure(config) {
+  Object.assign(options, config);
+}
+
+/** @param {string} name */
+function isAllowed(name) {
+  const allowed = options.allowedPages;
+  if (!allowed) return true;
+  if (allowed instanceof RegExp) return allowed.test(name);
+  return allowed.includes(name);
+}
+
+const PAGE_URL_PROTOCOLS = ['http:', 'https:', 'file:'];
+
+/**
+ * Check a page URL against the configured policy; lenient unless validation or a host allow-list is set
+ * @param {string} url
+ */
+export function validateUrl(url) {
+  if (!options.validateUrls && !options.allowedHosts) return;
+
+  let parsed;
+  try {
+    parsed = new URL(url);
+  } catch {
+    throw new RegistrationError(`invalid page url: ${JSON.stringify(url)}`);
+  }
+  if (!PAGE_URL_PROTOCOLS.includes(parsed.protocol))
+    throw new RegistrationError(`unsupported page url scheme ${parsed.protocol} in ${url}`);
+  if (options.allowedHosts && parsed.protocol !== 'file:' && !options.allowedHosts.includes(parsed.hostname))
+    throw new RegistrationError(`page url host ${parsed.hostname} is not in the allow-list`);
+}
 
 /** @param {string} root */
 export function init(root) {
+  if (!existsSync(root)) throw new Error(`root directory does not exist: ${root}`);
+  if (!statSync(root).isDirectory()) throw new Error(`root is not a directory: ${root}`);
+  mkdirSync(join(root, DAEBUG_DIR), { recursive: true });
+
   const master = join(root, MASTER_FILE);
   if (!existsSync(master)) {
     const content = daebugMD_template({
@@ -37,43 +129,207 @@ export function init(root) {
   }
 }
 
-/** @param {string} root @param {string} name @param {string} url */
-export function getOrCreate(root, name, url) {
-  let page = pages.get(name);
+/** Languages a page can run when it doesn't advertise any */
+export const DEFAULT_CAPABILITIES = ['js'];
+
+/** Capability flag of a throwaway page whose log is kept in memory and never left on disk */
+export const EPHEMERAL = 'ephemeral';
+
+/** Capabilities a page may advertise: the languages it runs, and the ephemeral flag */
+export const KNOWN_CAPABILITIES = ['js', 'ts', EPHEMERAL];
+
+/** @param {Page} page */
+export const isEphemeral = page => page.capabilities.includes(EPHEMERAL);
+
+/**
+ * @param {string} root
+ * @param {string} name
+ * @param {string} url
+ * @param {string[]} [capabilities] Languages the page advertises it can run
+ */
+export function getOrCreate(root, name, url, capabilities) {
+  name = renamed.get(name) ?? name;
+  let page = options.store.get(name);
   if (!page) {
-    const sanitized = sanitizeName(name);
-    const dir = join(root, DAEBUG_DIR);
-    let chosenFilename = null;
-    
-    if (existsSync(dir)) {
-      try {
-        for (const f of readdirSync(dir)) {
-          // Check if filename matches exactly (without .md extension)
-          const nameWithoutExt = f.replace(/\.md$/i, '');
-          if (nameWithoutExt.toLowerCase() !== sanitized.toLowerCase()) continue;
-          chosenFilename = f;
-          break;
-        }
-      } catch {}
-    }
+    if (!isAllowed(name)) throw new RegistrationError(`page "${name}" is not in the allow-list`);
+    validateUrl(url);
+    if (options.store.size() >= options.maxPages) makeRoom(name);
 
-    const file = join(root, DAEBUG_DIR, chosenFilename || `${sanitized}.md`);
-    page = { name, url, file, state: 'idle', lastSeen: Date.now() };
-    pages.set(name, page);
+    const file = logFileFor(root, name);
+    page = { name, url, file, state: 'idle', stateSince: Date.now(), lastSeen: Date.now(), capabilities: DEFAULT_CAPABILITIES };
+    options.store.insert(page);
+    events.record('page-registered', name, { url });
     
     const daebugReplFile = relative(root, file).replace(/\\/g, '/');
     console.log(`   📃 ${daebugReplFile}  for ${url}`);
     updateMaster(root);
   }
+  // A page known from the manifest comes alive when it first polls
+  if (page.state === 'disconnected') setState(page, 'idle');
   page.lastSeen = Date.now();
+  if (capabilities?.length) page.capabilities = capabilities;
+  options.store.update(page);
   return page;
 }
 
+/**
+ * Log file of a page: an existing one whose name matches ignoring case, else a new sanitized name
+ * @param {string} root
+ * @param {string} name
+ */
+function logFileFor(root, name) {
+  const sanitized = sanitizeName(name);
+  const dir = join(root, DAEBUG_DIR);
+  let chosenFilename = null;
+  
+  if (existsSync(dir)) {
+    try {
+      for (const f of readdirSync(dir)) {
+        // Check if filename matches exactly (without .md extension)
+        const nameWithoutExt = f.replace(/\.md$/i, '');
+        if (nameWithoutExt.toLowerCase() !== sanitized.toLowerCase()) continue;
+        chosenFilename = f;
+        break;
+      }
+    } catch {}
+  }
+
+  return join(root, DAEBUG_DIR, chosenFilename || `${sanitized}.md`);
+}
+
+/**
+ * Register the pages listed in `daebug/pages.json`, if there is one, as disconnected until
+ * they first poll, so they show from startup and jobs can be queued for them. The manifest is
+ * an array, or `{ "pages": [...] }`, of `{ name, url, capabilities? }`; bad entries are skipped.
+ * @param {string} root
+ * @returns {number} How many pages were added
+ */
+export function loadManifest(root) {
+  const file = join(root, DAEBUG_DIR, MANIFEST_FILE);
+  if (!existsSync(file)) return 0;
+  let entries;
+  try {
+    const manifest = JSON.parse(readFileSync(file, 'utf8'));
+    entries = Array.isArray(manifest) ? manifest : manifest?.pages;
+    if (!Array.isArray(entries)) throw new Error('expected an array of pages');
+  } catch (err) {
+    console.warn(`   📃 ignoring ${MANIFEST_FILE}: ${err instanceof Error ? err.message : err}`);
+    return 0;
+  }
+
+  let added = 0;
+  for (const entry of entries) {
+    const { name, url = '', capabilities } = entry || {};
+    if (typeof name !== 'string' || !name || typeof url !== 'string') {
+      console.warn(`   📃 ${MANIFEST_FILE}: skipping entry without a name: ${JSON.stringify(entry)}`);
+      continue;
+    }
+    if (options.store.get(name)) continue;
+    try {
+      if (!isAllowed(name)) throw new RegistrationError(`page "${name}" is not in the allow-list`);
+      validateUrl(url);
+      if (options.store.size() >= options.maxPages) makeRoom(name);
+    } catch (err) {
+      console.warn(`   📃 ${MANIFEST_FILE}: skipping ${name}: ${err instanceof Error ? err.message : err}`);
+      continue;
+    }
+    /** @type {Page} */
+    const page = {
+      name, url, file: logFileFor(root, name), state: 'disconnected', stateSince: Date.now(), lastSeen: Date.now(),
+      capabilities: Array.isArray(capabilities) && capabilities.length ? capabilities.map(String) : DEFAULT_CAPABILITIES
+    };
+    options.store.insert(page);
+    events.record('page-registered', name, { url, manifest: true });
+    added++;
+  }
+  if (added) {
+    console.log(`   📃 ${added} page(s) registered from ${MANIFEST_FILE}`);
+    updateMaster(root);
+  }
+  return added;
+}
+
+/**
+ * Free a registry slot for a new page, or refuse it, according to the configured policy.
+ * Only idle pages are evicted so no running job loses its page.
+ * @param {string} name
+ */
+function makeRoom(name) {
+  const stalest = options.whenFull === 'evict'
+    ? options.store.list()
+      .filter(p => p.state === 'idle')
+      .sort((a, b) => a.lastSeen - b.lastSeen)[0]
+    : undefined;
+  if (!stalest)
+    throw new RegistrationError(`page "${name}" rejected: registry is full (${options.maxPages} pages)`);
+
+  options.store.remove(stalest.name);
+  options.onEvicted?.(stalest);
+  events.record('page-evicted', stalest.name, { replacedBy: name });
+  console.log(`   📃 evicted ${stalest.name} to make room for ${name}`);
+}
+
+/**
+ * Remove a page on an operator's request, whatever its state
+ * @param {string} name
+ * @returns {Page | undefined} The removed page
+ */
+export function evict(name) {
+  const page = options.store.get(name);
+  if (!page) return undefined;
+  options.store.remove(name);
+  events.record('page-evicted', name, { reason: 'operator' });
+  console.log(`   📃 evicted ${name} on request`);
+  return page;
+}
+
+/**
+ * Drop pages that have not polled within `presenceTtlMs`. Only the registration goes:
+ * how long their logs stay on disk is up to `logRetentionMs`. Manifest pages that have yet
+ * to poll are kept: they are listed so jobs can wait for them, however long they take to open.
+ * @param {number} [now]
+ * @returns {Page[]} The expired pages
+ */
+export function expire(now = Date.now()) {
+  if (!options.presenceTtlMs) return [];
+  const expired = options.store.list()
+    .filter(page => page.state !== 'disconnected' && now - page.lastSeen > options.presenceTtlMs);
+  for (const page of expired) {
+    options.store.remove(page.name);
+    events.record('page-expired', page.name, { lastSeen: page.lastSeen });
+    console.log(`   📃 ${page.name} expired: not seen for ${Math.round((now - page.lastSeen) / 1000)}s`);
+  }
+  return expired;
+}
+
+/**
+ * Delete logs and log archives under the daebug directory that no registered page is using
+ * and that have not changed within `logRetentionMs`
+ * @param {string} root
+ * @param {number} [now]
+ * @returns {string[]} The deleted files
+ */
+export function expireLogs(root, now = Date.now()) {
+  const dir = join(root, DAEBUG_DIR);
+  if (!options.logRetentionMs || !existsSync(dir)) return [];
+  const live = new Set(options.store.list().map(page => page.file));
+  const removed = [];
+  for (const name of readdirSync(dir)) {
+    const file = join(dir, name);
+    if (!/\.md(\.gz)?$/i.test(name) || live.has(file)) continue;
+    const stat = statSync(file);
+    if (!stat.isFile() || now - stat.mtimeMs <= options.logRetentionMs) continue;
+    rmSync(file);
+    removed.push(file);
+  }
+  return removed;
+}
+
 /** @param {string} root */
 export function updateMaster(root) {
   const content = daebugMD_template({
     startTime: startTime,
-    pageList: Array.from(pages.values()),
+    pageList: all().map(page => options.jobSummary ? { ...page, queue: options.jobSummary(page.name) } : page),
     root
   });
   
@@ -81,6 +337,131 @@ export function updateMaster(root) {
 }
 
 /** @param {string} name */
-export const get = name => pages.get(name);
-export const all = () => Array.from(pages.values());
+export const get = name => options.store.get(name) ?? options.store.get(renamed.get(name) ?? '');
+
+/**
+ * Record the instance id a page polls with. Each page load picks a fresh id,
+ * so a different id under the same name means the page reloaded.
+ * @param {Page} page
+ * @param {string} instance
+ * @returns {boolean} Whether this is a reload of a previously seen instance
+ */
+export function noteInstance(page, instance) {
+  const reloaded = !!page.instance && page.instance !== instance;
+  if (reloaded) events.record('page-reloaded', page.name, { from: page.instance, to: instance });
+  page.instance = instance;
+  options.store.update(page);
+  return reloaded;
+}
+
+/**
+ * Move a page to a new state, noting when it changed. Setting the state it is already in
+ * leaves `stateSince` alone, so it measures how long the page has been in that state.
+ * @param {Page} page
+ * @param {Page['state']} state
+ */
+export function setState(page, state) {
+  if (page.state === state) return;
+  page.state = state;
+  page.stateSince = Date.now();
+  // Jobs can outlive their page's registration; don't write an evicted page back
+  if (options.store.get(page.name) === page) options.store.update(page);
+}
+
+/**
+ * Move a page to the log file it was renamed to; the page takes the new file's name
+ * @param {string} root
+ * @param {string} oldName
+ * @param {string} newFile
+ * @returns {Page | undefined} The moved page
+ */
+export function rename(root, oldName, newFile) {
+  const page = options.store.get(oldName);
+  if (!page) return undefined;
+  const newName = basename(newFile).replace(/\.md$/i, '');
+  options.store.remove(oldName);
+  page.name = newName;
+  page.file = newFile;
+  options.store.insert(page);
+  renamed.set(oldName, newName);
+  for (const [from, to] of renamed) if (to === oldName) renamed.set(from, newName);
+  renamed.delete(newName);
+  events.record('page-renamed', newName, { from: oldName });
+  console.log(`   📃 ${oldName} renamed to ${newName}`);
+  updateMaster(root);
+  return page;
+}
+
+/**
+ * Name the page's web worker registers under (matches the client's naming)
+ * @param {string} pageName
+ */
+export const workerNameFor = pageName => sanitizeName(pageName + '-webworker');
+
+/**
+ * Every registered page, sorted so views don't depend on registration order:
+ * by name, or most recently seen first (ties broken by name)
+ * @returns {Page[]}
+ */
+export function all() {
+  const byName = (/** @type {Page} */ a, /** @type {Page} */ b) => a.name < b.name ? -1 : a.name > b.name ? 1 : 0;
+  const list = options.store.list();
+  return options.listOrder === 'last-seen'
+    ? list.sort((a, b) => b.lastSeen - a.lastSeen || byName(a, b))
+    : list.sort(byName);
+}
+
 export const getStartTime = () => startTime;
+
+/**
+ * Plain copy of every registered page
+ * @returns {Page[]}
+ */
+export function snapshot() {
+  return options.store.list().map(page => ({ ...page, capabilities: [...page.capabilities] }));
+}
+
+/**
+ * Replace the registry contents with previously snapshotted pages
+ * @param {Page[]} list
+ */
+export function restore(list) {
+  options.store.clear();
+  for (const page of list) options.store.insert({
+    ...page,
+    stateSince: page.stateSince ?? page.lastSeen,
+    capabilities: [...(page.capabilities || DEFAULT_CAPABILITIES)]
+  });
+}
+
+/**
+ * Set the setup code a page runs once before its jobs; null clears it
+ * @param {string} name
+ * @param {string | null} prelude
+ */
+export function setPrelude(name, prelude) {
+  const page = options.store.get(name);
+  if (!page) return false;
+  if (prelude) page.prelude = prelude;
+  else delete page.prelude;
+  options.store.update(page);
+  return true;
+}
+
+/** @param {string} prelude */
+const hashPrelude = prelude => createHash('sha1').update(prelude).digest('hex').slice(0, 16);
+
+/**
+ * Decide what prelude travels with the next job. The code is only included when the page's
+ * cached copy (`preludeHash`, as last reported by the page) differs.
+ * @param {Page} page
+ * @param {string | undefined} prelude
+ * @returns {{ hash: string, code: string | null } | null}
+ */
+export function preludeToSend(page, prelude) {
+  if (!prelude) return null;
+  const hash = hashPrelude(prelude);
+  if (page.preludeHash === hash) return { hash, code: null };
+  page.preludeHash = hash;
+  return { hash, code: prelude };
+}
@@ -11,6 +11,7 @@ import { join } from 'node:path';
 // Import module to get access to functions
 // Note: sanitizeName and clockFmt are internal - we'll test them indirectly or export for testing
 import * as registry from './registry.js';
+import { createMemoryPageStore } from './page-store.js';
 
 test('getOrCreate creates new page when not exists', () => {
   const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
@@ -144,3 +145,308 @@ test('init creates master file when not exists', () => {
     rmSync(root, { recursive: true, force: true });
   }
 });
+
+test('getOrCreate accepts names in the allow-list', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    registry.configure({ allowedPages: ['allowed-page'] });
+    const page = registry.getOrCreate(root, 'allowed-page', 'http://localhost');
+    assert.strictEqual(page.name, 'allowed-page');
+  } finally {
+    registry.configure({ allowedPages: null });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('getOrCreate rejects names outside the allow-list', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    registry.configure({ allowedPages: /^app-/ });
+    assert.throws(
+      () => registry.getOrCreate(root, 'intruder-page', 'http://localhost'),
+      registry.RegistrationError);
+    assert.strictEqual(registry.get('intruder-page'), undefined);
+  } finally {
+    registry.configure({ allowedPages: null });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('init creates daebug directory', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    registry.init(root);
+    assert.ok(existsSync(join(root, 'daebug')));
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('init rejects a nonexistent root with a descriptive error', () => {
+  const root = join(tmpdir(), 'daebug-missing-' + Date.now());
+  assert.throws(() => registry.init(root), /root directory does not exist: .*daebug-missing-/);
+});
+
+test('init rejects a root that is a file', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const file = join(root, 'not-a-dir');
+    writeFileSync(file, '', 'utf8');
+    assert.throws(() => registry.init(file), /root is not a directory/);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('getOrCreate evicts the stalest idle page when full', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const oldest = registry.getOrCreate(root, 'cap-oldest', 'http://localhost');
+    oldest.lastSeen = 1;
+    registry.getOrCreate(root, 'cap-newer', 'http://localhost');
+    registry.configure({ maxPages: registry.all().length, whenFull: 'evict' });
+
+    registry.getOrCreate(root, 'cap-incoming', 'http://localhost');
+    assert.strictEqual(registry.get('cap-oldest'), undefined);
+    assert.ok(registry.get('cap-newer'));
+    assert.ok(registry.get('cap-incoming'));
+  } finally {
+    registry.configure({ maxPages: Infinity, whenFull: 'evict' });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('getOrCreate skips executing pages when evicting', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const busy = registry.getOrCreate(root, 'cap-busy', 'http://localhost');
+    busy.lastSeen = 0;
+    busy.state = 'executing';
+    registry.configure({ maxPages: registry.all().length, whenFull: 'evict' });
+
+    registry.getOrCreate(root, 'cap-after-busy', 'http://localhost');
+    assert.strictEqual(registry.get('cap-busy'), busy);
+  } finally {
+    registry.configure({ maxPages: Infinity, whenFull: 'evict' });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('getOrCreate rejects new pages when full under the reject policy', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    registry.getOrCreate(root, 'cap-existing', 'http://localhost');
+    const size = registry.all().length;
+    registry.configure({ maxPages: size, whenFull: 'reject' });
+
+    assert.throws(() => registry.getOrCreate(root, 'cap-rejected', 'http://localhost'), /registry is full/);
+    assert.strictEqual(registry.all().length, size);
+    // Known pages still refresh normally
+    assert.ok(registry.getOrCreate(root, 'cap-existing', 'http://localhost'));
+  } finally {
+    registry.configure({ maxPages: Infinity, whenFull: 'evict' });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('validateUrl is lenient by default', () => {
+  assert.doesNotThrow(() => registry.validateUrl('not a url'));
+});
+
+test('validateUrl accepts http, https and file urls', () => {
+  registry.configure({ validateUrls: true });
+  try {
+    assert.doesNotThrow(() => registry.validateUrl('http://localhost:8080/index.html'));
+    assert.doesNotThrow(() => registry.validateUrl('https://example.com/'));
+    assert.doesNotThrow(() => registry.validateUrl('file:///tmp/page.html'));
+  } finally {
+    registry.configure({ validateUrls: false });
+  }
+});
+
+test('validateUrl rejects unparseable urls and other schemes', () => {
+  registry.configure({ validateUrls: true });
+  try {
+    assert.throws(() => registry.validateUrl('not a url'), registry.RegistrationError);
+    assert.throws(() => registry.validateUrl(''), registry.RegistrationError);
+    assert.throws(() => registry.validateUrl('javascript:alert(1)'), /unsupported page url scheme/);
+  } finally {
+    registry.configure({ validateUrls: false });
+  }
+});
+
+test('getOrCreate rejects pages from hosts outside the allow-list', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  registry.configure({ allowedHosts: ['localhost'] });
+  try {
+    assert.ok(registry.getOrCreate(root, 'host-ok', 'http://localhost:8080/'));
+    assert.throws(() => registry.getOrCreate(root, 'host-bad', 'http://evil.example/'), /not in the allow-list/);
+    assert.strictEqual(registry.get('host-bad'), undefined);
+  } finally {
+    registry.configure({ allowedHosts: null });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('preludeToSend only includes the code when the page copy differs', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const page = registry.getOrCreate(root, 'prelude-page', 'http://localhost');
+    assert.strictEqual(registry.preludeToSend(page, undefined), null);
+
+    const first = registry.preludeToSend(page, 'const token = 1;');
+    assert.strictEqual(first?.code, 'const token = 1;');
+    // The page reports the same hash back: nothing to resend
+    page.preludeHash = first?.hash;
+    assert.deepStrictEqual(registry.preludeToSend(page, 'const token = 1;'), { hash: first?.hash, code: null });
+
+    const changed = registry.preludeToSend(page, 'const token = 2;');
+    assert.strictEqual(changed?.code, 'const token = 2;');
+    assert.notStrictEqual(changed?.hash, first?.hash);
+
+    // A reloaded page has lost its copy and gets the prelude again
+    page.preludeHash = undefined;
+    assert.strictEqual(registry.preludeToSend(page, 'const token = 2;')?.code, 'const token = 2;');
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('setPrelude attaches setup code to a registered page', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const page = registry.getOrCreate(root, 'prelude-set', 'http://localhost');
+    assert.strictEqual(registry.setPrelude('prelude-set', 'setup()'), true);
+    assert.strictEqual(page.prelude, 'setup()');
+    registry.setPrelude('prelude-set', null);
+    assert.strictEqual(page.prelude, undefined);
+    assert.strictEqual(registry.setPrelude('no-such-page', 'x'), false);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('all() lists pages in a stable order', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    for (const name of ['order-c', 'order-a', 'order-b']) registry.getOrCreate(root, name, 'http://localhost');
+    const ours = () => registry.all().map(p => p.name).filter(n => n.startsWith('order-'));
+    
+    assert.deepStrictEqual(ours(), ['order-a', 'order-b', 'order-c']);
+    assert.deepStrictEqual(ours(), ours());
+    
+    registry.configure({ listOrder: 'last-seen' });
+    /** @type {any} */(registry.get('order-a')).lastSeen = 1;
+    /** @type {any} */(registry.get('order-b')).lastSeen = 3;
+    /** @type {any} */(registry.get('order-c')).lastSeen = 2;
+    assert.deepStrictEqual(ours(), ['order-b', 'order-c', 'order-a']);
+    assert.deepStrictEqual(ours(), ours());
+  } finally {
+    registry.configure({ listOrder: 'name' });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('registry keeps its pages in the configured page store', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  const inner = createMemoryPageStore();
+  /** @type {string[]} */
+  const calls = [];
+  /** @type {import('./page-store.js').PageStore} */
+  const recording = {
+    ...inner,
+    insert: page => { calls.push('insert ' + page.name); inner.insert(page); },
+    update: page => { calls.push('update ' + page.name); inner.update(page); },
+    remove: name => { calls.push('remove ' + name); return inner.remove(name); }
+  };
+  const previous = registry.snapshot();
+  registry.configure({ store: recording });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'stored-page', 'http://localhost');
+    assert.strictEqual(inner.get('stored-page'), page);
+    assert.deepStrictEqual(registry.all().map(p => p.name), ['stored-page']);
+    registry.setPrelude('stored-page', 'setup()');
+    registry.evict('stored-page');
+    assert.deepStrictEqual(calls, ['insert stored-page', 'update stored-page', 'update stored-page', 'remove stored-page']);
+    assert.strictEqual(registry.get('stored-page'), undefined);
+  } finally {
+    registry.configure({ store: createMemoryPageStore() });
+    registry.restore(previous);
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('stateSince moves on state transitions but not on heartbeats', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const page = registry.getOrCreate(root, 'state-since-page', 'http://localhost');
+    const registeredAt = page.stateSince;
+    assert.strictEqual(typeof registeredAt, 'number');
+
+    await new Promise(resolve => setTimeout(resolve, 5));
+    registry.getOrCreate(root, 'state-since-page', 'http://localhost');
+    registry.setState(page, 'idle');
+    assert.strictEqual(page.stateSince, registeredAt);
+
+    registry.setState(page, 'executing');
+    assert.strictEqual(page.state, 'executing');
+    assert.ok(page.stateSince > registeredAt);
+    const executingSince = page.stateSince;
+
+    await new Promise(resolve => setTimeout(resolve, 5));
+    registry.getOrCreate(root, 'state-since-page', 'http://localhost');
+    registry.setState(page, 'executing');
+    assert.strictEqual(page.stateSince, executingSince);
+    registry.setState(page, 'idle');
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('manifest pages are disconnected until their first poll', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    writeFileSync(join(root, 'daebug', 'pages.json'), JSON.stringify({
+      pages: [
+        { name: 'manifest-app', url: 'http://localhost/app', capabilities: ['js', 'ts'] },
+        { url: 'http://localhost/nameless' }
+      ]
+    }));
+    assert.strictEqual(registry.loadManifest(root), 1);
+    const page = registry.get('manifest-app');
+    assert.strictEqual(page?.state, 'disconnected');
+    assert.deepStrictEqual(page?.capabilities, ['js', 'ts']);
+    assert.strictEqual(page?.file, join(root, 'daebug', 'manifest-app.md'));
+    assert.strictEqual(registry.loadManifest(root), 0);
+
+    const polled = registry.getOrCreate(root, 'manifest-app', 'http://localhost/app');
+    assert.strictEqual(polled, page);
+    assert.strictEqual(polled.state, 'idle');
+    registry.evict('manifest-app');
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('presence expiry spares manifest pages until their first poll', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  registry.configure({ presenceTtlMs: 1000 });
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    writeFileSync(join(root, 'daebug', 'pages.json'), JSON.stringify([{ name: 'manifest-late', url: 'http://localhost/late' }]));
+    registry.loadManifest(root);
+    const later = Date.now() + 60_000;
+    assert.ok(!registry.expire(later).some(page => page.name === 'manifest-late'));
+    assert.strictEqual(registry.get('manifest-late')?.state, 'disconnected');
+
+    registry.getOrCreate(root, 'manifest-late', 'http://localhost/late');
+    assert.ok(registry.expire(later).some(page => page.name === 'manifest-late'));
+    assert.strictEqual(registry.get('manifest-late'), undefined);
+  } finally {
+    registry.configure({ presenceTtlMs: 0 });
+    rmSync(root, { recursive: true, force: true });
+  }
+});
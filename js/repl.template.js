@@ -1,4 +1,5 @@
 // @ts-check
+import { getTheme, replyMarkerPattern, agentMarkerPattern } from './theme.js';
 
 /**
  * Format milliseconds as HH:MM:SS time string
@@ -70,27 +71,48 @@ export function findAgentHeaderAbove(lines, startIdx) {
   // Check for old format: > **agent** to
   if (idx >= 0 && /^>\s*\*\*/.test(lines[idx].trim())) return idx;
   // Check for new format: ### 🗣️agent to
-  if (idx >= 0 && /^###\s*🗣️/.test(lines[idx].trim())) return idx;
+  if (idx >= 0 && new RegExp('^###\\s*' + agentMarkerPattern()).test(lines[idx].trim())) return idx;
   return -1;
 }
 
+/**
+ * Format the line that stands in for a reply while the job runs
+ * @param {number} secs Elapsed seconds
+ * @returns {string}
+ */
+export function formatExecutingPlaceholder(secs) {
+  return `${getTheme().executing}executing (${secs}s)`;
+}
+
+/**
+ * Whether a line is an executing placeholder, with or without the theme marker
+ * @param {string} line
+ */
+export function isExecutingPlaceholder(line) {
+  const marker = getTheme().executing.trim().replace(/[-\\^$*+?.()|[\]{}]/g, '\\$&');
+  return new RegExp('^(?:' + marker + '\\s*)?executing \\(').test(line.trim());
+}
+
 /**
  * Find an executing block (agent header + "executing" placeholder)
  * @param {string[]} lines
  * @param {string} page
  * @param {string} agent
+ * @param {string} [thread] Only match the placeholder of this thread
  * @returns {{headerIdx: number, placeholderIdx: number} | null}
  */
-export function findExecutingBlock(lines, page, agent) {
+export function findExecutingBlock(lines, page, agent, thread) {
   const footerIdx = findFooter(lines);
   if (footerIdx < 0) return null;
+  const replyRe = new RegExp('^#{4}\\s*' + replyMarkerPattern());
   for (let i = footerIdx - 1; i >= 0; i--) {
     // Check for old format: > **page** to agent at
-    if (lines[i].startsWith(`> **${page}** to ${agent} at `) && /^executing \(/.test((lines[i + 1] || '').trim())) {
+    if (lines[i].startsWith(`> **${page}** to ${agent} at `) && isExecutingPlaceholder(lines[i + 1] || '')) {
       return { headerIdx: i, placeholderIdx: i + 1 };
     }
     // Check for new format: #### 👍page or #### 🚫page to agent at
-    if (/^#{4}\s*[👍🚫]/.test(lines[i]) && lines[i].includes(`${page} to ${agent} at `) && /^executing \(/.test((lines[i + 1] || '').trim())) {
+    if (replyRe.test(lines[i]) && lines[i].includes(`${page} to ${agent} at `) && isExecutingPlaceholder(lines[i + 1] || '')) {
+      if (thread && !lines[i].trimEnd().endsWith(` #${thread}`)) continue;
       return { headerIdx: i, placeholderIdx: i + 1 };
     }
   }
@@ -133,6 +155,8 @@ export function formatSessionGuide() {
  * @returns {string[]} Modified lines array with header/guide if needed
  */
 export function ensureFileHeader(lines, defaultTitle) {
+  // A blank file starts over as a fresh log rather than keeping its stray whitespace
+  if (lines.every(line => !line.trim())) lines = [];
   // Scan first 20 lines for level-1 header
   const scanLimit = Math.min(20, lines.length);
   for (let i = 0; i < scanLimit; i++) {
@@ -150,17 +174,53 @@ export function ensureFileHeader(lines, defaultTitle) {
   return [headerLine, '', ...guideLines, '', separator, '', ...lines];
 }
 
+/**
+ * Format the thread id suffix that ties a reply heading to its request heading
+ * @param {string} [thread]
+ */
+const threadSuffix = thread => thread ? ` #${thread}` : '';
+
 /**
  * Format agent/sender header (for requests being sent to a page)
  * @param {string} agent Name of the agent/sender
  * @param {string} target Name of the target/page
  * @param {string} ts ISO timestamp string
+ * @param {string} [thread] Thread id shared with the reply
  * @returns {string}
  */
-export function formatAgentHeader(agent, target, ts) {
-  return `### 🗣️${agent} to ${target} at ${clockFmt(ts)}`;
+export function formatAgentHeader(agent, target, ts, thread) {
+  return `### ${getTheme().agent}${agent} to ${target} at ${clockFmt(ts)}${threadSuffix(thread)}`;
 }
 
+/**
+ * Format a request a page makes of its agents, e.g. to run its test suite. The message is
+ * quoted so nothing in it reads as code for the page to run.
+ * @param {string} page Name of the page asking
+ * @param {string} id Id of the page request
+ * @param {string} message
+ * @param {string} ts ISO timestamp string
+ * @returns {string}
+ */
+export function formatPageRequest(page, id, message, ts) {
+  return [`### ${getTheme().ask}${page} to agent at ${clockFmt(ts)} #${id}`, '', ...quoteLines(message)].join('\n');
+}
+
+/**
+ * Format an agent's answer to a page request
+ * @param {string} agent
+ * @param {string} page
+ * @param {string} id Id of the page request answered
+ * @param {string} answer
+ * @param {string} ts ISO timestamp string
+ * @returns {string}
+ */
+export function formatPageRequestAnswer(agent, page, id, answer, ts) {
+  return [`#### ${getTheme().ask}${agent} to ${page} at ${clockFmt(ts)} #${id}`, '', ...quoteLines(answer)].join('\n');
+}
+
+/** @param {string} text */
+const quoteLines = text => text.split('\n').map(line => line ? `> ${line}` : '>');
+
 /**
  * Format reply header (response from a page)
  * @param {string} page Name of the page
@@ -168,11 +228,44 @@ export function formatAgentHeader(agent, target, ts) {
  * @param {string} ts ISO timestamp string
  * @param {number} dur Duration in milliseconds
  * @param {boolean} err Whether there was an error
+ * @param {string} [thread] Thread id of the request being answered
  * @returns {string}
  */
-export function formatReplyHeader(page, agent, ts, dur, err) {
-  const emoji = err ? '🚫' : '👍';
-  return `#### ${emoji}${page} to ${agent} at ${clockFmt(ts)} (${durationFmt(dur)})`;
+export function formatReplyHeader(page, agent, ts, dur, err, thread) {
+  const marker = err ? getTheme().failure : getTheme().success;
+  return `#### ${marker}${page} to ${agent} at ${clockFmt(ts)} (${durationFmt(dur)})${threadSuffix(thread)}`;
+}
+
+/**
+ * Format the reply heading shown while a job is still running
+ * @param {string} page
+ * @param {string} agent
+ * @param {string} ts ISO timestamp string
+ * @param {string} [thread]
+ * @returns {string}
+ */
+export function formatExecutingHeader(page, agent, ts, thread) {
+  return `#### ${getTheme().success}${page} to ${agent} at ${clockFmt(ts)}${threadSuffix(thread)}`;
+}
+
+/**
+ * Find the request heading carrying a thread id, with the end of its code block
+ * @param {string[]} lines
+ * @param {string} thread
+ * @returns {{headerIdx: number, end: number} | null}
+ */
+export function findThreadRequest(lines, thread) {
+  const footerIdx = findFooter(lines);
+  const limit = footerIdx >= 0 ? footerIdx : lines.length;
+  const requestRe = new RegExp('^###\\s*' + agentMarkerPattern() + '.*\\s#' + thread + '\\s*$');
+  const headerIdx = lines.slice(0, limit).findIndex(line => requestRe.test(line.trim()));
+  if (headerIdx < 0) return null;
+  let open = headerIdx + 1;
+  while (open < limit && !lines[open].trim()) open++;
+  if (open >= limit || !/^```/.test(lines[open].trim())) return null;
+  let end = open + 1;
+  while (end < limit && !/^```\s*$/.test(lines[end].trim())) end++;
+  return end < limit ? { headerIdx, end } : null;
 }
 
 /**
@@ -184,6 +277,51 @@ export function formatCodeBlock(code) {
   return `\`\`\`JS\n${code}\n\`\`\``;
 }
 
+/**
+ * Format a note about something that went wrong on the server, quoted so it stands apart
+ * from exchanges and is never mistaken for a request
+ * @param {string} context What the server was doing, e.g. 'parse'
+ * @param {string} message
+ * @param {string} ts ISO timestamp string
+ * @returns {string}
+ */
+export function formatErrorNote(context, message, ts) {
+  const [first, ...rest] = message.split('\n');
+  return [`> ${getTheme().warning}Daebug error (${context}) at ${clockFmt(ts)}: ${first}`, ...rest.map(line => `> ${line}`)].join('\n');
+}
+
+/**
+ * Format the note standing in for the value of a job whose `when` predicate did not hold
+ * @param {string} [when]
+ * @returns {string}
+ */
+export function formatSkipped(when) {
+  return when ? `> Skipped: \`${when.replace(/\s+/g, ' ').trim()}\` was not true` : '> Skipped';
+}
+
+/**
+ * Format a link to request code kept in a sidecar file instead of inline
+ * @param {string} href Path of the sidecar relative to the log
+ * @returns {string}
+ */
+export function formatCodeLink(href) {
+  return `[code](${href})`;
+}
+
+/**
+ * Format the attachments a result carried: links to those saved beside the log, and an
+ * error block for each one refused for its content type
+ * @param {Array<{ name: string, type: string, href?: string, rejected?: string }>} attachments
+ * @returns {string[]}
+ */
+export function formatAttachments(attachments) {
+  const saved = attachments.filter(a => a.href).map(a => `- [${a.name}](${a.href}) ${a.type}`);
+  return [
+    ...(saved.length ? [saved.join('\n')] : []),
+    ...attachments.filter(a => a.rejected).map(a => `\`\`\`Error\n${a.rejected}\n\`\`\``)
+  ];
+}
+
 /**
  * Format a single background event as a fenced block with level-5 header
  * @param {{type: string, level?: string, source?: string, eventAt?: string, message: string, stack?: string, caller?: string}} event
@@ -191,7 +329,7 @@ export function formatCodeBlock(code) {
  */
 export function formatBackgroundEvent(event) {
   if (event.type === 'error') {
-    const emoji = '🚫';
+    const emoji = getTheme().failure;
     const eventLabel = event.source || 'window.onerror';
     const fenceType = event.source || 'Error';
     let content = event.stack || event.message;
@@ -252,21 +390,72 @@ export function formatBackgroundEvent(event) {
 }
 
 /**
- * Format result blocks (value, error, or background events)
- * @param {{ok: boolean, value?: any, error?: any, errors?: string[], backgroundEvents?: any[]}} result
+ * Format what a job printed to the console, one line per call, under its own heading
+ * so it reads apart from the returned value
+ * @param {{level: string, message: string, eventAt?: string}[]} logs
+ * @returns {string}
+ */
+export function formatConsoleLogs(logs) {
+  const lines = logs.map(log => `[${log.level}] ${log.message}`);
+  return `##### Console\n\`\`\`Text\n${lines.join('\n')}\n\`\`\``;
+}
+
+/**
+ * A BigInt as the page sends it: `{ "$bigint": "<digits>" }`
+ * @param {unknown} value
+ * @returns {value is { $bigint: string }}
+ */
+const isTaggedBigInt = value => !!value && typeof value === 'object' && Object.keys(value).length === 1
+  && typeof (/** @type {any} */(value).$bigint) === 'string' && /^-?\d+$/.test(/** @type {any} */(value).$bigint);
+
+/**
+ * JSON text of a result value with tagged BigInts written back as the bare integers they were,
+ * every digit kept, rather than as the tag or a rounded number
+ * @param {unknown} value
+ * @param {number} [indent]
+ * @returns {string}
+ */
+export function resultJson(value, indent) {
+  return JSON.stringify(value, (key, v) => isTaggedBigInt(v) ? '\u0001' + v.$bigint + '\u0001' : v, indent)
+    .replace(/"\\u0001(-?\d+)\\u0001"/g, '$1');
+}
+
+/**
+ * Kind of JS value a result holds, told from its JSON form when the page sent no hint:
+ * `null`, `array`, `object`, `string`, `number`, `boolean`, `bigint` or `undefined`
+ * @param {unknown} value
+ * @returns {string}
+ */
+export function valueTypeOf(value) {
+  if (value === null) return 'null';
+  if (Array.isArray(value)) return 'array';
+  if (isTaggedBigInt(value)) return 'bigint';
+  return typeof value;
+}
+
+/**
+ * Format result blocks (value, error, console output, or background events).
+ * The value's fence names its type after the language, so `null`, `""` and `{}` read apart.
+ * @param {{ok: boolean, value?: any, valueType?: string, error?: any, skipped?: boolean, errors?: string[], logs?: {level: string, message: string, eventAt?: string}[], backgroundEvents?: any[], attachments?: any[]}} result
+ * @param {string} [when] Predicate of a guarded job
  * @returns {string[]} Array of formatted blocks
  */
-export function formatResultBlocks(result) {
+export function formatResultBlocks(result, when) {
   const blocks = [];
   
-  if (result.ok) {
+  if (result.skipped) {
+    blocks.push(formatSkipped(when));
+  } else if (result.ok) {
     const val = result.value;
-    const jsonStr = val && typeof val === 'object' ? JSON.stringify(val, null, 2) : String(val);
-    blocks.push(`\`\`\`JSON\n${jsonStr}\n\`\`\``);
+    const jsonStr = val && typeof val === 'object' ? resultJson(val, 2) : String(val);
+    blocks.push(`\`\`\`JSON ${result.valueType || valueTypeOf(val)}\n${jsonStr}\n\`\`\``);
   } else {
     const errorStr = String(result.error);
     blocks.push(`\`\`\`Error\n${errorStr}\n\`\`\``);
   }
+
+  if (result.attachments?.length) blocks.push(...formatAttachments(result.attachments));
+  if (result.logs?.length) blocks.push(formatConsoleLogs(result.logs));
   
   // Handle new backgroundEvents structure
   if (result.backgroundEvents?.length) {
@@ -299,6 +488,18 @@ export function formatResultBlocks(result) {
   return blocks;
 }
 
+/**
+ * Format the pass/fail line for a job submitted with an expected value
+ * @param {'pass' | 'fail'} verdict
+ * @param {unknown} expected
+ * @returns {string[]}
+ */
+export function formatVerdict(verdict, expected) {
+  if (verdict === 'pass') return [`${getTheme().pass}matches expected value`];
+  const json = expected && typeof expected === 'object' ? JSON.stringify(expected, null, 2) : JSON.stringify(expected);
+  return [`${getTheme().fail}expected:`, `\`\`\`JSON\n${json}\n\`\`\``];
+}
+
 /**
  * Format the footer separator with REPL instructions
  * @returns {string}
@@ -318,10 +519,59 @@ export function formatFooter() {
  *   target: string,
  *   time: string,
  *   code: string,
+ *   language: 'js' | 'ts',
+ *   context?: 'worker',
+ *   thread?: string,
  *   hasFooter: boolean
  * }} ParsedRequest
  */
 
+/**
+ * Normalise a fence language tag to the runtime language it asks for
+ * @param {string | undefined} lang
+ * @returns {'js' | 'ts'}
+ */
+const fenceLanguage = lang => /^t/i.test(lang || '') ? 'ts' : 'js';
+
+/**
+ * Options written in braces after a fence's language, ```js {timeout=5000, worker=true}.
+ * Numbers and booleans are converted, quoted values unquoted, and a bare key reads as true.
+ * @param {string} info What sits between the braces
+ * @returns {Record<string, string | number | boolean>}
+ */
+export function parseFenceOptions(info) {
+  /** @type {Record<string, string | number | boolean>} */
+  const options = {};
+  const optionRe = /([\w-]+)\s*(?:=\s*("(?:[^"\\]|\\.)*"|'[^']*'|[^,\s]+))?/g;
+  let m;
+  while ((m = optionRe.exec(info)) !== null) {
+    const raw = m[2];
+    options[m[1]] = raw === undefined || raw === 'true' ? true
+      : raw === 'false' ? false
+        : /^["']/.test(raw) ? raw.slice(1, -1).replace(/\\(.)/g, '$1')
+          : raw !== '' && Number.isFinite(Number(raw)) ? Number(raw)
+            : raw;
+  }
+  return options;
+}
+
+/**
+ * Whether code begins with a response header (old blockquote or heading format)
+ * @param {string} code
+ */
+function startsWithResponseHeader(code) {
+  return new RegExp('^(>|\\#{3,4})\\s*(\\*\\*\\S+\\*\\*|' + replyMarkerPattern() + '\\s*\\S+)\\s+to\\s+\\S+').test(code.trim());
+}
+
+/** Byte order mark some Windows editors put at the start of UTF-8 files */
+export const BOM = '\uFEFF';
+
+/**
+ * Drop a leading byte order mark, which would otherwise read as part of the first line
+ * @param {string} text
+ */
+export const stripBom = text => text.startsWith(BOM) ? text.slice(1) : text;
+
 /**
  * Parse a REPL request from markdown text
  * @param {string} text
@@ -329,6 +579,7 @@ export function formatFooter() {
  * @returns {ParsedRequest | null}
  */
 export function parseRequest(text, pageName) {
+  text = stripBom(text);
   const lines = text.split('\n');
   let footerIdx = -1;
   for (let i = lines.length - 1; i >= 0; i--) {
@@ -343,7 +594,7 @@ export function parseRequest(text, pageName) {
     if (!chunk.trim()) return null;
     
     // Try new format first (level-3 heading with emoji)
-    const newAgentRe = /^###\s*🗣️(\S+)\s+to\s+(\S+)\s+at\s+(\d{2}:\d{2}:\d{2})\s*$/;
+    const newAgentRe = new RegExp('^###\\s*' + agentMarkerPattern() + '\\s*(\\S+)\\s+to\\s+(\\S+)\\s+at\\s+(\\d{2}:\\d{2}:\\d{2})(?:\\s+#(\\w+))?\\s*$');
     // Fall back to old format (blockquote)
     const oldAgentRe = /^>\s*\*\*(\S+)\*\*\s+to\s+(\S+)\s+at\s+(\d{2}:\d{2}:\d{2})\s*$/;
     
@@ -353,37 +604,38 @@ export function parseRequest(text, pageName) {
     }
     
     const codeChunk = lines.slice(footerIdx + 1 + (headerMatch ? 1 : 0)).join('\n');
-    const codeMatch = /```(?:\s*(?:js|javascript))?\s*\n([\s\S]*?)```/i.exec(codeChunk);
+    // A trailing `worker` on the fence asks for the page's web worker: ```js worker
+    // and options in braces tune the job: ```js {timeout=5000, worker=true}
+    const codeMatch = /```(?:\s*(js|javascript|ts|typescript))?([^\S\n]+worker)?(?:[^\S\n]*\{([^}\n]*)\})?\s*\n([\s\S]*?)```/i.exec(codeChunk);
     
-    if (!codeMatch?.[1]?.trim()) return null;
+    if (!codeMatch?.[4]?.trim()) return null;
     
-    const code = codeMatch[1].endsWith('\n') ? codeMatch[1].slice(0, -1) : codeMatch[1];
+    const code = codeMatch[4].endsWith('\n') ? codeMatch[4].slice(0, -1) : codeMatch[4];
+    const fenceOptions = codeMatch[3] === undefined ? undefined : parseFenceOptions(codeMatch[3]);
 
     // Reject if code starts with a response header (old or new format)
-    if (/^(>|\#{3,4})\s*(\*\*\S+\*\*|[👍🚫]\S+)\s+to\s+\S+/.test(code.trim())) return null;
+    if (startsWithResponseHeader(code)) return null;
 
     return {
       agent: headerMatch?.[1] || 'agent',
       target: headerMatch?.[2] || pageName,
       time: headerMatch?.[3] || '',
       code: code,
+      language: fenceLanguage(codeMatch[1]),
+      ...((codeMatch[2] || fenceOptions?.worker === true) && { context: /** @type {const} */('worker') }),
+      ...(headerMatch?.[4] && { thread: headerMatch[4] }),
+      ...(fenceOptions && { options: fenceOptions }),
       hasFooter: true
     };
   }
 
   // No footer: seek last fenced block  
-  const codeRe = /^```(?:[a-z]*)?$[\r\n]([\s\S]*?)^```\s*$/img;
+  const codeRe = /^```([a-z]*)(?:[^\S\r\n]+worker)?(?:[^\S\r\n]*\{[^}\r\n]*\})?$[\r\n]([\s\S]*?)^```\s*$/img;
   let lastMatch = null, m;
-  while ((m = codeRe.exec(text)) !== null) {
-    // Only accept JS/javascript fences or fences with no language tag
-    const beforeCode = text.slice(Math.max(0, m.index - 100), m.index);
-    const fenceLine = beforeCode.split(/[\r\n]/).pop() || '';
-    const lang = fenceLine.replace(/^```/, '').trim().toLowerCase();
-    if (!lang || lang === 'js' || lang === 'javascript') {
-      lastMatch = { code: m[1], index: m.index };
-    }
-  }
-  if (!lastMatch) return null;
+  while ((m = codeRe.exec(text)) !== null)
+    lastMatch = { code: m[2], index: m.index, tag: m[1], lang: m[0].split(/[\r\n]/)[0].replace(/^```/, '') };
+  // Only a JavaScript or TypeScript fence, or one with no language tag, is a request
+  if (!lastMatch || !/^(?:js|javascript|ts|typescript)?$/i.test(lastMatch.tag)) return null;
 
   // Check for page reply header above (both old and new formats)
   const before = text.slice(0, lastMatch.index).split('\n');
@@ -396,7 +648,7 @@ export function parseRequest(text, pageName) {
       return null;
     }
     // Check for new format: #### 👍page or #### 🚫page
-    if (new RegExp('^#{4}\\s*[👍🚫]' + escName + '\\s+to\\s+\\S+\\s+at\\s+\\d{2}:\\d{2}:\\d{2}').test(before[idx].trim())) {
+    if (new RegExp('^#{4}\\s*' + replyMarkerPattern() + '\\s*' + escName + '\\s+to\\s+\\S+\\s+at\\s+\\d{2}:\\d{2}:\\d{2}').test(before[idx].trim())) {
       return null;
     }
   }
@@ -407,7 +659,13 @@ export function parseRequest(text, pageName) {
   if (!code.trim()) return null;
 
   // Reject if code starts with a response header (old or new format)
-  if (/^(>|\#{3,4})\s*(\*\*\S+\*\*|[👍🚫]\S+)\s+to\s+\S+/.test(code.trim())) return null;
-
-  return { agent: 'agent', target: pageName, time: '', code: code, hasFooter: false };
+  if (startsWithResponseHeader(code)) return null;
+
+  const info = /^([^{]*?)\s*(?:\{([^}]*)\})?$/.exec(lastMatch.lang) || ['', lastMatch.lang];
+  const fenceOptions = info[2] === undefined ? undefined : parseFenceOptions(info[2]);
+  const context = /\sworker$/i.test(info[1]) || fenceOptions?.worker === true ? /** @type {const} */('worker') : undefined;
+  return {
+    agent: 'agent', target: pageName, time: '', code: code, language: fenceLanguage(info[1]),
+    ...(context && { context }), ...(fenceOptions && { options: fenceOptions }), hasFooter: false
+  };
 }
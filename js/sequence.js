@@ -0,0 +1,48 @@
+// @ts-check
+import { fsStorage } from './storage.js';
+
+/** Name of the counters file inside the daebug directory */
+export const SEQUENCE_FILE = '.seq.json';
+
+/**
+ * Durable counters that keep counting across restarts: job ids, session numbers and the like.
+ * @typedef {{
+ *   next: (key: string) => number,
+ *   peek: (key: string) => number
+ * }} SequenceStore
+ */
+
+/**
+ * Counters kept in a JSON file, `{ "job": 12, "session": 3 }`. Every `next` re-reads the file,
+ * bumps the key and replaces the file through a rename, all synchronously: nothing else in the
+ * process can interleave with an increment, stores sharing the file see each other's counts,
+ * and a crash leaves either the old file or the new one, never half of one.
+ * @param {string} file
+ * @param {import('./storage.js').Storage} [storage]
+ * @returns {SequenceStore}
+ */
+export function createSequenceStore(file, storage = fsStorage) {
+  /** @returns {Record<string, number>} */
+  const load = () => {
+    if (!storage.exists(file)) return {};
+    try {
+      const counters = JSON.parse(storage.read(file));
+      return counters && typeof counters === 'object' ? counters : {};
+    } catch (err) {
+      console.warn(`[sequence] ignoring unreadable ${file}:`, err instanceof Error ? err.message : err);
+      return {};
+    }
+  };
+
+  return {
+    next: key => {
+      const counters = load();
+      const value = (Number(counters[key]) || 0) + 1;
+      counters[key] = value;
+      storage.write(file + '.tmp', JSON.stringify(counters, null, 2) + '\n');
+      storage.rename(file + '.tmp', file);
+      return value;
+    },
+    peek: key => Number(load()[key]) || 0
+  };
+}
@@ -0,0 +1,47 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { mkdtempSync, rmSync, existsSync } from 'node:fs';
+import { join } from 'node:path';
+import { tmpdir } from 'node:os';
+import { createSequenceStore, SEQUENCE_FILE } from './sequence.js';
+import { createMemoryStorage } from './storage.js';
+import { storedIds } from './ids.js';
+
+test('sequence store counts each key on its own', () => {
+  const store = createSequenceStore('/daebug/.seq.json', createMemoryStorage());
+  assert.deepEqual([store.next('job'), store.next('job'), store.next('session'), store.next('job')], [1, 2, 1, 3]);
+  assert.equal(store.peek('job'), 3);
+  assert.equal(store.peek('unused'), 0);
+});
+
+test('sequence store keeps counting after a restart', () => {
+  const dir = mkdtempSync(join(tmpdir(), 'daebug-seq-'));
+  try {
+    const file = join(dir, SEQUENCE_FILE);
+    const before = createSequenceStore(file);
+    const seen = [before.next('job'), before.next('job'), before.next('job')];
+
+    // A fresh store stands in for the restarted server reading the file back
+    const after = createSequenceStore(file);
+    seen.push(after.next('job'), before.next('job'));
+    assert.deepEqual(seen, [1, 2, 3, 4, 5]);
+    assert.equal(existsSync(file + '.tmp'), false);
+  } finally {
+    rmSync(dir, { recursive: true, force: true });
+  }
+});
+
+test('sequence store starts over from an unreadable file', () => {
+  const storage = createMemoryStorage({ '/seq.json': '{ not json' });
+  const store = createSequenceStore('/seq.json', storage);
+  assert.equal(store.next('job'), 1);
+  assert.deepEqual(JSON.parse(storage.read('/seq.json')), { job: 1 });
+});
+
+test('storedIds names jobs from the durable counter', () => {
+  const store = createSequenceStore('/seq.json', createMemoryStorage({ '/seq.json': '{"job":41}' }));
+  const ids = storedIds(store);
+  assert.equal(ids.next(1), '42');
+  assert.equal(ids.next(2), '43');
+});
@@ -0,0 +1,357 @@
+// @ts-check
+import { existsSync } from 'node:fs';
+import * as server from './server.js';
+import * as registry from './registry.js';
+import * as job from './job.js';
+import * as writer from './writer.js';
+import * as watcher from './watcher.js';
+import * as events from './events.js';
+import * as theme from './theme.js';
+import { storedIds } from './ids.js';
+
+/** Raised by `build()` when the collected options don't make sense together */
+export class ConfigError extends Error {
+  /** @param {string[]} problems */
+  constructor(problems) {
+    super('invalid server configuration: ' + problems.join('; '));
+    this.name = 'ConfigError';
+    this.problems = problems;
+  }
+}
+
+/**
+ * @typedef {{
+ *   root: string,
+ *   port: number,
+ *   start: (dirName: string, bannerPrefix: string) => Promise<void>,
+ *   markReady: () => void
+ * }} BuiltServer
+ */
+
+/**
+ * Collects server options from every module and applies them together once validated.
+ * Setters only record values; nothing is configured until `build()` succeeds.
+ */
+export class ServerBuilder {
+  /** @type {string | null} */
+  #root = null;
+  /** @type {number | null} */
+  #port = null;
+  /** @type {Partial<import('./server.js').ServerOptions>} */
+  #server = {};
+  /** @type {Partial<import('./registry.js').RegistryOptions>} */
+  #registry = {};
+  /** @type {Partial<import('./writer.js').WriterOptions>} */
+  #writer = {};
+  /** @type {Partial<import('./watcher.js').WatcherOptions>} */
+  #watcher = {};
+  /** @type {Partial<import('./job.js').JobManagerOptions>} */
+  #jobs = {};
+  /** @type {Partial<import('./events.js').EventLogOptions>} */
+  #events = {};
+  /** @type {Partial<import('./theme.js').Theme> | null} */
+  #theme = null;
+
+  /** @param {string} dir */
+  root(dir) { this.#root = dir; return this; }
+
+  /** @param {number} port */
+  port(port) { this.#port = port; return this; }
+
+  /** @param {string | null} token */
+  adminToken(token) { this.#server.adminToken = token; return this; }
+
+  /** @param {string | null} token Required on daebug's routes and logs; pages get it as a cookie with the client */
+  accessToken(token) { this.#server.accessToken = token; return this; }
+
+  /** @param {string} type Content-Type for daebug.md and page logs, e.g. 'text/plain; charset=utf-8' */
+  markdownContentType(type) { this.#server.markdownContentType = type; return this; }
+
+  /** @param {number | null} ms */
+  pollHold(ms) { this.#server.pollHoldMs = ms; return this; }
+
+  /** @param {number} ms Longest time limit a /-daebug-run request may ask for */
+  maxRunTimeout(ms) { this.#server.maxRunTimeoutMs = ms; return this; }
+
+  /** @param {number} count Most connections open at once before new ones are answered 503; 0 for no limit */
+  maxConnections(count) { this.#server.maxConnections = count; return this; }
+
+  /** @param {number} bytes Largest body a page or agent may post before it is answered 413 */
+  maxResultBytes(bytes) { this.#server.maxResultBytes = bytes; return this; }
+
+  /**
+   * Serve over HTTPS instead of plain HTTP
+   * @param {string} cert Path to the PEM certificate (chain)
+   * @param {string} key Path to its PEM private key
+   */
+  tls(cert, key) { this.#server.tls = { cert, key }; return this; }
+
+  /**
+   * Let pages from other origins poll and post results
+   * @param {string[]} origins e.g. ['http://localhost:5173'], or ['*'] for any
+   * @param {boolean} [credentials] Let them send cookies and authorization too
+   */
+  cors(origins, credentials = false) { this.#server.cors = { origins, credentials }; return this; }
+
+  /** @param {string[] | RegExp | null} names */
+  allowedPages(names) { this.#registry.allowedPages = names; return this; }
+
+  /** @param {string[] | null} hosts */
+  allowedHosts(hosts) { this.#registry.allowedHosts = hosts; return this; }
+
+  /** @param {boolean} enabled */
+  validateUrls(enabled) { this.#registry.validateUrls = enabled; return this; }
+
+  /** @param {number} max @param {'evict' | 'reject'} [whenFull] */
+  maxPages(max, whenFull) {
+    this.#registry.maxPages = max;
+    if (whenFull) this.#registry.whenFull = whenFull;
+    return this;
+  }
+
+  /** @param {number} ms Pages not seen for this long leave the registry; 0 keeps them */
+  presenceTtl(ms) { this.#registry.presenceTtlMs = ms; return this; }
+
+  /** @param {number} ms Logs of unregistered pages untouched for this long are deleted; 0 keeps them */
+  logRetention(ms) { this.#registry.logRetentionMs = ms; return this; }
+
+  /** @param {'name' | 'last-seen'} order */
+  listOrder(order) { this.#registry.listOrder = order; return this; }
+
+  /** @param {number} ms Default time a job may run; pages can override it in their config file */
+  jobTimeout(ms) { this.#jobs.timeoutMs = ms; return this; }
+
+  /** @param {number} ms How long finished jobs' results stay available at /-daebug-job/<id>/result */
+  resultRetention(ms) { this.#jobs.resultRetentionMs = ms; return this; }
+
+  /** @param {number} count Finished jobs' results kept per page within the retention window; 0 keeps them all */
+  resultsPerPage(count) { this.#jobs.resultsPerPage = count; return this; }
+
+  /** @param {number} bytes Most bytes of finished jobs' results held in memory, oldest dropped first; 0 for no cap */
+  resultRetentionBytes(bytes) { this.#jobs.resultRetentionBytes = bytes; return this; }
+
+  /** @param {number} ms How long a run's Idempotency-Key keeps answering with the job it created */
+  idempotencyWindow(ms) { this.#jobs.idempotencyWindowMs = ms; return this; }
+
+  /** @param {number} count Most Idempotency-Keys remembered at once, oldest forgotten first */
+  idempotencyKeys(count) { this.#jobs.idempotencyMaxKeys = count; return this; }
+
+  /**
+   * Limit each agent's jobs; 0 leaves a limit off
+   * @param {{ perMinute?: number, concurrent?: number }} limits
+   */
+  agentQuota({ perMinute, concurrent }) {
+    if (perMinute !== undefined) this.#jobs.agentJobsPerMinute = perMinute;
+    if (concurrent !== undefined) this.#jobs.agentMaxConcurrent = concurrent;
+    return this;
+  }
+
+  /** @param {import('./ids.js').IdGenerator} generator How new jobs are named, e.g. uuidIds for several servers */
+  jobIds(generator) { this.#jobs.idGenerator = generator; return this; }
+
+  /**
+   * Number jobs and sessions from durable counters, e.g. `createSequenceStore('daebug/.seq.json')`,
+   * so they keep climbing across restarts
+   * @param {import('./sequence.js').SequenceStore} store
+   */
+  sequences(store) {
+    this.#jobs.sequences = store;
+    this.#jobs.idGenerator = storedIds(store);
+    return this;
+  }
+
+  /** @param {import('./transpile.js').Transpiler | null} transpiler Used for TypeScript jobs on pages that only run JavaScript */
+  transpiler(transpiler) { this.#jobs.transpiler = transpiler; return this; }
+
+  /** @param {import('./job.js').CodeTransform | null} transform Applied to each job's code as it is sent to the page */
+  transformCode(transform) { this.#jobs.transformCode = transform; return this; }
+
+  /** @param {import('./dispatch.js').DispatchStrategy} strategy */
+  dispatch(strategy) { this.#jobs.strategy = strategy; return this; }
+
+  /** @param {{ deny?: RegExp[], allow?: RegExp[] | null }} rules */
+  safeMode({ deny, allow }) {
+    if (deny) this.#jobs.denyPatterns = deny;
+    if (allow !== undefined) this.#jobs.allowPatterns = allow;
+    return this;
+  }
+
+  /** @param {boolean} enabled List each page's queued and running jobs in daebug.md */
+  showJobQueues(enabled) { this.#registry.jobSummary = enabled ? job.queueSummary : null; return this; }
+
+  /** @param {'append' | 'newest-first'} order */
+  replyOrder(order) { this.#writer.order = order; return this; }
+
+  /** @param {boolean} enabled */
+  dedupReplies(enabled) { this.#writer.dedupReplies = enabled; return this; }
+
+  /** @param {number} ms @param {number} [maxBatch] */
+  coalesceWrites(ms, maxBatch) {
+    this.#writer.coalesceMs = ms;
+    if (maxBatch !== undefined) this.#writer.coalesceMaxBatch = maxBatch;
+    return this;
+  }
+
+  /**
+   * Buffer a log's writes once its results arrive this fast, flushing them together
+   * @param {number} perSecond Result rate that switches buffering on; 0 never buffers
+   * @param {{ flushMs?: number, maxBatch?: number }} [limits]
+   */
+  adaptiveFlush(perSecond, { flushMs, maxBatch } = {}) {
+    this.#writer.adaptiveFlushRate = perSecond;
+    if (flushMs !== undefined) this.#writer.adaptiveFlushMs = flushMs;
+    if (maxBatch !== undefined) this.#writer.adaptiveMaxBatch = maxBatch;
+    return this;
+  }
+
+  /** @param {number} bytes @param {boolean} [compress] */
+  rotateLogs(bytes, compress) {
+    this.#writer.rotateBytes = bytes;
+    if (compress !== undefined) this.#writer.compressArchives = compress;
+    return this;
+  }
+
+  /** @param {'preserve' | 'lf'} mode Keep each log's own line endings, or always write LF */
+  lineEndings(mode) { this.#writer.lineEndings = mode; return this; }
+
+  /**
+   * Content types result attachments may be written with, as `type/subtype` or `type/*`
+   * @param {string[]} allow
+   * @param {string[]} [deny] Refused even when `allow` matches
+   */
+  attachmentTypes(allow, deny = []) { this.#writer.attachmentTypes = { allow, deny }; return this; }
+
+  /** @param {'preserve' | 'strip'} mode Keep a log's leading byte order mark on rewrite, or drop it */
+  byteOrderMark(mode) { this.#writer.byteOrderMark = mode; return this; }
+
+  /**
+   * @param {'keep' | 'one' | 'none'} newline How many newlines a log ends with after each write
+   * @param {boolean} [stripSpaces] Also drop spaces and tabs at the ends of lines
+   */
+  trailingWhitespace(newline, stripSpaces) {
+    this.#writer.trailingNewline = newline;
+    if (stripSpaces !== undefined) this.#writer.stripTrailingSpaces = stripSpaces;
+    return this;
+  }
+
+  /** @param {import('./writer.js').ResultTransform | null} transform Applied to each result before it is written */
+  transformResults(transform) { this.#writer.transformResult = transform; return this; }
+
+  /** @param {number} bytes Size past which a log takes no more writes; 0 for no limit */
+  maxLogSize(bytes) { this.#writer.maxLogBytes = bytes; return this; }
+
+  /** @param {number} bytes Request code longer than this goes to a linked sidecar file; 0 keeps it inline */
+  codeSidecars(bytes) { this.#writer.codeSidecarBytes = bytes; return this; }
+
+  /** @param {'off' | 'also' | 'only'} mode Write each result to results/<job>.json as well as, or instead of, the log */
+  resultFiles(mode) { this.#writer.resultFiles = mode; return this; }
+
+  /** @param {boolean} enabled Write page logs at all; off, results go only to result files, HTTP and events */
+  markdown(enabled) { this.#writer.markdown = enabled; return this; }
+
+  /** @param {boolean} enabled */
+  threadIds(enabled) { this.#writer.threadIds = enabled; return this; }
+
+  /** @param {boolean} enabled */
+  executingIndicator(enabled) { this.#writer.executingIndicator = enabled; return this; }
+
+  /** @param {boolean} enabled */
+  persistContentHashes(enabled) { this.#watcher.persistContentHashes = enabled; return this; }
+
+  /** @param {number} count Lanes page logs are re-read in; one page's checks always share a lane */
+  watchShards(count) { this.#watcher.shards = count; return this; }
+
+  /** @param {string | null} file */
+  eventLog(file) { this.#events.file = file; return this; }
+
+  /**
+   * Drop repeats of an event type for the same page and payload within a window
+   * @param {string} type
+   * @param {number} windowMs 0 records every repeat
+   */
+  compactEvents(type, windowMs) { this.#events.compact = { ...this.#events.compact, [type]: windowMs }; return this; }
+
+  /** @param {Partial<import('./theme.js').Theme>} glyphs */
+  theme(glyphs) { this.#theme = glyphs; return this; }
+
+  /** @returns {string[]} */
+  #problems() {
+    const problems = [];
+    if (!this.#root) problems.push('root is required');
+    if (this.#port === null || !Number.isInteger(this.#port) || this.#port < 0 || this.#port > 65535)
+      problems.push(`port must be an integer between 0 and 65535 (got ${this.#port})`);
+    if (this.#server.adminToken === '') problems.push('admin token must not be empty');
+    if (this.#server.accessToken === '') problems.push('access token must not be empty');
+    if (this.#server.markdownContentType !== undefined && !this.#server.markdownContentType.trim()) problems.push('markdown content type must not be empty');
+    if (this.#server.pollHoldMs != null && !(this.#server.pollHoldMs >= 0)) problems.push('poll hold must be a non-negative number of milliseconds');
+    if (this.#server.maxRunTimeoutMs !== undefined && !(this.#server.maxRunTimeoutMs > 0)) problems.push('maximum run timeout must be positive');
+    if (this.#server.maxConnections !== undefined && !(Number.isInteger(this.#server.maxConnections) && this.#server.maxConnections >= 0)) problems.push('maximum connections must be a non-negative integer');
+    if (this.#server.maxResultBytes !== undefined && !(Number.isInteger(this.#server.maxResultBytes) && this.#server.maxResultBytes > 0)) problems.push('maximum result size must be a positive integer');
+    if (this.#server.tls && !(this.#server.tls.cert && this.#server.tls.key)) problems.push('TLS needs both a certificate and a key path');
+    if (this.#server.cors && !(this.#server.cors.origins.length && this.#server.cors.origins.every(origin => typeof origin === 'string' && origin)))
+      problems.push('CORS needs at least one origin, each a non-empty string');
+    if (this.#server.cors?.credentials && this.#server.cors.origins.includes('*'))
+      problems.push('CORS credentials need an explicit list of origins, not *');
+    for (const path of this.#server.tls ? [this.#server.tls.cert, this.#server.tls.key] : [])
+      if (path && !existsSync(path)) problems.push(`TLS file not found: ${path}`);
+    if (this.#jobs.timeoutMs !== undefined && !(this.#jobs.timeoutMs > 0)) problems.push('job timeout must be positive');
+    if (this.#jobs.agentJobsPerMinute !== undefined && !(this.#jobs.agentJobsPerMinute >= 0)) problems.push('agent jobs per minute must be non-negative');
+    if (this.#jobs.agentMaxConcurrent !== undefined && !(this.#jobs.agentMaxConcurrent >= 0)) problems.push('agent concurrent jobs must be non-negative');
+    if (this.#jobs.resultRetentionMs !== undefined && !(this.#jobs.resultRetentionMs >= 0)) problems.push('result retention must be non-negative');
+    if (this.#jobs.resultsPerPage !== undefined && !(Number.isInteger(this.#jobs.resultsPerPage) && this.#jobs.resultsPerPage >= 0)) problems.push('results per page must be a non-negative integer');
+    if (this.#jobs.resultRetentionBytes !== undefined && !(Number.isInteger(this.#jobs.resultRetentionBytes) && this.#jobs.resultRetentionBytes >= 0)) problems.push('result retention bytes must be a non-negative integer');
+    if (this.#jobs.idempotencyWindowMs !== undefined && !(this.#jobs.idempotencyWindowMs >= 0)) problems.push('idempotency window must be non-negative');
+    if (this.#jobs.idempotencyMaxKeys !== undefined && !(Number.isInteger(this.#jobs.idempotencyMaxKeys) && this.#jobs.idempotencyMaxKeys >= 1)) problems.push('idempotency keys must be a positive integer');
+    if (this.#registry.presenceTtlMs !== undefined && !(this.#registry.presenceTtlMs >= 0)) problems.push('presence TTL must be non-negative');
+    if (this.#registry.logRetentionMs !== undefined && !(this.#registry.logRetentionMs >= 0)) problems.push('log retention must be non-negative');
+    if (this.#registry.maxPages !== undefined && !(this.#registry.maxPages >= 1)) problems.push('maxPages must be at least 1');
+    if (this.#registry.whenFull && !['evict', 'reject'].includes(this.#registry.whenFull)) problems.push(`unknown whenFull policy ${this.#registry.whenFull}`);
+    if (this.#registry.listOrder && !['name', 'last-seen'].includes(this.#registry.listOrder)) problems.push(`unknown list order ${this.#registry.listOrder}`);
+    if (this.#writer.order && !['append', 'newest-first'].includes(this.#writer.order)) problems.push(`unknown reply order ${this.#writer.order}`);
+    if (this.#writer.resultFiles && !['off', 'also', 'only'].includes(this.#writer.resultFiles)) problems.push(`unknown result files mode ${this.#writer.resultFiles}`);
+    if (this.#writer.trailingNewline && !['keep', 'one', 'none'].includes(this.#writer.trailingNewline)) problems.push(`unknown trailing newline policy ${this.#writer.trailingNewline}`);
+    if (this.#writer.lineEndings && !['preserve', 'lf'].includes(this.#writer.lineEndings)) problems.push(`unknown line endings ${this.#writer.lineEndings}`);
+    for (const pattern of [...this.#writer.attachmentTypes?.allow || [], ...this.#writer.attachmentTypes?.deny || []])
+      if (!/^([\w.+-]+|\*)\/([\w.+-]+|\*)$/.test(pattern)) problems.push(`attachment type ${pattern} must look like type/subtype or type/*`);
+    if (this.#writer.byteOrderMark && !['preserve', 'strip'].includes(this.#writer.byteOrderMark)) problems.push(`unknown byte order mark mode ${this.#writer.byteOrderMark}`);
+    if (this.#writer.coalesceMs !== undefined && !(this.#writer.coalesceMs >= 0)) problems.push('coalesce window must be non-negative');
+    if (this.#writer.rotateBytes !== undefined && !(this.#writer.rotateBytes >= 0)) problems.push('rotation size must be non-negative');
+    if (this.#writer.maxLogBytes !== undefined && !(this.#writer.maxLogBytes >= 0)) problems.push('maximum log size must be non-negative');
+    if (this.#writer.adaptiveFlushRate !== undefined && !(this.#writer.adaptiveFlushRate >= 0)) problems.push('adaptive flush rate must be non-negative');
+    if (this.#writer.adaptiveFlushMs !== undefined && !(this.#writer.adaptiveFlushMs > 0)) problems.push('adaptive flush interval must be positive');
+    if (this.#writer.adaptiveMaxBatch !== undefined && !(this.#writer.adaptiveMaxBatch >= 1)) problems.push('adaptive flush batch must be at least 1');
+    if (this.#writer.coalesceMaxBatch !== undefined && !(this.#writer.coalesceMaxBatch >= 1)) problems.push('coalesce batch must be at least 1');
+    if (this.#watcher.shards !== undefined && !(Number.isInteger(this.#watcher.shards) && this.#watcher.shards >= 1)) problems.push('watch shards must be a positive integer');
+    for (const [type, windowMs] of Object.entries(this.#events.compact || {}))
+      if (!(windowMs >= 0)) problems.push(`compaction window for ${type} events must be non-negative`);
+    return problems;
+  }
+
+  /**
+   * Validate and apply the configuration
+   * @returns {BuiltServer}
+   */
+  build() {
+    const problems = this.#problems();
+    if (problems.length) throw new ConfigError(problems);
+
+    server.configure(this.#server);
+    registry.configure(this.#registry);
+    writer.configure(this.#writer);
+    watcher.configure(this.#watcher);
+    job.configure(this.#jobs);
+    events.configure(this.#events);
+    if (this.#theme) theme.configure(this.#theme);
+
+    const root = /** @type {string} */(this.#root);
+    const port = /** @type {number} */(this.#port);
+    return {
+      root,
+      port,
+      start: (dirName, bannerPrefix) => server.start(root, port, dirName, bannerPrefix),
+      markReady: () => server.markReady()
+    };
+  }
+}
+
+export const builder = () => new ServerBuilder();
@@ -0,0 +1,61 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { builder, ConfigError } from './server-builder.js';
+import * as registry from './registry.js';
+import * as writer from './writer.js';
+import * as server from './server.js';
+import * as theme from './theme.js';
+
+test('a fully configured build applies every option', () => {
+  try {
+    const app = builder()
+      .root('/srv/site')
+      .port(8302)
+      .adminToken('secret')
+      .pollHold(5000)
+      .allowedPages(['a', 'b'])
+      .allowedHosts(['localhost'])
+      .validateUrls(true)
+      .maxPages(10, 'reject')
+      .replyOrder('newest-first')
+      .dedupReplies(true)
+      .coalesceWrites(20, 10)
+      .executingIndicator(false)
+      .persistContentHashes(true)
+      .eventLog(null)
+      .theme(theme.PLAIN_THEME)
+      .build();
+
+    assert.strictEqual(app.root, '/srv/site');
+    assert.strictEqual(app.port, 8302);
+    assert.strictEqual(typeof app.start, 'function');
+    assert.strictEqual(theme.getTheme().success, theme.PLAIN_THEME.success);
+    assert.throws(() => registry.validateUrl('ftp://localhost/'), registry.RegistrationError);
+  } finally {
+    server.configure({ adminToken: null, pollHoldMs: null });
+    registry.configure({ allowedPages: null, allowedHosts: null, validateUrls: false, maxPages: Infinity, whenFull: 'evict' });
+    writer.configure({ order: 'append', dedupReplies: false, coalesceMs: 0, coalesceMaxBatch: 100, executingIndicator: true });
+    theme.configure(theme.EMOJI_THEME);
+  }
+});
+
+test('build reports every invalid option and applies none', () => {
+  let err;
+  try {
+    builder().port(70000).maxPages(0).coalesceWrites(-1).tls('/no/such/cert.pem', '').cors(['*'], true).idempotencyKeys(0).theme(theme.PLAIN_THEME).build();
+  } catch (e) {
+    err = e;
+  }
+
+  assert.ok(err instanceof ConfigError);
+  assert.ok(err.problems.some(p => p.includes('root is required')));
+  assert.ok(err.problems.some(p => p.includes('port must be')));
+  assert.ok(err.problems.some(p => p.includes('maxPages')));
+  assert.ok(err.problems.some(p => p.includes('coalesce window')));
+  assert.ok(err.problems.some(p => p.includes('TLS needs both')));
+  assert.ok(err.problems.some(p => p.includes('TLS file not found: /no/such/cert.pem')));
+  assert.ok(err.problems.some(p => p.includes('CORS credentials need an explicit list')));
+  assert.ok(err.problems.some(p => p.includes('idempotency keys must be')));
+  assert.strictEqual(theme.getTheme().success, theme.EMOJI_THEME.success);
+});
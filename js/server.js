@@ -1,14 +1,23 @@
 // @ts-check
 import { createServer } from 'node:http';
+import { createServer as createSecureServer } from 'node:https';
 import { readFileSync, writeFileSync, createReadStream, existsSync, readdirSync, statSync } from 'node:fs';
 import { join, extname, relative, sep, resolve, normalize } from 'node:path';
 import { URL } from 'node:url';
+import { timingSafeEqual, createHmac } from 'node:crypto';
 import * as registry from './registry.js';
 import * as job from './job.js';
 import * as writer from './writer.js';
 import * as watcher from './watcher.js';
-import { clientScript } from './client.js';
+import { summarizeDocument, diagnoseDocument } from './parser.js';
+import { transcript, formatTranscript } from './transcript.js';
+import { withPage } from './page-lock.js';
+import * as state from './snapshot.js';
+import { openApiDocument } from './openapi.js';
+import * as events from './events.js';
+import { clientScript, clientScriptWith } from './client.js';
 import { installShutdownHandlers } from './shutdown.js';
+import { acceptUpgrade, refuseUpgrade } from './websocket.js';
 import { formatTestProgress as formatTestProgressTemplate } from './test.template.js';
 import { nodeTestContent } from './modules/node-test.js';
 import { nodeAssertContent } from './modules/node-assert.js';
@@ -120,17 +129,248 @@ const DAEBUG_IMPORT_MAPS = {
 };
 
 /**
+ * @typedef {{
+ *   adminToken: string | null,
+ *   accessToken: string | null,
+ *   pollHoldMs: number | null,
+ *   maxRunTimeoutMs: number,
+ *   markdownContentType: string,
+ *   maxConnections: number,
+ *   maxResultBytes: number,
+ *   tls: { cert: string, key: string } | null,
+ *   cors: { origins: string[], credentials: boolean } | null
+ * }} ServerOptions
+ */
+
+/** @type {ServerOptions} */
+const options = {
+  adminToken: null,
+  // Required on daebug's own routes and logs when set; the site being debugged stays open
+  accessToken: null,
+  pollHoldMs: null,
+  maxRunTimeoutMs: 5 * 60_000,
+  markdownContentType: MIME['.md'],
+  // Most sockets open at once, long polls and websockets included; 0 for no limit
+  maxConnections: 0,
+  // Largest body a page or agent may post; bigger ones are answered 413, and a result fails its job
+  maxResultBytes: 16 * 1024 * 1024,
+  // PEM certificate and key paths; when set, pages, agents and websockets all go over TLS
+  tls: null,
+  // Origins (or '*') whose pages may call daebug cross-origin, and whether with cookies and auth
+  cors: null
+};
+
+/** Scheme the server answers on */
+const scheme = () => options.tls ? 'https' : 'http';
+
+/**
+ * Content-Type for a served file. Markdown logs and daebug.md use the configured type,
+ * since some proxies and viewers handle text/plain better than text/markdown.
+ * @param {string} file
+ */
+export function contentTypeFor(file) {
+  const ext = extname(file).toLowerCase();
+  if (ext === '.md' || ext === '.markdown') return options.markdownContentType;
+  return MIME[ext] || 'application/octet-stream';
+}
+
+/** How long a poll is held open waiting for work; randomized 10-15s unless configured */
+const pollHold = () => options.pollHoldMs ?? 10000 + Math.random() * 5000;
+
+/**
+ * A channel request a page is holding open: a long-poll for work or a cancellation watch
+ * @typedef {{
+ *   page: string,
+ *   instance: string | null,
+ *   kind: 'poll' | 'cancel-watch',
+ *   connectedAt: string,
+ *   lastSeen: string
+ * }} Connection
+ */
+
+/** @type {Map<number, Connection>} */
+const openConnections = new Map();
+let nextConnectionId = 1;
+
+/**
+ * Record a held channel request until the returned release function is called
+ * @param {import('./registry.js').Page} page
+ * @param {string | null} instance
+ * @param {Connection['kind']} kind
+ * @param {import('http').ServerResponse} res
+ */
+function trackConnection(page, instance, kind, res) {
+  const id = nextConnectionId++;
+  openConnections.set(id, {
+    page: page.name,
+    instance,
+    kind,
+    connectedAt: new Date().toISOString(),
+    lastSeen: new Date(page.lastSeen).toISOString()
+  });
+  const release = () => { openConnections.delete(id); };
+  // A page that goes away mid-hold drops off the list right away
+  res.once?.('close', release);
+  return release;
+}
+
+/**
+ * Order for JSON listings: stable from one request to the next, whatever order pages poll in
+ * or the registry is configured to list them in
+ * @param {{ name?: string, page?: string }} a
+ * @param {{ name?: string, page?: string }} b
+ */
+const byName = (a, b) => {
+  const x = a.name ?? a.page ?? '', y = b.name ?? b.page ?? '';
+  return x < y ? -1 : x > y ? 1 : 0;
+};
+
+/** Channel requests pages are holding open right now */
+export const connections = () => Array.from(openConnections.values())
+  .sort((a, b) => byName(a, b) || (a.connectedAt < b.connectedAt ? -1 : a.connectedAt > b.connectedAt ? 1 : 0));
+
+/** @param {Partial<ServerOptions>} config */
+export function configure(config) {
+  Object.assign(options, config);
+}
+
+/**
+ * Admin endpoints stay disabled until an admin token is configured
+ * @param {import('http').IncomingMessage} req
+ */
+function isAdmin(req) {
+  return !!options.adminToken && req.headers.authorization === `Bearer ${options.adminToken}`;
+}
+
+/** Response headers a cross-origin page may read */
+const CORS_EXPOSED_HEADERS = 'x-job-id, x-prelude-hash, x-prelude, x-run-id, x-snapshot-id, Retry-After';
+
+/**
+ * Add CORS headers for a request from an allowed origin, and answer its preflight.
+ * Requests from other origins get no headers, so the browser keeps their pages out.
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ * @returns {boolean} Whether the request was a preflight, now answered
+ */
+export function applyCors(req, res) {
+  const origin = req.headers.origin;
+  const cors = options.cors;
+  if (!cors || !origin) return false;
+  const any = cors.origins.includes('*');
+  if (!any && !cors.origins.includes(origin)) return false;
+
+  res.setHeader('Access-Control-Allow-Origin', any && !cors.credentials ? '*' : origin);
+  res.setHeader('Vary', 'Origin');
+  if (cors.credentials) res.setHeader('Access-Control-Allow-Credentials', 'true');
+  res.setHeader('Access-Control-Expose-Headers', CORS_EXPOSED_HEADERS);
+  if (req.method !== 'OPTIONS' || !req.headers['access-control-request-method']) return false;
+
+  res.writeHead(204, {
+    'Access-Control-Allow-Methods': 'GET, POST, PUT, DELETE',
+    'Access-Control-Allow-Headers': req.headers['access-control-request-headers'] || 'Content-Type, Authorization',
+    'Access-Control-Max-Age': '600'
+  }).end();
+  return true;
+}
+
+/**
+ * Cookie that lets a page and its workers reach daebug. It carries a credential derived from
+ * the access token, never the token itself, and only requests that already have access get it.
+ */
+const PAGE_COOKIE = 'daebug-page';
+const PAGE_COOKIE_PATTERN = /(?:^|;\s*)daebug-page=([^;]*)/;
+
+/** Query parameter a browser opens a page with, `?daebug-token=<token>`, to be given the page cookie */
+const ACCESS_QUERY = 'daebug-token';
+
+/** The page cookie's value for the configured access token */
+const pageCredential = () => createHmac('sha256', /** @type {string} */(options.accessToken)).update('daebug page').digest('base64url');
+
+/**
+ * The page cookie as the request sent it; a value that won't decode counts as none
+ * @param {import('http').IncomingMessage} req
+ * @returns {string | undefined}
+ */
+function pageCookie(req) {
+  const raw = PAGE_COOKIE_PATTERN.exec(req.headers.cookie || '')?.[1];
+  if (raw === undefined) return undefined;
+  try {
+    return decodeURIComponent(raw);
+  } catch {
+    return undefined;
+  }
+}
+
+/** @param {string | undefined} given @param {string} expected */
+function tokenMatches(given, expected) {
+  if (given === undefined) return false;
+  const a = Buffer.from(given), b = Buffer.from(expected);
+  return a.length === b.length && timingSafeEqual(a, b);
+}
+
+/**
+ * Whether a request may reach daebug's routes: always when no access token is configured,
+ * otherwise with the token (or the admin token) as a bearer, or the page cookie
+ * @param {import('http').IncomingMessage} req
+ */
+export function hasAccess(req) {
+  if (!options.accessToken) return true;
+  const bearer = /^Bearer (.+)$/.exec(req.headers.authorization || '')?.[1];
+  return tokenMatches(bearer, options.accessToken)
+    || (!!options.adminToken && tokenMatches(bearer, options.adminToken))
+    || tokenMatches(pageCookie(req), pageCredential());
+}
+
+/**
+ * Set-Cookie for a page being served, when an access token is configured and the request
+ * already has access, or names the token in `?daebug-token=`
+ * @param {import('http').IncomingMessage} req
+ * @param {URL} url
+ * @returns {string | undefined}
+ */
+export function pageCookieFor(req, url) {
+  if (!options.accessToken) return undefined;
+  if (!hasAccess(req) && !tokenMatches(url.searchParams.get(ACCESS_QUERY) ?? undefined, options.accessToken)) return undefined;
+  return `${PAGE_COOKIE}=${pageCredential()}; Path=/; HttpOnly; SameSite=Strict${options.tls ? '; Secure' : ''}`;
+}
+
+/**
+ * Paths an access token guards: every daebug route but the readiness probe and the client
+ * script, plus the registry and the page logs. The site's own files are served to anyone, as before.
+ * @param {string} pathname
+ */
+export const needsAccess = pathname =>
+  (pathname.startsWith('/-daebug') && pathname !== '/-daebug-health/ready' && pathname !== '/-daebug-client.js')
+  || pathname === '/daebug.md' || pathname.startsWith('/daebug/');
+
+/**
+ * Every route daebug serves, with the site's own files from root
  * @param {string} root
- * @param {number} port
- * @param {string} dirName
- * @param {string} bannerPrefix
+ * @returns {import('http').RequestListener}
  */
-export async function start(root, port, dirName, bannerPrefix) {
-  // Install handlers for graceful shutdown on Ctrl+C and other signals
-  installShutdownHandlers(root);
-  
-  const server = createServer((req, res) => {
+export function requestHandler(root) {
+  return (req, res) => {
     const url = new URL(req.url || '/', `http://${req.headers.host}`);
+    // Preflights carry no credentials, so they are answered before the access check
+    if (applyCors(req, res)) return;
+    
+    if (url.pathname === '/-daebug-health/ready' && req.method === 'GET') {
+      return handleReady(res);
+    }
+
+    // Pages on other origins load the client by <script src>, which may not carry the page cookie
+    if (url.pathname === '/-daebug-client.js' && req.method === 'GET') {
+      return res.writeHead(200, {
+        'Content-Type': MIME['.js'],
+        'Cache-Control': 'no-store, no-cache, must-revalidate, max-age=0',
+        'Pragma': 'no-cache'
+      }).end(clientScriptWith(options.cors?.credentials ? 'include' : 'same-origin'));
+    }
+
+    if (needsAccess(url.pathname) && !hasAccess(req)) {
+      console.warn(`👾𝟰𝟬𝟭 ${url.pathname}`);
+      return res.writeHead(401, { 'Content-Type': 'text/plain', 'WWW-Authenticate': 'Bearer' }).end('access token required');
+    }
     
     // Polling endpoints
     if (url.pathname === '/-daebug-channel') {
@@ -150,6 +390,125 @@ export async function start(root, port, dirName, bannerPrefix) {
       ]);
     }
     
+    // Per-page actions: forced eviction, structural summary of the log, dropping pending jobs
+    const evictMatch = /^\/-daebug-page\/([^/]+)$/.exec(url.pathname);
+    if (evictMatch && req.method === 'DELETE') {
+      return handleEvictPage(root, decodeURIComponent(evictMatch[1]), url, req, res);
+    }
+    const pageMatch = /^\/-daebug-page\/([^/]+)\/(ast|drain|prelude|reindex|diagnose|ask|stream)$/.exec(url.pathname);
+    if (pageMatch?.[2] === 'ast' && req.method === 'GET') {
+      return handlePageAst(decodeURIComponent(pageMatch[1]), res);
+    }
+    if (pageMatch?.[2] === 'diagnose' && req.method === 'GET') {
+      return handleDiagnose(decodeURIComponent(pageMatch[1]), res);
+    }
+    if (pageMatch?.[2] === 'stream' && req.method === 'GET') {
+      return handleLogStream(decodeURIComponent(pageMatch[1]), req, res);
+    }
+    if (pageMatch?.[2] === 'drain' && req.method === 'POST') {
+      return handleDrain(decodeURIComponent(pageMatch[1]), url, res);
+    }
+    if (pageMatch?.[2] === 'prelude' && (req.method === 'PUT' || req.method === 'DELETE')) {
+      return handlePrelude(decodeURIComponent(pageMatch[1]), req, res);
+    }
+    if (pageMatch?.[2] === 'reindex' && req.method === 'POST') {
+      return handleReindex(root, decodeURIComponent(pageMatch[1]), res);
+    }
+    
+    // Requests pages make of their agents, and the agents' answers
+    if (pageMatch?.[2] === 'ask' && req.method === 'POST') {
+      return readJson(req, res, payload => askAgents(decodeURIComponent(pageMatch[1]), payload, res));
+    }
+    if (url.pathname === '/-daebug-page-requests' && req.method === 'GET') {
+      return handlePageRequests(url, res);
+    }
+    const pageRequestMatch = /^\/-daebug-page-request\/([^/]+)$/.exec(url.pathname);
+    if (pageRequestMatch && req.method === 'GET') {
+      const request = job.getPageRequest(decodeURIComponent(pageRequestMatch[1]));
+      if (!request) return res.writeHead(404).end('no page request with id ' + pageRequestMatch[1]);
+      return res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(request));
+    }
+    if (pageRequestMatch && req.method === 'POST') {
+      return readJson(req, res, payload => answerAsk(decodeURIComponent(pageRequestMatch[1]), payload, res));
+    }
+    
+    // Structured outcome of a finished job
+    const resultMatch = /^\/-daebug-job\/([^/]+)\/result$/.exec(url.pathname);
+    if (resultMatch && req.method === 'GET') {
+      return handleJobResult(decodeURIComponent(resultMatch[1]), res);
+    }
+    
+    // Sessions grouping an agent's related jobs, and job listings filtered by session
+    if (url.pathname === '/-daebug-session' && req.method === 'POST') {
+      return handleOpenSession(req, res);
+    }
+    const sessionMatch = /^\/-daebug-session\/([^/]+)(\/export)?$/.exec(url.pathname);
+    if (sessionMatch?.[2] && req.method === 'GET') {
+      return handleSessionExport(decodeURIComponent(sessionMatch[1]), url, res);
+    }
+    if (sessionMatch && (req.method === 'GET' || req.method === 'DELETE')) {
+      return handleSession(decodeURIComponent(sessionMatch[1]), req.method, res);
+    }
+    if (url.pathname === '/-daebug-jobs' && req.method === 'GET') {
+      return handleListJobs(url, res);
+    }
+    if (url.pathname === '/-daebug-queue' && req.method === 'GET') {
+      return handleQueue(res);
+    }
+    if (url.pathname === '/-daebug-reap' && req.method === 'POST') {
+      return handleReap(res);
+    }
+    
+    // Channel requests currently held open by pages
+    if (url.pathname === '/-daebug-connections' && req.method === 'GET') {
+      return res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ connections: connections() }));
+    }
+    
+    // Registered pages, optionally with their job queues
+    if (url.pathname === '/-daebug-pages' && req.method === 'GET') {
+      return handlePages(url, res);
+    }
+    
+    // Filtered event history
+    if (url.pathname === '/-daebug-events' && req.method === 'GET') {
+      return handleEventStream(url, req, res);
+    }
+    if (url.pathname === '/-daebug-events/history' && req.method === 'GET') {
+      return handleEventHistory(url, res);
+    }
+    
+    // Machine-readable API description
+    if (url.pathname === '/-daebug-openapi.json' && req.method === 'GET') {
+      return res.writeHead(200, { 'Content-Type': MIME['.json'] }).end(JSON.stringify(openApiDocument(packageVersion())));
+    }
+    
+    // Whole-server state export/import
+    if (url.pathname === '/-daebug-snapshot' && (req.method === 'GET' || req.method === 'POST')) {
+      return handleSnapshot(req, res);
+    }
+    if (url.pathname === '/-daebug-snapshot/diff' && req.method === 'POST') {
+      return handleSnapshotDiff(req, res);
+    }
+    
+    // Run a snippet and answer with its result in the same request
+    if (url.pathname === '/-daebug-run' && req.method === 'POST') {
+      return handleRun(req, res);
+    }
+    const runMatch = /^\/-daebug-run\/([^/]+)$/.exec(url.pathname);
+    if (runMatch && req.method === 'DELETE') {
+      return handleCancelRun(decodeURIComponent(runMatch[1]), res);
+    }
+    
+    // Fan a snippet out to many pages
+    if (url.pathname === '/-daebug-broadcast' && req.method === 'POST') {
+      return handleBroadcast(req, res);
+    }
+    
+    // Job cancellation
+    if (url.pathname === '/-daebug-cancel' && req.method === 'POST') {
+      return handleCancel(url, res);
+    }
+    
     // Test discovery endpoint
     if (url.pathname === '/-daebug-discover-tests' && req.method === 'POST') {
       return handleTestDiscovery(root, req, res);
@@ -167,6 +526,8 @@ export async function start(root, port, dirName, bannerPrefix) {
     
     const file = join(root, path);
     const isIframeRunnerHTML = path === '/-daebug-iframe.html';
+    // A log being read must show the replies buffered for it
+    if (/\.md$/i.test(file)) writer.flushPending(file);
 
     if (!existsSync(file) && !isIframeRunnerHTML) {
       console.log(`👾𝟰𝟬𝟰 ${url.pathname}`);
@@ -202,7 +563,12 @@ export async function start(root, port, dirName, bannerPrefix) {
         injectClientScript(processImportMapHTML(readFileSync(file, 'utf8'), root));
             
       console.log(`👾serving HTML with import map injected: ${path}`);
-      return res.writeHead(200, { 'Content-Type': MIME['.html'] })
+      /** @type {Record<string, string>} */
+      const headers = { 'Content-Type': MIME['.html'] };
+      // The injected client, its workers and test modules all reach daebug with this cookie
+      const cookie = pageCookieFor(req, url);
+      if (cookie) headers['Set-Cookie'] = cookie;
+      return res.writeHead(200, headers)
         .end(html);
     }
     
@@ -226,16 +592,38 @@ export async function start(root, port, dirName, bannerPrefix) {
     
     // Everything else: stream as-is
     // Add cache-busting headers for JS files to prevent server-side caching
-    const headers = /** @type {Record<string, string>} */({ 'Content-Type': MIME[extname(file)] || 'application/octet-stream' });
+    const headers = /** @type {Record<string, string>} */({ 'Content-Type': contentTypeFor(file) });
     if (extname(file) === '.js') {
       headers['Cache-Control'] = 'no-store, no-cache, must-revalidate, max-age=0';
       headers['Pragma'] = 'no-cache';
     }
     res.writeHead(200, headers);
     createReadStream(file).pipe(res);
+  };
+}
+
+/**
+ * @param {string} root
+ * @param {number} port
+ * @param {string} dirName
+ * @param {string} bannerPrefix
+ */
+export async function start(root, port, dirName, bannerPrefix) {
+  // Install handlers for graceful shutdown on Ctrl+C and other signals
+  installShutdownHandlers(root);
+  registry.configure({ onEvicted: releasePage });
+  const sweepTimer = setInterval(() => sweep(root), SWEEP_INTERVAL_MS);
+  sweepTimer.unref();
+  
+  const server = createLimitedServer(requestHandler(root));
+  
+  server.on('upgrade', (req, socket) => {
+    if (refusedSockets.has(socket)) return refuseUpgrade(socket, 503, 'too many connections');
+    if (!hasAccess(req)) return refuseUpgrade(socket, 401, 'access token required');
+    handleUpgrade(req, socket);
   });
   
-  const desiredUrl = `http://localhost:${port}/`;
+  const desiredUrl = `${scheme()}://localhost:${port}/`;
   process.stdout.write(bannerPrefix + desiredUrl);
   
   const tryListenPort = (/** @type {number} */ portToTry) => {
@@ -303,7 +691,7 @@ export async function start(root, port, dirName, bannerPrefix) {
     const variant = dirName + i;
     const hash = hashString(variant.toLowerCase());
     const nextPort = 8100 + (hash % 1000);
-    const nextUrl = `http://localhost:${nextPort}/`;
+    const nextUrl = `${scheme()}://localhost:${nextPort}/`;
     
     process.stdout.write('\r' + bannerPrefix.replace('👉  ', '') + crossedUrl + '  👉  ' + nextUrl);
     
@@ -318,6 +706,15 @@ export async function start(root, port, dirName, bannerPrefix) {
   process.exit(1);
 }
 
+/** Version from package.json, for the API description */
+function packageVersion() {
+  try {
+    return JSON.parse(readFileSync(new URL('../package.json', import.meta.url), 'utf8')).version;
+  } catch {
+    return '0.0.0';
+  }
+}
+
 /**
  * Simple string hash function
  * @param {string} str
@@ -442,37 +839,132 @@ function mergeImportMaps(existing) {
 }
 
 /** @param {string} root @param {URL} url @param {import('http').ServerResponse} res */
-async function handlePoll(root, url, res) {
+export async function handlePoll(root, url, res) {
   const name = url.searchParams.get('name') || '';
   if (!name) return res.writeHead(400).end('missing name');
   
-  const page = registry.getOrCreate(root, name, url.searchParams.get('url') || '');
-  watcher.watchPage(root, page);
+  const caps = url.searchParams.get('caps')?.split(',').map(cap => cap.trim()).filter(Boolean);
+  const unknown = caps?.find(cap => !registry.KNOWN_CAPABILITIES.includes(cap));
+  if (unknown) return res.writeHead(400).end('unknown capability: ' + unknown);
+
+  let page;
+  try {
+    page = registry.getOrCreate(root, name, url.searchParams.get('url') || '', caps);
+  } catch (err) {
+    if (err instanceof registry.RegistrationError) {
+      console.warn(`👾𝟰𝟬𝟯 ${err.message}`);
+      return res.writeHead(403).end(err.message);
+    }
+    throw err;
+  }
+  // A job queued before the page first connected is its job now
+  if (page.state === 'idle' && job.get(page.name)) registry.setState(page, 'executing');
+  // Ephemeral pages have no log on disk to watch; their jobs arrive over HTTP
+  if (registry.isEphemeral(page)) writer.markEphemeral(page.file, page.name + ' Session');
+  else watcher.watchPage(root, page);
+  
+  // A new instance id under the same name is a reload: jobs pinned to the old one can't run
+  const instance = url.searchParams.get('instance');
+  if (instance && registry.noteInstance(page, instance)) job.invalidatePinned(page);
+  
+  // Cancellation watch: the page holds this open while it executes a job
+  const cancelId = url.searchParams.get('cancel');
+  if (cancelId) {
+    const release = trackConnection(page, instance, 'cancel-watch', res);
+    const stop = await job.waitForCancel(cancelId, pollHold()).finally(release);
+    return res.writeHead(200, { 'Content-Type': 'text/plain' }).end(stop ? 'cancelled' : '');
+  }
+  
+  // The page reports the prelude it has already run, if any
+  page.preludeHash = url.searchParams.get('prelude') || undefined;
   
-  let j = job.get(page.name);
+  let j = job.forPoller(page.name);
   if (!j) {
-    // Long-polling: wait for a job to become available (randomized 10-15s timeout)
-    const pollTimeout = 10000 + Math.random() * 5000;
-    j = await job.waitForJob(page.name, pollTimeout);
+    // Long-polling: wait for a job to become available
+    const release = trackConnection(page, instance, 'poll', res);
+    j = await job.waitForJob(page.name, pollHold()).finally(release);
+    // The page went away while we held the poll: leave the job for its next poll
+    if (res.destroyed) return;
+    // Cancelled while the wake-up was on its way: the page must not run it
+    if (j?.finishedAt) j = null;
   }
   
   if (!j) {
     return res.writeHead(200, { 'Content-Type': 'application/javascript' }).end('');
   }
   
+  let code;
+  try {
+    code = job.dispatchedCode(j);
+  } catch (err) {
+    console.warn(`[poll] code transform failed for job ${j.id}`, err);
+    job.complete(j, { ok: false, error: `code transform failed: ${err instanceof Error ? err.message : String(err)}` });
+    return res.writeHead(200, { 'Content-Type': 'application/javascript' }).end('');
+  }
+
   if (!j.startedAt) job.start(j);
-  res.writeHead(200, { 'Content-Type': 'application/javascript', 'x-job-id': j.id }).end(j.code);
+  /** @type {Record<string, string>} */
+  const headers = { 'Content-Type': 'application/javascript', 'x-job-id': j.id };
+  if (j.when) headers['x-job-when'] = encodeURIComponent(j.when);
+  const prelude = registry.preludeToSend(page, j.prelude);
+  if (prelude) {
+    headers['x-prelude-hash'] = prelude.hash;
+    if (prelude.code) headers['x-prelude'] = encodeURIComponent(prelude.code);
+  }
+  res.writeHead(200, headers).end(code);
+}
+
+/**
+ * The job a realm is running, for a result it posts under its name. A page's name is followed
+ * through a rename; a worker's, which the registry may not know, is taken as it is. Jobs still
+ * waiting in the realm's queue have sent nothing yet, so no result is theirs.
+ * @param {string} name
+ * @returns {job.Job | undefined}
+ */
+function runningJobOf(name) {
+  for (const poller of new Set([registry.get(name)?.name ?? name, name])) {
+    const j = job.forPoller(poller);
+    if (j?.startedAt) return j;
+  }
+  return undefined;
+}
+
+/**
+ * Refuse a result body over `maxResultBytes` with 413, failing the job it was meant for
+ * so the agent hears about it instead of waiting for the timeout
+ * @param {string} name Page the result came from
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ */
+function refuseResult(name, req, res) {
+  const message = `result too large: the body exceeds the limit of ${options.maxResultBytes} bytes`;
+  console.warn(`[result] ${name}: ${message}`);
+  const j = runningJobOf(name);
+  if (j) job.complete(j, { ok: false, error: message });
+  res.writeHead(413, { 'Content-Type': 'text/plain', 'Connection': 'close' }).end(message);
+  // Let the rest of the body drain so the client reads the answer rather than a reset
+  req.resume();
 }
 
 /** @param {URL} url @param {import('http').IncomingMessage} req @param {import('http').ServerResponse} res */
-function handleResult(url, req, res) {
-  let body = '';
-  req.setEncoding('utf8');
-  req.on('data', chunk => body += chunk);
+export function handleResult(url, req, res) {
+  const name = url.searchParams.get('name') || '';
+  if (Number(req.headers['content-length']) > options.maxResultBytes) return refuseResult(name, req, res);
+
+  /** @type {Buffer[]} */
+  const chunks = [];
+  let received = 0;
+  req.on('data', chunk => {
+    if (res.headersSent) return;
+    received += chunk.length;
+    if (received > options.maxResultBytes) return refuseResult(name, req, res);
+    chunks.push(chunk);
+  });
   req.on('end', () => {
+    if (res.headersSent) return;
+    const body = Buffer.concat(chunks).toString('utf8');
     try {
       const payload = JSON.parse(body);
-      const name = url.searchParams.get('name') || '';
       
       // Handle worker timeout diagnostics
       if (payload.type === 'worker-timeout') {
@@ -501,20 +993,675 @@ function handleResult(url, req, res) {
         return res.writeHead(200).end('ok');
       }
       
-      // Handle normal job results
-      const j = job.get(name);
-      if (j) {
-        writer.writeReply(j, payload);
-        job.finish(j);
-      }
+      // Handle normal job results; a late result for a job already timed out or cancelled is dropped
+      const j = runningJobOf(name);
+      if (j && (!payload.jobId || payload.jobId === j.id)) job.complete(j, payload);
       res.writeHead(200).end('ok');
     } catch (err) {
       console.error('[result] error:', err);
+      const page = registry.get(name);
+      if (page) writer.writeError(page.file, 'result', `could not handle a result from the page: ${err instanceof Error ? err.message : String(err)}`);
       res.writeHead(500).end('error');
     }
   });
 }
 
+/** @param {import('http').IncomingMessage} req @param {import('http').ServerResponse} res */
+function handleBroadcast(req, res) {
+  readJson(req, res, payload => {
+    if (typeof payload.code !== 'string' || !payload.code.trim()) return res.writeHead(400).end('missing code');
+
+    const created = job.broadcast(payload.agent || 'agent', payload.code, {
+      names: payload.pages,
+      capability: payload.capability,
+      state: payload.state,
+      limit: payload.limit,
+      language: payload.language,
+      expect: payload.expect,
+      when: typeof payload.when === 'string' ? payload.when : undefined,
+      pin: payload.pin,
+      notBefore: typeof payload.notBefore === 'string' ? Date.parse(payload.notBefore) : payload.notBefore,
+      delayMs: payload.delayMs
+    });
+    console.log(`👾broadcast to ${created.length} page(s)`);
+    res.writeHead(200, { 'Content-Type': 'application/json' })
+      .end(JSON.stringify({ jobs: created.map(j => ({ page: j.page.name, id: j.id })) }));
+  });
+}
+
+/** @param {import('http').IncomingMessage} req @param {import('http').ServerResponse} res */
+function handleRun(req, res) {
+  readJson(req, res, payload => {
+    const key = req.headers['idempotency-key'];
+    runSync(payload, res, typeof key === 'string' && key ? key : undefined).catch(err => {
+      console.error('[run] error:', err);
+      res.writeHead(500).end('error');
+    });
+  });
+}
+
+/**
+ * Runs waiting on their job, by run id, with the means to give up on them
+ * @type {Map<string, { job: job.Job, cancel: () => void }>}
+ */
+const pendingRuns = new Map();
+
+let nextRunId = 1;
+
+/**
+ * Submit a job and hold the request until it finishes. The job waits its turn behind any the
+ * page already has, and its time limit counts from submission, so the wait is part of it.
+ * A requested `timeoutMs` replaces the page's time limit but never exceeds `maxRunTimeoutMs`;
+ * a job that runs out of time, queued or running, answers 504.
+ * The run id, the client's own `runId` or one assigned here, comes back in `x-run-id` and lets
+ * `DELETE /-daebug-run/<id>` cancel the job and answer this request as cancelled.
+ * A retry with the idempotency key of an earlier run waits on that run's job instead of a new one.
+ * @param {{ page?: unknown, agent?: string, code?: unknown, language?: 'js' | 'ts', when?: unknown, timeoutMs?: unknown, runId?: unknown }} payload
+ * @param {import('http').ServerResponse} res
+ * @param {string} [idempotencyKey] From the Idempotency-Key header
+ */
+export async function runSync(payload, res, idempotencyKey) {
+  if (payload.runId !== undefined && (typeof payload.runId !== 'string' || !payload.runId)) return res.writeHead(400).end('runId must be a non-empty string');
+  const runId = /** @type {string | undefined} */(payload.runId) ?? 'run-' + nextRunId++;
+  if (pendingRuns.has(runId)) return res.writeHead(409).end('run already in progress: ' + runId);
+  if (typeof payload.page !== 'string' || !payload.page) return res.writeHead(400).end('missing page');
+  if (typeof payload.code !== 'string' || !payload.code.trim()) return res.writeHead(400).end('missing code');
+  if (payload.timeoutMs !== undefined && !(typeof payload.timeoutMs === 'number' && payload.timeoutMs > 0))
+    return res.writeHead(400).end('timeoutMs must be a positive number');
+  const page = registry.get(payload.page);
+  if (!page) return res.writeHead(404).end('page not found: ' + payload.page);
+  const agent = payload.agent || 'agent';
+
+  const timeoutMs = payload.timeoutMs !== undefined ? Math.min(payload.timeoutMs, options.maxRunTimeoutMs) : undefined;
+  let j;
+  try {
+    const creating = withPage(page.name, () => job.create(page, agent, payload.code, true, {
+      language: payload.language,
+      when: typeof payload.when === 'string' ? payload.when : undefined,
+      timeoutMs,
+      idempotencyKey
+    }));
+    j = creating instanceof Promise ? await creating : creating;
+  } catch (err) {
+    if (err instanceof job.QuotaExceededError)
+      return res.writeHead(429, { 'Retry-After': String(Math.ceil(err.retryAfterMs / 1000)) }).end(err.message);
+    if (err instanceof job.IdempotencyKeyReusedError) return res.writeHead(422).end(err.message);
+    if (!(err instanceof job.JobRejectedError)) throw err;
+    return res.writeHead(400).end(err.message);
+  }
+  let cancelled = false;
+  /** @type {() => void} */
+  let cancel = () => { };
+  const cancelling = new Promise(resolve => { cancel = () => { cancelled = true; resolve(undefined); }; });
+  pendingRuns.set(runId, { job: j, cancel });
+  try {
+    await Promise.race([job.settled(j), cancelling]);
+  } finally {
+    pendingRuns.delete(runId);
+  }
+
+  const headers = { 'Content-Type': 'application/json', 'x-run-id': runId };
+  if (cancelled)
+    return res.writeHead(200, headers).end(JSON.stringify({ id: j.id, runId, ok: false, error: 'Cancelled', cancelled: true }));
+  const result = job.outcome(j.id);
+  const { ok, value, error, skipped, logs, durationMs } = result || { ok: false, error: 'job finished without a result', durationMs: 0 };
+  res.writeHead(j.timedOut ? 504 : 200, headers)
+    .end(JSON.stringify({ id: j.id, runId, ok, value, error, skipped, logs, durationMs, ...(j.timedOut && { timedOut: true }) }));
+}
+
+/**
+ * Sockets accepted while `maxConnections` were already open; requests on them get a 503
+ * @type {WeakSet<import('net').Socket | import('stream').Duplex>}
+ */
+const refusedSockets = new WeakSet();
+
+/**
+ * An HTTP server, HTTPS when `tls` is set, that counts its open sockets and answers every
+ * request on one beyond `maxConnections` with 503 and a hang-up, rather than leaving it to pile up.
+ * Upgrade listeners check `refusedSockets` themselves.
+ * @param {import('http').RequestListener} handler
+ */
+export function createLimitedServer(handler) {
+  let open = 0;
+  /** @type {import('http').RequestListener} */
+  const listener = (req, res) => {
+    if (!refusedSockets.has(req.socket)) return handler(req, res);
+    res.writeHead(503, { 'Content-Type': 'text/plain', 'Retry-After': '1', Connection: 'close' }).end('too many connections');
+  };
+  const server = options.tls
+    ? createSecureServer({ cert: readFileSync(options.tls.cert), key: readFileSync(options.tls.key) }, listener)
+    : createServer(listener);
+  // Requests carry the TLS socket, not the TCP one underneath, so TLS counts its sockets instead
+  server.on(options.tls ? 'secureConnection' : 'connection', socket => {
+    if (options.maxConnections > 0 && open >= options.maxConnections) {
+      refusedSockets.add(socket);
+      return;
+    }
+    open++;
+    socket.once('close', () => open--);
+  });
+  return server;
+}
+
+/**
+ * Route a websocket upgrade; only per-job result subscriptions are offered
+ * @param {import('http').IncomingMessage} req
+ * @param {import('stream').Duplex} socket
+ */
+export function handleUpgrade(req, socket) {
+  const url = new URL(req.url || '/', `http://${req.headers.host}`);
+  const match = /^\/-daebug-job\/([^/]+)\/ws$/.exec(url.pathname);
+  if (!match) return refuseUpgrade(socket, 404, 'no websocket at ' + url.pathname);
+  const id = decodeURIComponent(match[1]);
+  const j = job.byId(id);
+  const finished = j ? undefined : job.outcome(id);
+  if (!j && !finished) return refuseUpgrade(socket, 404, 'no job with id ' + id);
+  const ws = acceptUpgrade(req, socket);
+  if (ws) watchJob(id, j, ws);
+}
+
+/**
+ * Push a job's state changes to a subscriber, then its result, then hang up
+ * @param {string} id
+ * @param {import('./job.js').Job | undefined} j The job while it is still active
+ * @param {import('./websocket.js').PushSocket} ws
+ */
+async function watchJob(id, j, ws) {
+  if (j) {
+    ws.send({ type: 'state', id, state: j.startedAt ? 'running' : 'queued' });
+    if (!j.startedAt) job.started(j).then(() => ws.send({ type: 'state', id, state: 'running' }));
+    await job.settled(j);
+  }
+  const result = job.outcome(id);
+  const { ok, value, error, skipped, durationMs } = result || { ok: false, error: 'job finished without a result', durationMs: 0 };
+  ws.send({ type: 'result', id, ok, value, error, skipped, durationMs, ...(j?.timedOut && { timedOut: true }) });
+  ws.close();
+}
+
+/**
+ * Give up on a run: its job is cancelled and the request waiting on it answers as cancelled
+ * @param {string} runId
+ * @param {import('http').ServerResponse} res
+ */
+export function handleCancelRun(runId, res) {
+  const run = pendingRuns.get(runId);
+  if (!run) return res.writeHead(404).end('no run in progress with id ' + runId);
+  const dispatched = !!run.job.startedAt;
+  job.cancel(run.job.page.name, run.job.id);
+  run.cancel();
+  console.log(`👾run ${runId} cancelled (job ${run.job.id})`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ runId, job: run.job.id, dispatched }));
+}
+
+/** @param {URL} url @param {import('http').ServerResponse} res */
+function handleCancel(url, res) {
+  const name = url.searchParams.get('name') || '';
+  const j = job.cancel(name);
+  if (!j) return res.writeHead(404).end('no job for ' + name);
+  console.log(`👾cancel ${name} job ${j.id}`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ id: j.id, dispatched: !!j.startedAt }));
+}
+
+/** @param {string} name @param {import('http').ServerResponse} res */
+export function handlePageAst(name, res) {
+  const page = registry.get(name);
+  if (!page || !existsSync(page.file)) return res.writeHead(404).end('page not found: ' + name);
+  writer.flushPending(page.file);
+  const summary = summarizeDocument(readFileSync(page.file, 'utf8'));
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ name: page.name, ...summary }));
+}
+
+/**
+ * Text a log gained between two versions of it. The writer inserts replies above the footer
+ * rather than at the end, so what is new is what lies between the unchanged start and end.
+ * @param {string} before
+ * @param {string} after
+ */
+function addedText(before, after) {
+  let start = 0;
+  while (start < before.length && start < after.length && before[start] === after[start]) start++;
+  let end = 0;
+  while (end < before.length - start && end < after.length - start
+    && before[before.length - 1 - end] === after[after.length - 1 - end]) end++;
+  return after.slice(start, after.length - end);
+}
+
+/**
+ * Follow a page's log like `tail -f` over one chunked response: the log as it stands, then
+ * the text each change adds, until the client hangs up. Removed text is not signalled.
+ * @param {string} name
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ */
+export function handleLogStream(name, req, res) {
+  const page = registry.get(name);
+  if (!page) return res.writeHead(404).end('page not found: ' + name);
+  const file = page.file;
+  /** @param {boolean} flush */
+  const read = flush => writer.isEphemeral(file) || existsSync(file) ? writer.readLog(file, flush) : '';
+  let sent = read(true);
+  res.writeHead(200, { 'Content-Type': contentTypeFor(file), 'Cache-Control': 'no-cache' });
+  if (sent) res.write(sent);
+  else res.flushHeaders();
+  const stop = writer.onLogChange(file, () => {
+    const current = read(false);
+    const added = addedText(sent, current);
+    sent = current;
+    if (added) res.write(added);
+  });
+  req.on('close', stop);
+}
+
+/**
+ * Explain what the server would do with a page's log right now: the request it finds, whether
+ * that is already answered or in a language it does not run, and any job holding the page
+ * @param {string} name
+ * @param {import('http').ServerResponse} res
+ */
+export function handleDiagnose(name, res) {
+  const page = registry.get(name);
+  if (!page) return res.writeHead(404).end('page not found: ' + name);
+  if (existsSync(page.file)) writer.flushPending(page.file);
+  const diagnosis = existsSync(page.file)
+    ? diagnoseDocument(readFileSync(page.file, 'utf8'), page.name)
+    : { request: null, empty: true, answered: false, language: null, recognized: false, pending: null, reasons: ['page has no log yet'] };
+  const active = job.get(page.name);
+  const reasons = [...diagnosis.reasons];
+  if (active) reasons.push(`job ${active.id} is already ${active.startedAt ? 'running' : 'queued'} on the page`);
+  else if (page.state !== 'idle') reasons.push(`page is ${page.state}`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({
+    page: { name: page.name, state: page.state, stateSince: page.stateSince, lastSeen: page.lastSeen },
+    job: active ? { id: active.id, state: active.startedAt ? 'running' : 'queued' } : null,
+    ...diagnosis,
+    pending: !!diagnosis.pending,
+    dispatchable: !!diagnosis.pending && !active,
+    reasons
+  }));
+}
+
+/**
+ * Read a request body, answering 413 when it is over `maxResultBytes`
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ * @param {(body: string) => void} then
+ */
+function readBody(req, res, then) {
+  const refuse = () => {
+    res.writeHead(413, { 'Content-Type': 'text/plain', 'Connection': 'close' })
+      .end(`request too large: the body exceeds the limit of ${options.maxResultBytes} bytes`);
+    req.resume();
+  };
+  if (Number(req.headers['content-length']) > options.maxResultBytes) return refuse();
+
+  /** @type {Buffer[]} */
+  const chunks = [];
+  let received = 0;
+  req.on('data', chunk => {
+    if (res.headersSent) return;
+    received += chunk.length;
+    if (received > options.maxResultBytes) return refuse();
+    chunks.push(chunk);
+  });
+  req.on('end', () => {
+    if (!res.headersSent) then(Buffer.concat(chunks).toString('utf8'));
+  });
+}
+
+/**
+ * Read a JSON request body, answering 400 when it does not parse and 413 when it is over `maxResultBytes`
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ * @param {(payload: any) => void} then
+ */
+function readJson(req, res, then) {
+  readBody(req, res, body => {
+    let payload;
+    try {
+      payload = JSON.parse(body);
+    } catch {
+      return res.writeHead(400).end('invalid JSON');
+    }
+    then(payload);
+  });
+}
+
+/**
+ * A page asks its agents for something: the request is noted in its log and stays pending
+ * until an agent answers it
+ * @param {string} name
+ * @param {{ message?: unknown }} payload
+ * @param {import('http').ServerResponse} res
+ */
+export function askAgents(name, payload, res) {
+  const page = registry.get(name);
+  if (!page) return res.writeHead(404).end('page not found: ' + name);
+  if (typeof payload?.message !== 'string' || !payload.message.trim()) return res.writeHead(400).end('missing message');
+  const request = job.ask(page, payload.message);
+  console.log(`👾page request ${request.id} from ${page.name}`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(request));
+}
+
+/**
+ * @param {string} id
+ * @param {{ agent?: unknown, answer?: unknown }} payload
+ * @param {import('http').ServerResponse} res
+ */
+export function answerAsk(id, payload, res) {
+  if (typeof payload?.answer !== 'string') return res.writeHead(400).end('missing answer');
+  const agent = typeof payload.agent === 'string' && payload.agent ? payload.agent : 'agent';
+  let request;
+  try {
+    request = job.answerPageRequest(id, agent, payload.answer);
+  } catch (err) {
+    return res.writeHead(409).end(err instanceof Error ? err.message : String(err));
+  }
+  if (!request) return res.writeHead(404).end('no page request with id ' + id);
+  console.log(`👾page request ${id} answered by ${agent}`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(request));
+}
+
+/** @param {URL} url @param {import('http').ServerResponse} res */
+export function handlePageRequests(url, res) {
+  const requests = job.pageRequestsFor({ page: url.searchParams.get('page'), pending: url.searchParams.get('pending') === '1' });
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ requests }));
+}
+
+/** @param {string} id @param {import('http').ServerResponse} res */
+export function handleJobResult(id, res) {
+  const result = job.outcome(id);
+  if (!result) return res.writeHead(404).end('no finished job with id ' + id);
+  const { ok, value, error, skipped, logs, durationMs } = result;
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ ok, value, error, skipped, logs, durationMs }));
+}
+
+/** @param {import('http').IncomingMessage} req @param {import('http').ServerResponse} res */
+function handleOpenSession(req, res) {
+  readJson(req, res, payload => {
+    if (typeof payload.page !== 'string' || !payload.page) return res.writeHead(400).end('missing page');
+    const page = registry.get(payload.page);
+    if (!page) return res.writeHead(404).end('page not found: ' + payload.page);
+    const session = job.openSession(page.name, payload.agent || 'agent');
+    console.log(`👾session ${session.id} open for ${session.agent} on ${page.name}`);
+    res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(session));
+  });
+}
+
+/** @param {string} id @param {string} method @param {import('http').ServerResponse} res */
+export function handleSession(id, method, res) {
+  const session = method === 'DELETE' ? job.closeSession(id) : job.getSession(id);
+  if (!session) return res.writeHead(404).end('no session with id ' + id);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(session));
+}
+
+/**
+ * A session's exchanges as a standalone transcript, JSON by default or `?format=markdown`
+ * @param {string} id
+ * @param {URL} url
+ * @param {import('http').ServerResponse} res
+ */
+export function handleSessionExport(id, url, res) {
+  const format = url.searchParams.get('format') || 'json';
+  if (format !== 'json' && format !== 'markdown') return res.writeHead(400).end('unknown format ' + format);
+  const exported = transcript(id);
+  if (!exported) return res.writeHead(404).end('no session with id ' + id);
+  if (format === 'markdown') return res.writeHead(200, { 'Content-Type': 'text/markdown; charset=utf-8' }).end(formatTranscript(exported));
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(exported));
+}
+
+/** @param {URL} url @param {import('http').ServerResponse} res */
+export function handleListJobs(url, res) {
+  const session = url.searchParams.get('session');
+  const jobs = job.list({ session });
+  if (!jobs) return res.writeHead(404).end('no session with id ' + session);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ jobs }));
+}
+
+/**
+ * Every page's line-up of waiting jobs, in the order the dispatch strategy would run them
+ * @param {import('http').ServerResponse} res
+ */
+export function handleQueue(res) {
+  const queues = job.queue(registry.all().map(page => page.name));
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ queues }));
+}
+
+/**
+ * Time out overdue jobs now instead of waiting for their timers
+ * @param {import('http').ServerResponse} res
+ */
+export function handleReap(res) {
+  const reaped = job.reapTimeouts().map(j => ({
+    id: j.id,
+    page: j.page.name,
+    startedAt: j.startedAt,
+    finishedAt: j.finishedAt,
+    timedOut: !!j.timedOut
+  }));
+  if (reaped.length) console.log(`👾reap: ${reaped.map(j => j.id).join(', ')} timed out`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ reaped }));
+}
+
+/** @param {string} name @param {URL} url @param {import('http').ServerResponse} res */
+export function handleDrain(name, url, res) {
+  return withPage(name, () => {
+    if (!registry.get(name)) return res.writeHead(404).end('page not found: ' + name);
+    const dropped = job.drain(name, { includeDispatched: url.searchParams.get('dispatched') === '1' });
+    console.log(`👾drain ${name}: ${dropped} job(s) dropped`);
+    res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ dropped }));
+  });
+}
+
+/**
+ * Re-read a page's log on demand, dispatching a request the file watcher never saw
+ * @param {string} root
+ * @param {string} name
+ * @param {import('http').ServerResponse} res
+ */
+export async function handleReindex(root, name, res) {
+  const page = registry.get(name);
+  if (!page) return res.writeHead(404).end('page not found: ' + name);
+  const dispatched = await watcher.reindex(root, page);
+  console.log(`👾reindex ${name}: ${dispatched ? 'job ' + dispatched.id : 'nothing new'}`);
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({
+    job: dispatched?.id ?? null,
+    pending: job.get(name)?.id ?? null
+  }));
+}
+
+/**
+ * Let go of everything held for a page that left the registry
+ * @param {import('./registry.js').Page} page
+ */
+export function releasePage(page) {
+  watcher.unwatchPage(page.name);
+  if (registry.isEphemeral(page)) writer.discard(page.file);
+}
+
+/** Set once startup has prepared the daebug directory, loaded saved state and started watching */
+let ready = false;
+
+/** @param {boolean} [value] */
+export function markReady(value = true) {
+  ready = value;
+}
+
+/**
+ * Readiness probe: 503 until startup has finished, so orchestrators hold traffic back until then
+ * @param {import('http').ServerResponse} res
+ */
+export function handleReady(res) {
+  if (!ready) return res.writeHead(503, { 'Content-Type': 'text/plain' }).end('starting');
+  res.writeHead(200, { 'Content-Type': 'text/plain' }).end('ready');
+}
+
+/** How often expired registrations and logs are looked for */
+const SWEEP_INTERVAL_MS = 30_000;
+
+/**
+ * Expire pages that stopped polling, answering their jobs, then delete logs past retention.
+ * The two are independent: an expired page's log stays until its own retention runs out.
+ * @param {string} root
+ * @param {number} [now]
+ * @returns {{ expired: string[], deletedLogs: string[] }}
+ */
+export function sweep(root, now = Date.now()) {
+  const expired = registry.expire(now);
+  for (const page of expired) {
+    job.abandon(page.name, 'Page registration expired');
+    releasePage(page);
+  }
+  if (expired.length) registry.updateMaster(root);
+  return { expired: expired.map(page => page.name), deletedLogs: registry.expireLogs(root, now) };
+}
+
+/**
+ * Kick a wedged page: its job is answered and dropped, its log optionally archived
+ * @param {string} root
+ * @param {string} name
+ * @param {URL} url
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ */
+export function handleEvictPage(root, name, url, req, res) {
+  if (!isAdmin(req)) return res.writeHead(403).end('admin token required');
+  // A check already reading the log finishes first, so the reset never lands mid-dispatch
+  return withPage(name, () => {
+    if (!registry.get(name)) return res.writeHead(404).end('page not found: ' + name);
+
+    const dropped = job.abandon(name, 'Page evicted by operator');
+    const page = /** @type {import('./registry.js').Page} */(registry.evict(name));
+    const archive = url.searchParams.get('archive') === '1' && !registry.isEphemeral(page) && existsSync(page.file) ? writer.rotate(page.file) || null : null;
+    releasePage(page);
+    registry.updateMaster(root);
+
+    console.log(`👾evicted ${name}: ${dropped} job(s) dropped`);
+    res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({
+      page: { name: page.name, url: page.url, file: page.file, state: page.state, lastSeen: page.lastSeen },
+      dropped,
+      archive
+    }));
+  });
+}
+
+/** @param {URL} url @param {import('http').ServerResponse} res */
+export function handlePages(url, res) {
+  const withJobs = url.searchParams.get('jobs') === '1';
+  const pages = registry.all().sort(byName).map(page => ({
+    name: page.name,
+    url: page.url,
+    state: page.state,
+    stateSince: page.stateSince,
+    lastSeen: page.lastSeen,
+    capabilities: page.capabilities,
+    ...(withJobs && {
+      queue: job.queueSummary(page.name),
+      pageRequests: job.pageRequestsFor({ page: page.name, pending: true }).map(request => request.id)
+    })
+  }));
+  res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify({ pages }));
+}
+
+/** @param {URL} url @param {import('http').ServerResponse} res */
+export async function handleEventHistory(url, res) {
+  try {
+    const matches = await events.history({
+      page: url.searchParams.get('page'),
+      type: url.searchParams.get('type'),
+      since: url.searchParams.get('since')
+    });
+    res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(matches));
+  } catch (err) {
+    console.error('[events] history error:', err);
+    res.writeHead(500).end('error');
+  }
+}
+
+/** How often an idle event stream gets a comment line, so proxies don't close it */
+const EVENT_STREAM_HEARTBEAT_MS = 15_000;
+
+/**
+ * Stream events as they happen, as Server-Sent Events named by their type with the event's JSON
+ * as data: pages registering, renamed or evicted, jobs created, started and finished, sessions.
+ * `page` and `type` narrow the stream as they do the history.
+ * @param {URL} url
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ */
+export function handleEventStream(url, req, res) {
+  const page = url.searchParams.get('page');
+  const type = url.searchParams.get('type');
+  res.writeHead(200, { 'Content-Type': 'text/event-stream', 'Cache-Control': 'no-cache', 'Connection': 'keep-alive' });
+  res.write(': connected\n\n');
+  const stop = events.subscribe(event => {
+    if (page && event.page !== page) return;
+    if (type && event.type !== type) return;
+    res.write(`event: ${event.type}\ndata: ${JSON.stringify(event)}\n\n`);
+  });
+  const heartbeat = setInterval(() => res.write(': keep-alive\n\n'), EVENT_STREAM_HEARTBEAT_MS);
+  heartbeat.unref();
+  req.on('close', () => {
+    stop();
+    clearInterval(heartbeat);
+  });
+}
+
+/** @param {string} name @param {import('http').IncomingMessage} req @param {import('http').ServerResponse} res */
+function handlePrelude(name, req, res) {
+  readBody(req, res, body => {
+    const ok = registry.setPrelude(name, req.method === 'PUT' ? body : null);
+    if (!ok) return res.writeHead(404).end('page not found: ' + name);
+    console.log(`👾prelude for ${name} ${req.method === 'PUT' ? 'set' : 'cleared'}`);
+    res.writeHead(200).end('ok');
+  });
+}
+
+/** @param {import('http').IncomingMessage} req @param {import('http').ServerResponse} res */
+export function handleSnapshot(req, res) {
+  if (!isAdmin(req)) return res.writeHead(403).end('admin token required');
+
+  if (req.method === 'GET') {
+    const taken = state.snapshot();
+    const id = state.retain(taken);
+    return res.writeHead(200, { 'Content-Type': 'application/json', 'x-snapshot-id': id }).end(JSON.stringify(taken));
+  }
+
+  readJson(req, res, payload => {
+    try {
+      state.restore(payload);
+      console.log('👾state restored from snapshot');
+      res.writeHead(200).end('ok');
+    } catch (err) {
+      res.writeHead(400).end(String(err));
+    }
+  });
+}
+
+/**
+ * Compare two snapshots, each given as the id a snapshot GET returned or as a snapshot body
+ * @param {import('http').IncomingMessage} req
+ * @param {import('http').ServerResponse} res
+ */
+export function handleSnapshotDiff(req, res) {
+  if (!isAdmin(req)) return res.writeHead(403).end('admin token required');
+
+  readJson(req, res, payload => {
+    try {
+      const { from, to } = payload;
+      /** @param {unknown} ref @param {string} side @returns {state.StateSnapshot} */
+      const resolve = (ref, side) => {
+        const found = typeof ref === 'string' ? state.recall(ref) : /** @type {state.StateSnapshot} */(ref);
+        if (!found || !Array.isArray(found.pages) || !Array.isArray(found.jobs?.jobs))
+          throw new Error(typeof ref === 'string' ? `unknown snapshot id ${ref}` : `missing or malformed ${side} snapshot`);
+        return found;
+      };
+      const result = state.diff(resolve(from, 'from'), resolve(to, 'to'));
+      res.writeHead(200, { 'Content-Type': 'application/json' }).end(JSON.stringify(result));
+    } catch (err) {
+      res.writeHead(400).end(String(err));
+    }
+  });
+}
+
 /**
  * Convert glob pattern to regex
  * Supports:
@@ -648,12 +1795,8 @@ export function glob(dir, patterns, exclude) {
  * @param {import('http').ServerResponse} res
  */
 function handleTestDiscovery(root, req, res) {
-  let body = '';
-  req.setEncoding('utf8');
-  req.on('data', chunk => body += chunk);
-  req.on('end', () => {
+  readJson(req, res, payload => {
     try {
-      const payload = JSON.parse(body);
       const patterns = Array.isArray(payload.files) ? payload.files : [payload.files || '**/*.test.js'];
       const cwd = payload.cwd || root;
       const exclude = payload.exclude || ['node_modules/**', '.git/**', 'dist/**', 'build/**'];
@@ -682,12 +1825,8 @@ function handleTestDiscovery(root, req, res) {
  * @param {import('http').ServerResponse} res
  */
 function handleTestProgress(req, res) {
-  let body = '';
-  req.setEncoding('utf8');
-  req.on('data', chunk => body += chunk);
-  req.on('end', () => {
+  readJson(req, res, payload => {
     try {
-      const payload = JSON.parse(body);
       const realmName = payload.realmName || 'unknown';
       const page = registry.get(realmName);
       
@@ -3,6 +3,7 @@ import { writeFileSync, existsSync } from 'node:fs';
 import { join } from 'node:path';
 import { daebugMD_template } from './daebug.md.template.js';
 import * as registry from './registry.js';
+import * as writer from './writer.js';
 
 let shutdownHandlersInstalled = false;
 let rootPath = '';
@@ -52,6 +53,7 @@ export function installShutdownHandlers(root) {
  */
 function writeShutdownMessage(reason) {
   if (!rootPath) return;
+  writer.flushPending();
   
   const daebugFile = join(rootPath, 'daebug.md');
   if (!existsSync(daebugFile)) return;
@@ -0,0 +1,120 @@
+// @ts-check
+import * as registry from './registry.js';
+import * as job from './job.js';
+
+/**
+ * @typedef {{
+ *   version: 1,
+ *   takenAt: string,
+ *   pages: import('./registry.js').Page[],
+ *   jobs: import('./job.js').JobsSnapshot
+ * }} StateSnapshot
+ */
+
+/**
+ * Capture registry and jobs together at a single point in time
+ * @returns {StateSnapshot}
+ */
+export function snapshot() {
+  return {
+    version: 1,
+    takenAt: new Date().toISOString(),
+    pages: registry.snapshot(),
+    jobs: job.snapshot()
+  };
+}
+
+/**
+ * Restore registry and jobs from a snapshot; pages first so jobs can attach to them
+ * @param {StateSnapshot} state
+ */
+export function restore(state) {
+  if (!state || state.version !== 1 || !Array.isArray(state.pages) || !state.jobs)
+    throw new Error('unrecognised state snapshot');
+  registry.restore(state.pages);
+  job.restore(state.jobs, registry.get);
+}
+
+/** How many snapshots served over HTTP stay addressable by id for diffing */
+const RETAINED_SNAPSHOTS = 16;
+
+/** @type {Map<string, StateSnapshot>} */
+const retained = new Map();
+let nextSnapshotId = 1;
+
+/**
+ * Keep a snapshot so a later diff can refer to it by id; the oldest is dropped past the limit
+ * @param {StateSnapshot} state
+ * @returns {string}
+ */
+export function retain(state) {
+  const id = String(nextSnapshotId++);
+  retained.set(id, state);
+  if (retained.size > RETAINED_SNAPSHOTS) retained.delete(/** @type {string} */(retained.keys().next().value));
+  return id;
+}
+
+/** @param {string} id */
+export const recall = id => retained.get(id);
+
+/**
+ * @typedef {'queued' | 'running' | 'finished'} JobState
+ */
+
+/**
+ * @typedef {{
+ *   pages: {
+ *     added: string[],
+ *     removed: string[],
+ *     changed: Array<{ name: string, from: string, to: string }>
+ *   },
+ *   jobs: {
+ *     added: Array<{ id: string, page: string }>,
+ *     terminated: Array<{ id: string, page: string }>,
+ *     changed: Array<{ id: string, page: string, from: JobState, to: JobState }>
+ *   }
+ * }} StateDiff
+ */
+
+/** @param {JobsSnapshotEntry} job @returns {JobState} */
+const jobState = job => job.finishedAt ? 'finished' : job.startedAt ? 'running' : 'queued';
+
+/** @typedef {import('./job.js').JobsSnapshot['jobs'][number]} JobsSnapshotEntry */
+
+/**
+ * What changed from one snapshot to a later one. Jobs leave a snapshot once they finish,
+ * so a job present only in the earlier one is reported as terminated.
+ * @param {StateSnapshot} from
+ * @param {StateSnapshot} to
+ * @returns {StateDiff}
+ */
+export function diff(from, to) {
+  const pagesBefore = new Map(from.pages.map(page => [page.name, page]));
+  const pagesAfter = new Map(to.pages.map(page => [page.name, page]));
+  const jobsBefore = new Map(from.jobs.jobs.map(job => [job.id, job]));
+  const jobsAfter = new Map(to.jobs.jobs.map(job => [job.id, job]));
+
+  /** @type {StateDiff} */
+  const result = {
+    pages: { added: [], removed: [], changed: [] },
+    jobs: { added: [], terminated: [], changed: [] }
+  };
+
+  for (const [name, page] of pagesAfter) {
+    const before = pagesBefore.get(name);
+    if (!before) result.pages.added.push(name);
+    else if (before.state !== page.state) result.pages.changed.push({ name, from: before.state, to: page.state });
+  }
+  for (const name of pagesBefore.keys())
+    if (!pagesAfter.has(name)) result.pages.removed.push(name);
+
+  for (const [id, job] of jobsAfter) {
+    const before = jobsBefore.get(id);
+    if (!before) result.jobs.added.push({ id, page: job.page });
+    else if (jobState(before) !== jobState(job)) result.jobs.changed.push({ id, page: job.page, from: jobState(before), to: jobState(job) });
+  }
+  for (const [id, job] of jobsBefore)
+    if (!jobsAfter.has(id)) result.jobs.terminated.push({ id, page: job.page });
+
+  return result;
+}
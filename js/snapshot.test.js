@@ -0,0 +1,78 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { mkdtempSync, rmSync } from 'node:fs';
+import { tmpdir } from 'node:os';
+import { join } from 'node:path';
+import * as registry from './registry.js';
+import * as job from './job.js';
+import * as state from './snapshot.js';
+
+test('snapshot round-trips registry and jobs through JSON', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const busy = registry.getOrCreate(root, 'snap-busy', 'http://localhost/a', ['js', 'ts']);
+    registry.getOrCreate(root, 'snap-idle', 'http://localhost/b');
+    const j = job.create(busy, 'agent', '1 + 1', true, { language: 'ts' });
+    j.startedAt = new Date().toISOString();
+
+    const before = state.snapshot();
+    const saved = JSON.parse(JSON.stringify(before));
+
+    job.finish(j);
+    registry.restore([]);
+    assert.strictEqual(registry.get('snap-busy'), undefined);
+
+    state.restore(saved);
+    const after = state.snapshot();
+    assert.deepStrictEqual({ ...after, takenAt: '' }, { ...before, takenAt: '' });
+
+    const restored = job.get('snap-busy');
+    assert.ok(restored);
+    assert.strictEqual(restored.page, registry.get('snap-busy'));
+    assert.strictEqual(restored.page.state, 'executing');
+    assert.strictEqual(restored.language, 'ts');
+    job.finish(restored);
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('restore rejects malformed snapshots', () => {
+  assert.throws(() => state.restore(/** @type {*} */({ pages: [] })), /unrecognised state snapshot/);
+});
+
+test('diff reports an added page and a job that started running', () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+  try {
+    const page = registry.getOrCreate(root, 'diff-first', 'http://localhost/a');
+    const j = job.create(page, 'agent', '1 + 1');
+    const before = JSON.parse(JSON.stringify(state.snapshot()));
+
+    registry.getOrCreate(root, 'diff-second', 'http://localhost/b');
+    job.start(j);
+    const after = JSON.parse(JSON.stringify(state.snapshot()));
+
+    const changes = state.diff(before, after);
+    assert.deepStrictEqual(changes.pages, { added: ['diff-second'], removed: [], changed: [] });
+    assert.deepStrictEqual(changes.jobs, {
+      added: [],
+      terminated: [],
+      changed: [{ id: j.id, page: 'diff-first', from: 'queued', to: 'running' }]
+    });
+
+    job.finish(j);
+    const done = state.diff(after, state.snapshot());
+    assert.deepStrictEqual(done.jobs.terminated, [{ id: j.id, page: 'diff-first' }]);
+  } finally {
+    registry.restore([]);
+    rmSync(root, { recursive: true, force: true });
+  }
+});
+
+test('retained snapshots are recalled by id', () => {
+  const taken = state.snapshot();
+  const id = state.retain(taken);
+  assert.strictEqual(state.recall(id), taken);
+  assert.strictEqual(state.recall('no-such-id'), undefined);
+});
@@ -0,0 +1,67 @@
+// @ts-check
+import { readFileSync, writeFileSync, existsSync, mkdirSync, renameSync, rmSync } from 'node:fs';
+
+/**
+ * File operations the writer performs on per-instance logs
+ * @typedef {{
+ *   exists: (file: string) => boolean,
+ *   read: (file: string) => string,
+ *   write: (file: string, content: string) => void,
+ *   readBytes: (file: string) => Buffer,
+ *   writeBytes: (file: string, content: Buffer) => void,
+ *   rename: (from: string, to: string) => void,
+ *   remove: (file: string) => void,
+ *   ensureDir: (dir: string) => void
+ * }} Storage
+ */
+
+/** @type {Storage} */
+export const fsStorage = {
+  exists: file => existsSync(file),
+  read: file => readFileSync(file, 'utf8'),
+  write: (file, content) => writeFileSync(file, content, 'utf8'),
+  readBytes: file => readFileSync(file),
+  writeBytes: (file, content) => writeFileSync(file, content),
+  rename: (from, to) => renameSync(from, to),
+  remove: file => rmSync(file, { force: true }),
+  ensureDir: dir => { mkdirSync(dir, { recursive: true }); }
+};
+
+/**
+ * Storage kept entirely in memory, for tests and for logs that must not touch disk
+ * @param {Record<string, string>} [initial]
+ * @returns {Storage & { files: Map<string, string>, blobs: Map<string, Buffer> }}
+ */
+export function createMemoryStorage(initial = {}) {
+  const files = new Map(Object.entries(initial));
+  /** @type {Map<string, Buffer>} */
+  const blobs = new Map();
+  return {
+    files,
+    blobs,
+    exists: file => files.has(file) || blobs.has(file),
+    read: file => {
+      const content = files.get(file);
+      if (content === undefined)
+        throw Object.assign(new Error(`ENOENT: no such file, open '${file}'`), { code: 'ENOENT' });
+      return content;
+    },
+    write: (file, content) => { files.set(file, content); },
+    readBytes: file => {
+      const content = blobs.get(file) ?? (files.has(file) ? Buffer.from(/** @type {string} */(files.get(file))) : undefined);
+      if (content === undefined)
+        throw Object.assign(new Error(`ENOENT: no such file, open '${file}'`), { code: 'ENOENT' });
+      return content;
+    },
+    writeBytes: (file, content) => { blobs.set(file, content); },
+    rename: (from, to) => {
+      const content = files.get(from);
+      if (content === undefined)
+        throw Object.assign(new Error(`ENOENT: no such file, rename '${from}' -> '${to}'`), { code: 'ENOENT' });
+      files.delete(from);
+      files.set(to, content);
+    },
+    remove: file => { files.delete(file); blobs.delete(file); },
+    ensureDir: () => { }
+  };
+}
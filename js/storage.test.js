@@ -0,0 +1,44 @@
+// @ts-check
+import { test, describe } from 'node:test';
+import assert from 'node:assert/strict';
+import { createMemoryStorage } from './storage.js';
+
+describe('createMemoryStorage', () => {
+  test('reads back what was written', () => {
+    const storage = createMemoryStorage();
+    storage.write('/a.md', 'hello');
+    assert.strictEqual(storage.exists('/a.md'), true);
+    assert.strictEqual(storage.read('/a.md'), 'hello');
+  });
+
+  test('starts from initial content', () => {
+    const storage = createMemoryStorage({ '/seed.md': 'seed' });
+    assert.strictEqual(storage.read('/seed.md'), 'seed');
+  });
+
+  test('read of a missing file throws ENOENT', () => {
+    const storage = createMemoryStorage();
+    assert.throws(() => storage.read('/missing.md'), { code: 'ENOENT' });
+  });
+
+  test('rename moves content to the new path', () => {
+    const storage = createMemoryStorage({ '/old.md': 'content' });
+    storage.rename('/old.md', '/new.md');
+    assert.strictEqual(storage.exists('/old.md'), false);
+    assert.strictEqual(storage.read('/new.md'), 'content');
+  });
+
+  test('bytes round-trip and are visible to exists', () => {
+    const storage = createMemoryStorage();
+    storage.writeBytes('/blob.gz', Buffer.from([1, 2, 3]));
+    assert.strictEqual(storage.exists('/blob.gz'), true);
+    assert.deepStrictEqual([...storage.readBytes('/blob.gz')], [1, 2, 3]);
+  });
+
+  test('remove drops text and bytes', () => {
+    const storage = createMemoryStorage({ '/gone.md': 'content' });
+    storage.remove('/gone.md');
+    storage.remove('/never-there.md');
+    assert.strictEqual(storage.exists('/gone.md'), false);
+  });
+});
@@ -0,0 +1,81 @@
+// @ts-check
+
+/**
+ * Glyphs used to mark headings and verdicts in generated markdown
+ * @typedef {{
+ *   success: string,
+ *   failure: string,
+ *   agent: string,
+ *   title: string,
+ *   executing: string,
+ *   warning: string,
+ *   ask: string,
+ *   pass: string,
+ *   fail: string
+ * }} Theme
+ */
+
+/** @type {Theme} */
+export const EMOJI_THEME = Object.freeze({
+  success: '👍',
+  failure: '🚫',
+  agent: '🗣️',
+  title: '👾',
+  executing: '⏳ ',
+  warning: '⚠️ ',
+  ask: '📣',
+  pass: '✅ ',
+  fail: '❌ '
+});
+
+/** ASCII markers for terminals and viewers that render emoji poorly */
+/** @type {Theme} */
+export const PLAIN_THEME = Object.freeze({
+  success: '[ok] ',
+  failure: '[error] ',
+  agent: '>> ',
+  title: '',
+  executing: '',
+  warning: '[!] ',
+  ask: '<< ',
+  pass: '[pass] ',
+  fail: '[fail] '
+});
+
+/** @type {Theme} */
+const theme = { ...EMOJI_THEME };
+
+/** @param {Partial<Theme>} config */
+export function configure(config) {
+  Object.assign(theme, config);
+}
+
+/** @returns {Readonly<Theme>} */
+export const getTheme = () => theme;
+
+/**
+ * Run `fn` with some glyphs overridden, restoring the configured theme afterwards
+ * @template T
+ * @param {Partial<Theme> | undefined} overrides
+ * @param {() => T} fn
+ * @returns {T}
+ */
+export function withTheme(overrides, fn) {
+  if (!overrides) return fn();
+  const saved = { ...theme };
+  Object.assign(theme, overrides);
+  try {
+    return fn();
+  } finally {
+    Object.assign(theme, saved);
+  }
+}
+
+/** @param {string} glyph */
+const escapeGlyph = glyph => glyph.trim().replace(/[-\\^$*+?.()|[\]{}]/g, '\\$&');
+
+/** Regex source matching the marker of a reply heading, success or failure */
+export const replyMarkerPattern = () => `(?:${escapeGlyph(theme.success)}|${escapeGlyph(theme.failure)})`;
+
+/** Regex source matching the marker of a request heading */
+export const agentMarkerPattern = () => escapeGlyph(theme.agent);
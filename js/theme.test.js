@@ -0,0 +1,52 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { configure, EMOJI_THEME, PLAIN_THEME } from './theme.js';
+import { formatAgentHeader, formatReplyHeader, formatBackgroundEvent, formatVerdict, findExecutingBlock, parseRequest } from './repl.template.js';
+import { daebugMD_template } from './daebug.md.template.js';
+
+const isAscii = (/** @type {string} */ text) => /^[\x00-\x7f]*$/.test(text);
+
+test('plain theme produces ASCII headings', () => {
+  configure(PLAIN_THEME);
+  try {
+    const ts = new Date().toISOString();
+    const output = [
+      formatAgentHeader('agent', 'page', ts),
+      formatReplyHeader('page', 'agent', ts, 5, false),
+      formatReplyHeader('page', 'agent', ts, 5, true),
+      formatBackgroundEvent({ type: 'error', message: 'boom' }),
+      ...formatVerdict('pass', 2),
+      ...formatVerdict('fail', 2),
+      daebugMD_template({ startTime: Date.now() }).split('\n')[0]
+    ].join('\n');
+    assert.ok(isAscii(output), output);
+    assert.match(output, /^### >> agent to page at /m);
+    assert.match(output, /^#### \[ok\] page to agent at /m);
+    assert.match(output, /^#### \[error\] page to agent at /m);
+    assert.match(output, /^\[pass\] matches expected value$/m);
+    assert.match(output, /^\[fail\] expected:$/m);
+    assert.match(output, /^# Daebug remote debugging REPL/m);
+  } finally {
+    configure(EMOJI_THEME);
+  }
+});
+
+test('parser matches the markers of the active theme', () => {
+  configure(PLAIN_THEME);
+  try {
+    const footer = '> Append your JavaScript snippet below to execute against this page.';
+    const request = parseRequest([footer, '### >> alice to page at 12:00:00', '```js', 'x', '```'].join('\n'), 'page');
+    assert.strictEqual(request?.agent, 'alice');
+
+    const lines = ['#### [ok] page to alice at 12:00:01', 'executing (0s)', '', footer];
+    assert.deepStrictEqual(findExecutingBlock(lines, 'page', 'alice'), { headerIdx: 0, placeholderIdx: 1 });
+  } finally {
+    configure(EMOJI_THEME);
+  }
+});
+
+test('default theme keeps the emoji markers', () => {
+  assert.strictEqual(formatAgentHeader('a', 'p', new Date().toISOString()).startsWith('### 🗣️a to p'), true);
+  assert.match(daebugMD_template({ startTime: Date.now() }), /^# 👾 Daebug/);
+});
@@ -0,0 +1,91 @@
+// @ts-check
+import * as job from './job.js';
+import { resultJson } from './repl.template.js';
+
+/**
+ * @typedef {{
+ *   job: string,
+ *   requestedAt: string,
+ *   language: 'js' | 'ts',
+ *   code: string,
+ *   status: 'ok' | 'error' | 'skipped' | 'pending' | 'expired',
+ *   value?: unknown,
+ *   error?: unknown,
+ *   logs?: { level: string, message: string }[],
+ *   durationMs?: number
+ * }} TranscriptExchange
+ */
+
+/**
+ * @typedef {{
+ *   session: string,
+ *   page: string,
+ *   agent: string,
+ *   openedAt: string,
+ *   closedAt: string | null,
+ *   exchanges: TranscriptExchange[]
+ * }} Transcript
+ */
+
+/**
+ * A session's exchanges in submission order, each with the code sent and what came back.
+ * Jobs still running are `pending`; those whose result has aged out of retention are `expired`.
+ * @param {string} id
+ * @returns {Transcript | undefined} undefined for an unknown session
+ */
+export function transcript(id) {
+  const session = job.getSession(id);
+  if (!session) return undefined;
+  return {
+    session: session.id,
+    page: session.page,
+    agent: session.agent,
+    openedAt: session.openedAt,
+    closedAt: session.closedAt,
+    exchanges: session.jobs.map(({ id, requestedAt, language, code }) => {
+      const base = { job: id, requestedAt, language, code };
+      const outcome = job.outcome(id);
+      if (!outcome) return { ...base, status: job.byId(id) ? 'pending' : 'expired' };
+      return {
+        ...base,
+        status: outcome.skipped ? 'skipped' : outcome.ok ? 'ok' : 'error',
+        ...(outcome.ok ? outcome.value !== undefined && { value: outcome.value } : { error: outcome.error }),
+        ...(outcome.logs?.length && { logs: outcome.logs.map(({ level, message }) => ({ level, message })) }),
+        durationMs: outcome.durationMs
+      };
+    })
+  };
+}
+
+/**
+ * Plain markdown of a transcript: no page markers, footer or placeholders, just headings,
+ * the code and its outcome, so it reads cleanly outside daebug
+ * @param {Transcript} transcript
+ * @returns {string}
+ */
+export function formatTranscript(transcript) {
+  const out = [
+    `# Session ${transcript.session}: ${transcript.agent} with ${transcript.page}`,
+    '',
+    `Opened ${transcript.openedAt}${transcript.closedAt ? `, closed ${transcript.closedAt}` : ''}.`
+  ];
+  transcript.exchanges.forEach((exchange, i) => {
+    out.push('', `## ${i + 1}. Job ${exchange.job} at ${exchange.requestedAt}`, '', '```' + exchange.language, exchange.code, '```', '');
+    if (exchange.status === 'ok') {
+      const value = exchange.value;
+      out.push(`Result${exchange.durationMs !== undefined ? ` after ${exchange.durationMs}ms` : ''}:`, '', '```json',
+        value !== null && typeof value === 'object' ? resultJson(value, 2) : String(value), '```');
+    } else if (exchange.status === 'error') {
+      out.push('Error:', '', '```text', typeof exchange.error === 'string' ? exchange.error : resultJson(exchange.error, 2), '```');
+    } else if (exchange.status === 'skipped') {
+      out.push('Skipped: its condition did not hold.');
+    } else if (exchange.status === 'pending') {
+      out.push('Still running.');
+    } else {
+      out.push('Result no longer retained.');
+    }
+    if (exchange.logs?.length)
+      out.push('', 'Console:', '', '```text', ...exchange.logs.map(log => `[${log.level}] ${log.message}`), '```');
+  });
+  return out.join('\n') + '\n';
+}
@@ -0,0 +1,61 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { mkdirSync, mkdtempSync, rmSync } from 'node:fs';
+import { tmpdir } from 'node:os';
+import { join } from 'node:path';
+import * as registry from './registry.js';
+import * as job from './job.js';
+import { transcript, formatTranscript } from './transcript.js';
+
+/** @param {(sessionId: string) => void} body */
+function withSession(body) {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-transcript-'));
+  try {
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'transcript-page', 'http://localhost', ['js', 'ts']);
+    const session = job.openSession(page.name, 'alice');
+
+    const first = job.create(page, 'alice', '6 * 7');
+    job.start(first);
+    job.finish(first, { ok: true, value: { answer: 42 }, logs: [{ level: 'log', message: 'thinking' }] });
+    const second = job.create(page, 'alice', 'boom()', true, { language: 'ts' });
+    job.start(second);
+    job.finish(second, { ok: false, error: 'ReferenceError: boom is not defined' });
+    const third = job.create(page, 'alice', 'await later()');
+    try {
+      body(session.id);
+    } finally {
+      job.finish(third);
+      job.closeSession(session.id);
+    }
+  } finally {
+    rmSync(root, { recursive: true, force: true });
+  }
+}
+
+test('transcript lists a session\'s exchanges in order with their outcomes', () => withSession(id => {
+  const exported = transcript(id);
+  assert.equal(exported?.page, 'transcript-page');
+  assert.equal(exported?.agent, 'alice');
+  assert.deepEqual(exported?.exchanges.map(({ job, requestedAt, durationMs, ...rest }) => rest), [
+    { language: 'js', code: '6 * 7', status: 'ok', value: { answer: 42 }, logs: [{ level: 'log', message: 'thinking' }] },
+    { language: 'ts', code: 'boom()', status: 'error', error: 'ReferenceError: boom is not defined' },
+    { language: 'js', code: 'await later()', status: 'pending' }
+  ]);
+  assert.deepEqual(JSON.parse(JSON.stringify(exported)), exported);
+}));
+
+test('markdown transcript carries code and results without log markers', () => withSession(id => {
+  const markdown = formatTranscript(/** @type {import('./transcript.js').Transcript} */(transcript(id)));
+  assert.match(markdown, /^# Session \S+: alice with transcript-page\n/);
+  assert.match(markdown, /## 1\. Job \S+ at \S+\n\n```js\n6 \* 7\n```\n\nResult after \d+ms:\n\n```json\n\{\n  "answer": 42\n\}\n```/);
+  assert.match(markdown, /Console:\n\n```text\n\[log\] thinking\n```/);
+  assert.match(markdown, /```ts\nboom\(\)\n```\n\nError:\n\n```text\nReferenceError: boom is not defined\n```/);
+  assert.match(markdown, /```js\nawait later\(\)\n```\n\nStill running\./);
+  assert.doesNotMatch(markdown, /🗣️|👍|🚫|Append your JavaScript/);
+}));
+
+test('transcript of an unknown session is undefined', () => {
+  assert.equal(transcript('no-such-session'), undefined);
+});
@@ -0,0 +1,60 @@
+// @ts-check
+import { createHash } from 'node:crypto';
+import { spawnSync } from 'node:child_process';
+
+/**
+ * Turns TypeScript source into JavaScript a page can run
+ * @typedef {(code: string) => string} Transpiler
+ */
+
+/** Raised when TypeScript code could not be turned into JavaScript */
+export class TranspileError extends Error {
+  /** @param {string} message */
+  constructor(message) {
+    super(message);
+    this.name = 'TranspileError';
+  }
+}
+
+/** @type {Map<string, string>} */
+const cache = new Map();
+
+/**
+ * Transpile through `transpiler`, reusing earlier output for identical code
+ * @param {string} code
+ * @param {Transpiler} transpiler
+ * @returns {string}
+ */
+export function transpile(code, transpiler) {
+  const key = createHash('sha1').update(code).digest('hex');
+  const cached = cache.get(key);
+  if (cached !== undefined) return cached;
+
+  let output;
+  try {
+    output = transpiler(code);
+  } catch (err) {
+    if (err instanceof TranspileError) throw err;
+    throw new TranspileError(err instanceof Error ? err.message : String(err));
+  }
+  cache.set(key, output);
+  return output;
+}
+
+/** Forget cached output, e.g. after switching transpilers */
+export const clearCache = () => cache.clear();
+
+/**
+ * A transpiler that pipes code through an external tool: source on stdin, JavaScript on stdout
+ * @param {string} command
+ * @param {string[]} [args]
+ * @returns {Transpiler}
+ */
+export function commandTranspiler(command, args = []) {
+  return code => {
+    const result = spawnSync(command, args, { input: code, encoding: 'utf8' });
+    if (result.error) throw new TranspileError(`${command}: ${result.error.message}`);
+    if (result.status !== 0) throw new TranspileError(`${command} exited with ${result.status}: ${result.stderr.trim()}`);
+    return result.stdout;
+  };
+}
@@ -0,0 +1,22 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { transpile, clearCache, commandTranspiler, TranspileError } from './transpile.js';
+
+test('transpile caches output by code', () => {
+  clearCache();
+  let calls = 0;
+  const stub = (/** @type {string} */ code) => { calls++; return code.toUpperCase(); };
+  assert.strictEqual(transpile('a', stub), 'A');
+  assert.strictEqual(transpile('a', stub), 'A');
+  assert.strictEqual(transpile('b', stub), 'B');
+  assert.strictEqual(calls, 2);
+});
+
+test('commandTranspiler pipes code through the tool', () => {
+  const echo = commandTranspiler(process.execPath, ['-e', 'process.stdin.pipe(process.stdout)']);
+  assert.strictEqual(echo('let x = 1'), 'let x = 1');
+
+  const failing = commandTranspiler(process.execPath, ['-e', 'console.error("bad input"); process.exit(2)']);
+  assert.throws(() => failing('x'), (/** @type {*} */ err) => err instanceof TranspileError && /exited with 2: bad input/.test(err.message));
+});
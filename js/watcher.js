@@ -1,62 +1,265 @@
 // @ts-check
-import { watch, readFileSync, existsSync, writeFileSync } from 'node:fs';
+import { watch, readFileSync, existsSync, writeFileSync, readdirSync } from 'node:fs';
 import { spawn } from 'node:child_process';
-import { join } from 'node:path';
-import { parseRequest } from './parser.js';
+import { createHash } from 'node:crypto';
+import { join, relative, dirname, basename } from 'node:path';
+import { parseRequest, endsInOpenFence } from './parser.js';
 import * as job from './job.js';
 import * as registry from './registry.js';
+import * as writer from './writer.js';
+import * as pageConfig from './page-config.js';
+import { withPage } from './page-lock.js';
 import { daebugMD_template } from './daebug.md.template.js';
 
 const DEBOUNCE_MS = 150;
+const PARSE_RETRY_LIMIT = 3;
+const PARSE_RETRY_MS = 100;
 
 /** @type {Map<string, ReturnType<typeof setTimeout>>} */
 const timers = new Map();
 
-/** @type {Set<string>} */
-const activeWatchers = new Set();
+/**
+ * Close functions of the file watchers, by page name
+ * @type {Map<string, () => void>}
+ */
+const activeWatchers = new Map();
+
+/**
+ * Content last handled for each watched page, by page name
+ * @type {Map<string, { lastContent: string }>}
+ */
+const trackedContent = new Map();
 
 const seenFiles = new Set();
 
+/**
+ * @typedef {{
+ *   persistContentHashes: boolean,
+ *   shards: number
+ * }} WatcherOptions
+ */
+
+/** @type {WatcherOptions} */
+const options = {
+  persistContentHashes: false,
+  shards: 4
+};
+
+/** @param {Partial<WatcherOptions>} config */
+export function configure(config) {
+  Object.assign(options, config);
+}
+
+const CONTENT_HASH_FILE = join('daebug', '.content-hashes.json');
+
+/**
+ * Hash of the last content processed per page file (relative to root), kept on disk so
+ * a restart recognises files it has already handled instead of re-running their last request
+ * @type {Map<string, string>}
+ */
+const contentHashes = new Map();
+
+/** @param {string} text */
+const hashContent = text => createHash('sha1').update(text).digest('hex');
+
+/** @param {string} root */
+export function loadContentHashes(root) {
+  contentHashes.clear();
+  try {
+    const saved = JSON.parse(readFileSync(join(root, CONTENT_HASH_FILE), 'utf8'));
+    for (const [file, hash] of Object.entries(saved)) contentHashes.set(file, String(hash));
+  } catch {
+    // No cache yet, or unreadable: start empty
+  }
+}
+
+/** @param {string} root @param {string} file @param {string} text */
+export function rememberContent(root, file, text) {
+  if (!options.persistContentHashes) return;
+  contentHashes.set(relative(root, file), hashContent(text));
+  try {
+    writeFileSync(join(root, CONTENT_HASH_FILE), JSON.stringify(Object.fromEntries(contentHashes), null, 2), 'utf8');
+  } catch (err) {
+    console.warn('[watcher] could not save content hashes:', err);
+  }
+}
+
+/** @param {string} root @param {string} file @param {string} text */
+export function isKnownContent(root, file, text) {
+  return options.persistContentHashes && contentHashes.get(relative(root, file)) === hashContent(text);
+}
+
+/**
+ * Lanes page checks run in, one after another within a lane. A page always hashes to the same
+ * lane, so its own checks never overlap, while a burst of edits to pages in other lanes is
+ * worked through alongside rather than behind it.
+ * @type {Promise<unknown>[]}
+ */
+let lanes = [];
+
+/**
+ * Lane of `shards` lanes that work for a page runs in
+ * @param {string} key Page name
+ * @param {number} count
+ */
+export const shardOf = (key, count) => parseInt(hashContent(key).slice(0, 8), 16) % count;
+
+/**
+ * Run a task in its page's lane once the tasks queued there before it have settled
+ * @template T
+ * @param {string} key Page name
+ * @param {() => T | Promise<T>} task
+ * @returns {Promise<T>}
+ */
+export function inShard(key, task) {
+  const count = Math.max(1, options.shards);
+  if (lanes.length !== count) lanes = Array.from({ length: count }, () => Promise.resolve());
+  const lane = shardOf(key, count);
+  const run = lanes[lane].then(task);
+  lanes[lane] = run.catch(() => { });
+  return run;
+}
+
 /** @param {string} file */
 export const hasFileBeenSeen = file => seenFiles.has(file);
 /** @param {string} file */
 export const markFileSeen = file => seenFiles.add(file);
 
-/** @param {string} root @param {import('./registry.js').Page} page */
-export function watchPage(root, page) {
+/**
+ * Parse a page file, re-reading it after a short delay while it yields no request and ends
+ * inside an unclosed code fence: editors that save in two steps leave a half-written file
+ * behind for a moment. A file still cut short after the last retry is reported as an error.
+ * @param {() => string} read
+ * @param {(text: string) => import('./parser.js').Request | null} parse
+ * @param {{ retries?: number, delayMs?: number }} [options]
+ * @returns {Promise<{ text: string, request: import('./parser.js').Request | null }>}
+ */
+export async function parseWithRetry(read, parse, { retries = PARSE_RETRY_LIMIT, delayMs = PARSE_RETRY_MS } = {}) {
+  for (let attempt = 0; ; attempt++) {
+    const text = read();
+    const request = parse(text);
+    if (request || !endsInOpenFence(text)) return { text, request };
+    if (attempt >= retries) throw new Error('the last code block is never closed');
+    await new Promise(resolve => setTimeout(resolve, delayMs));
+  }
+}
+
+/**
+ * Read a page's file and dispatch the request it ends with, unless the content was handled already.
+ * A forced check re-reads even unchanged or previously handled content, but still leaves a page
+ * that is running a job alone.
+ * @param {string} root
+ * @param {import('./registry.js').Page} page
+ * @param {{ lastContent: string }} tracked Content last handled for the page
+ * @param {boolean} [force]
+ * @returns {Promise<job.Job | undefined>} The job dispatched, if any
+ */
+async function checkPage(root, page, tracked, force = false) {
+  try {
+    if (!existsSync(page.file)) {
+      const movedTo = tracked.lastContent ? findRenamedFile(page.file, tracked.lastContent) : null;
+      tracked.lastContent = '';
+      if (movedTo) renamePage(root, page, movedTo);
+      return;
+    }
+    
+    markFileSeen(page.file);
+    const current = readFileSync(page.file, 'utf8');
+    if (current !== tracked.lastContent) writer.logChanged(page.file);
+    if (force ? job.get(page.name) : current === tracked.lastContent) return;
+    if (!force && !tracked.lastContent && isKnownContent(root, page.file, current)) {
+      // Already handled before a restart
+      tracked.lastContent = current;
+      return;
+    }
+    
+    let req;
+    try {
+      const parsed = await parseWithRetry(
+        () => readFileSync(page.file, 'utf8'),
+        text => parseRequest(text, page.name));
+      tracked.lastContent = parsed.text;
+      rememberContent(root, page.file, tracked.lastContent);
+      req = parsed.request;
+    } catch (err) {
+      writer.writeError(page.file, 'parse', `Could not parse request: ${err instanceof Error ? err.message : String(err)}`);
+      // Remember our own diagnostic write so it doesn't trigger another parse attempt
+      tracked.lastContent = readFileSync(page.file, 'utf8');
+      rememberContent(root, page.file, tracked.lastContent);
+      return;
+    }
+    if (!req) return;
+    
+    const snippetRaw = (req.code || '').replace(/\s+/g, ' ').trim();
+    const snippet = snippetRaw.length > 20 ? snippetRaw.slice(0, 20) + '...' : snippetRaw;
+    console.info(`> ${req.agent} to ${page.name} "${snippet}"`);
+    let created;
+    try {
+      created = job.create(page, req.agent, req.code, req.hasFooter, { language: req.language, context: req.context, thread: req.thread, ...fenceJobOptions(req.options) });
+    } catch (err) {
+      if (!(err instanceof job.JobRejectedError)) throw err;
+      writeRejection(page, req, err.message);
+      tracked.lastContent = readFileSync(page.file, 'utf8');
+      rememberContent(root, page.file, tracked.lastContent);
+      return;
+    }
+    registry.updateMaster(root);
+    return created;
+  } catch (err) {
+    if (err && typeof err === 'object' && 'code' in err && err.code === 'ENOENT') return;
+    console.warn(`[${page.name}] error:`, err);
+    writer.writeError(page.file, 'watch', err instanceof Error ? err.message : String(err));
+  }
+}
+
+/**
+ * Job options a request's fence asked for: `timeout` in milliseconds, `when` and `priority`.
+ * `worker` is already the request's context; anything else is left alone.
+ * @param {import('./parser.js').Request['options']} fence
+ * @returns {import('./job.js').JobOptions}
+ */
+export function fenceJobOptions(fence) {
+  if (!fence) return {};
+  return {
+    ...(typeof fence.timeout === 'number' && fence.timeout > 0 && { timeoutMs: fence.timeout }),
+    ...(typeof fence.when === 'string' && fence.when && { when: fence.when }),
+    ...(typeof fence.priority === 'number' && { priority: fence.priority })
+  };
+}
+
+/**
+ * Re-read and re-parse a page's file now, for edits the file watcher missed or when it is off.
+ * An unanswered request at the end of the file is dispatched unless the page already has a job.
+ * @param {string} root
+ * @param {import('./registry.js').Page} page
+ * @returns {Promise<job.Job | undefined>} The job dispatched, if any
+ */
+export function reindex(root, page) {
+  clearTimeout(timers.get(page.name));
+  return withPage(page.name, () => inShard(page.name, () => checkPage(root, page, trackedContent.get(page.name) || { lastContent: '' }, true)));
+}
+
+/**
+ * @param {string} root
+ * @param {import('./registry.js').Page} page
+ * @param {string} [knownContent] Content already handled, which must not dispatch again
+ */
+export function watchPage(root, page, knownContent = '') {
   if (activeWatchers.has(page.name)) return;
-  activeWatchers.add(page.name);
   
-  let lastContent = '';
+  const tracked = { lastContent: knownContent };
+  trackedContent.set(page.name, tracked);
   /** @type {ReturnType<typeof watch> | null} */
   let watcher = null;
+  /** @type {ReturnType<typeof watch> | null} */
+  let configWatcher = null;
+  activeWatchers.set(page.name, () => {
+    watcher?.close();
+    configWatcher?.close();
+  });
   
-  const check = () => {
-    try {
-      if (!existsSync(page.file)) {
-        lastContent = '';
-        return;
-      }
-      
-      markFileSeen(page.file);
-      const text = readFileSync(page.file, 'utf8');
-      if (text === lastContent) return;
-      
-      lastContent = text;
-      const req = parseRequest(text, page.name);
-      if (!req) return;
-      
-      const snippetRaw = (req.code || '').replace(/\s+/g, ' ').trim();
-      const snippet = snippetRaw.length > 20 ? snippetRaw.slice(0, 20) + '...' : snippetRaw;
-      console.info(`> ${req.agent} to ${page.name} "${snippet}"`);
-      job.create(page, req.agent, req.code, req.hasFooter);
-      registry.updateMaster(root);
-    } catch (err) {
-      if (err && typeof err === 'object' && 'code' in err && err.code !== 'ENOENT') {
-        console.warn(`[${page.name}] error:`, err);
-      }
-    }
-  };
+  // The page stays held from the moment a check is queued until it has dispatched
+  const check = () => withPage(page.name, () => inShard(page.name, () => checkPage(root, page, tracked)));
   
   const debounce = () => {
     const t = timers.get(page.name);
@@ -70,7 +273,9 @@ export function watchPage(root, page) {
     try {
       if (existsSync(page.file)) {
         watcher = watch(page.file, (evt) => {
-          if (evt === 'rename' && !existsSync(page.file)) setupWatch();
+          // The watched inode is gone: deleted, or replaced by a new file under the same name.
+          // Re-arm on the path, falling back to the directory until the file reappears.
+          if (evt === 'rename') setupWatch();
           debounce();
         });
       } else {
@@ -90,10 +295,100 @@ export function watchPage(root, page) {
     }
   };
   
+  // Per-page overrides are reread once their file changes
+  const configName = basename(pageConfig.configFileFor(page.file));
+  try {
+    configWatcher = watch(dirname(page.file), (evt, file) => {
+      if (file && basename(file) === configName) pageConfig.invalidate(page.file);
+    });
+  } catch (err) {
+    if (err && typeof err === 'object' && 'code' in err && err.code !== 'ENOENT') {
+      console.warn(`[${page.name}] config watch failed:`, err);
+    }
+  }
+  
   setupWatch();
   check();
 }
 
+/**
+ * Where a vanished page log went: another `.md` file beside it, not owned by any page,
+ * holding exactly the content last seen in the old file
+ * @param {string} oldFile
+ * @param {string} lastContent
+ * @returns {string | null}
+ */
+export function findRenamedFile(oldFile, lastContent) {
+  const dir = dirname(oldFile);
+  const owned = new Set(registry.all().map(p => p.file));
+  /** @type {string[]} */
+  let names = [];
+  try {
+    names = readdirSync(dir).filter(f => /\.md$/i.test(f));
+  } catch {
+    return null;
+  }
+  for (const name of names) {
+    const file = join(dir, name);
+    if (owned.has(file)) continue;
+    try {
+      if (readFileSync(file, 'utf8') === lastContent) return file;
+    } catch {}
+  }
+  return null;
+}
+
+/**
+ * Follow a page log to its new file: the registry entry and any job move to the new name
+ * and the new file is watched in place of the old one
+ * @param {string} root
+ * @param {import('./registry.js').Page} page
+ * @param {string} newFile
+ */
+export function renamePage(root, page, newFile) {
+  const oldName = page.name;
+  unwatchPage(oldName);
+  registry.rename(root, oldName, newFile);
+  job.rename(oldName, page.name);
+  let content = '';
+  try { content = readFileSync(newFile, 'utf8'); } catch {}
+  watchPage(root, page, content);
+}
+
+/**
+ * Stop watching a page's file
+ * @param {string} pageName
+ */
+export function unwatchPage(pageName) {
+  activeWatchers.get(pageName)?.();
+  activeWatchers.delete(pageName);
+  trackedContent.delete(pageName);
+  clearTimeout(timers.get(pageName));
+  timers.delete(pageName);
+}
+
+/**
+ * Answer a request that was refused before dispatch with an error reply
+ * @param {import('./registry.js').Page} page
+ * @param {import('./parser.js').Request} req
+ * @param {string} message
+ */
+function writeRejection(page, req, message) {
+  const now = new Date().toISOString();
+  writer.writeReply({
+    id: '',
+    page,
+    agent: req.agent,
+    code: req.code,
+    language: req.language,
+    requestHasFooter: req.hasFooter,
+    requestedAt: now,
+    startedAt: null,
+    finishedAt: now,
+    timeout: null
+  }, { ok: false, error: message });
+}
+
 /**
  * Watch daebug.md for %%SHUTDOWN%% marker and shutdown server if found
  * @param {string} root
@@ -1,10 +1,13 @@
 // @ts-check
 import { strict as assert } from 'node:assert';
 import { test, describe } from 'node:test';
-import { mkdtempSync, writeFileSync, rmSync, existsSync, readFileSync } from 'node:fs';
+import { mkdtempSync, mkdirSync, writeFileSync, rmSync, existsSync, readFileSync, renameSync } from 'node:fs';
 import { join } from 'node:path';
 import { tmpdir } from 'node:os';
-import { hasFileBeenSeen, markFileSeen } from './watcher.js';
+import { hasFileBeenSeen, markFileSeen, parseWithRetry, configure, loadContentHashes, rememberContent, isKnownContent, findRenamedFile, renamePage, unwatchPage, inShard, shardOf, reindex, fenceJobOptions, watchPage } from './watcher.js';
+import * as registry from './registry.js';
+import * as job from './job.js';
+import { parseRequest } from './parser.js';
 
 describe('watcher file tracking', () => {
   test('hasFileBeenSeen returns false for new file', () => {
@@ -125,3 +128,260 @@ describe('watcher edge cases', () => {
     assert.strictEqual(hasFileBeenSeen(specialPath), true);
   });
 });
+
+describe('parseWithRetry', () => {
+  const footer = '> Append your JavaScript snippet below to execute against this page.\n';
+  /** @param {string} text */
+  const parse = text => parseRequest(text, 'page');
+
+  test('re-reads a file caught halfway through a save', async () => {
+    const reads = [footer + '```js\n1 +', footer + '```js\n1 + 1\n```\n'];
+    let count = 0;
+    const result = await parseWithRetry(() => { count++; return reads.shift() || ''; }, parse, { delayMs: 1 });
+
+    assert.strictEqual(count, 2);
+    assert.strictEqual(result.request?.code, '1 + 1');
+    assert.ok(result.text.includes('1 + 1'));
+  });
+
+  test('gives up after the retry limit on a fence that stays open', async () => {
+    let count = 0;
+    await assert.rejects(
+      parseWithRetry(() => { count++; return footer + '```js\n1 +'; }, parse, { retries: 2, delayMs: 1 }),
+      /never closed/);
+    assert.strictEqual(count, 3);
+  });
+
+  test('does not retry a complete file with no request', async () => {
+    let count = 0;
+    const result = await parseWithRetry(() => { count++; return footer + 'no code here\n'; }, parse, { delayMs: 1 });
+    assert.strictEqual(count, 1);
+    assert.strictEqual(result.request, null);
+  });
+});
+
+describe('content hash cache', () => {
+  test('a restart recognises content that was already processed', () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-hash-'));
+    mkdirSync(join(root, 'daebug'));
+    const file = join(root, 'daebug', 'page.md');
+    configure({ persistContentHashes: true });
+    try {
+      rememberContent(root, file, 'answered content');
+      // Simulate a fresh process: drop memory and reload from disk
+      loadContentHashes(join(root, 'elsewhere'));
+      assert.equal(isKnownContent(root, file, 'answered content'), false);
+      loadContentHashes(root);
+
+      assert.equal(isKnownContent(root, file, 'answered content'), true);
+      assert.equal(isKnownContent(root, file, 'answered content\nnew request'), false);
+    } finally {
+      configure({ persistContentHashes: false });
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+
+  test('nothing is remembered unless persistence is enabled', () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-hash-'));
+    mkdirSync(join(root, 'daebug'));
+    try {
+      rememberContent(root, join(root, 'daebug', 'page.md'), 'text');
+      assert.equal(existsSync(join(root, 'daebug', '.content-hashes.json')), false);
+      assert.equal(isKnownContent(root, join(root, 'daebug', 'page.md'), 'text'), false);
+    } finally {
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+});
+
+describe('page renames', () => {
+  test('a renamed log keeps its page entry and pending job under the new name', () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+    try {
+      mkdirSync(join(root, 'daebug'), { recursive: true });
+      const page = registry.getOrCreate(root, 'before-rename', 'http://localhost');
+      const content = '# before-rename Session\n';
+      writeFileSync(page.file, content);
+      const pending = job.create(page, 'agent', 'stillQueued()');
+      
+      const oldFile = page.file;
+      const newFile = join(root, 'daebug', 'after-rename.md');
+      renameSync(oldFile, newFile);
+      assert.strictEqual(findRenamedFile(oldFile, content), newFile);
+      
+      renamePage(root, page, newFile);
+      assert.strictEqual(page.name, 'after-rename');
+      assert.strictEqual(page.file, newFile);
+      assert.strictEqual(registry.get('after-rename'), page);
+      // The page still polls under its old name until it reloads
+      assert.strictEqual(registry.get('before-rename'), page);
+      assert.strictEqual(registry.getOrCreate(root, 'before-rename', 'http://localhost'), page);
+      assert.strictEqual(job.get('after-rename'), pending);
+      assert.strictEqual(job.get('before-rename'), undefined);
+      assert.strictEqual(job.forPoller('after-rename'), pending);
+      job.finish(pending);
+    } finally {
+      unwatchPage('after-rename');
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+  
+  test('findRenamedFile ignores files owned by other pages or with other content', () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-test-'));
+    try {
+      mkdirSync(join(root, 'daebug'), { recursive: true });
+      const other = registry.getOrCreate(root, 'rename-owner', 'http://localhost');
+      writeFileSync(other.file, 'same');
+      writeFileSync(join(root, 'daebug', 'different.md'), 'not the same');
+      assert.strictEqual(findRenamedFile(join(root, 'daebug', 'gone.md'), 'same'), null);
+    } finally {
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+});
+
+describe('sharded page checks', () => {
+  /** @param {number} ms */
+  const sleep = ms => new Promise(resolve => setTimeout(resolve, ms));
+
+  test('checks for pages in different lanes run concurrently, one page\'s checks in order', async () => {
+    configure({ shards: 4 });
+    const first = 'sharded-a';
+    const second = ['sharded-b', 'sharded-c', 'sharded-d', 'sharded-e', 'sharded-f'].find(name => shardOf(name, 4) !== shardOf(first, 4));
+    assert.ok(second);
+
+    /** @type {string[]} */
+    const trace = [];
+    /** @param {string} label */
+    const slowCheck = label => async () => {
+      trace.push(label + ' start');
+      await sleep(40);
+      trace.push(label + ' end');
+    };
+    await Promise.all([
+      inShard(first, slowCheck('a1')),
+      inShard(second, slowCheck('b')),
+      inShard(first, slowCheck('a2'))
+    ]);
+
+    // Both lanes started before either finished, while the same page waited its turn
+    assert.deepStrictEqual(trace.slice(0, 2), ['a1 start', 'b start']);
+    assert.ok(trace.indexOf('a2 start') > trace.indexOf('a1 end'));
+  });
+
+  test('a failing check does not stall its lane', async () => {
+    await assert.rejects(inShard('sharded-a', () => { throw new Error('boom'); }), /boom/);
+    assert.strictEqual(await inShard('sharded-a', () => 'next'), 'next');
+  });
+});
+
+describe('fence options', () => {
+  test('a fence\'s timeout and worker flag reach the job it creates', async () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-fence-'));
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    try {
+      const page = registry.getOrCreate(root, 'fence-page', 'http://localhost/');
+      registry.getOrCreate(root, registry.workerNameFor('fence-page'), 'worker://fence-page');
+      writeFileSync(page.file, [
+        '> Append your JavaScript snippet below',
+        '### 🗣️agent to fence-page at 12:34:56',
+        '```js {timeout=5000, worker=true}',
+        'self.name',
+        '```'
+      ].join('\n'), 'utf8');
+
+      const created = await reindex(root, page);
+      assert.strictEqual(created?.timeoutMs, 5000);
+      assert.strictEqual(created?.context, 'worker');
+      if (created) job.finish(created);
+    } finally {
+      registry.evict(registry.workerNameFor('fence-page'));
+      registry.evict('fence-page');
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+
+  test('unknown and malformed options are not passed on', () => {
+    assert.deepStrictEqual(fenceJobOptions({ timeout: -1, priority: 2, label: 'x', when: '' }), { priority: 2 });
+    assert.deepStrictEqual(fenceJobOptions(undefined), {});
+  });
+});
+
+describe('deleted logs', () => {
+  /**
+   * @param {() => unknown} condition
+   * @param {number} [timeoutMs]
+   */
+  const waitFor = async (condition, timeoutMs = 2000) => {
+    for (const start = Date.now(); !condition(); await new Promise(resolve => setTimeout(resolve, 25)))
+      if (Date.now() - start > timeoutMs) return false;
+    return true;
+  };
+
+  test('edits to a log deleted and created again are still seen', async () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-recreate-'));
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'recreated-page', 'http://localhost/');
+    try {
+      writeFileSync(page.file, '# recreated-page Session\n');
+      watchPage(root, page);
+      await new Promise(resolve => setTimeout(resolve, 50));
+
+      rmSync(page.file);
+      await new Promise(resolve => setTimeout(resolve, 100));
+      writeFileSync(page.file, '# recreated-page Session\n');
+      await new Promise(resolve => setTimeout(resolve, 100));
+      writeFileSync(page.file, [
+        '# recreated-page Session',
+        '> Append your JavaScript snippet below',
+        '### 🗣️agent to recreated-page at 12:00:00',
+        '```js',
+        'afterRecreate()',
+        '```'
+      ].join('\n'));
+
+      assert.ok(await waitFor(() => job.get('recreated-page')), 'no job for the edit made after recreating the log');
+      assert.strictEqual(job.get('recreated-page')?.code, 'afterRecreate()');
+    } finally {
+      const pending = job.get('recreated-page');
+      if (pending) job.finish(pending);
+      unwatchPage('recreated-page');
+      registry.evict('recreated-page');
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+
+  test('edits after a log is replaced by another file under its name are still seen', async () => {
+    const root = mkdtempSync(join(tmpdir(), 'daebug-replace-'));
+    mkdirSync(join(root, 'daebug'), { recursive: true });
+    const page = registry.getOrCreate(root, 'replaced-page', 'http://localhost/');
+    try {
+      writeFileSync(page.file, '# replaced-page Session\n');
+      watchPage(root, page);
+      await new Promise(resolve => setTimeout(resolve, 50));
+
+      // Editors often save by writing a new file and renaming it over the old one
+      const saved = join(root, 'daebug', 'replaced-page.md.tmp');
+      writeFileSync(saved, '# replaced-page Session\n\nnotes\n');
+      renameSync(saved, page.file);
+      await new Promise(resolve => setTimeout(resolve, 250));
+      writeFileSync(page.file, [
+        '# replaced-page Session',
+        '> Append your JavaScript snippet below',
+        '### 🗣️agent to replaced-page at 12:00:00',
+        '```js',
+        'afterReplace()',
+        '```'
+      ].join('\n'));
+
+      assert.ok(await waitFor(() => job.get('replaced-page')), 'no job for the edit made after replacing the log');
+      assert.strictEqual(job.get('replaced-page')?.code, 'afterReplace()');
+    } finally {
+      const pending = job.get('replaced-page');
+      if (pending) job.finish(pending);
+      unwatchPage('replaced-page');
+      registry.evict('replaced-page');
+      rmSync(root, { recursive: true, force: true });
+    }
+  });
+});
@@ -0,0 +1,95 @@
+// @ts-check
+import { createHash } from 'node:crypto';
+import { STATUS_CODES } from 'node:http';
+
+/** Fixed GUID the handshake hashes with the client key (RFC 6455 §1.3) */
+const HANDSHAKE_GUID = '258EAFA5-E914-47DA-95CA-C5AB0DC85B11';
+
+/**
+ * Just enough of RFC 6455 to push JSON to a subscriber: the handshake, unmasked text frames
+ * from the server and a close frame. Whatever the client sends is ignored until it closes.
+ * @typedef {{
+ *   send: (message: unknown) => void,
+ *   close: (code?: number) => void,
+ *   closed: () => boolean
+ * }} PushSocket
+ */
+
+/**
+ * Answer an upgrade request, or refuse it with a plain HTTP status when it is not a websocket handshake
+ * @param {import('http').IncomingMessage} req
+ * @param {import('stream').Duplex} socket
+ * @returns {PushSocket | null}
+ */
+export function acceptUpgrade(req, socket) {
+  const key = req.headers['sec-websocket-key'];
+  if (String(req.headers.upgrade).toLowerCase() !== 'websocket' || typeof key !== 'string') {
+    refuseUpgrade(socket, 400, 'expected a websocket upgrade');
+    return null;
+  }
+  const accept = createHash('sha1').update(key + HANDSHAKE_GUID).digest('base64');
+  socket.write([
+    'HTTP/1.1 101 Switching Protocols',
+    'Upgrade: websocket',
+    'Connection: Upgrade',
+    'Sec-WebSocket-Accept: ' + accept,
+    '', ''
+  ].join('\r\n'));
+
+  let closed = false;
+  const end = () => { closed = true; };
+  socket.on('close', end);
+  socket.on('error', end);
+  socket.on('data', data => {
+    // Opcode 0x8: the client is closing; echo the close and hang up
+    if ((data[0] & 0x0f) === 0x8 && !closed) {
+      socket.end(frame(0x8, Buffer.alloc(0)));
+      closed = true;
+    }
+  });
+
+  return {
+    send: message => {
+      if (!closed) socket.write(frame(0x1, Buffer.from(JSON.stringify(message))));
+    },
+    close: (code = 1000) => {
+      if (closed) return;
+      closed = true;
+      const payload = Buffer.alloc(2);
+      payload.writeUInt16BE(code);
+      socket.end(frame(0x8, payload));
+    },
+    closed: () => closed
+  };
+}
+
+/**
+ * Turn down an upgrade before the handshake with an ordinary HTTP response
+ * @param {import('stream').Duplex} socket
+ * @param {number} status
+ * @param {string} message
+ */
+export function refuseUpgrade(socket, status, message) {
+  const body = Buffer.from(message);
+  socket.end(`HTTP/1.1 ${status} ${STATUS_CODES[status] || 'Error'}\r\nContent-Type: text/plain\r\nContent-Length: ${body.length}\r\nConnection: close\r\n\r\n` + message);
+}
+
+/**
+ * A single unmasked frame with FIN set
+ * @param {number} opcode
+ * @param {Buffer} payload
+ */
+function frame(opcode, payload) {
+  const length = payload.length;
+  const header = length < 126 ? Buffer.from([0x80 | opcode, length])
+    : length < 65536 ? Buffer.from([0x80 | opcode, 126, length >> 8, length & 0xff])
+      : Buffer.concat([Buffer.from([0x80 | opcode, 127]), bigLength(length)]);
+  return Buffer.concat([header, payload]);
+}
+
+/** @param {number} length */
+function bigLength(length) {
+  const bytes = Buffer.alloc(8);
+  bytes.writeBigUInt64BE(BigInt(length));
+  return bytes;
+}
@@ -0,0 +1,87 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { createServer, request } from 'node:http';
+import { mkdtempSync, mkdirSync, rmSync } from 'node:fs';
+import { tmpdir } from 'node:os';
+import { join } from 'node:path';
+import { handleUpgrade } from './server.js';
+import * as registry from './registry.js';
+import * as job from './job.js';
+
+/**
+ * Open a websocket on the server and collect its text frames until it closes
+ * @param {number} port
+ * @param {string} path
+ * @returns {Promise<{ status: number, frames: any[] }>}
+ */
+function subscribe(port, path) {
+  return new Promise((resolve, reject) => {
+    const req = request({
+      port,
+      path,
+      headers: { Connection: 'Upgrade', Upgrade: 'websocket', 'Sec-WebSocket-Version': '13', 'Sec-WebSocket-Key': 'dGhlIHNhbXBsZSBub25jZQ==' }
+    });
+    req.on('response', res => resolve({ status: res.statusCode || 0, frames: [] }));
+    req.on('upgrade', (res, socket, head) => {
+      assert.strictEqual(res.headers['sec-websocket-accept'], 's3pPLMBiTxaQ9kYGzzhZRbK+xOo=');
+      let buffer = head;
+      /** @type {any[]} */
+      const frames = [];
+      // Server frames are unmasked and small enough for a 7- or 16-bit length
+      const readFrames = () => {
+        while (buffer.length >= 2) {
+          let length = buffer[1] & 0x7f, offset = 2;
+          if (length === 126) { length = buffer.readUInt16BE(2); offset = 4; }
+          if (buffer.length < offset + length) break;
+          const opcode = buffer[0] & 0x0f;
+          if (opcode === 0x1) frames.push(JSON.parse(buffer.subarray(offset, offset + length).toString()));
+          buffer = buffer.subarray(offset + length);
+        }
+      };
+      readFrames();
+      socket.on('data', data => {
+        buffer = Buffer.concat([buffer, data]);
+        readFrames();
+      });
+      socket.on('close', () => resolve({ status: 101, frames }));
+    });
+    req.on('error', reject);
+    req.end();
+  });
+}
+
+test('a job websocket reports the job starting and its final result, then closes', async () => {
+  const root = mkdtempSync(join(tmpdir(), 'daebug-ws-'));
+  mkdirSync(join(root, 'daebug'), { recursive: true });
+  const server = createServer().on('upgrade', handleUpgrade);
+  await new Promise(resolve => server.listen(0, () => resolve(undefined)));
+  const port = /** @type {import('node:net').AddressInfo} */(server.address()).port;
+  try {
+    const page = registry.getOrCreate(root, 'ws-page', 'http://localhost');
+    const j = job.create(page, 'agent', '6 * 7');
+    const subscription = subscribe(port, `/-daebug-job/${j.id}/ws`);
+
+    // Another task plays the page: it picks the job up and reports back
+    setTimeout(() => {
+      job.start(j);
+      setTimeout(() => job.finish(j, { ok: true, value: 42 }), 20);
+    }, 50);
+
+    const { status, frames } = await subscription;
+    assert.strictEqual(status, 101);
+    assert.deepStrictEqual(frames.map(f => f.type === 'state' ? f.state : f.type), ['queued', 'running', 'result']);
+    assert.strictEqual(frames[2].id, j.id);
+    assert.strictEqual(frames[2].ok, true);
+    assert.strictEqual(frames[2].value, 42);
+
+    // Subscribing after the job is over still delivers its kept result
+    const late = await subscribe(port, `/-daebug-job/${j.id}/ws`);
+    assert.deepStrictEqual(late.frames.map(f => f.value), [42]);
+
+    assert.strictEqual((await subscribe(port, '/-daebug-job/no-such-job/ws')).status, 404);
+  } finally {
+    server.close();
+    rmSync(root, { recursive: true, force: true });
+  }
+});
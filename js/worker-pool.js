@@ -0,0 +1,127 @@
+// @ts-check
+
+/**
+ * Bounded set of reusable workers. Tasks run on an idle worker, a new one is spawned while the
+ * pool is below its size, and the rest wait in order. Every worker is reset after each task so
+ * one job's globals never leak into the next; a worker whose reset fails is dropped.
+ * Knows nothing of Worker or the browser: `spawn`, `reset` and `terminate` supply the platform.
+ * @template W
+ */
+export class WorkerPool {
+  /** @type {W[]} */
+  #idle = [];
+  /** @type {Set<W>} */
+  #busy = new Set();
+  /** @type {Array<{ resolve: (worker: W) => void, reject: (err: unknown) => void }>} */
+  #queue = [];
+  #spawning = 0;
+  #size;
+  #spawn;
+  #reset;
+  #terminate;
+
+  /**
+   * @param {number} size Most workers alive at once
+   * @param {{
+   *   spawn: () => W | Promise<W>,
+   *   reset: (worker: W) => void | Promise<void>,
+   *   terminate?: (worker: W) => void
+   * }} platform
+   */
+  constructor(size, { spawn, reset, terminate }) {
+    if (!(Number.isInteger(size) && size >= 1)) throw new RangeError(`pool size must be a positive integer (got ${size})`);
+    this.#size = size;
+    this.#spawn = spawn;
+    this.#reset = reset;
+    this.#terminate = terminate || (() => { });
+  }
+
+  get size() { return this.#size; }
+
+  /** @returns {{ size: number, idle: number, busy: number, queued: number }} */
+  stats() {
+    return { size: this.#size, idle: this.#idle.length, busy: this.#busy.size, queued: this.#queue.length };
+  }
+
+  /**
+   * Run a task on a worker of the pool once one is free
+   * @template T
+   * @param {(worker: W) => T | Promise<T>} task
+   * @returns {Promise<T>}
+   */
+  async run(task) {
+    const worker = await this.#acquire();
+    try {
+      return await task(worker);
+    } finally {
+      this.#release(worker);
+    }
+  }
+
+  /**
+   * Change how many workers the pool may hold. Shrinking terminates idle workers at once
+   * and busy ones as they finish.
+   * @param {number} size
+   */
+  resize(size) {
+    if (!(Number.isInteger(size) && size >= 1)) throw new RangeError(`pool size must be a positive integer (got ${size})`);
+    this.#size = size;
+    while (this.#idle.length && this.#live() > size) this.#terminate(/** @type {W} */(this.#idle.pop()));
+    this.#fill();
+  }
+
+  /** Terminate every idle worker; busy ones are terminated as their tasks finish */
+  close() {
+    this.#size = 0;
+    for (const worker of this.#idle.splice(0)) this.#terminate(worker);
+  }
+
+  #live() {
+    return this.#idle.length + this.#busy.size + this.#spawning;
+  }
+
+  /** @returns {Promise<W>} */
+  #acquire() {
+    return new Promise((resolve, reject) => {
+      this.#queue.push({ resolve, reject });
+      this.#fill();
+    });
+  }
+
+  /** Hand idle workers to waiting tasks, spawning while there is room */
+  #fill() {
+    while (this.#queue.length && this.#idle.length) {
+      const worker = /** @type {W} */(this.#idle.shift());
+      this.#busy.add(worker);
+      this.#queue.shift()?.resolve(worker);
+    }
+    while (this.#queue.length > this.#spawning && this.#live() < this.#size) {
+      this.#spawning++;
+      Promise.resolve().then(() => this.#spawn()).then(
+        worker => { this.#spawning--; this.#idle.push(worker); this.#fill(); },
+        err => {
+          this.#spawning--;
+          // With no worker left to wait for, the oldest task fails instead of hanging
+          if (!this.#live()) this.#queue.shift()?.reject(err);
+          else console.warn('[worker-pool] spawn failed', err);
+        });
+    }
+  }
+
+  /** @param {W} worker */
+  #release(worker) {
+    Promise.resolve().then(() => this.#reset(worker)).then(
+      () => {
+        this.#busy.delete(worker);
+        if (this.#live() >= this.#size) this.#terminate(worker);
+        else this.#idle.push(worker);
+        this.#fill();
+      },
+      err => {
+        console.warn('[worker-pool] reset failed, dropping worker', err);
+        this.#busy.delete(worker);
+        this.#terminate(worker);
+        this.#fill();
+      });
+  }
+}
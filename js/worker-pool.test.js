@@ -0,0 +1,94 @@
+// @ts-check
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { WorkerPool } from './worker-pool.js';
+
+/** Pool of plain objects standing in for workers, recording what happens to them */
+function fakePool(size, { failReset = false } = {}) {
+  /** @type {string[]} */
+  const log = [];
+  let spawned = 0;
+  const pool = new WorkerPool(size, {
+    spawn: () => ({ id: ++spawned, globals: /** @type {Record<string, unknown>} */({}) }),
+    reset: worker => {
+      log.push(`reset ${worker.id}`);
+      if (failReset) throw new Error('reset failed');
+      worker.globals = {};
+    },
+    terminate: worker => { log.push(`terminate ${worker.id}`); }
+  });
+  return { pool, log, spawned: () => spawned };
+}
+
+/** A task that holds its worker until released */
+function heldTask() {
+  /** @type {() => void} */
+  let release = () => { };
+  const held = new Promise(resolve => { release = () => resolve(undefined); });
+  /** @type {{ id: number } | null} */
+  let ranOn = null;
+  /** @param {{ id: number }} worker */
+  const task = async worker => { ranOn = worker; await held; return worker.id; };
+  return { task, release, ranOn: () => ranOn };
+}
+
+test('tasks beyond the pool size queue until a worker is released', async () => {
+  const { pool, spawned } = fakePool(2);
+  const [a, b, c] = [heldTask(), heldTask(), heldTask()];
+  const runs = [pool.run(a.task), pool.run(b.task), pool.run(c.task)];
+  await new Promise(resolve => setImmediate(resolve));
+
+  assert.deepStrictEqual(pool.stats(), { size: 2, idle: 0, busy: 2, queued: 1 });
+  assert.strictEqual(spawned(), 2);
+  assert.strictEqual(c.ranOn(), null);
+
+  a.release();
+  assert.strictEqual(await runs[0], 1);
+  await new Promise(resolve => setImmediate(resolve));
+  assert.strictEqual(c.ranOn()?.id, 1, 'the queued task reuses the released worker');
+  assert.strictEqual(spawned(), 2);
+
+  b.release();
+  c.release();
+  await Promise.all(runs);
+  await new Promise(resolve => setImmediate(resolve));
+  assert.deepStrictEqual(pool.stats(), { size: 2, idle: 2, busy: 0, queued: 0 });
+});
+
+test('a worker is reset between jobs so globals do not leak', async () => {
+  const { pool, log } = fakePool(1);
+  await pool.run(worker => { worker.globals.leaked = 'secret'; });
+  const seen = await pool.run(worker => worker.globals.leaked);
+  assert.strictEqual(seen, undefined);
+  assert.deepStrictEqual(log, ['reset 1', 'reset 1']);
+});
+
+test('a worker whose reset fails is dropped and replaced', async () => {
+  const { pool, log, spawned } = fakePool(1, { failReset: true });
+  await pool.run(() => { });
+  const id = await pool.run(worker => worker.id);
+  assert.strictEqual(id, 2);
+  assert.strictEqual(spawned(), 2);
+  assert.ok(log.includes('terminate 1'));
+});
+
+test('a failing task still releases its worker', async () => {
+  const { pool } = fakePool(1);
+  await assert.rejects(pool.run(() => { throw new Error('boom'); }), /boom/);
+  assert.strictEqual(await pool.run(worker => worker.id), 1);
+});
+
+test('shrinking terminates idle workers beyond the new size', async () => {
+  const { pool, log } = fakePool(3);
+  const held = [heldTask(), heldTask(), heldTask()];
+  const runs = held.map(h => pool.run(h.task));
+  await new Promise(resolve => setImmediate(resolve));
+  held.forEach(h => h.release());
+  await Promise.all(runs);
+  await new Promise(resolve => setImmediate(resolve));
+
+  pool.resize(1);
+  assert.deepStrictEqual(pool.stats(), { size: 1, idle: 1, busy: 0, queued: 0 });
+  assert.strictEqual(log.filter(line => line.startsWith('terminate')).length, 2);
+  assert.throws(() => pool.resize(0), RangeError);
+});
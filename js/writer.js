@@ -1,70 +1,471 @@
 // @ts-check
-import { readFileSync, writeFileSync, existsSync, mkdirSync } from 'node:fs';
+import { createHash, randomBytes } from 'node:crypto';
+import { gzipSync, gunzipSync } from 'node:zlib';
+import { fsStorage, createMemoryStorage } from './storage.js';
+import { getTheme, replyMarkerPattern, withTheme } from './theme.js';
+import * as pageConfig from './page-config.js';
 import { hasFileBeenSeen } from './watcher.js';
-import { dirname } from 'node:path';
+import { dirname, join } from 'node:path';
 import {
   clockFmt,
   durationFmt,
   findFooter,
+  BOM,
   findLastFencedBlock,
   findAgentHeaderAbove,
   findExecutingBlock,
+  findThreadRequest,
   formatAgentHeader,
+  formatCodeLink,
   formatReplyHeader,
+  formatExecutingHeader,
   formatCodeBlock,
   formatResultBlocks,
+  formatVerdict,
   formatFooter,
   formatBackgroundEvent,
+  formatExecutingPlaceholder,
+  formatErrorNote,
+  formatPageRequest,
+  formatPageRequestAnswer,
+  resultJson,
   ensureFileHeader
 } from './repl.template.js';
 
+/**
+ * Rewrites a result before it reaches the log, e.g. to redact secrets or trim large values
+ * @typedef {(result: JobResult, job: import('./job.js').Job) => JobResult} ResultTransform
+ */
+
+/**
+ * @typedef {{
+ *   ok: boolean,
+ *   value?: any,
+ *   valueType?: string,
+ *   error?: any,
+ *   skipped?: boolean,
+ *   errors?: string[],
+ *   logs?: { level: string, message: string, eventAt?: string }[],
+ *   backgroundEvents?: any[],
+ *   attachments?: Attachment[]
+ * }} JobResult
+ */
+
+/**
+ * A file a result carries, base64 in `data` as the page sends it. Once written, `href`
+ * points at the saved copy and `data` is dropped; a refused one keeps only why in `rejected`.
+ * @typedef {{
+ *   name: string,
+ *   type: string,
+ *   data?: string,
+ *   href?: string,
+ *   rejected?: string
+ * }} Attachment
+ */
+
+/**
+ * @typedef {{
+ *   dedupReplies: boolean,
+ *   order: 'append' | 'newest-first',
+ *   storage: import('./storage.js').Storage,
+ *   coalesceMs: number,
+ *   coalesceMaxBatch: number,
+ *   adaptiveFlushRate: number,
+ *   adaptiveFlushMs: number,
+ *   adaptiveMaxBatch: number,
+ *   executingIndicator: boolean,
+ *   threadIds: boolean,
+ *   rotateBytes: number,
+ *   compressArchives: boolean,
+ *   lineEndings: 'preserve' | 'lf',
+ *   byteOrderMark: 'preserve' | 'strip',
+ *   trailingNewline: 'keep' | 'one' | 'none',
+ *   stripTrailingSpaces: boolean,
+ *   maxLogBytes: number,
+ *   codeSidecarBytes: number,
+ *   resultFiles: 'off' | 'also' | 'only',
+ *   markdown: boolean,
+ *   attachmentTypes: { allow: string[], deny: string[] },
+ *   transformResult: ResultTransform | null
+ * }} WriterOptions
+ */
+
+/** @type {WriterOptions} */
+const options = {
+  dedupReplies: false,
+  order: 'append',
+  storage: fsStorage,
+  coalesceMs: 0,
+  coalesceMaxBatch: 100,
+  adaptiveFlushRate: 0,
+  adaptiveFlushMs: 250,
+  adaptiveMaxBatch: 50,
+  executingIndicator: true,
+  threadIds: false,
+  rotateBytes: 0,
+  compressArchives: false,
+  lineEndings: 'preserve',
+  byteOrderMark: 'preserve',
+  trailingNewline: 'keep',
+  stripTrailingSpaces: false,
+  maxLogBytes: 0,
+  codeSidecarBytes: 0,
+  resultFiles: 'off',
+  // Off, no page log is written at all: results reach agents only as result files, over HTTP and as events
+  markdown: true,
+  // Content types a result's attachments may have, as `type/subtype` or `type/*`; deny wins
+  attachmentTypes: { allow: ['*/*'], deny: [] },
+  transformResult: null
+};
+
+/** @param {Partial<WriterOptions>} config */
+export function configure(config) {
+  Object.assign(options, config);
+}
+
+/** Raised instead of growing a log past `maxLogBytes` */
+export class LogFullError extends Error {
+  /** @param {string} file @param {number} limit */
+  constructor(file, limit) {
+    super(`log full: ${file} would exceed ${limit} bytes`);
+    this.name = 'LogFullError';
+  }
+}
+
+/**
+ * Logs that refused a write for being full, with their size at the time. A log stops
+ * counting as full once it is rotated or shrinks below that size.
+ * @type {Map<string, number>}
+ */
+const fullLogs = new Map();
+
+/**
+ * Whether a log refused a write for being full and has not been reset or rotated since
+ * @param {string} file
+ */
+export function isLogFull(file) {
+  const sizeWhenFull = fullLogs.get(file);
+  if (sizeWhenFull === undefined) return false;
+  const storage = storageFor(file);
+  if (options.maxLogBytes && storage.exists(file) && Buffer.byteLength(storage.read(file)) >= sizeWhenFull) return true;
+  fullLogs.delete(file);
+  return false;
+}
+
+/**
+ * In-memory logs of ephemeral pages, keyed by the log file they would otherwise have on disk.
+ * Each log gets its own storage so its archives go with it when the page is discarded.
+ * @type {Map<string, import('./storage.js').Storage>}
+ */
+const ephemeralLogs = new Map();
+
+/**
+ * Stand-ins for a log's storage while buffered writes are replayed: the live file is read once,
+ * kept in memory across every write, and written back once at the end
+ * @type {Map<string, import('./storage.js').Storage>}
+ */
+const overlays = new Map();
+
+/** @param {string} file */
+const storageFor = file => overlays.get(file) ?? ephemeralLogs.get(file) ?? options.storage;
+
+/**
+ * Keep a page's log in memory only, starting it with a header and footer as an agent would on disk
+ * @param {string} file
+ * @param {string} title
+ */
+export function markEphemeral(file, title) {
+  if (ephemeralLogs.has(file)) return;
+  const storage = createMemoryStorage();
+  storage.write(file, [...ensureFileHeader([], title), '', formatFooter()].join('\n'));
+  ephemeralLogs.set(file, storage);
+}
+
+/** @param {string} file */
+export const isEphemeral = file => ephemeralLogs.has(file);
+
+/**
+ * Current content of a log, whether it lives on disk or in memory
+ * @param {string} file
+ * @param {boolean} [flush] Write out coalesced appends first; off for change listeners,
+ *   which run in the middle of a write
+ * @returns {string}
+ */
+export const readLog = (file, flush = true) => {
+  if (flush) flushPending(file);
+  return storageFor(file).read(file);
+};
+
+/**
+ * Callbacks waiting to hear that a log changed, by file
+ * @type {Map<string, Set<() => void>>}
+ */
+const changeListeners = new Map();
+
+/**
+ * Hear about every change to a log: the writer's own, and edits others make that the
+ * watcher reports through `logChanged`
+ * @param {string} file
+ * @param {() => void} listener
+ * @returns {() => void} Stops listening
+ */
+export function onLogChange(file, listener) {
+  const listeners = changeListeners.get(file) || new Set();
+  changeListeners.set(file, listeners.add(listener));
+  return () => {
+    listeners.delete(listener);
+    if (!listeners.size) changeListeners.delete(file);
+  };
+}
+
+/**
+ * Tell those listening that a log changed
+ * @param {string} file
+ */
+export function logChanged(file) {
+  for (const listener of changeListeners.get(file) || []) {
+    try { listener(); }
+    catch (err) { console.warn('[writer] change listener failed', err); }
+  }
+}
+
+/**
+ * Drop an ephemeral page's log along with anything left of it on disk
+ * @param {string} file
+ */
+export function discard(file) {
+  ephemeralLogs.delete(file);
+  lineEndings.delete(file);
+  byteOrderMarks.delete(file);
+  clearTimeout(pendingAppends.get(file)?.timer);
+  pendingAppends.delete(file);
+  clearTimeout(deferredWrites.get(file)?.timer);
+  deferredWrites.delete(file);
+  if (options.storage.exists(file)) options.storage.remove(file);
+}
+
+/**
+ * Line ending each log was last read with, so rewrites keep the file's own convention
+ * @type {Map<string, string>}
+ */
+const lineEndings = new Map();
+
+/**
+ * Logs that were last read starting with a byte order mark, which rewrites keep unless stripping
+ * @type {Set<string>}
+ */
+const byteOrderMarks = new Set();
+
+/**
+ * Read a log with its line endings normalized to LF and any byte order mark set aside,
+ * noting the file's dominant ending and whether it had the mark
+ * @param {string} file
+ * @returns {string}
+ */
+function readText(file) {
+  let raw = storageFor(file).read(file);
+  if (raw.startsWith(BOM)) {
+    raw = raw.slice(1);
+    byteOrderMarks.add(file);
+  } else byteOrderMarks.delete(file);
+  const crlf = (raw.match(/\r\n/g) || []).length;
+  const lf = (raw.match(/\n/g) || []).length - crlf;
+  lineEndings.set(file, options.lineEndings === 'preserve' && crlf > lf ? '\r\n' : '\n');
+  return crlf ? raw.replace(/\r\n/g, '\n') : raw;
+}
+
+/**
+ * Apply the trailing whitespace policy, so what linters see does not depend on which writes
+ * touched the log last. Stripping covers every line, since the whole log is rewritten each time.
+ * @param {string} content LF-joined text
+ */
+function tidyEnds(content) {
+  if (options.stripTrailingSpaces) content = content.replace(/[ \t]+$/gm, '');
+  if (options.trailingNewline === 'one') return content.replace(/\n*$/, '\n');
+  if (options.trailingNewline === 'none') return content.replace(/\n+$/, '');
+  return content;
+}
+
+/**
+ * Put back the byte order mark a log was read with, unless marks are being stripped
+ * @param {string} file
+ * @param {string} text
+ */
+function withBom(file, text) {
+  return options.byteOrderMark === 'preserve' && byteOrderMarks.has(file) ? BOM + text : text;
+}
+
+/**
+ * Write LF-joined text using the line ending the file was read with
+ * @param {string} file
+ * @param {string} content
+ * @param {string} [eol]
+ */
+function writeText(file, content, eol = lineEndings.get(file) || '\n') {
+  if (!options.markdown) return;
+  const storage = storageFor(file);
+  content = tidyEnds(content);
+  const text = withBom(file, eol === '\n' ? content : content.replace(/\n/g, eol));
+  if (options.maxLogBytes && Buffer.byteLength(text) > options.maxLogBytes) {
+    fullLogs.set(file, storage.exists(file) ? Buffer.byteLength(storage.read(file)) : 0);
+    throw new LogFullError(file, options.maxLogBytes);
+  }
+  storage.write(file, text);
+  logChanged(file);
+}
+
+/**
+ * The request's code as written into the log: inline, or, past `codeSidecarBytes`, saved
+ * to `code/<job>-<hash>.<ext>` beside the log and linked so the log stays readable.
+ * The hash keeps sidecars of different sessions apart, since job ids restart with the server.
+ * @param {import('./job.js').Job} job
+ * @returns {string}
+ */
+function requestCode(job) {
+  if (!options.codeSidecarBytes || Buffer.byteLength(job.code) <= options.codeSidecarBytes)
+    return formatCodeBlock(job.code);
+  const hash = createHash('sha1').update(job.code).digest('hex').slice(0, 8);
+  const href = `code/${job.id}-${hash}.${job.language === 'ts' ? 'ts' : 'js'}`;
+  const storage = storageFor(job.page.file);
+  const dir = join(dirname(job.page.file), 'code');
+  if (!storage.exists(dir)) storage.ensureDir(dir);
+  storage.write(join(dirname(job.page.file), href), job.code);
+  return formatCodeLink(href);
+}
+
+/**
+ * Why an attachment of this content type may not be written, or null when it may
+ * @param {string} type
+ * @returns {string | null}
+ */
+export function attachmentRefusal(type) {
+  const essence = String(type || '').split(';')[0].trim().toLowerCase();
+  if (!/^[\w.+-]+\/[\w.+-]+$/.test(essence)) return `content type ${JSON.stringify(type)} is not a valid media type`;
+  /** @param {string} pattern */
+  const matches = pattern => {
+    const [kind, sub] = pattern.toLowerCase().split('/');
+    const [typeKind, typeSub] = essence.split('/');
+    return (kind === '*' || kind === typeKind) && (sub === '*' || sub === typeSub);
+  };
+  const { allow, deny } = options.attachmentTypes;
+  if (deny.some(matches) || !allow.some(matches)) return `content type ${essence} is not accepted`;
+  return null;
+}
+
+/**
+ * Save a result's attachments as `attachments/<job>-<n>-<name>` beside the log, refusing
+ * any whose content type the allow and deny lists rule out before a byte reaches disk
+ * @param {import('./job.js').Job} job
+ * @param {JobResult} result
+ * @returns {JobResult} The result with each attachment's data swapped for its link or refusal
+ */
+function saveAttachments(job, result) {
+  if (!result.attachments?.length) return result;
+  const storage = storageFor(job.page.file);
+  const dir = join(dirname(job.page.file), 'attachments');
+  const attachments = result.attachments.map(({ data, ...attachment }, i) => {
+    const name = String(attachment.name || `attachment-${i + 1}`);
+    const refusal = attachmentRefusal(attachment.type);
+    if (refusal) {
+      console.warn(`[writer] job ${job.id}: attachment ${name} refused, ${refusal}`);
+      return { name, type: attachment.type, rejected: `attachment ${name} was not written: ${refusal}` };
+    }
+    const href = `attachments/${job.id}-${i + 1}-${name.replace(/[^\w.-]+/g, '_')}`;
+    if (!storage.exists(dir)) storage.ensureDir(dir);
+    storage.writeBytes(join(dirname(job.page.file), href), Buffer.from(data || '', 'base64'));
+    return { name, type: attachment.type, href };
+  });
+  return { ...result, attachments };
+}
+
+/**
+ * Write a job's result as `results/<job>.json` beside its log, for tools that would
+ * rather not parse markdown
+ * @param {import('./job.js').Job} job
+ * @param {JobResult} result
+ */
+function writeResultFile(job, result) {
+  const timestamp = new Date().toISOString();
+  const storage = storageFor(job.page.file);
+  const dir = join(dirname(job.page.file), 'results');
+  if (!storage.exists(dir)) storage.ensureDir(dir);
+  storage.write(join(dir, `${job.id}.json`), JSON.stringify({
+    jobId: job.id,
+    page: job.page.name,
+    ok: result.ok,
+    ...(result.ok ? { value: result.value } : { error: result.error }),
+    ...(result.skipped && { skipped: true }),
+    ...(result.logs?.length && { logs: result.logs }),
+    ...(result.attachments?.length && { attachments: result.attachments }),
+    durationMs: job.startedAt ? Date.parse(timestamp) - Date.parse(job.startedAt) : 0,
+    timestamp
+  }, null, 2));
+}
+
+/**
+ * Read back code a log links to in a sidecar file
+ * @param {string} file The log
+ * @param {string} href The link target, relative to the log
+ * @returns {string | undefined}
+ */
+export function readSidecar(file, href) {
+  const path = join(dirname(file), href);
+  const storage = storageFor(file);
+  return storage.exists(path) ? storage.read(path) : undefined;
+}
+
+/**
+ * Thread id written into the job's request and reply headings: the one the agent wrote,
+ * or a fresh short id when thread ids are switched on
+ * @param {import('./job.js').Job} job
+ * @returns {string | undefined}
+ */
+function threadFor(job) {
+  if (!job.thread && options.threadIds) job.thread = randomBytes(3).toString('hex');
+  return job.thread;
+}
+
 /**
  * Write a system diagnostic message to a page's chat log
  * @param {string} file - Path to the page's chat file
  * @param {string} message - Diagnostic message
  */
 export function writeDiagnostic(file, message) {
-  if (!existsSync(file)) {
-    // File doesn't exist yet, create it with diagnostic
-    let lines = [];
-    lines = ensureFileHeader(lines, 'System Diagnostic');
-  const nowIso = new Date().toISOString();
-  const timestamp = clockFmt(nowIso);
-    
-    const content = [
-      ...lines,
-      '',
-      `### 🗣️System at ${timestamp}`,
-      '```Text',
-      message,
-      '```',
-      '',
-      formatFooter()
-    ].join('\n');
-    
-    writeFileSync(file, content, 'utf8');
-    return;
-  }
-  
-  let lines = readFileSync(file, 'utf8').split('\n');
-  lines = ensureFileHeader(lines, 'System Diagnostic');
-  
-  const footerIdx = findFooter(lines) >= 0 ? findFooter(lines) : lines.length;
-  const nowIso = new Date().toISOString();
-  const timestamp = clockFmt(nowIso);
-  
-  const output = [
-    ...lines.slice(0, footerIdx),
+  const timestamp = clockFmt(new Date().toISOString());
+  appendSection(file, 'System Diagnostic', [
     '',
-    `### 🗣️System at ${timestamp}`,
+    `### ${getTheme().agent}System at ${timestamp}`,
     '```Text',
     message,
-    '```',
-    '',
-    formatFooter()
-  ].join('\n');
-  
-  writeFileSync(file, output, 'utf8');
+    '```'
+  ], true);
+}
+
+/**
+ * Note a server-side failure in the page's log, so a request that silently went nowhere
+ * explains itself. Failing to write the note is only logged: this runs on error paths already.
+ * @param {string} file
+ * @param {string} context What the server was doing, e.g. 'parse' or 'result'
+ * @param {string} message
+ */
+export function writeError(file, context, message) {
+  try {
+    appendSection(file, 'System Diagnostic', ['', formatErrorNote(context, message, new Date().toISOString())], false);
+  } catch (err) {
+    console.warn(`[writer] could not note ${context} error in ${file}`, err);
+  }
+}
+
+/**
+ * Note a request the page made of its agents, or an agent's answer to one, above the footer
+ * @param {string} file
+ * @param {import('./job.js').PageRequest} request
+ */
+export function writePageRequest(file, request) {
+  const section = request.answeredAt
+    ? formatPageRequestAnswer(request.agent || 'agent', request.page, request.id, request.answer || '', request.answeredAt)
+    : formatPageRequest(request.page, request.id, request.message, request.askedAt);
+  appendSection(file, 'Page Requests', ['', section], true);
 }
 
 /**
@@ -73,40 +474,7 @@ export function writeDiagnostic(file, message) {
  * @param {string} markdown - Test progress markdown
  */
 export function writeTestProgress(file, markdown) {
-  if (!existsSync(file)) {
-    // Create parent directory if it doesn't exist
-    const dir = dirname(file);
-    if (dir && !existsSync(dir)) {
-      mkdirSync(dir, { recursive: true });
-    }
-    /** @type {string[]} */
-    let lines = [];
-    lines = ensureFileHeader(lines, 'Test Progress');
-    
-    const content = [
-      ...lines,
-      markdown,
-      '',
-      formatFooter()
-    ].join('\n');
-    
-    writeFileSync(file, content, 'utf8');
-    return;
-  }
-  
-  let lines = readFileSync(file, 'utf8').split('\n');
-  lines = ensureFileHeader(lines, 'Test Progress');
-  
-  const footerIdx = findFooter(lines) >= 0 ? findFooter(lines) : lines.length;
-  
-  const output = [
-    ...lines.slice(0, footerIdx),
-    markdown,
-    '',
-    formatFooter()
-  ].join('\n');
-  
-  writeFileSync(file, output, 'utf8');
+  appendSection(file, 'Test Progress', [markdown], true);
 }
 
 /**
@@ -116,75 +484,265 @@ export function writeTestProgress(file, markdown) {
  * @param {string} timestamp - Timestamp of flush
  */
 export function writeBackgroundEvents(file, events, timestamp) {
-  if (!existsSync(file)) return; // No file yet, can't write background events
-  
-  let lines = readFileSync(file, 'utf8').split('\n');
-  lines = ensureFileHeader(lines, 'Background Events');
-  
-  const footerIdx = findFooter(lines) >= 0 ? findFooter(lines) : lines.length;
-  
   const pageName = file.match(/([^/\\]+)\.md$/)?.[1] || 'page';
-  const blocks = events.map(formatBackgroundEvent);
-  
+  // No file yet, can't write background events
+  appendSection(file, 'Background Events', [
+    '',
+    `### ${getTheme().agent}${pageName} background at ${timestamp}`,
+    ...events.map(formatBackgroundEvent)
+  ], false);
+}
+
+/**
+ * Sections waiting to be appended to a file in one read-modify-write
+ * @type {Map<string, { title: string, create: boolean, sections: string[][], timer: ReturnType<typeof setTimeout> }>}
+ */
+const pendingAppends = new Map();
+
+/**
+ * Append a section above the footer. With `coalesceMs` set, bursts of sections for the
+ * same file are batched so a fast stream of progress reports costs one rewrite, not one each.
+ * @param {string} file
+ * @param {string} title Header title used if the file has none
+ * @param {string[]} section
+ * @param {boolean} create Whether a missing file should be created
+ */
+function appendSection(file, title, section, create) {
+  // Behind buffered replies, so it lands after them
+  if (deferredWrites.has(file)) return deferWrite(file, () => applyAppends(file, title, [section], create));
+  if (!(options.coalesceMs > 0)) return applyAppends(file, title, [section], create);
+
+  const pending = pendingAppends.get(file);
+  if (!pending) {
+    const timer = setTimeout(() => flushPending(file), options.coalesceMs);
+    // Shutdown flushes what is still held, so the timer need not keep the process up
+    try { timer.unref(); } catch {}
+    pendingAppends.set(file, { title, create, sections: [section], timer });
+    return;
+  }
+  pending.sections.push(section);
+  pending.create ||= create;
+  if (pending.sections.length >= options.coalesceMaxBatch) flushPending(file);
+}
+
+/**
+ * Write out sections still held by the coalescer and writes buffered for busy logs
+ * @param {string} [file] Only this file; all files when omitted
+ */
+export function flushPending(file) {
+  for (const target of file ? [file] : [...pendingAppends.keys()]) {
+    const pending = pendingAppends.get(target);
+    if (!pending) continue;
+    pendingAppends.delete(target);
+    clearTimeout(pending.timer);
+    applyAppends(target, pending.title, pending.sections, pending.create);
+  }
+  for (const target of file ? [file] : [...deferredWrites.keys()]) flushDeferred(target);
+}
+
+/**
+ * When each log last received results, within the past second, for measuring result rates
+ * @type {Map<string, number[]>}
+ */
+const resultTimes = new Map();
+
+/**
+ * Record a result for a log; true while its results arrive at `adaptiveFlushRate` per second or faster
+ * @param {string} file
+ */
+function countResult(file) {
+  if (!(options.adaptiveFlushRate > 0)) return false;
+  const now = Date.now();
+  const recent = (resultTimes.get(file) || []).filter(at => at > now - 1000);
+  recent.push(now);
+  resultTimes.set(file, recent);
+  return recent.length >= options.adaptiveFlushRate;
+}
+
+/**
+ * Executing placeholders, replies and sections held for logs receiving results fast.
+ * They are replayed in order against one read of the log and written back in one write
+ * after `adaptiveFlushMs`, once `adaptiveMaxBatch` pile up, or when the log is read.
+ * @type {Map<string, { writes: Array<() => void>, timer: ReturnType<typeof setTimeout> }>}
+ */
+const deferredWrites = new Map();
+
+/**
+ * Run a log write now, or hold it while the log is buffering
+ * @param {string} file
+ * @param {() => void} write
+ * @param {boolean} [busy] Whether the log's result rate calls for buffering
+ */
+function deferWrite(file, write, busy = false) {
+  const deferred = deferredWrites.get(file);
+  if (!deferred && !busy) return write();
+  if (!deferred) {
+    const timer = setTimeout(() => flushDeferred(file), options.adaptiveFlushMs);
+    try { timer.unref(); } catch {}
+    deferredWrites.set(file, { writes: [write], timer });
+    return;
+  }
+  deferred.writes.push(write);
+  if (deferred.writes.length >= options.adaptiveMaxBatch) flushDeferred(file);
+}
+
+/** @param {string} file */
+function flushDeferred(file) {
+  const deferred = deferredWrites.get(file);
+  if (!deferred) return;
+  deferredWrites.delete(file);
+  clearTimeout(deferred.timer);
+
+  const base = storageFor(file);
+  /** @type {string | null} */
+  let content = base.exists(file) ? base.read(file) : null;
+  let dirty = false;
+  /** @type {unknown[]} */
+  const failures = [];
+  overlays.set(file, {
+    ...base,
+    exists: f => f === file ? content !== null : base.exists(f),
+    read: f => f === file ? /** @type {string} */(content ?? base.read(f)) : base.read(f),
+    write: (f, text) => { if (f === file) { content = text; dirty = true; } else base.write(f, text); }
+  });
+  try {
+    for (const write of deferred.writes) {
+      try {
+        write();
+      } catch (err) {
+        console.warn(`[writer] buffered write to ${file} failed`, err);
+        failures.push(err);
+      }
+    }
+  } finally {
+    overlays.delete(file);
+  }
+  if (dirty && content !== null) base.write(file, content);
+  for (const err of failures) writeError(file, 'write', err instanceof Error ? err.message : String(err));
+}
+
+/**
+ * @param {string} file
+ * @param {string} title
+ * @param {string[][]} sections
+ * @param {boolean} create
+ */
+function applyAppends(file, title, sections, create) {
+  const storage = storageFor(file);
+  const exists = storage.exists(file);
+  if (!exists) {
+    if (!create) return;
+    const dir = dirname(file);
+    if (dir && !storage.exists(dir)) storage.ensureDir(dir);
+  }
+
+  const lines = ensureFileHeader(exists ? readText(file).split('\n') : [], title);
+  const footerIdx = exists && findFooter(lines) >= 0 ? findFooter(lines) : lines.length;
+
   const output = [
     ...lines.slice(0, footerIdx),
-    '',
-    `### 🗣️${pageName} background at ${timestamp}`,
-    ...blocks,
+    ...sections.flat(),
     '',
     formatFooter()
   ].join('\n');
-  
-  writeFileSync(file, output, 'utf8');
+
+  writeText(file, output);
 }
 
 // Export helper functions for testing (now from repl.template.js)
 export { clockFmt, durationFmt, findFooter, findLastFencedBlock, findAgentHeaderAbove, formatBackgroundEvent, buildBlocks };
 
 /**
- * @param {{ ok: boolean, value?: any, error?: any, errors?: string[], backgroundEvents?: any[] }} result
+ * @param {JobResult} result
+ * @param {string} [when] Predicate of a guarded job, quoted when the job was skipped
  * @returns {string[]} Array of formatted blocks
  */
-const buildBlocks = result => {
-  return formatResultBlocks(result);
+const buildBlocks = (result, when) => {
+  return formatResultBlocks(result, when);
 };
 
+/**
+ * Writer settings for a page: its own config file wins over the server-wide options
+ * @param {import('./registry.js').Page} page
+ */
+function settingsFor(page) {
+  const own = pageConfig.forPage(page);
+  return {
+    order: own.order ?? options.order,
+    rotateBytes: own.rotateBytes ?? options.rotateBytes,
+    theme: own.theme
+  };
+}
+
 /**
  * @param {import('./job.js').Job} job
- * @param {{ ok: boolean, value?: any, error?: any, errors?: string[], backgroundEvents?: any[] }} result
+ * @param {JobResult} result
  */
 export function writeReply(job, result) {
+  if (options.transformResult) result = options.transformResult(result, job);
+  result = saveAttachments(job, result);
+  // A request refused before dispatch has no job id to name a file after, and only the log tells its author why
+  const rejected = !job.id;
+  if (options.resultFiles !== 'off' && !rejected) writeResultFile(job, result);
+  if ((options.resultFiles === 'only' && !rejected) || !options.markdown) return;
+  const settings = settingsFor(job.page);
+  deferWrite(job.page.file, () => withTheme(settings.theme, () => replyInTheme(job, result, settings)), countResult(job.page.file));
+}
+
+/**
+ * @param {import('./job.js').Job} job
+ * @param {JobResult} result
+ * @param {ReturnType<typeof settingsFor>} settings
+ */
+function replyInTheme(job, result, settings) {
   const nowIso = new Date().toISOString();
   const duration = job.startedAt ? (Date.parse(nowIso) - Date.parse(job.startedAt)) : 0;
 
   const v = result.value;
   let resultText = result.ok 
-    ? (v && typeof v === 'object' ? JSON.stringify(v) : String(v))
+    ? (v && typeof v === 'object' ? resultJson(v) : String(v))
     : (result.error ?? '');
   resultText = (resultText || '').replace(/\s+/g, ' ').trim();
   if (resultText.length > 100) resultText = resultText.slice(0, 100) + '...';
   console.info(`> ${job.page.name} to ${job.agent} ${result.ok ? 'succeeded' : 'failed'} in ${durationFmt(duration)} "${resultText}"`);
+  flushPending(job.page.file);
 
-  if (!existsSync(job.page.file)) {
+  if (!storageFor(job.page.file).exists(job.page.file)) {
     if (hasFileBeenSeen(job.page.file)) 
       console.warn(`[writer] writeReply: target file missing ${job.page.file}; skipping write`);
     return;
   }
   
-  let lines = readFileSync(job.page.file, 'utf8').split('\n');
+  let lines = readText(job.page.file).split('\n');
   lines = ensureFileHeader(lines, job.page.name + ' Session');
   
-  const execBlock = findExecutingBlock(lines, job.page.name, job.agent);
+  const thread = threadFor(job);
+  const execBlock = findExecutingBlock(lines, job.page.name, job.agent, thread);
   let footerIdx = findFooter(lines);
   if (footerIdx < 0) footerIdx = lines.length;
   
-  const reply = formatReplyHeader(job.page.name, job.agent, nowIso, duration, !result.ok);
-  const blocks = buildBlocks(result);
+  const reply = formatReplyHeader(job.page.name, job.agent, nowIso, duration, !result.ok, thread);
+  const blocks = [...(job.verdict ? formatVerdict(job.verdict, job.expect) : []), ...buildBlocks(result, job.when)];
   
-  const agent = formatAgentHeader(job.agent, job.page.name, job.requestedAt || nowIso);
-  const code = formatCodeBlock(job.code);
+  if (settings.order === 'newest-first') {
+    writeLog(job.page.file, layoutNewestFirst(lines, job, [reply, ...blocks], execBlock), settings.rotateBytes);
+    return;
+  }
+
+  // A request already in the log answers in place, right under its own code block
+  const threaded = !execBlock && thread ? findThreadRequest(lines, thread) : null;
+  if (threaded) {
+    const rest = lines.slice(threaded.end + 1);
+    while (rest.length && !rest[0].trim()) rest.shift();
+    writeLog(job.page.file, [...lines.slice(0, threaded.end + 1), '', reply, ...blocks, '', ...rest].join('\n'), settings.rotateBytes);
+    return;
+  }
+
+  const agent = formatAgentHeader(job.agent, job.page.name, job.requestedAt || nowIso, thread);
+  const code = requestCode(job);
   
-  let output;
+  /** @type {string[]} */
+  let prefix;
   if (execBlock) {
     // Remove the executing block (header + placeholder lines)
     const beforeExec = lines.slice(0, execBlock.headerIdx);
@@ -193,29 +751,213 @@ export function writeReply(job, result) {
     const newFooterIdx = findFooter(combinedLines);
     const finalFooterIdx = newFooterIdx >= 0 ? newFooterIdx : combinedLines.length;
     
-    output = [
-      ...combinedLines.slice(0, finalFooterIdx),
-      '',
-      reply,
-      ...blocks,
-      '',
-      formatFooter()].join('\n');
+    prefix = combinedLines.slice(0, finalFooterIdx);
   } else if (job.requestHasFooter === false) {
     const lastFence = findLastFencedBlock(lines);
     if (lastFence) {
       const agentIdx = findAgentHeaderAbove(lines, lastFence.start);
-      const prefixLines = agentIdx >= 0 
+      prefix = agentIdx >= 0 
         ? lines.slice(0, lastFence.end + 1)
         : [...lines.slice(0, lastFence.start), agent, lines.slice(lastFence.start, lastFence.end + 1).join('\n')];
-      output = [...prefixLines, '', reply, ...blocks, '', formatFooter()].join('\n');
     } else {
-      output = [...lines.slice(0, footerIdx), '', agent, code, '', reply, ...blocks, '', formatFooter()].join('\n');
+      prefix = [...lines.slice(0, footerIdx), '', agent, code];
     }
   } else {
-    output = [...lines.slice(0, footerIdx), '', agent, code, '', reply, ...blocks, '', formatFooter()].join('\n');
+    prefix = [...lines.slice(0, footerIdx), '', agent, code];
   }
+
+  const collapsed = options.dedupReplies ? collapseRepeatedReply(prefix, reply, blocks) : null;
+  const output = collapsed
+    ? [...collapsed, '', formatFooter()].join('\n')
+    : [...prefix, '', reply, ...blocks, '', formatFooter()].join('\n');
   
-  writeFileSync(job.page.file, output, 'utf8');
+  writeLog(job.page.file, output, settings.rotateBytes);
+}
+
+/**
+ * Write a finished exchange, rotating the log once it outgrows `rotateBytes`
+ * @param {string} file
+ * @param {string} content
+ * @param {number} rotateBytes
+ */
+function writeLog(file, content, rotateBytes) {
+  writeText(file, content);
+  if (rotateBytes > 0 && Buffer.byteLength(content) > rotateBytes) rotate(file);
+}
+
+/**
+ * Name of the n-th archived segment of a log: `page.md` → `page.3.md` (or `page.3.md.gz`)
+ * @param {string} file
+ * @param {number} n
+ * @param {boolean} compressed
+ */
+const archiveName = (file, n, compressed) => file.replace(/(\.md)?$/, `.${n}.md`) + (compressed ? '.gz' : '');
+
+/**
+ * Move the log's exchanges into the next numbered archive segment and start the live file
+ * over with just its header and footer. Segments are gzipped when `compressArchives` is on.
+ * @param {string} file
+ * @returns {number} The archive segment number, or 0 when logs are not written
+ */
+export function rotate(file) {
+  if (!options.markdown) return 0;
+  flushPending(file);
+  const content = readText(file);
+  const eol = lineEndings.get(file);
+  const storage = storageFor(file);
+  let n = 1;
+  while (storage.exists(archiveName(file, n, false)) || storage.exists(archiveName(file, n, true))) n++;
+
+  const archived = withBom(file, eol === '\r\n' ? content.replace(/\n/g, eol) : content);
+  if (options.compressArchives) storage.writeBytes(archiveName(file, n, true), gzipSync(archived));
+  else storage.write(archiveName(file, n, false), archived);
+
+  const lines = content.split('\n');
+  const firstExchange = lines.findIndex(line => /^#{3,}\s/.test(line));
+  const header = firstExchange >= 0 ? lines.slice(0, firstExchange) : lines.slice(0, Math.max(findFooter(lines), 0));
+  while (header.length && !header[header.length - 1].trim()) header.pop();
+  fullLogs.delete(file);
+  writeText(file, [...header, '', formatFooter()].join('\n'));
+  return n;
+}
+
+/**
+ * Read an archived segment of a log, decompressing it if it was gzipped
+ * @param {string} file The live log the segment was rotated out of
+ * @param {number} n
+ * @returns {string}
+ */
+export function readArchive(file, n) {
+  const compressed = archiveName(file, n, true);
+  const storage = storageFor(file);
+  if (storage.exists(compressed)) return gunzipSync(storage.readBytes(compressed)).toString('utf8');
+  return storage.read(archiveName(file, n, false));
+}
+
+/**
+ * Lay out an exchange newest-first: the request and its answer go directly under the file header.
+ * An executing placeholder already sitting under its request is replaced in place.
+ * @param {string[]} lines
+ * @param {import('./job.js').Job} job
+ * @param {string[]} answer Lines that follow the request (reply or executing placeholder)
+ * @param {{headerIdx: number, placeholderIdx: number} | null} execBlock
+ * @returns {string}
+ */
+function layoutNewestFirst(lines, job, answer, execBlock) {
+  if (execBlock)
+    return [...lines.slice(0, execBlock.headerIdx), ...answer, ...lines.slice(execBlock.placeholderIdx + 1)].join('\n');
+
+  let footerIdx = findFooter(lines);
+  if (footerIdx < 0) footerIdx = lines.length;
+  const agent = formatAgentHeader(job.agent, job.page.name, job.requestedAt || new Date().toISOString(), threadFor(job));
+
+  let body = lines.slice(0, footerIdx);
+  let request = [agent, requestCode(job)];
+  if (job.requestHasFooter === false) {
+    const lastFence = findLastFencedBlock(lines);
+    if (lastFence) {
+      const agentIdx = findAgentHeaderAbove(lines, lastFence.start);
+      const start = agentIdx >= 0 ? agentIdx : lastFence.start;
+      request = [...(agentIdx >= 0 ? [] : [agent]), ...lines.slice(start, lastFence.end + 1)];
+      body = [...lines.slice(0, start), ...lines.slice(lastFence.end + 1)];
+    }
+  }
+  while (body.length && !body[body.length - 1].trim()) body.pop();
+
+  const at = findTopInsertionPoint(body);
+  const rest = body.slice(at);
+  while (rest.length && !rest[0].trim()) rest.shift();
+
+  return [
+    ...body.slice(0, at),
+    '',
+    ...request,
+    '',
+    ...answer,
+    ...(rest.length ? ['', ...rest] : []),
+    '',
+    formatFooter()
+  ].join('\n');
+}
+
+/**
+ * Find where the newest exchange goes: below the header separator, or just below the title
+ * @param {string[]} lines
+ * @returns {number}
+ */
+function findTopInsertionPoint(lines) {
+  for (let i = 0; i < lines.length; i++) {
+    if (/^#{3,}\s/.test(lines[i])) break;
+    if (/^-{3,}$/.test(lines[i].trim())) return i + 1;
+  }
+  return lines.findIndex(line => /^# /.test(line)) + 1;
+}
+
+/**
+ * Fold a repeated run into one copy of its result: when the request just written has the
+ * same code as the previous request, and the new result is identical to that request's reply,
+ * the earlier reply keeps only its heading and the new one carries the result with the run's
+ * `(xN)` count. Every request stays, each with a reply heading that answers it.
+ * @param {string[]} prefix Document lines up to and including the request being answered
+ * @param {string} reply Heading of the new reply
+ * @param {string[]} blocks Formatted result blocks for the new reply
+ * @returns {string[] | null} Replacement document lines, or null when nothing collapses
+ */
+function collapseRepeatedReply(prefix, reply, blocks) {
+  const lines = prefix.join('\n').split('\n');
+
+  const request = findTrailingFence(lines, lines.length);
+  if (!request) return null;
+  const agentIdx = findAgentHeaderAbove(lines, request.start);
+  const requestStart = agentIdx >= 0 ? agentIdx : request.start;
+
+  let replyIdx = requestStart - 1;
+  const replyHeading = new RegExp('^#{4}\\s*' + replyMarkerPattern());
+  while (replyIdx >= 0 && !replyHeading.test(lines[replyIdx])) {
+    // A request or section heading in between means there is no reply directly above
+    if (/^#{1,3}\s/.test(lines[replyIdx])) return null;
+    replyIdx--;
+  }
+  if (replyIdx < 0) return null;
+
+  const previousBlocks = lines.slice(replyIdx + 1, requestStart).join('\n').trim();
+  if (!previousBlocks || previousBlocks !== blocks.join('\n').trim()) return null;
+
+  const previous = findTrailingFence(lines, replyIdx);
+  if (!previous || previous.code !== request.code) return null;
+
+  const countMatch = /\s\(x(\d+)\)$/.exec(lines[replyIdx]);
+  const count = countMatch ? Number(countMatch[1]) + 1 : 2;
+  const earlierReply = countMatch ? lines[replyIdx].slice(0, countMatch.index) : lines[replyIdx];
+  return [...lines.slice(0, replyIdx), earlierReply, '', ...lines.slice(requestStart), '', `${reply} (x${count})`, ...blocks];
+}
+
+/**
+ * Find a fenced block ending on the last non-blank line before `endIdx`
+ * @param {string[]} lines
+ * @param {number} endIdx
+ * @returns {{ start: number, end: number, code: string } | null}
+ */
+function findTrailingFence(lines, endIdx) {
+  let end = endIdx - 1;
+  while (end >= 0 && !lines[end].trim()) end--;
+  if (end < 0 || !/^```/.test(lines[end].trim())) return null;
+  let start = end - 1;
+  while (start >= 0 && !/^```/.test(lines[start].trim())) start--;
+  if (start < 0) return null;
+  return { start, end, code: lines.slice(start + 1, end).join('\n') };
+}
+
+/**
+ * Refresh the elapsed time shown by an executing placeholder
+ * @param {import('./job.js').Job} job
+ * @param {number} secs
+ */
+export function writeExecutingElapsed(job, secs) {
+  if (!storageFor(job.page.file).exists(job.page.file)) return;
+  const text = readText(job.page.file);
+  const updated = text.replace(/executing \(\d+s\)/, `executing (${secs}s)`);
+  if (updated !== text) writeText(job.page.file, updated);
 }
 
 /**
@@ -223,21 +965,40 @@ export function writeReply(job, result) {
  * @param {import('./job.js').Job} job
  */
 export function writeExecuting(job) {
-  if (!existsSync(job.page.file)) {
+  // With results going only to JSON files nothing would ever replace the placeholder
+  if (!options.executingIndicator || options.resultFiles === 'only' || !options.markdown) return;
+  const settings = settingsFor(job.page);
+  deferWrite(job.page.file, () => withTheme(settings.theme, () => executingInTheme(job, settings)));
+}
+
+/**
+ * @param {import('./job.js').Job} job
+ * @param {ReturnType<typeof settingsFor>} settings
+ */
+function executingInTheme(job, settings) {
+  flushPending(job.page.file);
+  if (!storageFor(job.page.file).exists(job.page.file)) {
     if (hasFileBeenSeen(job.page.file)) 
       console.warn(`[writer] writeExecuting: target file missing ${job.page.file}; skipping write`);
     return;
   }
   
-  let lines = readFileSync(job.page.file, 'utf8').split('\n');
+  let lines = readText(job.page.file).split('\n');
   lines = ensureFileHeader(lines, job.page.name + ' Session');
   
   const footerIdx = findFooter(lines) >= 0 ? findFooter(lines) : lines.length;
   const nowIso = new Date().toISOString();
-  const agent = formatAgentHeader(job.agent, job.page.name, job.requestedAt || nowIso);
-  const code = formatCodeBlock(job.code);
-  const executing = `#### 👍${job.page.name} to ${job.agent} at ${clockFmt(nowIso)}`;
+  const thread = threadFor(job);
+  const agent = formatAgentHeader(job.agent, job.page.name, job.requestedAt || nowIso, thread);
+  const code = requestCode(job);
+  const executing = formatExecutingHeader(job.page.name, job.agent, nowIso, thread);
+  const placeholder = formatExecutingPlaceholder(0);
   
+  if (settings.order === 'newest-first') {
+    writeText(job.page.file, layoutNewestFirst(lines, job, [executing, placeholder], null));
+    return;
+  }
+
   let output;
   if (job.requestHasFooter === false) {
     const lastFence = findLastFencedBlock(lines);
@@ -246,13 +1007,13 @@ export function writeExecuting(job) {
       const prefixLines = agentIdx >= 0 
         ? lines.slice(0, lastFence.end + 1)
         : [...lines.slice(0, lastFence.start), agent, lines.slice(lastFence.start, lastFence.end + 1).join('\n')];
-      output = [...prefixLines, '', executing, 'executing (0s)', '', formatFooter()].join('\n');
+      output = [...prefixLines, '', executing, placeholder, '', formatFooter()].join('\n');
     } else {
-      output = [...lines.slice(0, footerIdx), '', agent, code, '', executing, 'executing (0s)', '', formatFooter()].join('\n');
+      output = [...lines.slice(0, footerIdx), '', agent, code, '', executing, placeholder, '', formatFooter()].join('\n');
     }
   } else {
-    output = [...lines.slice(0, footerIdx), '', agent, code, '', executing, 'executing (0s)', '', formatFooter()].join('\n');
+    output = [...lines.slice(0, footerIdx), '', agent, code, '', executing, placeholder, '', formatFooter()].join('\n');
   }
 
-  writeFileSync(job.page.file, output, 'utf8');
+  writeText(job.page.file, output);
 }
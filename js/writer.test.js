@@ -1,9 +1,14 @@
 // @ts-check
 import { test, describe } from 'node:test';
 import assert from 'node:assert/strict';
-import { writeFileSync, readFileSync, unlinkSync, existsSync } from 'node:fs';
 import { join } from 'node:path';
-import { clockFmt, durationFmt, findFooter, findLastFencedBlock, findAgentHeaderAbove, buildBlocks, formatBackgroundEvent, writeDiagnostic } from './writer.js';
+import { clockFmt, durationFmt, findFooter, findLastFencedBlock, findAgentHeaderAbove, buildBlocks, formatBackgroundEvent, writeDiagnostic, writeReply, attachmentRefusal, configure } from './writer.js';
+import { createMemoryStorage } from './storage.js';
+import { parsePendingRequests } from './parser.js';
+
+// Keep per-instance logs in memory so write-path tests never touch the filesystem
+const storage = createMemoryStorage();
+configure({ storage });
 
 // clockFmt tests
 test('clockFmt pads single digit hours', () => {
@@ -175,14 +180,14 @@ test('buildBlocks creates JSON block for successful primitive result', () => {
   const result = { ok: true, value: 42 };
   const blocks = buildBlocks(result);
   assert.strictEqual(blocks.length, 1);
-  assert.strictEqual(blocks[0], '```JSON\n42\n```');
+  assert.strictEqual(blocks[0], '```JSON number\n42\n```');
 });
 
 test('buildBlocks creates JSON block for successful object result', () => {
   const result = { ok: true, value: { x: 1, y: 2 } };
   const blocks = buildBlocks(result);
   assert.strictEqual(blocks.length, 1);
-  assert.match(blocks[0], /```JSON\n/);
+  assert.match(blocks[0], /```JSON object\n/);
   assert.match(blocks[0], /"x": 1/);
 });
 
@@ -217,19 +222,50 @@ test('buildBlocks handles empty errors array', () => {
 test('buildBlocks handles null value', () => {
   const result = { ok: true, value: null };
   const blocks = buildBlocks(result);
-  assert.strictEqual(blocks[0], '```JSON\nnull\n```');
+  assert.strictEqual(blocks[0], '```JSON null\nnull\n```');
 });
 
 test('buildBlocks handles undefined value', () => {
   const result = { ok: true, value: undefined };
   const blocks = buildBlocks(result);
-  assert.strictEqual(blocks[0], '```JSON\nundefined\n```');
+  assert.strictEqual(blocks[0], '```JSON undefined\nundefined\n```');
 });
 
 test('buildBlocks handles string value', () => {
   const result = { ok: true, value: 'hello' };
   const blocks = buildBlocks(result);
-  assert.strictEqual(blocks[0], '```JSON\nhello\n```');
+  assert.strictEqual(blocks[0], '```JSON string\nhello\n```');
+});
+
+test('buildBlocks tells every kind of value apart by its fence', () => {
+  const fence = (/** @type {*} */ result) => buildBlocks({ ok: true, ...result })[0];
+  const rendered = [
+    fence({ value: null }),
+    fence({ value: '' }),
+    fence({ value: {} }),
+    fence({ value: [] }),
+    fence({ value: 0 }),
+    fence({ value: '0' }),
+    fence({ value: false }),
+    fence({ value: 'false' }),
+    fence({ value: undefined })
+  ];
+  assert.deepStrictEqual(rendered, [
+    '```JSON null\nnull\n```',
+    '```JSON string\n\n```',
+    '```JSON object\n{}\n```',
+    '```JSON array\n[]\n```',
+    '```JSON number\n0\n```',
+    '```JSON string\n0\n```',
+    '```JSON boolean\nfalse\n```',
+    '```JSON string\nfalse\n```',
+    '```JSON undefined\nundefined\n```'
+  ]);
+  assert.strictEqual(new Set(rendered).size, rendered.length);
+});
+
+test('buildBlocks prefers the type the page reported', () => {
+  assert.strictEqual(buildBlocks({ ok: true, valueType: 'function' })[0], '```JSON function\nundefined\n```');
 });
 
 // formatBackgroundEvent tests
@@ -366,10 +402,19 @@ test('buildBlocks with backgroundEvents array', () => {
   };
   const blocks = buildBlocks(result);
   assert.strictEqual(blocks.length, 2);
-  assert.strictEqual(blocks[0], '```JSON\n42\n```');
+  assert.strictEqual(blocks[0], '```JSON number\n42\n```');
   assert.match(blocks[1], /console\.log/);
 });
 
+test('buildBlocks renders console logs in their own section after the value', () => {
+  const blocks = buildBlocks({
+    ok: true,
+    value: 42,
+    logs: [{ level: 'log', message: 'starting' }, { level: 'warn', message: 'slow' }]
+  });
+  assert.deepStrictEqual(blocks, ['```JSON number\n42\n```', '##### Console\n```Text\n[log] starting\n[warn] slow\n```']);
+});
+
 test('buildBlocks with multiple backgroundEvents', () => {
   const result = {
     ok: true,
@@ -432,17 +477,17 @@ describe('writeDiagnostic', () => {
   const testFile = join(process.cwd(), 'test-diagnostic-temp.md');
   
   test('creates file with diagnostic when file does not exist', () => {
-    if (existsSync(testFile)) unlinkSync(testFile);
+    storage.files.delete(testFile);
     
     writeDiagnostic(testFile, 'Test diagnostic message');
     
-    assert.ok(existsSync(testFile));
-    const content = readFileSync(testFile, 'utf8');
+    assert.ok(storage.exists(testFile));
+    const content = storage.read(testFile);
     assert.match(content, /# System Diagnostic/);
     assert.match(content, /Test diagnostic message/);
     assert.match(content, /Append your JavaScript snippet below/);
     
-    unlinkSync(testFile);
+    storage.files.delete(testFile);
   });
   
   test('appends diagnostic to existing file', () => {
@@ -460,14 +505,14 @@ describe('writeDiagnostic', () => {
       ''
     ].join('\n');
     
-    writeFileSync(testFile, initial, 'utf8');
+    storage.write(testFile, initial);
     writeDiagnostic(testFile, 'Worker timeout detected');
     
-    const content = readFileSync(testFile, 'utf8');
+    const content = storage.read(testFile);
     assert.match(content, /Worker timeout detected/);
     assert.match(content, /System.*at \d{2}:\d{2}:\d{2}/);
     
-    unlinkSync(testFile);
+    storage.files.delete(testFile);
   });
   
   test('inserts diagnostic above footer', () => {
@@ -480,10 +525,10 @@ describe('writeDiagnostic', () => {
       ''
     ].join('\n');
     
-    writeFileSync(testFile, initial, 'utf8');
+    storage.write(testFile, initial);
     writeDiagnostic(testFile, 'Restart attempt 3');
     
-    const content = readFileSync(testFile, 'utf8');
+    const content = storage.read(testFile);
     const lines = content.split('\n');
   const footerIdx = lines.findIndex(l => l.includes('Append your JavaScript snippet below'));
     const diagnosticIdx = lines.findIndex(l => l.includes('Restart attempt 3'));
@@ -492,7 +537,7 @@ describe('writeDiagnostic', () => {
     assert.ok(footerIdx >= 0);
     assert.ok(diagnosticIdx < footerIdx, 'Diagnostic should be above footer');
     
-    unlinkSync(testFile);
+    storage.files.delete(testFile);
   });
 });
 
@@ -613,3 +658,1101 @@ describe('writer edge cases', () => {
     assert.ok(result.includes('Line 2'));
   });
 });
+
+describe('writeReply deduplication', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {string} code */
+  const makeJob = (file, code) => /** @type {*} */({
+    id: '1',
+    page: { name: 'dedup-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code,
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: null,
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('repeated identical result increments a count instead of duplicating', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-dedup-temp.md');
+    storage.write(file, '# Dedup\n\n' + footer);
+    writer.configure({ dedupReplies: true });
+    try {
+      writer.writeReply(makeJob(file, 'runTests()'), { ok: true, value: { pass: 3 } });
+      writer.writeReply(makeJob(file, 'runTests()'), { ok: true, value: { pass: 3 } });
+      let content = storage.read(file);
+      assert.strictEqual(content.split('runTests()').length - 1, 2);
+      assert.strictEqual(content.split('"pass": 3').length - 1, 1);
+      assert.match(content, /#### 👍dedup-page to agent at .* \(x2\)/);
+
+      writer.writeReply(makeJob(file, 'runTests()'), { ok: true, value: { pass: 3 } });
+      content = storage.read(file);
+      assert.strictEqual(content.split('runTests()').length - 1, 3);
+      assert.strictEqual(content.split('"pass": 3').length - 1, 1);
+      assert.match(content, /\(x3\)/);
+      assert.doesNotMatch(content, /\(x2\)/);
+      assert.match(content, /Append your JavaScript snippet below/);
+      // Each request keeps a reply heading, so none of them reads as waiting to run
+      assert.strictEqual(content.split('#### 👍dedup-page').length - 1, 3);
+      assert.deepStrictEqual(parsePendingRequests(content, 'dedup-page'), []);
+    } finally {
+      writer.configure({ dedupReplies: false });
+      storage.files.delete(file);
+    }
+  });
+
+  test('a different result is appended as a new reply', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-dedup-temp-2.md');
+    storage.write(file, '# Dedup\n\n' + footer);
+    writer.configure({ dedupReplies: true });
+    try {
+      writer.writeReply(makeJob(file, 'runTests()'), { ok: true, value: { pass: 3 } });
+      writer.writeReply(makeJob(file, 'runTests()'), { ok: true, value: { pass: 2 } });
+      const content = storage.read(file);
+      assert.strictEqual(content.split('runTests()').length - 1, 2);
+      assert.doesNotMatch(content, /\(x\d+\)/);
+    } finally {
+      writer.configure({ dedupReplies: false });
+      storage.files.delete(file);
+    }
+  });
+
+  test('duplicates are kept when deduplication is off', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-dedup-temp-3.md');
+    storage.write(file, '# Dedup\n\n' + footer);
+    try {
+      writer.writeReply(makeJob(file, '1 + 1'), { ok: true, value: 2 });
+      writer.writeReply(makeJob(file, '1 + 1'), { ok: true, value: 2 });
+      const content = storage.read(file);
+      assert.strictEqual(content.split('1 + 1').length - 1, 2);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('writeReply ordering', () => {
+  const initial = [
+    '# Ordering',
+    '',
+    'Intro text',
+    '',
+    '---',
+    '',
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {string} code */
+  const makeJob = (file, code) => /** @type {*} */({
+    id: '1',
+    page: { name: 'order-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code,
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: null,
+    finishedAt: null,
+    timeout: null
+  });
+
+  /** @param {'append' | 'newest-first'} order */
+  const writeTwo = async order => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), `test-order-${order}-temp.md`);
+    storage.write(file, initial);
+    writer.configure({ order });
+    try {
+      writer.writeReply(makeJob(file, 'first()'), { ok: true, value: 'one' });
+      writer.writeReply(makeJob(file, 'second()'), { ok: true, value: 'two' });
+      return storage.read(file);
+    } finally {
+      writer.configure({ order: 'append' });
+      storage.files.delete(file);
+    }
+  };
+
+  test('append order puts the latest exchange last', async () => {
+    const content = await writeTwo('append');
+    assert.ok(content.indexOf('first()') < content.indexOf('second()'));
+    assert.ok(content.indexOf('two') < content.indexOf('Append your JavaScript snippet below'));
+  });
+
+  test('newest-first order puts the latest exchange right under the header', async () => {
+    const content = await writeTwo('newest-first');
+    const separator = content.indexOf('\n---\n');
+    assert.ok(separator > content.indexOf('Intro text'));
+    assert.ok(separator < content.indexOf('second()'));
+    assert.ok(content.indexOf('second()') < content.indexOf('two'));
+    assert.ok(content.indexOf('two') < content.indexOf('first()'));
+    assert.ok(content.indexOf('first()') < content.indexOf('one'));
+    assert.ok(content.trimEnd().endsWith('> Append your JavaScript snippet below to execute against this page.'));
+  });
+
+  test('newest-first replaces the executing placeholder in place', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-order-executing-temp.md');
+    storage.write(file, initial);
+    writer.configure({ order: 'newest-first' });
+    try {
+      writer.writeReply(makeJob(file, 'older()'), { ok: true, value: 'old' });
+      const j = makeJob(file, 'newer()');
+      writer.writeExecuting(j);
+      assert.ok(storage.read(file).indexOf('executing (0s)') < storage.read(file).indexOf('older()'));
+
+      writer.writeReply(j, { ok: true, value: 'new' });
+      const content = storage.read(file);
+      assert.doesNotMatch(content, /executing \(/);
+      assert.ok(content.indexOf('newer()') < content.indexOf('```JSON string\nnew'));
+      assert.ok(content.indexOf('```JSON string\nnew') < content.indexOf('older()'));
+      assert.strictEqual(content.split('newer()').length - 1, 1);
+    } finally {
+      writer.configure({ order: 'append' });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('write coalescing', () => {
+  test('a burst of progress reports is applied in a few writes', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-coalesce-temp.md');
+    let writes = 0;
+    const counting = { ...storage, write: (/** @type {string} */ f, /** @type {string} */ c) => { writes++; storage.write(f, c); } };
+    writer.configure({ storage: counting, coalesceMs: 20 });
+    try {
+      for (let i = 0; i < 50; i++) writer.writeTestProgress(file, `report ${i}`);
+      assert.strictEqual(writes, 0);
+      await new Promise(resolve => setTimeout(resolve, 50));
+
+      assert.ok(writes <= 2, `expected at most 2 writes, got ${writes}`);
+      const content = storage.read(file);
+      assert.ok(content.indexOf('report 0\n') < content.indexOf('report 49\n'));
+      assert.strictEqual(content.match(/^report \d+$/gm)?.length, 50);
+      assert.match(content, /Append your JavaScript snippet below/);
+    } finally {
+      writer.configure({ storage, coalesceMs: 0 });
+      storage.files.delete(file);
+    }
+  });
+
+  test('pending sections are flushed before a reply is written', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-coalesce-reply-temp.md');
+    storage.write(file, '# Coalesce\n');
+    writer.configure({ coalesceMs: 1000 });
+    try {
+      writer.writeTestProgress(file, 'progress before reply');
+      writer.writeReply(/** @type {*} */({
+        id: '1',
+        page: { name: 'coalesce-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+        agent: 'agent',
+        code: 'done()',
+        requestHasFooter: true,
+        requestedAt: new Date().toISOString(),
+        startedAt: null,
+        finishedAt: null,
+        timeout: null
+      }), { ok: true, value: 1 });
+      const content = storage.read(file);
+      assert.ok(content.indexOf('progress before reply') >= 0);
+      assert.ok(content.indexOf('progress before reply') < content.indexOf('done()'));
+    } finally {
+      writer.configure({ coalesceMs: 0 });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('executing indicator', () => {
+  const footer = '----------------------------------------------------------------------\n> Append your JavaScript snippet below to execute against this page.\n\n';
+
+  /** @param {string} file */
+  const makeJob = file => /** @type {*} */({
+    id: '1',
+    page: { name: 'exec-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: 'slow()',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('marker appears at dispatch and is replaced by the reply', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-executing-temp.md');
+    storage.write(file, '# Exec\n\n' + footer);
+    try {
+      const j = makeJob(file);
+      writer.writeExecuting(j);
+      assert.match(storage.read(file), /^⏳ executing \(0s\)$/m);
+
+      writer.writeExecutingElapsed(j, 7);
+      assert.match(storage.read(file), /^⏳ executing \(7s\)$/m);
+
+      writer.writeReply(j, { ok: true, value: 'done' });
+      const content = storage.read(file);
+      assert.doesNotMatch(content, /executing \(/);
+      assert.strictEqual(content.split('slow()').length - 1, 1);
+      assert.match(content, /```JSON string\ndone\n```/);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('no marker is written when the indicator is disabled', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-executing-off-temp.md');
+    storage.write(file, '# Exec\n\n' + footer);
+    writer.configure({ executingIndicator: false });
+    try {
+      const j = makeJob(file);
+      writer.writeExecuting(j);
+      assert.doesNotMatch(storage.read(file), /executing/);
+
+      writer.writeReply(j, { ok: true, value: 'done' });
+      assert.match(storage.read(file), /slow\(\)[\s\S]*```JSON string\ndone/);
+    } finally {
+      writer.configure({ executingIndicator: true });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('writeReply thread ids', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {string} code @param {string} [thread] */
+  const makeJob = (file, code, thread) => /** @type {*} */({
+    id: '1',
+    page: { name: 'thread-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code,
+    ...(thread && { thread }),
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('reply lands under the request with the same thread id', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-thread-temp.md');
+    storage.write(file, [
+      '# Threads',
+      '',
+      '### 🗣️agent to thread-page at 10:00:00 #aaa111',
+      '```JS',
+      'first()',
+      '```',
+      '',
+      '### 🗣️agent to thread-page at 10:00:01 #bbb222',
+      '```JS',
+      'second()',
+      '```',
+      '',
+      footer
+    ].join('\n'));
+    try {
+      writer.writeReply(makeJob(file, 'first()', 'aaa111'), { ok: true, value: 'one' });
+      const content = storage.read(file);
+      assert.match(content, /first\(\)\n```\n\n#### 👍thread-page to agent at \S+ \(\d+ms\) #aaa111\n```JSON string\none\n```\n\n### 🗣️agent to thread-page at 10:00:01 #bbb222/);
+      assert.strictEqual(content.split('first()').length - 1, 1);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('generated thread ids match the request, placeholder and reply headings', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-thread-temp-2.md');
+    storage.write(file, '# Threads\n\n' + footer);
+    writer.configure({ threadIds: true });
+    try {
+      const j = makeJob(file, 'work()');
+      writer.writeExecuting(j);
+      assert.match(j.thread, /^[0-9a-f]{6}$/);
+      writer.writeReply(j, { ok: true, value: 'done' });
+      const content = storage.read(file);
+      assert.match(content, new RegExp(`### 🗣️agent to thread-page at \\S+ #${j.thread}\n`));
+      assert.match(content, new RegExp(`#### 👍thread-page to agent at \\S+ \\(\\d+ms\\) #${j.thread}\n`));
+      assert.doesNotMatch(content, /executing \(/);
+    } finally {
+      writer.configure({ threadIds: false });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('log rotation', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file */
+  const makeJob = file => /** @type {*} */({
+    id: '1',
+    page: { name: 'rotate-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: 'big()',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('oversized logs rotate into gzipped segments that read back intact', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-rotate-temp.md');
+    storage.write(file, '# Rotate\n\n' + footer);
+    writer.configure({ rotateBytes: 200, compressArchives: true });
+    try {
+      writer.writeReply(makeJob(file), { ok: true, value: 'x'.repeat(300) });
+      const archived = join(process.cwd(), 'test-rotate-temp.1.md.gz');
+      const gz = storage.blobs.get(archived);
+      assert.ok(gz);
+      assert.deepStrictEqual([...gz.subarray(0, 2)], [0x1f, 0x8b]);
+
+      const original = writer.readArchive(file, 1);
+      assert.match(original, /big\(\)/);
+      assert.match(original, new RegExp('x'.repeat(300)));
+
+      const live = storage.read(file);
+      assert.match(live, /^# Rotate/);
+      assert.doesNotMatch(live, /big\(\)/);
+      assert.match(live, /Append your JavaScript snippet below/);
+
+      writer.writeReply(makeJob(file), { ok: true, value: 'y'.repeat(300) });
+      assert.match(writer.readArchive(file, 2), /yyy/);
+    } finally {
+      writer.configure({ rotateBytes: 0, compressArchives: false });
+      storage.files.delete(file);
+      storage.blobs.delete(join(process.cwd(), 'test-rotate-temp.1.md.gz'));
+      storage.blobs.delete(join(process.cwd(), 'test-rotate-temp.2.md.gz'));
+    }
+  });
+
+  test('uncompressed segments are plain markdown', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-rotate-temp-2.md');
+    storage.write(file, '# Rotate\n\n### 🗣️agent to rotate-page at 10:00:00\n```JS\nold()\n```\n\n' + footer);
+    try {
+      assert.strictEqual(writer.rotate(file), 1);
+      assert.match(storage.read(join(process.cwd(), 'test-rotate-temp-2.1.md')), /old\(\)/);
+      assert.strictEqual(writer.readArchive(file, 1), storage.read(join(process.cwd(), 'test-rotate-temp-2.1.md')));
+    } finally {
+      storage.files.delete(file);
+      storage.files.delete(join(process.cwd(), 'test-rotate-temp-2.1.md'));
+    }
+  });
+});
+
+describe('writeReply verdicts', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {'pass' | 'fail'} verdict */
+  const makeJob = (file, verdict) => /** @type {*} */({
+    id: '1',
+    page: { name: 'verdict-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: '1 + 1',
+    expect: 2,
+    verdict,
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('a passing job is marked under its reply heading', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-verdict-temp.md');
+    storage.write(file, '# Verdict\n\n' + footer);
+    try {
+      writer.writeReply(makeJob(file, 'pass'), { ok: true, value: 2 });
+      assert.match(storage.read(file), /#### 👍verdict-page to agent .*\n✅ matches expected value\n```JSON number\n2\n```/);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('a failing job shows the expected value', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-verdict-temp-2.md');
+    storage.write(file, '# Verdict\n\n' + footer);
+    try {
+      writer.writeReply(makeJob(file, 'fail'), { ok: true, value: 3 });
+      assert.match(storage.read(file), /❌ expected:\n```JSON\n2\n```\n```JSON number\n3\n```/);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('line endings', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\r\n');
+  const source = '# Windows\r\n\r\n### 🗣️agent to crlf-page at 10:00:00\r\n```JS\r\n1 + 1\r\n```\r\n\r\n' + footer;
+
+  /** @param {string} file */
+  const makeJob = file => /** @type {*} */({
+    id: '1',
+    page: { name: 'crlf-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: '1 + 1',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('replies to a CRLF log keep CRLF throughout', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-crlf-temp.md');
+    storage.write(file, source);
+    try {
+      writer.writeReply(makeJob(file), { ok: true, value: { a: 1, b: [2] } });
+      const content = storage.read(file);
+      assert.match(content, /#### 👍crlf-page to agent/);
+      assert.doesNotMatch(content, /(^|[^\r])\n/);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('diagnostics appended to a CRLF log keep CRLF', () => {
+    const file = join(process.cwd(), 'test-crlf-temp-2.md');
+    storage.write(file, source);
+    try {
+      writeDiagnostic(file, 'Worker restarted');
+      const content = storage.read(file);
+      assert.match(content, /Worker restarted/);
+      assert.doesNotMatch(content, /(^|[^\r])\n/);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('lf mode rewrites a CRLF log with LF only', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-crlf-temp-3.md');
+    storage.write(file, source);
+    writer.configure({ lineEndings: 'lf' });
+    try {
+      writer.writeReply(makeJob(file), { ok: true, value: 2 });
+      const content = storage.read(file);
+      assert.match(content, /#### 👍crlf-page to agent/);
+      assert.doesNotMatch(content, /\r/);
+    } finally {
+      writer.configure({ lineEndings: 'preserve' });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('byte order marks', () => {
+  const source = '\uFEFF# Windows\n\n### 🗣️agent to bom-page at 10:00:00\n```JS\n1 + 1\n```\n\n' + [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file */
+  const makeJob = file => /** @type {*} */({
+    id: '1',
+    page: { name: 'bom-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: '1 + 1',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('a reply keeps the single leading mark and the header intact', () => {
+    const file = join(process.cwd(), 'test-bom-temp.md');
+    storage.write(file, source);
+    try {
+      writeReply(makeJob(file), { ok: true, value: 2 });
+      const content = storage.read(file);
+      assert.ok(content.startsWith('\uFEFF# Windows\n'));
+      assert.equal(content.split('\uFEFF').length, 2);
+      assert.equal(content.slice(1).match(/^# /gm)?.length, 1);
+      assert.match(content, /#### 👍bom-page to agent/);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('strip mode drops the mark on rewrite', () => {
+    const file = join(process.cwd(), 'test-bom-temp-2.md');
+    storage.write(file, source);
+    configure({ byteOrderMark: 'strip' });
+    try {
+      writeReply(makeJob(file), { ok: true, value: 2 });
+      const content = storage.read(file);
+      assert.ok(content.startsWith('# Windows\n'));
+      assert.match(content, /#### 👍bom-page to agent/);
+    } finally {
+      configure({ byteOrderMark: 'preserve' });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('result transform', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  test('a redacting transform is applied before the reply is written', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-transform-temp.md');
+    storage.write(file, '# Transform\n\n' + footer);
+    writer.configure({
+      transformResult: result => result.ok && result.value?.token
+        ? { ...result, value: { ...result.value, token: '[redacted]' } }
+        : result
+    });
+    try {
+      writer.writeReply(/** @type {*} */({
+        id: '1',
+        page: { name: 'transform-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+        agent: 'agent',
+        code: 'session()',
+        requestHasFooter: true,
+        requestedAt: new Date().toISOString(),
+        startedAt: new Date().toISOString(),
+        finishedAt: null,
+        timeout: null
+      }), { ok: true, value: { user: 'ada', token: 's3cr3t' } });
+      const content = storage.read(file);
+      assert.match(content, /\[redacted\]/);
+      assert.doesNotMatch(content, /s3cr3t/);
+      assert.match(content, /ada/);
+    } finally {
+      writer.configure({ transformResult: null });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('maximum log size', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  test('a reply that would overflow the log is refused until the log is rotated', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-full-temp.md');
+    storage.write(file, '# Full\n\n' + footer);
+    writer.configure({ maxLogBytes: 400 });
+    try {
+      const job = /** @type {*} */({
+        id: '1',
+        page: { name: 'full-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+        agent: 'agent',
+        code: 'big()',
+        requestHasFooter: true,
+        requestedAt: new Date().toISOString(),
+        startedAt: new Date().toISOString(),
+        finishedAt: null,
+        timeout: null
+      });
+      const before = storage.read(file);
+      assert.throws(() => writer.writeReply(job, { ok: true, value: 'x'.repeat(500) }), writer.LogFullError);
+      assert.strictEqual(storage.read(file), before);
+      assert.strictEqual(writer.isLogFull(file), true);
+
+      writer.rotate(file);
+      assert.strictEqual(writer.isLogFull(file), false);
+    } finally {
+      writer.configure({ maxLogBytes: 0 });
+      storage.files.delete(file);
+      storage.files.delete(join(process.cwd(), 'test-full-temp.1.md'));
+    }
+  });
+});
+
+describe('code sidecars', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  test('long request code is linked from the log and parses back to the original', async () => {
+    const writer = await import('./writer.js');
+    const { parseDocument } = await import('./parser.js');
+    const file = join(process.cwd(), 'test-sidecar-temp.md');
+    const code = Array.from({ length: 20 }, (_, i) => `const step${i} = await run(${i});`).join('\n');
+    storage.write(file, '# Sidecar\n\n' + footer);
+    writer.configure({ codeSidecarBytes: 100 });
+    try {
+      writer.writeReply(/** @type {*} */({
+        id: '7',
+        page: { name: 'sidecar-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+        agent: 'agent',
+        code,
+        language: 'js',
+        requestHasFooter: true,
+        requestedAt: new Date().toISOString(),
+        startedAt: new Date().toISOString(),
+        finishedAt: null,
+        timeout: null
+      }), { ok: true, value: 'done' });
+
+      const log = storage.read(file);
+      assert.match(log, /^\[code\]\(code\/7-[0-9a-f]{8}\.js\)$/m);
+      assert.doesNotMatch(log, /step19/);
+
+      const doc = parseDocument(log, 'sidecar-page', href => writer.readSidecar(file, href));
+      assert.strictEqual(doc.exchanges.length, 1);
+      assert.strictEqual(doc.exchanges[0].request.code, code);
+      assert.strictEqual(doc.exchanges[0].reply?.ok, true);
+    } finally {
+      writer.configure({ codeSidecarBytes: 0 });
+      for (const name of [...storage.files.keys()])
+        if (name === file || name.startsWith(join(process.cwd(), 'code'))) storage.files.delete(name);
+    }
+  });
+});
+
+describe('result files', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {string} id */
+  const makeJob = (file, id) => /** @type {*} */({
+    id,
+    page: { name: 'json-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: 'answer()',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date(Date.now() - 25).toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('each result is also written as JSON beside the log', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-json-temp.md');
+    const resultFile = join(process.cwd(), 'results', '31.json');
+    storage.write(file, '# JSON\n\n' + footer);
+    writer.configure({ resultFiles: 'also' });
+    try {
+      writer.writeReply(makeJob(file, '31'), { ok: true, value: { answer: 42 } });
+      const written = JSON.parse(storage.read(resultFile));
+      assert.strictEqual(written.jobId, '31');
+      assert.strictEqual(written.page, 'json-page');
+      assert.strictEqual(written.ok, true);
+      assert.deepStrictEqual(written.value, { answer: 42 });
+      assert.strictEqual(written.error, undefined);
+      assert.ok(written.durationMs >= 25);
+      assert.ok(!Number.isNaN(Date.parse(written.timestamp)));
+      assert.match(storage.read(file), /json-page to agent/);
+    } finally {
+      writer.configure({ resultFiles: 'off' });
+      storage.files.delete(file);
+      storage.files.delete(resultFile);
+    }
+  });
+
+  test('only mode leaves the log untouched', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-json-temp-2.md');
+    const resultFile = join(process.cwd(), 'results', '32.json');
+    const log = '# JSON\n\n' + footer;
+    storage.write(file, log);
+    writer.configure({ resultFiles: 'only' });
+    try {
+      writer.writeReply(makeJob(file, '32'), { ok: false, error: 'TypeError: nope' });
+      const written = JSON.parse(storage.read(resultFile));
+      assert.strictEqual(written.ok, false);
+      assert.strictEqual(written.error, 'TypeError: nope');
+      assert.strictEqual(storage.read(file), log);
+    } finally {
+      writer.configure({ resultFiles: 'off' });
+      storage.files.delete(file);
+      storage.files.delete(resultFile);
+    }
+  });
+
+  test('only mode still answers a rejected request in the log, without a result file', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-json-temp-4.md');
+    storage.write(file, '# JSON\n\n' + footer);
+    const filesBefore = storage.files.size;
+    writer.configure({ resultFiles: 'only' });
+    try {
+      writer.writeReply(makeJob(file, ''), { ok: false, error: 'request rejected: too long' });
+      assert.match(storage.read(file), /json-page to agent[\s\S]*request rejected: too long/);
+      assert.strictEqual(storage.exists(join(process.cwd(), 'results', '.json')), false);
+      assert.strictEqual(storage.files.size, filesBefore);
+    } finally {
+      writer.configure({ resultFiles: 'off' });
+      storage.files.delete(file);
+    }
+  });
+
+  test('with markdown off a result reaches its result file and no log is touched', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-json-temp-3.md');
+    const resultFile = join(process.cwd(), 'results', '33.json');
+    const log = '# JSON\n\n' + footer;
+    storage.write(file, log);
+    const logsBefore = Array.from(storage.files.keys()).filter(name => name.endsWith('.md'));
+    writer.configure({ resultFiles: 'also', markdown: false });
+    try {
+      const job = makeJob(file, '33');
+      writer.writeExecuting(job);
+      writer.writeReply(job, { ok: true, value: 'quiet' });
+      writer.writeDiagnostic(file, 'worker restarted');
+      writer.writeError(file, 'result', 'could not handle a result');
+      assert.strictEqual(JSON.parse(storage.read(resultFile)).value, 'quiet');
+      assert.strictEqual(storage.read(file), log);
+      assert.deepStrictEqual(Array.from(storage.files.keys()).filter(name => name.endsWith('.md')), logsBefore);
+    } finally {
+      writer.configure({ resultFiles: 'off', markdown: true });
+      storage.files.delete(file);
+      storage.files.delete(resultFile);
+    }
+  });
+});
+
+describe('result attachments', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {string} id */
+  const makeJob = (file, id) => /** @type {*} */({
+    id,
+    page: { name: 'attach-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: 'snap()',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+  const png = Buffer.from([0x89, 0x50, 0x4e, 0x47]);
+
+  test('an allowed image is written beside the log and linked', () => {
+    const file = join(process.cwd(), 'test-attach-temp.md');
+    const saved = join(process.cwd(), 'attachments', '41-1-shot.png');
+    storage.write(file, '# Attach\n\n' + footer);
+    configure({ attachmentTypes: { allow: ['image/*'], deny: [] } });
+    try {
+      writeReply(makeJob(file, '41'), { ok: true, value: 1, attachments: [{ name: 'shot.png', type: 'image/png', data: png.toString('base64') }] });
+      assert.deepStrictEqual(storage.readBytes(saved), png);
+      assert.match(storage.read(file), /- \[shot\.png\]\(attachments\/41-1-shot\.png\) image\/png/);
+    } finally {
+      configure({ attachmentTypes: { allow: ['*/*'], deny: [] } });
+      storage.files.delete(file);
+      storage.blobs.delete(saved);
+    }
+  });
+
+  test('a denied type is refused with an error and never written', () => {
+    const file = join(process.cwd(), 'test-attach-temp-2.md');
+    storage.write(file, '# Attach\n\n' + footer);
+    configure({ attachmentTypes: { allow: ['*/*'], deny: ['text/html'] } });
+    try {
+      writeReply(makeJob(file, '42'), { ok: true, value: 1, attachments: [{ name: 'page.html', type: 'text/html; charset=utf-8', data: Buffer.from('<script></script>').toString('base64') }] });
+      assert.ok(![...storage.blobs.keys()].some(key => key.includes('42-1-')));
+      assert.match(storage.read(file), /```Error\nattachment page\.html was not written: content type text\/html is not accepted\n```/);
+    } finally {
+      configure({ attachmentTypes: { allow: ['*/*'], deny: [] } });
+      storage.files.delete(file);
+    }
+  });
+
+  test('attachmentRefusal applies the allow list, wildcards and deny overrides', () => {
+    configure({ attachmentTypes: { allow: ['image/*', 'application/json'], deny: ['image/svg+xml'] } });
+    try {
+      assert.strictEqual(attachmentRefusal('image/png'), null);
+      assert.strictEqual(attachmentRefusal('Application/JSON; charset=utf-8'), null);
+      assert.match(String(attachmentRefusal('image/svg+xml')), /not accepted/);
+      assert.match(String(attachmentRefusal('text/plain')), /not accepted/);
+      assert.match(String(attachmentRefusal('nonsense')), /not a valid media type/);
+    } finally {
+      configure({ attachmentTypes: { allow: ['*/*'], deny: [] } });
+    }
+  });
+});
+
+describe('adaptive flushing', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file @param {number} n */
+  const makeJob = (file, n) => /** @type {*} */({
+    id: String(n),
+    page: { name: 'busy-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: `step(${n})`,
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  /**
+   * Run jobs back to back against a log, counting writes to it
+   * @param {string} file
+   * @param {number} count
+   */
+  async function runBurst(file, count) {
+    const writer = await import('./writer.js');
+    storage.write(file, '# Busy\n\n' + footer);
+    let writes = 0;
+    const write = storage.write;
+    storage.write = (f, content) => { if (f === file) writes++; write(f, content); };
+    try {
+      for (let n = 1; n <= count; n++) {
+        const job = makeJob(file, n);
+        writer.writeExecuting(job);
+        writer.writeReply(job, { ok: true, value: n });
+      }
+      return { writes, log: writer.readLog(file) };
+    } finally {
+      storage.write = write;
+      storage.files.delete(file);
+    }
+  }
+
+  test('a log receiving results fast is written in batches', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-adaptive-temp.md');
+    const immediate = await runBurst(file, 40);
+
+    writer.configure({ adaptiveFlushRate: 5, adaptiveFlushMs: 60_000, adaptiveMaxBatch: 1000 });
+    try {
+      const buffered = await runBurst(file, 40);
+      assert.strictEqual(immediate.writes, 80);
+      assert.ok(buffered.writes < 10, `expected few writes, got ${buffered.writes}`);
+      /** @param {string} log */
+      const timeless = log => log.replace(/\d\d:\d\d:\d\d/g, 'T').replace(/\b\d+(\.\d+)?m?s\b/g, 'D');
+      assert.strictEqual(timeless(buffered.log), timeless(immediate.log), 'reading the log flushes what was buffered');
+    } finally {
+      writer.configure({ adaptiveFlushRate: 0, adaptiveFlushMs: 250, adaptiveMaxBatch: 50 });
+    }
+  });
+});
+
+describe('trailing whitespace', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  /** @param {string} file */
+  const makeJob = file => /** @type {*} */({
+    id: '1',
+    page: { name: 'tidy-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: '1 + 1',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  test('logs end in exactly one newline however often they are written', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-tidy-temp.md');
+    storage.write(file, '# Tidy   \n\nsome notes\t\n\n' + footer + '\n\n');
+    writer.configure({ trailingNewline: 'one', stripTrailingSpaces: true });
+    try {
+      writeDiagnostic(file, 'first');
+      const once = storage.read(file);
+      assert.ok(once.endsWith('page.\n'), JSON.stringify(once.slice(-10)));
+      assert.doesNotMatch(once, /[ \t]$/m);
+
+      writer.writeReply(makeJob(file), { ok: true, value: 2 });
+      writeDiagnostic(file, 'second');
+      const twice = storage.read(file);
+      assert.ok(twice.endsWith('page.\n'));
+      assert.doesNotMatch(twice, /[ \t]$/m);
+    } finally {
+      writer.configure({ trailingNewline: 'keep', stripTrailingSpaces: false });
+      storage.files.delete(file);
+    }
+  });
+
+  test('none leaves no newline at the end', () => {
+    const file = join(process.cwd(), 'test-tidy-temp-2.md');
+    storage.write(file, '# Tidy\n\n' + footer);
+    configure({ trailingNewline: 'none' });
+    try {
+      writeDiagnostic(file, 'first');
+      writeDiagnostic(file, 'second');
+      const content = storage.read(file);
+      assert.ok(content.endsWith('page.'));
+      assert.strictEqual(content.match(/second/g)?.length, 1);
+    } finally {
+      configure({ trailingNewline: 'keep' });
+      storage.files.delete(file);
+    }
+  });
+});
+
+describe('error notes', () => {
+  const footer = [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n');
+
+  test('a parse error is noted in the log above the footer', async () => {
+    const writer = await import('./writer.js');
+    const { parseRequest } = await import('./parser.js');
+    const file = join(process.cwd(), 'test-error-note-temp.md');
+    storage.write(file, '# Errors\n\n' + footer);
+    try {
+      writer.writeError(file, 'parse', 'Could not parse request: unterminated fence\nat line 12');
+      const content = storage.read(file);
+      assert.match(content, /^> ⚠️ Daebug error \(parse\) at \d\d:\d\d:\d\d: Could not parse request: unterminated fence\n> at line 12$/m);
+      assert.ok(content.indexOf('Daebug error') < content.indexOf('> Append your JavaScript'));
+      assert.strictEqual(parseRequest(content, 'errors'), null);
+    } finally {
+      storage.files.delete(file);
+    }
+  });
+
+  test('no log is created just to hold a note', async () => {
+    const writer = await import('./writer.js');
+    const file = join(process.cwd(), 'test-error-note-missing.md');
+    writer.writeError(file, 'result', 'boom');
+    assert.strictEqual(storage.exists(file), false);
+  });
+});
+
+test('a skipped job is answered with a note quoting its predicate', async () => {
+  const writer = await import('./writer.js');
+  const file = join(process.cwd(), 'test-skipped-temp.md');
+  storage.write(file, '# Guarded\n\n' + [
+    '----------------------------------------------------------------------',
+    '> Append your JavaScript snippet below to execute against this page.',
+    '',
+    ''
+  ].join('\n'));
+  try {
+    writer.writeReply(/** @type {*} */({
+      id: '1',
+      page: { name: 'guarded', url: '', file, state: 'executing', lastSeen: Date.now() },
+      agent: 'agent',
+      code: 'deploy()',
+      when: 'window.ready',
+      requestHasFooter: true,
+      requestedAt: new Date().toISOString(),
+      startedAt: new Date().toISOString(),
+      finishedAt: null,
+      timeout: null
+    }), { ok: true, skipped: true });
+    const content = storage.read(file);
+    assert.match(content, /^> Skipped: `window\.ready` was not true$/m);
+    assert.doesNotMatch(content, /```JSON undefined/);
+  } finally {
+    storage.files.delete(file);
+  }
+});
+
+describe('blank logs', () => {
+  /** @param {string} file */
+  const makeJob = file => /** @type {*} */({
+    id: '1',
+    page: { name: 'blank-page', url: '', file, state: 'executing', lastSeen: Date.now() },
+    agent: 'agent',
+    code: '1 + 1',
+    requestHasFooter: true,
+    requestedAt: new Date().toISOString(),
+    startedAt: new Date().toISOString(),
+    finishedAt: null,
+    timeout: null
+  });
+
+  for (const [label, blank] of [['an empty', ''], ['a whitespace-only', '  \n\n\t\n']]) {
+    test(`${label} log gains a header on its first reply`, async () => {
+      const writer = await import('./writer.js');
+      const { parseDocument } = await import('./parser.js');
+      const file = join(process.cwd(), 'test-blank-temp.md');
+      storage.write(file, blank);
+      try {
+        writer.writeReply(makeJob(file), { ok: true, value: 2 });
+        const content = storage.read(file);
+        assert.match(content, /^# blank-page Session\n/);
+        assert.doesNotMatch(content, /^[ \t]+$/m);
+        const doc = parseDocument(content, 'blank-page');
+        assert.strictEqual(doc.title, 'blank-page Session');
+        assert.strictEqual(doc.exchanges.length, 1);
+        assert.ok(doc.exchanges[0].reply?.ok);
+      } finally {
+        storage.files.delete(file);
+      }
+    });
+  }
+});